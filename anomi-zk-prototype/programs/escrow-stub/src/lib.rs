@@ -0,0 +1,136 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("C19EtHY1MuDsY3nUWX4vyzdsW8zeGKB2b9NqqTLvDCNV");
+
+/// Stub escrow program for tests: takes custody of a market's escrowed tokens
+/// through the market's `lock` / `release` CPIs
+#[program]
+pub mod escrow_stub {
+    use super::*;
+
+    /// Create the custody vault for `mint`; only `escrow_authority` (the
+    /// market's escrow authority PDA) may release from it
+    pub fn initialize_custody(ctx: Context<InitializeCustody>, escrow_authority: Pubkey) -> Result<()> {
+        let custody = &mut ctx.accounts.custody;
+        custody.mint = ctx.accounts.mint.key();
+        custody.escrow_authority = escrow_authority;
+        custody.bump = ctx.bumps.custody;
+        Ok(())
+    }
+
+    /// Called by the market when an ask is placed
+    pub fn lock(ctx: Context<Lock>, amount: u64) -> Result<()> {
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.source.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        msg!("EscrowStub: locked {} tokens from {}", amount, ctx.accounts.source.key());
+        Ok(())
+    }
+
+    /// Called by the market, signed by its escrow authority, to pay tokens out
+    pub fn release(ctx: Context<Release>, amount: u64) -> Result<()> {
+        let custody = &ctx.accounts.custody;
+        let seeds = &[b"custody", custody.mint.as_ref(), &[custody.bump]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.custody.to_account_info(),
+                },
+                &[&seeds[..]],
+            ),
+            amount,
+        )?;
+        msg!("EscrowStub: released {} tokens to {}", amount, ctx.accounts.destination.key());
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Custody {
+    pub mint: Pubkey,
+    pub escrow_authority: Pubkey,
+    pub bump: u8,
+}
+
+impl Custody {
+    pub const LEN: usize = 8 + 32 + 32 + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitializeCustody<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Custody::LEN,
+        seeds = [b"custody", mint.key().as_ref()],
+        bump,
+    )]
+    pub custody: Account<'info, Custody>,
+
+    #[account(
+        init,
+        payer = payer,
+        token::mint = mint,
+        token::authority = custody,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Lock<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub source: Account<'info, TokenAccount>,
+
+    #[account(seeds = [b"custody", custody.mint.as_ref()], bump = custody.bump)]
+    pub custody: Account<'info, Custody>,
+
+    #[account(mut, seeds = [b"vault", custody.mint.as_ref()], bump)]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Release<'info> {
+    pub escrow_authority: Signer<'info>,
+
+    /// CHECK: token account the market pays out to; checked by the token program
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"custody", custody.mint.as_ref()],
+        bump = custody.bump,
+        has_one = escrow_authority,
+    )]
+    pub custody: Account<'info, Custody>,
+
+    #[account(mut, seeds = [b"vault", custody.mint.as_ref()], bump)]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
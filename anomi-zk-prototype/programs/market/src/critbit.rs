@@ -11,7 +11,10 @@ use crate::error::ErrorCode;
 /// In our case, keys are prices, and values are order queues at that price.
 
 /// Maximum depth of the CritBit tree (supports 2^64 price levels)
-pub const CRITBIT_MAX_DEPTH: usize = 64;
+/// 
+/// Also the number of key bits: every bit position (`prefix_len`, critical
+/// bits) is a `u8` in `0..CRITBIT_MAX_DEPTH`, bit 0 being the least significant.
+pub const CRITBIT_MAX_DEPTH: u8 = 64;
 
 /// Node in the CritBit tree
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
@@ -26,7 +29,7 @@ pub struct CritBitNode {
     pub left: u32,
     /// Right child index (higher prices)
     pub right: u32,
-    /// Critical bit position
+    /// Critical bit position (inner nodes only, always < `CRITBIT_MAX_DEPTH`)
     pub prefix_len: u8,
     /// Is this a leaf node?
     pub is_leaf: bool,
@@ -49,6 +52,19 @@ impl CritBitNode {
         }
     }
     
+    /// Unused node on the free chain, linking to `next_free`
+    pub fn free(next_free: u32) -> Self {
+        Self {
+            key: 0,
+            order_index: 0,
+            parent: Self::EMPTY,
+            left: next_free,
+            right: Self::EMPTY,
+            prefix_len: 0,
+            is_leaf: false,
+        }
+    }
+    
     pub fn new_inner(prefix_len: u8) -> Self {
         Self {
             key: 0,
@@ -69,7 +85,7 @@ pub struct CritBitTree {
     pub root: u32,
     /// Number of leaf nodes (price levels)
     pub leaf_count: u32,
-    /// Next free node index
+    /// Head of the free node chain (free nodes are linked through `left`)
     pub free_list: u32,
     /// Nodes (preallocated array)
     pub nodes: Vec<CritBitNode>,
@@ -77,40 +93,35 @@ pub struct CritBitTree {
 
 impl CritBitTree {
     /// Initialize a new CritBit tree with capacity
+    /// Every node starts on the free chain: node i links to node i + 1
     pub fn new(capacity: usize) -> Self {
         let mut nodes = Vec::with_capacity(capacity);
-        for _ in 0..capacity {
-            nodes.push(CritBitNode {
-                key: 0,
-                order_index: 0,
-                parent: CritBitNode::EMPTY,
-                left: CritBitNode::EMPTY,
-                right: CritBitNode::EMPTY,
-                prefix_len: 0,
-                is_leaf: false,
-            });
+        for i in 0..capacity {
+            let next = if i + 1 < capacity { (i + 1) as u32 } else { CritBitNode::EMPTY };
+            nodes.push(CritBitNode::free(next));
         }
         
         Self {
             root: CritBitNode::EMPTY,
             leaf_count: 0,
-            free_list: 0,
+            free_list: if capacity > 0 { 0 } else { CritBitNode::EMPTY },
             nodes,
         }
     }
     
-    /// Find the critical bit where two keys differ
-    fn find_critical_bit(key1: u64, key2: u64) -> u8 {
+    /// Find the highest bit where two keys differ, or None if they are identical
+    fn find_critical_bit(key1: u64, key2: u64) -> Option<u8> {
         let xor = key1 ^ key2;
         if xor == 0 {
-            return 64; // Keys are identical
+            return None;
         }
-        63 - xor.leading_zeros() as u8
+        // xor != 0, so leading_zeros() is at most 63 and the result is in 0..64
+        Some(CRITBIT_MAX_DEPTH - 1 - xor.leading_zeros() as u8)
     }
     
-    /// Get the bit at a specific position in a key
+    /// Get the bit at a specific position in a key (false for positions past the key)
     fn get_bit(key: u64, bit_pos: u8) -> bool {
-        if bit_pos >= 64 {
+        if bit_pos >= CRITBIT_MAX_DEPTH {
             return false;
         }
         (key >> bit_pos) & 1 == 1
@@ -119,14 +130,46 @@ impl CritBitTree {
     /// Allocate a new node from the free list
     fn alloc_node(&mut self) -> Result<u32> {
         require!(
-            self.free_list < self.nodes.len() as u32,
+            self.free_list != CritBitNode::EMPTY,
             ErrorCode::OrderBookFull
         );
         let index = self.free_list;
-        self.free_list += 1;
+        self.free_list = self.nodes[index as usize].left;
         Ok(index)
     }
     
+    /// Return a node to the free list
+    fn free_node(&mut self, index: u32) {
+        self.nodes[index as usize] = CritBitNode::free(self.free_list);
+        self.free_list = index;
+    }
+    
+    /// Number of nodes available for allocation
+    pub fn free_node_count(&self) -> usize {
+        let mut count = 0;
+        let mut current = self.free_list;
+        while current != CritBitNode::EMPTY && count < self.nodes.len() {
+            count += 1;
+            current = self.nodes[current as usize].left;
+        }
+        count
+    }
+    
+    /// Number of nodes currently holding leaves or inner nodes
+    pub fn used_node_count(&self) -> usize {
+        self.nodes.len() - self.free_node_count()
+    }
+    
+    /// Check whether `insert(key, ..)` would succeed without touching the tree
+    /// (the first leaf takes one node, every later leaf an inner node plus a leaf)
+    pub fn can_insert(&self, key: u64) -> bool {
+        if self.find(key).is_some() {
+            return true;
+        }
+        let needed = if self.root == CritBitNode::EMPTY { 1 } else { 2 };
+        needed <= self.free_node_count()
+    }
+    
     /// Insert a new price level into the tree
     pub fn insert(&mut self, key: u64, order_index: u32) -> Result<()> {
         // Empty tree case
@@ -145,14 +188,11 @@ impl CritBitTree {
             
             if node.is_leaf {
                 // Found a leaf - need to create a new inner node
-                if node.key == key {
+                let Some(crit_bit) = Self::find_critical_bit(key, node.key) else {
                     // Price level already exists - update order index
                     self.nodes[current as usize].order_index = order_index;
                     return Ok(());
-                }
-                
-                // Find critical bit
-                let crit_bit = Self::find_critical_bit(key, node.key);
+                };
                 
                 // Create new inner node
                 let inner_index = self.alloc_node()?;
@@ -207,7 +247,7 @@ impl CritBitTree {
     /// Remove a price level from the tree
     pub fn remove(&mut self, key: u64) -> Result<u32> {
         if self.root == CritBitNode::EMPTY {
-            return Err(ErrorCode::OrderNotFound.into());
+            return Err(ErrorCode::PriceLevelNotFound.into());
         }
         
         // Find the leaf node with this key
@@ -217,7 +257,7 @@ impl CritBitTree {
             
             if node.is_leaf {
                 if node.key != key {
-                    return Err(ErrorCode::OrderNotFound.into());
+                    return Err(ErrorCode::PriceLevelNotFound.into());
                 }
                 
                 let order_index = node.order_index;
@@ -226,6 +266,7 @@ impl CritBitTree {
                 if node.parent == CritBitNode::EMPTY {
                     self.root = CritBitNode::EMPTY;
                     self.leaf_count = 0;
+                    self.free_node(current);
                     return Ok(order_index);
                 }
                 
@@ -253,6 +294,10 @@ impl CritBitTree {
                     self.nodes[sibling_index as usize].parent = grandparent_index;
                 }
                 
+                // The leaf and its parent inner node are no longer referenced
+                self.free_node(current);
+                self.free_node(parent_index);
+                
                 self.leaf_count -= 1;
                 return Ok(order_index);
             }
@@ -370,6 +415,32 @@ impl CritBitTree {
             (None, None) => None,
         }
     }
+    
+    /// Collect all leaves as (key, order_index), sorted by key ascending
+    /// CritBit trees don't maintain BST ordering, so leaves are gathered then sorted
+    pub fn leaves(&self) -> Vec<(u64, u32)> {
+        let mut leaves = Vec::with_capacity(self.leaf_count as usize);
+        if self.root == CritBitNode::EMPTY {
+            return leaves;
+        }
+        
+        let mut stack = vec![self.root];
+        while let Some(node_index) = stack.pop() {
+            if node_index == CritBitNode::EMPTY {
+                continue;
+            }
+            let node = self.nodes[node_index as usize];
+            if node.is_leaf {
+                leaves.push((node.key, node.order_index));
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+        
+        leaves.sort_unstable_by_key(|(key, _)| *key);
+        leaves
+    }
 }
 
 #[cfg(test)]
@@ -418,4 +489,118 @@ mod tests {
         assert_eq!(tree.find(100), Some(0));
         assert_eq!(tree.find(200), Some(1));
     }
+    
+    #[test]
+    fn test_critbit_two_leaf_remove_promotes_sibling() {
+        // Exactly enough nodes for two leaves and their inner node
+        let mut tree = CritBitTree::new(3);
+        tree.insert(100, 0).unwrap();
+        tree.insert(200, 1).unwrap();
+        assert!(!tree.nodes[tree.root as usize].is_leaf);
+        
+        // The surviving leaf becomes the root and the inner node is freed
+        assert_eq!(tree.remove(100).unwrap(), 0);
+        let root = tree.nodes[tree.root as usize];
+        assert!(root.is_leaf);
+        assert_eq!((root.key, root.order_index), (200, 1));
+        assert_eq!(root.parent, CritBitNode::EMPTY);
+        assert_eq!(tree.leaf_count, 1);
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (1, 2));
+        assert_eq!(tree.find(100), None);
+        assert_eq!(tree.find(200), Some(1));
+        
+        // Both freed nodes are reusable for the next insert
+        tree.insert(150, 2).unwrap();
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (3, 0));
+        assert_eq!(tree.leaves(), vec![(150, 2), (200, 1)]);
+        assert_eq!(tree.min(), Some((150, 2)));
+        assert_eq!(tree.max(), Some((200, 1)));
+        
+        // Removing the other leaf promotes the new sibling the same way
+        assert_eq!(tree.remove(200).unwrap(), 1);
+        assert_eq!(tree.nodes[tree.root as usize].key, 150);
+        assert_eq!(tree.nodes[tree.root as usize].parent, CritBitNode::EMPTY);
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (1, 2));
+    }
+    
+    #[test]
+    fn test_critbit_node_counts_reflect_reclaimed_nodes() {
+        let mut tree = CritBitTree::new(5);
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (0, 5));
+        
+        // First leaf takes one node, each later leaf an inner node plus a leaf
+        tree.insert(100, 0).unwrap();
+        tree.insert(200, 1).unwrap();
+        tree.insert(150, 2).unwrap();
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (5, 0));
+        assert!(tree.insert(300, 3).is_err());
+        
+        // Removing a leaf frees it and its parent inner node
+        tree.remove(150).unwrap();
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (3, 2));
+        
+        // Reclaimed nodes are reused
+        tree.insert(300, 3).unwrap();
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (5, 0));
+        assert_eq!(tree.leaves(), vec![(100, 0), (200, 1), (300, 3)]);
+        
+        tree.remove(100).unwrap();
+        tree.remove(200).unwrap();
+        tree.remove(300).unwrap();
+        assert_eq!((tree.used_node_count(), tree.free_node_count()), (0, 5));
+        assert_eq!(tree.min(), None);
+    }
+    
+    #[test]
+    fn test_critical_bit_boundaries() {
+        assert_eq!(CritBitTree::find_critical_bit(7, 7), None);
+        assert_eq!(CritBitTree::find_critical_bit(0, 1), Some(0));
+        assert_eq!(CritBitTree::find_critical_bit(0, 1 << 63), Some(63));
+        assert_eq!(CritBitTree::find_critical_bit(u64::MAX, u64::MAX >> 1), Some(63));
+        
+        assert!(CritBitTree::get_bit(1 << 63, 63));
+        assert!(!CritBitTree::get_bit(u64::MAX >> 1, 63));
+        assert!(!CritBitTree::get_bit(u64::MAX, CRITBIT_MAX_DEPTH));
+        assert!(!CritBitTree::get_bit(u64::MAX, u8::MAX));
+    }
+    
+    #[test]
+    fn test_critbit_routes_keys_differing_only_in_bit_63() {
+        let low = 42;
+        let high = 42 | (1 << 63);
+        let mut tree = CritBitTree::new(10);
+        tree.insert(low, 0).unwrap();
+        tree.insert(high, 1).unwrap();
+        
+        let root = tree.nodes[tree.root as usize];
+        assert!(!root.is_leaf);
+        assert_eq!(root.prefix_len, 63);
+        assert_eq!(tree.nodes[root.left as usize].key, low);
+        assert_eq!(tree.nodes[root.right as usize].key, high);
+        
+        assert_eq!(tree.find(low), Some(0));
+        assert_eq!(tree.find(high), Some(1));
+        assert_eq!(tree.find(43 | (1 << 63)), None);
+        assert_eq!((tree.min(), tree.max()), (Some((low, 0)), Some((high, 1))));
+        
+        // Extremes of the key space sit on either side of a bit-63 split too
+        tree.insert(0, 2).unwrap();
+        tree.insert(u64::MAX, 3).unwrap();
+        assert_eq!(tree.leaves(), vec![(0, 2), (low, 0), (high, 1), (u64::MAX, 3)]);
+        assert_eq!(tree.remove(high).unwrap(), 1);
+        assert_eq!(tree.find(u64::MAX), Some(3));
+        assert_eq!(tree.find(low), Some(0));
+    }
+    
+    #[test]
+    fn test_critbit_leaves_sorted() {
+        let mut tree = CritBitTree::new(100);
+        
+        tree.insert(300, 0).unwrap();
+        tree.insert(100, 1).unwrap();
+        tree.insert(200, 2).unwrap();
+        tree.insert(150, 3).unwrap();
+        
+        assert_eq!(tree.leaves(), vec![(100, 1), (150, 3), (200, 2), (300, 0)]);
+    }
 }
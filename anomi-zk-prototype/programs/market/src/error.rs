@@ -6,12 +6,36 @@ pub enum ErrorCode {
     #[msg("Amount must be greater than zero")]
     InvalidAmount,
 
+    #[msg("Order quantity exceeds the market's maximum order quantity")]
+    QuantityTooLarge,
+
     #[msg("Price must be greater than zero")]
     InvalidPrice,
 
+    #[msg("Price scale must be a power of ten")]
+    InvalidPriceScale,
+
+    #[msg("Jurisdiction must be a two-letter uppercase ISO 3166-1 country code")]
+    InvalidJurisdiction,
+
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+
     // Account validation
     #[msg("Token account owner does not match seller")]
     InvalidTokenAccountOwner,
+    
+    #[msg("Refund account is not the token account that funded the order")]
+    RefundAccountMismatch,
+    
+    #[msg("Unwrap needs a wrapped SOL refund account owned by the signer")]
+    InvalidUnwrap,
+    
+    #[msg("Attestation account is not owned by this program")]
+    InvalidAttestation,
+    
+    #[msg("Owner state account is not owned by this program")]
+    InvalidOwnerState,
 
     #[msg("Token mint does not match expected mint")]
     InvalidMint,
@@ -26,6 +50,15 @@ pub enum ErrorCode {
     #[msg("Invalid program ID")]
     InvalidProgramId,
 
+    #[msg("Unauthorized - only the market authority can perform this")]
+    UnauthorizedAuthority,
+
+    #[msg("Unauthorized - only the market arbitrator can resolve disputes")]
+    UnauthorizedArbitrator,
+
+    #[msg("Unauthorized - only the pending authority can accept the transfer")]
+    UnauthorizedPendingAuthority,
+
     // Order book errors
     #[msg("Order book is full")]
     OrderBookFull,
@@ -33,12 +66,45 @@ pub enum ErrorCode {
     #[msg("Order not found")]
     OrderNotFound,
 
+    #[msg("No price level at this price on this side of the book")]
+    PriceLevelNotFound,
+
+    #[msg("Price level exists but does not contain this order")]
+    OrderNotFoundInQueue,
+
     #[msg("Invalid order side")]
     InvalidSide,
 
+    #[msg("Cancel batch is empty or larger than the maximum")]
+    InvalidCancelBatch,
+
     #[msg("No matching orders found for this bid")]
     NoMatchingOrders,
 
+    #[msg("This side of the order book is frozen for new orders")]
+    SideFrozen,
+
+    #[msg("Placing orders too quickly - wait for the market's placement cooldown")]
+    Throttled,
+
+    #[msg("Maker's heartbeat deadline has not lapsed, or no deadman switch is armed")]
+    MakerNotStale,
+
+    #[msg("Order expiry must be in the future")]
+    InvalidExpiry,
+
+    #[msg("Order would narrow the spread below the market's minimum")]
+    SpreadTooTight,
+
+    #[msg("Market is outside its trading hours")]
+    MarketClosed,
+
+    #[msg("Market is paused by its authority")]
+    MarketPaused,
+
+    #[msg("Trading hours must open before they close")]
+    InvalidTradingHours,
+
     // Order type specific
     #[msg("Self-trade not allowed")]
     SelfTradeNotAllowed,
@@ -49,10 +115,25 @@ pub enum ErrorCode {
     #[msg("Fill-or-kill order cannot be fully filled")]
     FillOrKillNotFilled,
 
+    #[msg("Match produced more fills than the fill log can hold")]
+    FillLogFull,
+
+    #[msg("Worst fill price deviates from the best price by more than the allowed slippage")]
+    SlippageExceeded,
+
     // Payment
-    #[msg("Payment method string is too long (max 100 characters)")]
+    #[msg("Payment method string is too long (max 32 bytes)")]
     PaymentMethodTooLong,
     
+    #[msg("Token account balance is too low to escrow the order")]
+    InsufficientFunds,
+    
+    #[msg("The order's extras account was not passed or doesn't belong to it")]
+    OrderExtrasMissing,
+    
+    #[msg("Client order ID already names an order with extras in the book")]
+    ClientOrderIdInUse,
+    
     // P2P Settlement
     #[msg("Unauthorized action - only order owner can perform this")]
     UnauthorizedAction,
@@ -60,6 +141,48 @@ pub enum ErrorCode {
     #[msg("Settlement delay has not expired yet")]
     SettlementDelayNotExpired,
     
+    #[msg("A settlement proof for this order was rejected recently; retry after the backoff")]
+    VerificationBackoff,
+    
+    #[msg("Order payment status does not allow this action")]
+    InvalidPaymentStatus,
+    
+    #[msg("Order has already settled")]
+    OrderAlreadySettled,
+    
+    #[msg("Settlement token account does not match the order's settlement destination")]
+    InvalidSettlementDestination,
+    
+    #[msg("Settlement batch is empty, too large, or missing destination accounts")]
+    InvalidSettlementBatch,
+    
+    #[msg("The order's on-settle hook program must be the first remaining account")]
+    SettleHookMissing,
+    
+    #[msg("Referral share must be 1 to 1000 bps and name a recipient account")]
+    InvalidReferral,
+    
+    #[msg("The order's referral token account must follow any hook program in remaining accounts")]
+    ReferralAccountMissing,
+    
+    #[msg("Escrow release exceeds the tokens the order book has locked")]
+    EscrowLedgerMismatch,
+    
+    #[msg("The market's escrow program and its accounts must follow any other remaining accounts")]
+    EscrowProgramMissing,
+    
+    #[msg("Escrow custody can only change while the book has no tokens locked")]
+    EscrowInUse,
+    
+    #[msg("This instruction does not support a market with a delegated escrow program")]
+    EscrowDelegated,
+    
+    #[msg("Import batch is empty, too large, crossing, or older than orders already resting")]
+    InvalidImport,
+    
+    #[msg("Escrow vault does not hold the tokens for the imported asks")]
+    ImportNotFunded,
+    
     // ZK Proof verification
     #[msg("Invalid ZK proof")]
     InvalidProof,
@@ -69,4 +192,10 @@ pub enum ErrorCode {
     
     #[msg("ZK proof verification failed")]
     ProofVerificationFailed,
+    
+    #[msg("ZK proof payment timestamp is older than the market's proof validity window")]
+    ProofExpired,
+    
+    #[msg("Verifying key has the wrong size or a non-canonical coordinate")]
+    InvalidVerifyingKey,
 }
@@ -0,0 +1,166 @@
+//! Delegated escrow custody
+//!
+//! A market whose `escrow_program` is set keeps asks' tokens in that program
+//! instead of the in-house escrow vault. The market CPIs into two Anchor
+//! instructions it must implement:
+//!
+//! - `lock(amount: u64)`: accounts `[owner (signer), source (writable), ..]`,
+//!   moving `amount` from the owner's token account into custody
+//! - `release(amount: u64)`: accounts `[escrow_authority (signer), destination
+//!   (writable), ..]`, paying `amount` out of custody; `escrow_authority` is the
+//!   market's `[b"escrow_authority", mint]` PDA
+//!
+//! The trailing accounts are the escrow program's own (vault, token program,
+//! ...). Callers pass the escrow program followed by those accounts as
+//! remaining accounts and the market forwards them unchanged.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use crate::error::ErrorCode;
+
+/// The delegated escrow program and the accounts forwarded to it, starting at
+/// `offset` in `remaining_accounts`
+pub fn delegated_accounts<'a, 'info>(
+    remaining_accounts: &'a [AccountInfo<'info>],
+    offset: usize,
+    escrow_program: &Pubkey,
+) -> Result<(&'a AccountInfo<'info>, &'a [AccountInfo<'info>])> {
+    let Some((program, forwarded)) = remaining_accounts
+        .get(offset..)
+        .and_then(|accounts| accounts.split_first())
+        .filter(|(program, _)| program.key() == *escrow_program && program.executable)
+    else {
+        return Err(ErrorCode::EscrowProgramMissing.into());
+    };
+    Ok((program, forwarded))
+}
+
+/// Move `amount` from `source` into the escrow program's custody; `owner`
+/// must have signed the outer instruction
+pub fn lock<'info>(
+    escrow_program: &AccountInfo<'info>,
+    forwarded: &[AccountInfo<'info>],
+    owner: AccountInfo<'info>,
+    source: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let ix = lock_instruction(escrow_program.key(), owner.key(), source.key(), forwarded, amount);
+    let mut accounts = vec![owner, source, escrow_program.clone()];
+    accounts.extend_from_slice(forwarded);
+    invoke(&ix, &accounts)?;
+    Ok(())
+}
+
+/// Pay `amount` out of the escrow program's custody to `destination`, signed
+/// by the market's escrow authority PDA
+pub fn release<'info>(
+    escrow_program: &AccountInfo<'info>,
+    forwarded: &[AccountInfo<'info>],
+    escrow_authority: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let ix = release_instruction(
+        escrow_program.key(),
+        escrow_authority.key(),
+        destination.key(),
+        forwarded,
+        amount,
+    );
+    let mut accounts = vec![escrow_authority, destination, escrow_program.clone()];
+    accounts.extend_from_slice(forwarded);
+    invoke_signed(&ix, &accounts, signer_seeds)?;
+    Ok(())
+}
+
+/// CPI into the escrow program's `lock` (see module docs)
+pub fn lock_instruction(
+    escrow_program: Pubkey,
+    owner: Pubkey,
+    source: Pubkey,
+    forwarded: &[AccountInfo],
+    amount: u64,
+) -> Instruction {
+    custody_instruction(
+        b"global:lock",
+        escrow_program,
+        [owner, source],
+        forwarded,
+        amount,
+    )
+}
+
+/// CPI into the escrow program's `release` (see module docs)
+pub fn release_instruction(
+    escrow_program: Pubkey,
+    escrow_authority: Pubkey,
+    destination: Pubkey,
+    forwarded: &[AccountInfo],
+    amount: u64,
+) -> Instruction {
+    custody_instruction(
+        b"global:release",
+        escrow_program,
+        [escrow_authority, destination],
+        forwarded,
+        amount,
+    )
+}
+
+fn custody_instruction(
+    preimage: &[u8],
+    escrow_program: Pubkey,
+    [signer, token_account]: [Pubkey; 2],
+    forwarded: &[AccountInfo],
+    amount: u64,
+) -> Instruction {
+    let mut data = hash(preimage).to_bytes()[..8].to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(signer, true),
+        AccountMeta::new(token_account, false),
+    ];
+    accounts.extend(forwarded.iter().map(|account| AccountMeta {
+        pubkey: account.key(),
+        is_signer: account.is_signer,
+        is_writable: account.is_writable,
+    }));
+
+    Instruction {
+        program_id: escrow_program,
+        accounts,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custody_instruction_layout() {
+        let program = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let ix = release_instruction(program, authority, destination, &[], 750);
+
+        assert_eq!(ix.program_id, program);
+        assert_eq!(
+            ix.accounts,
+            vec![
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(destination, false),
+            ]
+        );
+        assert_eq!(&ix.data[..8], &hash(b"global:release").to_bytes()[..8]);
+        assert_eq!(u64::from_le_bytes(ix.data[8..].try_into().unwrap()), 750);
+
+        let lock = lock_instruction(program, authority, destination, &[], 750);
+        assert_eq!(&lock.data[..8], &hash(b"global:lock").to_bytes()[..8]);
+        assert_eq!(lock.accounts, ix.accounts);
+    }
+}
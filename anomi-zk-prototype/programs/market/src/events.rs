@@ -0,0 +1,147 @@
+use anchor_lang::prelude::*;
+use crate::order::Side;
+use crate::order_book::BookLevel;
+
+/// Emitted when an order rests in the book
+#[event]
+pub struct OrderPlaced {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+    pub memo: [u8; 16],
+}
+
+/// Why tokens moved into or out of the escrow vault
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscrowChangeReason {
+    Placement,
+    Cancel,
+    Replace,
+    AdminCancel,
+    Expiry,
+    Settlement,
+    DisputeResolution,
+    Import,
+}
+
+/// Emitted whenever tokens enter or leave escrow
+/// 
+/// Monitors compare `new_expected_total` against the vault's actual balance
+/// to detect leaks.
+#[event]
+pub struct EscrowChanged {
+    pub mint: Pubkey,
+    /// Tokens into (positive) or out of (negative) the vault
+    pub delta: i128,
+    pub reason: EscrowChangeReason,
+    /// Tokens held for unsettled asks by all of the market's books after the
+    /// change (`Market::escrow_locked`); fills don't change it
+    pub new_expected_total: u64,
+}
+
+/// Emitted when an order is removed by cancellation
+#[event]
+pub struct OrderCancelled {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price: u64,
+    /// Remaining quantity removed from the book
+    pub quantity: u64,
+    /// Token account the remaining escrow was refunded to (default = none, e.g. bids)
+    pub refund_account: Pubkey,
+}
+
+/// Point-in-time audit dump emitted by `freeze_and_snapshot`
+#[event]
+pub struct BookAudit {
+    pub slot: u64,
+    pub timestamp: i64,
+    /// Every bid level, best (highest) first
+    pub bids: Vec<BookLevel>,
+    /// Every ask level, best (lowest) first
+    pub asks: Vec<BookLevel>,
+    pub total_orders: u64,
+    /// `OrderBook::ladder_checksum` of `bids` and `asks`
+    pub checksum: [u8; 32],
+}
+
+/// Emitted when the market authority force-cancels an order
+#[event]
+pub struct AdminCancelled {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price: u64,
+    /// Remaining quantity removed from the book (refunded for asks)
+    pub quantity: u64,
+    /// Operator-defined reason (e.g. sanctioned counterparty)
+    pub reason: u16,
+}
+
+/// Emitted when `sweep_expired` removes an order past its expiry
+#[event]
+pub struct OrderExpired {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price: u64,
+    /// Remaining quantity removed from the book (refunded for asks)
+    pub quantity: u64,
+}
+
+/// Emitted once per fill against a resting maker order
+#[event]
+pub struct OrderMatched {
+    pub trade_id: u64,
+    pub maker_order_id: u128,
+    pub maker_side: Side,
+    pub taker_owner: Pubkey,
+    pub taker_side: Side,
+    pub price: u64,
+    pub quantity: u64,
+}
+
+/// Emitted when a settlement proof is accepted and escrow released
+#[event]
+pub struct SettlementVerified {
+    pub order_id: u128,
+    pub quantity: u64,
+    pub memo: [u8; 16],
+}
+
+/// Emitted when a settlement proof fails the pairing check; the attempt is
+/// recorded on the order and no proof is accepted before `retry_at`
+#[event]
+pub struct SettlementVerificationFailed {
+    pub order_id: u128,
+    pub failed_verification_count: u8,
+    pub retry_at: i64,
+}
+
+/// Emitted when a seller re-designates where an order's escrow settles to
+#[event]
+pub struct SettlementDestinationChanged {
+    pub order_id: u128,
+    pub previous: Pubkey,
+    pub destination: Pubkey,
+}
+
+/// Emitted when a fill pushes a maker order across its `fill_notify_bps` threshold
+#[event]
+pub struct OrderFillThresholdReached {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub remaining_quantity: u64,
+}
+
+/// Warning: best bid is at or above best ask after a book update
+/// Monitoring should alert; the book keeps operating
+#[event]
+pub struct BookCrossed {
+    pub best_bid: u64,
+    pub best_ask: u64,
+}
@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, Transfer};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token::{self, CloseAccount, Token, Transfer};
 use anchor_spl::token_interface::{TokenAccount, Mint};
 
 declare_id!("Bk2pKQsXXvjPChX2G8AWgwoefnwRbTSirtHGnG8yUEdB");
@@ -9,12 +10,19 @@ declare_id!("Bk2pKQsXXvjPChX2G8AWgwoefnwRbTSirtHGnG8yUEdB");
 // ============================================================================
 pub mod critbit;
 pub mod error;
+pub mod escrow;
+pub mod events;
+pub mod matching;
 pub mod order;
 pub mod order_book;
+pub mod proof;
 
 use error::ErrorCode;
-use order::{Order, OrderType, Side, generate_order_id};
-use order_book::OrderBook;
+use events::{AdminCancelled, BookAudit, EscrowChangeReason, EscrowChanged, OrderCancelled, OrderExpired, OrderFillThresholdReached, OrderMatched, OrderPlaced, SettlementDestinationChanged, SettlementVerificationFailed, SettlementVerified};
+use matching::MatchRequest;
+use order::{ImportedOrder, NewOrderParams, Order, OrderExtras, OrderOptions, OrderType, QueueDiscipline, ReferralSplit, Side, TakerFilters, generate_order_id, is_valid_jurisdiction, is_valid_price_scale};
+use order_book::{BaseQuote, BookCapacity, BookDiagnostics, BookIntegrity, BookSnapshot, EscrowInvariant, Fill, LevelOrder, LifetimeStats, MarketStats, MatchCostEstimate, OrderBook, OrderStatus, PriceCheckpoint};
+use proof::{SettlementRequest, SettlementResult, SettlementStatus, MAX_SETTLEMENT_BATCH};
 
 // ============================================================================
 // Account Structures
@@ -26,13 +34,539 @@ pub struct Market {
     pub authority: Pubkey,
     pub token_mint: Pubkey,
     pub next_order_sequence: u64,  // Counter for generating order IDs
+    pub arbitrator: Pubkey,        // Resolves payment disputes (separate from authority)
+    pub bids_frozen: bool,         // Reject new bids (cancels and matches still allowed)
+    pub asks_frozen: bool,         // Reject new asks (cancels and matches still allowed)
+    pub min_activation_delay: u32, // Floor on every order's activation delay (seconds)
+    pub pending_authority: Pubkey, // Proposed new authority (default = none pending)
+    pub price_scale: u32,          // Every `price` is an integer at this power-of-ten scale
+    pub max_order_quantity: u64,   // Largest single-order quantity (0 = unlimited)
+    pub next_trade_id: u64,        // Counter for fill (trade) IDs
+    pub placement_cooldown_seconds: u32, // Minimum gap between one owner's placements (0 = none)
+    pub proof_validity_seconds: u32, // Max age of a settlement proof's payment timestamp (0 = no limit)
+    pub queue_discipline: QueueDiscipline, // Priority within a price level (FIFO or LIFO)
+    pub min_spread_bps: u16,       // Narrowest spread a resting order may leave (0 = no floor)
+    pub trading_open_ts: i64,      // Placements and matches allowed from here (0 = no start)
+    pub trading_close_ts: i64,     // ...until here, exclusive (0 = no end)
+    pub escrow_program: Pubkey,    // Program holding escrowed tokens (default = in-house vault)
+    pub escrow_locked: u64,        // Tokens held for unsettled asks by all of this market's books (see `OrderBook::escrow_locked`)
+    pub paused: bool,              // Reject every order, match, cancel and settlement (admin setters still allowed)
 }
 
 impl Market {
+    /// Seconds between `mark_payment_made` and the earliest settlement
+    pub const SETTLEMENT_DELAY_SECONDS: u32 = 10;
+    
+    /// Taker fee in basis points of notional, as quoted by `quote_for_base`
+    /// (the market charges none yet)
+    pub const TAKER_FEE_BPS: u16 = 0;
+    
     pub const LEN: usize = 8 +  // discriminator
                           32 + // authority
                           32 + // token_mint
-                          8;   // next_order_sequence
+                          8 +  // next_order_sequence
+                          32 + // arbitrator
+                          1 +  // bids_frozen
+                          1 +  // asks_frozen
+                          4 +  // min_activation_delay
+                          32 + // pending_authority
+                          4 +  // price_scale
+                          8 +  // max_order_quantity
+                          8 +  // next_trade_id
+                          4 +  // placement_cooldown_seconds
+                          4 +  // proof_validity_seconds
+                          1 +  // queue_discipline
+                          2 +  // min_spread_bps
+                          8 +  // trading_open_ts
+                          8 +  // trading_close_ts
+                          32 + // escrow_program
+                          8 +  // escrow_locked
+                          1;   // paused
+    
+    /// Quote amount for `quantity` base units at `price`, using this market's scale
+    pub fn notional(&self, price: u64, quantity: u64) -> Result<u64> {
+        order::notional(price, quantity, self.price_scale)
+    }
+    
+    /// Client-facing configuration, decoupled from the raw account layout
+    pub fn config(&self) -> MarketConfig {
+        MarketConfig {
+            version: MarketConfig::VERSION,
+            authority: self.authority,
+            arbitrator: self.arbitrator,
+            token_mint: self.token_mint,
+            price_scale: self.price_scale,
+            bids_frozen: self.bids_frozen,
+            asks_frozen: self.asks_frozen,
+            min_activation_delay: self.min_activation_delay,
+            max_order_quantity: self.max_order_quantity,
+            placement_cooldown_seconds: self.placement_cooldown_seconds,
+            proof_validity_seconds: self.proof_validity_seconds,
+            queue_discipline: self.queue_discipline,
+            settlement_delay_seconds: Self::SETTLEMENT_DELAY_SECONDS,
+            min_spread_bps: self.min_spread_bps,
+            trading_open_ts: self.trading_open_ts,
+            trading_close_ts: self.trading_close_ts,
+            paused: self.paused,
+        }
+    }
+    
+    /// Whether `now` is inside the trading window (cancels ignore the window)
+    pub fn is_open(&self, now: i64) -> bool {
+        now >= self.trading_open_ts && (self.trading_close_ts == 0 || now < self.trading_close_ts)
+    }
+    
+    /// Whether new orders on `side` are currently rejected
+    pub fn is_side_frozen(&self, side: Side) -> bool {
+        match side {
+            Side::Bid => self.bids_frozen,
+            Side::Ask => self.asks_frozen,
+        }
+    }
+    
+    /// Every check a placement runs before it moves escrow or touches the book:
+    /// market pause, trading hours, frozen side, size cap, book capacity and post-only
+    /// crossing, minimum spread, payment method, options and the owner's
+    /// placement cooldown.
+    /// Shared by `place_limit_order_v2`, `cancel_replace` and the
+    /// `validate_order` dry run; returns the price the order would rest at
+    /// (a crossing `PostOnlyReprice` order is moved one tick inside the spread)
+    pub fn check_placement(
+        &self,
+        order_book: &OrderBook,
+        owner_state: &OwnerState,
+        params: &NewOrderParams,
+        now: i64,
+    ) -> Result<u64> {
+        let NewOrderParams { side, quantity, order_type, ref payment_method, ref options, .. } = *params;
+        
+        require!(!self.paused, ErrorCode::MarketPaused);
+        require!(self.is_open(now), ErrorCode::MarketClosed);
+        require!(!self.is_side_frozen(side), ErrorCode::SideFrozen);
+        require!(
+            self.max_order_quantity == 0 || quantity <= self.max_order_quantity,
+            ErrorCode::QuantityTooLarge
+        );
+        
+        let price = order_book.post_only_price(side, params.price, order_type)?;
+        order_book.check_placement(side, price, quantity, order_type)?;
+        order_book.check_min_spread(side, price, self.min_spread_bps)?;
+        
+        require!(
+            payment_method.len() <= Order::MAX_PAYMENT_METHOD_LEN,
+            ErrorCode::PaymentMethodTooLong
+        );
+        require!(
+            options.expiry_timestamp == 0 || options.expiry_timestamp > now,
+            ErrorCode::InvalidExpiry
+        );
+        require!(
+            side == Side::Ask || (options.reserve_price == 0 && !options.requires_attestation),
+            ErrorCode::InvalidSide
+        );
+        // Only an order that escrows tokens has a settlement to split
+        require!(
+            options.referral.is_none() || side != options.fiat_payer(),
+            ErrorCode::InvalidSide
+        );
+        require!(
+            options.referral.iter().all(ReferralSplit::is_valid),
+            ErrorCode::InvalidReferral
+        );
+        require!(
+            options.jurisdiction.into_iter().all(is_valid_jurisdiction),
+            ErrorCode::InvalidJurisdiction
+        );
+        require!(
+            !owner_state.is_throttled(self.placement_cooldown_seconds, now),
+            ErrorCode::Throttled
+        );
+        Ok(price)
+    }
+
+    /// Build the order `params` describes for `owner`, resting at `price` (as
+    /// returned by `check_placement`) under the market's next order sequence;
+    /// escrow is refunded to and settled into `funding_account` unless the
+    /// options name another settlement destination
+    pub fn new_order(
+        &mut self,
+        owner: Pubkey,
+        funding_account: Pubkey,
+        params: NewOrderParams,
+        price: u64,
+        now: i64,
+    ) -> Order {
+        let NewOrderParams {
+            side,
+            quantity,
+            order_type,
+            client_order_id,
+            payment_method,
+            options,
+            ..
+        } = params;
+        let sequence_number = self.next_order_sequence;
+        self.next_order_sequence += 1;
+
+        let mut order = Order::new(
+            generate_order_id(&owner, sequence_number, now),
+            owner,
+            quantity,
+            price,
+            now,
+            order_type,
+            side,
+            client_order_id,
+            payment_method,
+        )
+        .with_options(&options);
+        order.settlement_destination = options.settlement_destination.unwrap_or(funding_account);
+        order.activation_delay = order.activation_delay.max(self.min_activation_delay);
+        order.sequence_number = sequence_number;
+        order.funding_account = funding_account;
+        order
+    }
+
+    /// Taker request for read-only match previews: no owner, KYC attestation
+    /// or jurisdiction, any payment method
+    pub fn preview_request(&self, side: Side, quantity: u64, limit_price: u64, now: i64) -> MatchRequest<'static> {
+        MatchRequest {
+            side,
+            max_quantity: quantity,
+            limit_price,
+            taker_owner: Pubkey::default(),
+            accepted_payment_methods: &[],
+            max_makers: 0,
+            taker_attested: false,
+            taker_jurisdiction: [0; 2],
+            discipline: self.queue_discipline,
+            now,
+        }
+    }
+    
+    /// Whether escrowed tokens are held by `escrow_program` rather than the in-house vault
+    pub fn has_delegated_escrow(&self) -> bool {
+        self.escrow_program != Pubkey::default()
+    }
+    
+    /// Record `amount` newly escrowed by `order_book`, on its ledger and the
+    /// market's total (every quote book of the mint shares one escrow vault),
+    /// and emit `EscrowChanged`
+    pub fn lock_escrow(&mut self, order_book: &mut OrderBook, amount: u64, reason: EscrowChangeReason) -> Result<()> {
+        let escrow_locked = self.escrow_locked.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        order_book.lock_escrow(amount)?;
+        self.escrow_locked = escrow_locked;
+        self.emit_escrow_changed(amount as i128, reason);
+        Ok(())
+    }
+    
+    /// Record `amount` paid out of `order_book`'s escrow, on its ledger and the
+    /// market's total, and emit `EscrowChanged`
+    pub fn release_escrow(&mut self, order_book: &mut OrderBook, amount: u64, reason: EscrowChangeReason) -> Result<()> {
+        let escrow_locked = self
+            .escrow_locked
+            .checked_sub(amount)
+            .ok_or(ErrorCode::EscrowLedgerMismatch)?;
+        order_book.release_escrow(amount)?;
+        self.escrow_locked = escrow_locked;
+        self.emit_escrow_changed(-(amount as i128), reason);
+        Ok(())
+    }
+    
+    fn emit_escrow_changed(&self, delta: i128, reason: EscrowChangeReason) {
+        emit!(EscrowChanged {
+            mint: self.token_mint,
+            delta,
+            reason,
+            new_expected_total: self.escrow_locked,
+        });
+    }
+    
+    /// Compare the escrow locked by all of the market's books against the vault's balance
+    pub fn escrow_invariant(&self, vault_balance: u64) -> EscrowInvariant {
+        EscrowInvariant::new(self.escrow_locked, vault_balance)
+    }
+
+    /// Pay `amount` of `order_book`'s escrow out to `to`, signed by the mint's
+    /// escrow authority PDA, and record it with `release_escrow`
+    ///
+    /// Every refund and payout of escrowed tokens goes through here; callers
+    /// check beforehand that the orders being paid out still hold their escrow.
+    pub fn refund_from_escrow<'info>(
+        &mut self,
+        order_book: &mut OrderBook,
+        vault: &EscrowVault<'_, 'info>,
+        to: AccountInfo<'info>,
+        amount: u64,
+        reason: EscrowChangeReason,
+    ) -> Result<()> {
+        let bump = [vault.authority_bump];
+        let seeds: &[&[u8]] = &[b"escrow_authority", self.token_mint.as_ref(), &bump];
+        let signer_seeds = &[seeds];
+        match vault.delegated {
+            Some((program, forwarded)) => {
+                escrow::release(program, forwarded, vault.authority.clone(), to, amount, signer_seeds)?;
+            }
+            None => {
+                let transfer_ctx = CpiContext::new_with_signer(
+                    vault.token_program.clone(),
+                    Transfer {
+                        from: vault.vault.clone(),
+                        to,
+                        authority: vault.authority.clone(),
+                    },
+                    signer_seeds,
+                );
+                token::transfer(transfer_ctx, amount)?;
+            }
+        }
+        self.release_escrow(order_book, amount, reason)
+    }
+}
+
+/// Where a market's escrowed tokens are paid out from: the mint's in-house
+/// vault, or the delegated escrow program when the market has one, with the
+/// escrow authority PDA that signs for either
+pub struct EscrowVault<'a, 'info> {
+    pub vault: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+    pub authority_bump: u8,
+    pub token_program: AccountInfo<'info>,
+    /// The delegated escrow program and the accounts forwarded to it
+    /// (see `escrow::delegated_accounts`)
+    pub delegated: Option<(&'a AccountInfo<'info>, &'a [AccountInfo<'info>])>,
+}
+
+impl<'a, 'info> EscrowVault<'a, 'info> {
+    /// The in-house vault, paid out of by `authority` (bump `authority_bump`)
+    pub fn new(
+        vault: &impl ToAccountInfo<'info>,
+        authority: &impl ToAccountInfo<'info>,
+        authority_bump: u8,
+        token_program: &impl ToAccountInfo<'info>,
+    ) -> Self {
+        Self {
+            vault: vault.to_account_info(),
+            authority: authority.to_account_info(),
+            authority_bump,
+            token_program: token_program.to_account_info(),
+            delegated: None,
+        }
+    }
+}
+
+/// Market configuration returned by `get_market_config`
+/// 
+/// Stable across `Market` layout changes: fields are only ever appended, and
+/// `version` is bumped when they are, so older clients can decode the prefix
+/// they know.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarketConfig {
+    pub version: u8,
+    pub authority: Pubkey,
+    pub arbitrator: Pubkey,
+    pub token_mint: Pubkey,
+    pub price_scale: u32,
+    pub bids_frozen: bool,
+    pub asks_frozen: bool,
+    pub min_activation_delay: u32,
+    pub max_order_quantity: u64,
+    pub placement_cooldown_seconds: u32,
+    pub proof_validity_seconds: u32,
+    pub queue_discipline: QueueDiscipline,
+    pub settlement_delay_seconds: u32,
+    pub min_spread_bps: u16,
+    pub trading_open_ts: i64,
+    pub trading_close_ts: i64,
+    pub paused: bool,
+}
+
+impl MarketConfig {
+    pub const VERSION: u8 = 4;
+}
+
+/// A recent placement, kept so a retried `client_order_id` can be recognised
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecentPlacement {
+    pub client_order_id: u64,
+    pub order_id: u128,
+    pub timestamp: i64, // 0 = empty slot
+}
+
+impl RecentPlacement {
+    pub const LEN: usize = 8 + 16 + 8;
+}
+
+/// Result of `match_order`: fills come back inline when they fit in return
+/// data, otherwise they are written to the taker's `FillLog` account
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MatchResult {
+    pub fill_count: u32,
+    /// Fills were written to the `FillLog` account and `fills` is empty
+    pub fills_in_account: bool,
+    pub fills: Vec<Fill>,
+    /// Order the unfilled remainder of a `Limit` or post-only match rests as
+    pub resting_order_id: Option<u128>,
+}
+
+/// One order named in a `cancel_orders` call; `side` and `price` locate it,
+/// falling back to a search by ID as in `cancel_order`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CancelRequest {
+    pub order_id: u128,
+    pub side: Side,
+    pub price: u64,
+}
+
+/// Per-order outcome of `cancel_orders`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CancelResult {
+    pub order_id: u128,
+    /// False if the order was missing, not cancellable by the signer, or had
+    /// no refund account among the remaining accounts
+    pub cancelled: bool,
+}
+
+/// Per-taker scratch account holding the fills of the last match that was
+/// too large for return data
+#[account]
+pub struct FillLog {
+    pub owner: Pubkey,
+    pub slot: u64, // Slot of the match the fills belong to
+    pub fills: Vec<Fill>, // Empty when the last match returned its fills inline
+}
+
+impl FillLog {
+    /// Fills one log can hold: a match fills each maker order at most once,
+    /// so one per order a full book can hold
+    pub const MAX_FILLS: usize = OrderBook::MAX_ORDERS;
+    
+    pub const LEN: usize = 8 +  // discriminator
+                          32 + // owner
+                          8 +  // slot
+                          4 + Self::MAX_FILLS * Fill::LEN; // fills
+}
+
+/// A market's Groth16 verifying key for the payment circuit
+/// 
+/// A key doesn't fit in one transaction, so it is written into `staged` in
+/// chunks (`write_verifying_key`) and swapped in whole by `set_verifying_key`.
+#[account]
+pub struct VerifyingKey {
+    pub market: Pubkey,
+    pub active: Vec<u8>, // Key settlement proofs are checked against (empty = none yet)
+    pub staged: Vec<u8>, // Key being written, `proof::VERIFYING_KEY_LEN` bytes
+    pub version: u32,    // Number of keys activated so far
+}
+
+impl VerifyingKey {
+    pub const LEN: usize = 8 +  // discriminator
+                          32 + // market
+                          4 + proof::VERIFYING_KEY_LEN + // active
+                          4 + proof::VERIFYING_KEY_LEN + // staged
+                          4;   // version
+}
+
+/// The market authority's record that `taker` passed KYC, required to fill
+/// asks placed with `requires_attestation`
+#[account]
+pub struct TakerAttestation {
+    pub market: Pubkey,
+    pub taker: Pubkey,
+    pub expires_at: i64, // Unix timestamp the attestation lapses at (0 = never)
+}
+
+impl TakerAttestation {
+    pub const LEN: usize = 8 +  // discriminator
+                          32 + // market
+                          32 + // taker
+                          8;   // expires_at
+    
+    /// Whether `account` (the taker's attestation PDA) holds an attestation valid at `now`;
+    /// an account that was never created means the taker isn't attested
+    pub fn is_valid(account: &AccountInfo, now: i64) -> Result<bool> {
+        if account.data_is_empty() {
+            return Ok(false);
+        }
+        require_keys_eq!(*account.owner, crate::ID, ErrorCode::InvalidAttestation);
+        let attestation = Self::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+        Ok(attestation.expires_at == 0 || now < attestation.expires_at)
+    }
+}
+
+/// Per-owner state for a market, created on the owner's first placement
+#[account]
+#[derive(Default)]
+pub struct OwnerState {
+    pub owner: Pubkey,
+    pub last_placement_ts: i64, // Time of the owner's last accepted placement (0 = never)
+    pub recent_placements: [RecentPlacement; 8], // OwnerState::RECENT_PLACEMENTS, oldest overwritten first
+    pub recent_cursor: u8, // Next slot to overwrite in `recent_placements`
+    pub heartbeat_deadline: i64, // Orders may be cancelled by anyone after this (0 = deadman switch off)
+}
+
+impl OwnerState {
+    /// Placements remembered per owner for idempotent retries
+    pub const RECENT_PLACEMENTS: usize = 8;
+    
+    /// A `client_order_id` placed again within this many seconds returns the original order
+    pub const IDEMPOTENCY_WINDOW_SECONDS: i64 = 60;
+    
+    pub const LEN: usize = 8 +  // discriminator
+                          32 + // owner
+                          8 +  // last_placement_ts
+                          Self::RECENT_PLACEMENTS * RecentPlacement::LEN + // recent_placements
+                          1 +  // recent_cursor
+                          8;   // heartbeat_deadline
+    
+    /// State held in `account` (the owner's state PDA); an owner who has
+    /// never placed has no account yet and gets the empty state
+    pub fn load(account: &AccountInfo) -> Result<Self> {
+        if account.data_is_empty() {
+            return Ok(Self::default());
+        }
+        require_keys_eq!(*account.owner, crate::ID, ErrorCode::InvalidOwnerState);
+        Self::try_deserialize(&mut &account.try_borrow_data()?[..])
+    }
+    
+    /// Whether a placement at `now` comes sooner than `cooldown_seconds` after the last one
+    pub fn is_throttled(&self, cooldown_seconds: u32, now: i64) -> bool {
+        self.last_placement_ts != 0
+            && now < self.last_placement_ts.saturating_add(cooldown_seconds as i64)
+    }
+    
+    /// Whether the owner armed the deadman switch and missed its deadline
+    pub fn is_stale(&self, now: i64) -> bool {
+        self.heartbeat_deadline != 0 && now > self.heartbeat_deadline
+    }
+    
+    /// Order placed under `client_order_id` within the idempotency window before `now`
+    /// (`client_order_id` 0 carries no idempotency key)
+    pub fn recent_order_id(&self, client_order_id: u64, now: i64) -> Option<u128> {
+        if client_order_id == 0 {
+            return None;
+        }
+        self.recent_placements
+            .iter()
+            .filter(|placement| {
+                placement.timestamp != 0
+                    && placement.client_order_id == client_order_id
+                    && now < placement.timestamp.saturating_add(Self::IDEMPOTENCY_WINDOW_SECONDS)
+            })
+            .max_by_key(|placement| placement.timestamp)
+            .map(|placement| placement.order_id)
+    }
+    
+    /// Remember a placement, overwriting the oldest slot
+    pub fn record_placement(&mut self, client_order_id: u64, order_id: u128, now: i64) {
+        let slot = self.recent_cursor as usize % Self::RECENT_PLACEMENTS;
+        self.recent_placements[slot] = RecentPlacement {
+            client_order_id,
+            order_id,
+            timestamp: now,
+        };
+        self.recent_cursor = ((slot + 1) % Self::RECENT_PLACEMENTS) as u8;
+    }
 }
 
 #[program]
@@ -49,462 +583,2844 @@ pub mod market {
     }
 
     /// Initialize the market account
-    pub fn initialize_market(ctx: Context<InitializeMarket>) -> Result<()> {
+    /// `price_scale` is the power of ten every order `price` is expressed at (e.g. 1_000_000)
+    pub fn initialize_market(ctx: Context<InitializeMarket>, price_scale: u32) -> Result<()> {
+        require!(is_valid_price_scale(price_scale), ErrorCode::InvalidPriceScale);
+        
         let market = &mut ctx.accounts.market;
         market.authority = ctx.accounts.authority.key();
         market.token_mint = ctx.accounts.token_mint.key();
         market.next_order_sequence = 0;
+        market.arbitrator = ctx.accounts.authority.key();
+        market.bids_frozen = false;
+        market.asks_frozen = false;
+        market.min_activation_delay = 0;
+        market.pending_authority = Pubkey::default();
+        market.price_scale = price_scale;
+        market.max_order_quantity = 0;
+        market.next_trade_id = 0;
+        market.placement_cooldown_seconds = 0;
+        market.proof_validity_seconds = 0;
+        market.queue_discipline = QueueDiscipline::Fifo;
+        market.min_spread_bps = 0;
+        market.trading_open_ts = 0;
+        market.trading_close_ts = 0;
+        market.escrow_program = Pubkey::default();
+        market.escrow_locked = 0;
+        market.paused = false;
         
         msg!("Market: Initialized market for mint: {}", market.token_mint);
         msg!("Market: Authority set to: {}", market.authority);
+        msg!("Market: Price scale: {}", market.price_scale);
         Ok(())
     }
 
-    /// Initialize order book with CritBit tree
-    pub fn initialize_order_book_v2(ctx: Context<InitializeOrderBook>) -> Result<()> {
-        let order_book = &mut ctx.accounts.order_book;
-        let market = ctx.accounts.market.key();
-        let token_mint = ctx.accounts.token_mint.key();
+    /// Propose a new market authority (authority only)
+    /// The handoff completes when the proposed key calls `accept_authority`
+    pub fn propose_authority(ctx: Context<UpdateMarket>, new_authority: Pubkey) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.pending_authority = new_authority;
         
-        // Initialize OrderBook with CritBit trees
-        // Use double deref to assign to Account wrapper
-        **order_book = OrderBook::new(market, token_mint, token_mint);
+        msg!("Market: Authority transfer proposed to: {}", new_authority);
+        Ok(())
+    }
+
+    /// Accept a proposed authority transfer (pending authority only)
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.authority = market.pending_authority;
+        market.pending_authority = Pubkey::default();
         
-        msg!("Market: Initialized OrderBook for mint: {}", token_mint);
-        msg!("Market: Supports {} price levels", OrderBook::MAX_PRICE_LEVELS);
-        msg!("Market: CritBit trees initialized for bids and asks");
+        msg!("Market: Authority transferred to: {}", market.authority);
         Ok(())
     }
 
-    /// Place a limit order
-    pub fn place_limit_order_v2(
-        ctx: Context<PlaceLimitOrder>,
-        side: Side,
-        price: u64,
-        quantity: u64,
-        order_type: OrderType,
-        client_order_id: u64,
-        payment_method: String,
-    ) -> Result<u128> {
-        require!(quantity > 0, ErrorCode::InvalidAmount);
-        require!(price > 0, ErrorCode::InvalidPrice);
+    /// Set the arbitrator allowed to resolve payment disputes (authority only)
+    pub fn set_arbitrator(ctx: Context<UpdateMarket>, arbitrator: Pubkey) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.arbitrator = arbitrator;
         
+        msg!("Market: Arbitrator set to: {}", arbitrator);
+        Ok(())
+    }
+
+    /// Freeze or unfreeze new orders on one side of the book (authority only)
+    /// Cancels and matches against resting orders are unaffected
+    pub fn set_side_frozen(ctx: Context<UpdateMarket>, side: Side, frozen: bool) -> Result<()> {
         let market = &mut ctx.accounts.market;
-        let order_book = &mut ctx.accounts.order_book;
+        match side {
+            Side::Bid => market.bids_frozen = frozen,
+            Side::Ask => market.asks_frozen = frozen,
+        }
         
-        // Generate unique u128 order ID
-        let order_id = generate_order_id(
-            &ctx.accounts.owner.key(),
-            market.next_order_sequence,
-            Clock::get()?.unix_timestamp,
-        );
-        market.next_order_sequence += 1;
+        msg!("Market: {:?} side frozen: {}", side, frozen);
+        Ok(())
+    }
+
+    /// Set the minimum activation delay applied to every new order (authority only)
+    pub fn set_min_activation_delay(ctx: Context<UpdateMarket>, min_activation_delay: u32) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.min_activation_delay = min_activation_delay;
+        
+        msg!("Market: Minimum activation delay set to: {}s", min_activation_delay);
+        Ok(())
+    }
+
+    /// Cap the quantity of any single new order (authority only, 0 = unlimited)
+    pub fn set_max_order_quantity(ctx: Context<UpdateMarket>, max_order_quantity: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.max_order_quantity = max_order_quantity;
+        
+        msg!("Market: Max order quantity set to: {}", max_order_quantity);
+        Ok(())
+    }
+
+    /// Pause or resume the whole market (authority only)
+    /// While paused nothing touches a book or escrow: placements, matches,
+    /// cancels, sweeps, payment marks, settlements and dispute resolutions all
+    /// fail with `MarketPaused`. Admin configuration is unaffected.
+    pub fn set_paused(ctx: Context<UpdateMarket>, paused: bool) -> Result<()> {
+        ctx.accounts.market.paused = paused;
+        
+        msg!("Market: Paused: {}", paused);
+        Ok(())
+    }
+
+    /// Pause the market and emit a `BookAudit` of the full ladder (authority only)
+    /// 
+    /// The market is paused (see `set_paused`) so the book can't change after
+    /// the snapshot; it stays paused until resumed with `set_paused`.
+    pub fn freeze_and_snapshot(ctx: Context<FreezeAndSnapshot>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.paused = true;
+        
+        let order_book = &ctx.accounts.order_book;
+        let bids = order_book.levels(Side::Bid);
+        let asks = order_book.levels(Side::Ask);
+        let checksum = OrderBook::ladder_checksum(&bids, &asks);
+        let clock = Clock::get()?;
         
         msg!(
-            "Market: Placing limit order - owner: {}, side: {:?}, price: {}, qty: {}, type: {:?}",
-            ctx.accounts.owner.key(),
-            side,
-            price,
-            quantity,
-            order_type
+            "Market: Frozen for audit at slot {} ({} bid / {} ask levels)",
+            clock.slot,
+            bids.len(),
+            asks.len()
         );
+        emit!(BookAudit {
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+            bids,
+            asks,
+            total_orders: order_book.total_orders,
+            checksum,
+        });
+        Ok(())
+    }
+
+    /// Set the minimum time between one owner's placements (authority only, 0 = none)
+    /// Cancels are never throttled
+    pub fn set_placement_cooldown(ctx: Context<UpdateMarket>, placement_cooldown_seconds: u32) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.placement_cooldown_seconds = placement_cooldown_seconds;
         
-        // Create Order struct
-        let order = Order::new(
-            order_id,
-            ctx.accounts.owner.key(),
-            quantity,
-            price,
-            Clock::get()?.unix_timestamp,
-            order_type,
-            side,
-            client_order_id,
-            payment_method,
-        );
+        msg!("Market: Placement cooldown set to: {}s", placement_cooldown_seconds);
+        Ok(())
+    }
+
+    /// Set how old a settlement proof's payment may be (authority only, 0 = no limit)
+    pub fn set_proof_validity(ctx: Context<UpdateMarket>, proof_validity_seconds: u32) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.proof_validity_seconds = proof_validity_seconds;
         
-        // If this is an Ask order, transfer tokens to escrow
-        if side == Side::Ask {
-            let transfer_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.owner_token_account.to_account_info(),
-                    to: ctx.accounts.escrow_vault.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info(),
-                },
-            );
-            token::transfer(transfer_ctx, quantity)?;
-            msg!("Market: {} tokens transferred to escrow", quantity);
-        }
+        msg!("Market: Proof validity set to: {}s", proof_validity_seconds);
+        Ok(())
+    }
+
+    /// Set the narrowest spread (bps of mid) a resting order may leave (authority only, 0 = no floor)
+    /// Crossing orders are unaffected
+    pub fn set_min_spread(ctx: Context<UpdateMarket>, min_spread_bps: u16) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.min_spread_bps = min_spread_bps;
         
-        // Insert into CritBit-based order book
-        order_book.insert_order(order)?;
+        msg!("Market: Minimum spread set to: {} bps", min_spread_bps);
+        Ok(())
+    }
+
+    /// Write `bytes` at `offset` into the staged verifying key (authority only)
+    /// Settlement is unaffected until `set_verifying_key` activates the staged key
+    pub fn write_verifying_key(ctx: Context<SetVerifyingKey>, offset: u32, bytes: Vec<u8>) -> Result<()> {
+        let verifying_key = &mut ctx.accounts.verifying_key;
+        verifying_key.market = ctx.accounts.market.key();
+        verifying_key.staged.resize(proof::VERIFYING_KEY_LEN, 0);
         
-        msg!("Market: Order inserted successfully - ID: {}", order_id);
-        msg!("Market: Total orders in book: {}", order_book.total_orders);
+        let start = offset as usize;
+        let end = start.checked_add(bytes.len()).ok_or(ErrorCode::MathOverflow)?;
+        require!(end <= proof::VERIFYING_KEY_LEN, ErrorCode::InvalidVerifyingKey);
+        verifying_key.staged[start..end].copy_from_slice(&bytes);
         
-        Ok(order_id)
+        msg!("Market: Staged verifying key bytes [{}, {})", start, end);
+        Ok(())
     }
 
-    /// Cancel an order and return escrowed tokens
-    pub fn cancel_order(
-        ctx: Context<CancelOrder>,
-        order_id: u128,
-        side: Side,
-        price: u64,
-    ) -> Result<()> {
-        let order_book = &mut ctx.accounts.order_book;
-        
-        // Remove order from order book
-        let order = order_book.remove_order(order_id, side, price)?;
+    /// Activate the staged verifying key (authority only)
+    /// From here on `verify_settlement` runs the Groth16 pairing check against it;
+    /// proofs made for the previous key no longer verify
+    pub fn set_verifying_key(ctx: Context<SetVerifyingKey>) -> Result<()> {
+        let verifying_key = &mut ctx.accounts.verifying_key;
+        proof::check_verifying_key(&verifying_key.staged)?;
+        verifying_key.market = ctx.accounts.market.key();
+        verifying_key.active = verifying_key.staged.clone();
+        verifying_key.version += 1;
         
-        // Verify the caller is the order owner
+        msg!("Market: Verifying key set (version {})", verifying_key.version);
+        Ok(())
+    }
+
+    /// Record that `taker` passed KYC until `expires_at` (authority only, 0 = no expiry)
+    /// Re-attesting an existing taker just moves the expiry
+    pub fn attest_taker(ctx: Context<AttestTaker>, taker: Pubkey, expires_at: i64) -> Result<()> {
         require!(
-            order.owner == ctx.accounts.owner.key(),
-            ErrorCode::UnauthorizedCancellation
+            expires_at == 0 || expires_at > Clock::get()?.unix_timestamp,
+            ErrorCode::InvalidExpiry
         );
+        let attestation = &mut ctx.accounts.attestation;
+        attestation.market = ctx.accounts.market.key();
+        attestation.taker = taker;
+        attestation.expires_at = expires_at;
         
-        msg!(
-            "Market: Cancelling order - ID: {}, owner: {}, side: {:?}, price: {}",
-            order_id,
-            ctx.accounts.owner.key(),
-            side,
-            price
+        msg!("Market: Taker {} attested until {}", taker, expires_at);
+        Ok(())
+    }
+
+    /// Withdraw `taker`'s attestation (authority only); rent goes back to the authority
+    pub fn revoke_attestation(_ctx: Context<RevokeAttestation>, taker: Pubkey) -> Result<()> {
+        msg!("Market: Attestation for taker {} revoked", taker);
+        Ok(())
+    }
+
+    /// Restrict placements and matches to `[open_ts, close_ts)` (authority only)
+    /// 0 leaves that end unbounded; cancels are allowed at any time
+    pub fn set_trading_hours(ctx: Context<UpdateMarket>, open_ts: i64, close_ts: i64) -> Result<()> {
+        require!(
+            open_ts >= 0 && close_ts >= 0 && (close_ts == 0 || open_ts < close_ts),
+            ErrorCode::InvalidTradingHours
         );
+        let market = &mut ctx.accounts.market;
+        market.trading_open_ts = open_ts;
+        market.trading_close_ts = close_ts;
         
-        // If this was an Ask order, return escrowed tokens
-        if side == Side::Ask {
-            let remaining_quantity = order.quantity; // quantity is already the remaining amount
-            
-            if remaining_quantity > 0 {
-                let token_mint_key = ctx.accounts.token_mint.key();
-                let seeds = &[
-                    b"escrow_authority",
-                    token_mint_key.as_ref(),
-                    &[ctx.bumps.escrow_authority],
-                ];
-                let signer_seeds = &[&seeds[..]];
-                
-                let transfer_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.escrow_vault.to_account_info(),
-                        to: ctx.accounts.owner_token_account.to_account_info(),
-                        authority: ctx.accounts.escrow_authority.to_account_info(),
-                    },
-                    signer_seeds,
-                );
-                
-                token::transfer(transfer_ctx, remaining_quantity)?;
-                msg!("Market: Returned {} tokens from escrow", remaining_quantity);
-            }
-        }
+        msg!("Market: Trading hours set to [{}, {})", open_ts, close_ts);
+        Ok(())
+    }
+
+    /// Smallest best-price move, in basis points, that records a price checkpoint
+    /// (authority only; 0 records every change)
+    pub fn set_checkpoint_threshold(ctx: Context<UpdateOrderBook>, threshold_bps: u16) -> Result<()> {
+        ctx.accounts.order_book.checkpoint_threshold_bps = threshold_bps;
         
-        msg!("Market: Order cancelled successfully");
-        msg!("Market: Total orders remaining: {}", order_book.total_orders);
+        msg!("Market: Price checkpoint threshold set to: {} bps", threshold_bps);
+        Ok(())
+    }
+
+    /// Delegate escrow custody to `escrow_program`, or back to the in-house
+    /// vault with the default key (authority only; see `escrow` for the CPI interface)
+    /// 
+    /// Only allowed while none of the market's books has tokens locked, so
+    /// custody never changes under resting orders. Delegated custody is routed through
+    /// `place_limit_order_v2`, `cancel_order` and `verify_settlement`; the other
+    /// instructions that move escrow reject with `EscrowDelegated` meanwhile.
+    pub fn set_escrow_program(ctx: Context<SetEscrowProgram>, escrow_program: Pubkey) -> Result<()> {
+        require!(ctx.accounts.market.escrow_locked == 0, ErrorCode::EscrowInUse);
+        ctx.accounts.market.escrow_program = escrow_program;
         
+        msg!("Market: Escrow program set to: {}", escrow_program);
         Ok(())
     }
 
-    /// Mark payment as made by buyer (P2P fiat settlement stub)
-    pub fn mark_payment_made(
-        ctx: Context<MarkPayment>,
-        order_id: u128,
-    ) -> Result<()> {
+    /// Choose which order at a price level matches first (authority only)
+    /// Applies to resting orders too; cancellation is unaffected
+    pub fn set_queue_discipline(ctx: Context<UpdateMarket>, queue_discipline: QueueDiscipline) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.queue_discipline = queue_discipline;
+        
+        msg!("Market: Queue discipline set to: {:?}", queue_discipline);
+        Ok(())
+    }
+
+    /// Initialize order book with CritBit tree
+    pub fn initialize_order_book_v2(ctx: Context<InitializeOrderBook>) -> Result<()> {
         let order_book = &mut ctx.accounts.order_book;
-        let clock = Clock::get()?;
+        let market = ctx.accounts.market.key();
+        let token_mint = ctx.accounts.token_mint.key();
+        let quote_mint = ctx.accounts.quote_mint.key();
         
-        // Find the order in the order book
-        // This is a simplified implementation - in production would need more robust order tracking
-        for queue in order_book.order_queues.iter_mut() {
-            for order in queue.orders.iter_mut() {
-                if order.order_id == order_id {
-                    require!(
-                        order.owner == ctx.accounts.buyer.key(),
-                        ErrorCode::UnauthorizedAction
-                    );
-                    
-                    // Update payment status
-                    order.payment_status = order::PaymentStatus::PaymentMarked;
-                    order.payment_marked_timestamp = clock.unix_timestamp;
-                    order.settlement_timestamp = clock.unix_timestamp + 10; // 10 second delay
-                    
-                    msg!("Payment marked for order {}. Settlement in 10 seconds.", order_id);
-                    return Ok(());
-                }
-            }
-        }
+        // Initialize OrderBook with CritBit trees
+        // Use double deref to assign to Account wrapper
+        **order_book = OrderBook::new(market, token_mint, quote_mint);
         
-        Err(ErrorCode::OrderNotFound.into())
+        msg!("Market: Initialized OrderBook for mint: {} quoted in: {}", token_mint, quote_mint);
+        msg!(
+            "Market: Supports {} price levels and at least {} resting orders",
+            OrderBook::MAX_PRICE_LEVELS,
+            OrderBook::MAX_RESTING_ORDERS
+        );
+        msg!("Market: CritBit trees initialized for bids and asks");
+        Ok(())
     }
 
-    /// Verify settlement after delay and release tokens with ZK proof verification
+    /// Place a limit order
     /// 
-    /// Public signals format: [emailHash[8], fromHeaderHash[8], orderId[2]]
-    /// Proof format: Groth16 proof (a, b, c points)
-    pub fn verify_settlement(
-        ctx: Context<VerifySettlement>,
-        order_id: u128,
-        proof_a: Vec<u8>,      // G1 point (64 bytes: 32 bytes x + 32 bytes y)
-        proof_b: Vec<u8>,      // G2 point (128 bytes: 4 coordinates)
-        proof_c: Vec<u8>,      // G1 point (64 bytes: 32 bytes x + 32 bytes y)
-        public_signals: Vec<String>, // Public signals from circuit
-    ) -> Result<()> {
-        let order_book = &mut ctx.accounts.order_book;
+    /// Idempotent per owner: placing a non-zero `client_order_id` that the owner
+    /// already placed within `OwnerState::IDEMPOTENCY_WINDOW_SECONDS` (among
+    /// their last `OwnerState::RECENT_PLACEMENTS` placements) returns the original
+    /// order's ID and changes nothing, so retried transactions can't double-place.
+    /// 
+    /// An order whose side isn't `options.fiat_payer` (asks, by default) escrows
+    /// its quantity here and is paid out by settlement once the fiat is proven.
+    /// 
+    /// On a market with an `escrow_program`, those tokens are locked through
+    /// it; the escrow program and its accounts are passed as remaining accounts.
+    /// 
+    /// Options that need extras (memo, cancel delegate, settle hook, referral)
+    /// are kept in the order's `OrderExtras` account, passed as the first
+    /// remaining account and paid for by the owner.
+    pub fn place_limit_order_v2<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceLimitOrder<'info>>,
+        params: NewOrderParams,
+    ) -> Result<u128> {
         let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        let client_order_id = params.client_order_id;
         
-        // Find the order
-        for queue in order_book.order_queues.iter_mut() {
-            for order in queue.orders.iter_mut() {
-                if order.order_id == order_id {
-                    // Check settlement delay has passed
-                    require!(
-                        clock.unix_timestamp >= order.settlement_timestamp,
-                        ErrorCode::SettlementDelayNotExpired
-                    );
-                    
-                    // Verify ZK proof
-                    // Public signals: [emailHash[8], fromHeaderHash[8], orderId[2]]
-                    // Expected format: 18 strings total
-                    require!(
-                        public_signals.len() >= 18,
-                        ErrorCode::InvalidProof
-                    );
-                    
-                    // Extract order ID from public signals (last 2 elements)
-                    let proof_order_id_low = public_signals[16].parse::<u64>()
-                        .map_err(|_| ErrorCode::InvalidProof)?;
-                    let proof_order_id_high = public_signals[17].parse::<u64>()
-                        .map_err(|_| ErrorCode::InvalidProof)?;
-                    let proof_order_id = (proof_order_id_high as u128) << 64 | (proof_order_id_low as u128);
-                    
-                    // Verify order ID matches
-                    require!(
-                        proof_order_id == order_id,
-                        ErrorCode::ProofOrderIdMismatch
-                    );
-                    
-                    // Verify proof format
-                    require!(
-                        proof_a.len() == 64 && proof_b.len() == 128 && proof_c.len() == 64,
-                        ErrorCode::InvalidProof
-                    );
-                    
-                    // TODO: Full Groth16 proof verification
-                    // This requires a verifier program or library like solana-zk
-                    // For now, we verify the proof structure and order ID match
-                    // In production, add CPI call to verifier program or use on-chain verifier
-                    
-                    msg!("ZK proof structure verified for order {}", order_id);
-                    msg!("Email hash (first): {}", public_signals[0]);
-                    msg!("From header hash (first): {}", public_signals[8]);
-                    
-                    // Update status
-                    order.payment_status = order::PaymentStatus::Verified;
-                    
-                    // Transfer tokens from escrow to seller
-                    let token_mint = ctx.accounts.token_mint.key();
-                    let seeds = &[
-                        b"escrow_authority",
-                        token_mint.as_ref(),
-                        &[ctx.bumps.escrow_authority],
-                    ];
-                    let signer = &[&seeds[..]];
-                    
-                    let cpi_accounts = Transfer {
-                        from: ctx.accounts.escrow_vault.to_account_info(),
-                        to: ctx.accounts.seller_token_account.to_account_info(),
-                        authority: ctx.accounts.escrow_authority.to_account_info(),
-                    };
-                    let cpi_program = ctx.accounts.token_program.to_account_info();
-                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-                    
-                    token::transfer(cpi_ctx, order.quantity)?;
-                    
-                    msg!("Settlement verified for order {}. Tokens released.", order_id);
-                    return Ok(());
-                }
-            }
+        if let Some(order_id) = ctx.accounts.owner_state.recent_order_id(client_order_id, now) {
+            msg!(
+                "Market: client order {} was already placed as order {}, returning it",
+                client_order_id,
+                order_id
+            );
+            return Ok(order_id);
         }
         
-        Err(ErrorCode::OrderNotFound.into())
-    }
-    
-    /// Reset the order book (close and allow re-init with new structure)
-    pub fn reset_order_book(ctx: Context<ResetOrderBook>) -> Result<()> {
-        msg!("Order book reset. Re-initialize with new structure.");
-        Ok(())
+        let market = &mut ctx.accounts.market;
+        let order_book = &mut ctx.accounts.order_book;
+        let owner_state = &mut ctx.accounts.owner_state;
+        
+        // A crossing PostOnlyReprice order rests one tick inside the spread;
+        // the adjusted price is what's stored and reported in OrderPlaced
+        let price = market.check_placement(order_book, owner_state, &params, now)?;
+        if price != params.price {
+            msg!("Market: Post-only order repriced from {} to {}", params.price, price);
+        }
+        owner_state.owner = ctx.accounts.owner.key();
+        owner_state.last_placement_ts = now;
+        let options = params.options;
+        
+        let order = market.new_order(
+            ctx.accounts.owner.key(),
+            ctx.accounts.owner_token_account.key(),
+            params,
+            price,
+            now,
+        );
+        let Order { order_id, side, quantity, order_type, .. } = order;
+        
+        msg!(
+            "Market: Placing limit order - owner: {}, side: {:?}, price: {}, qty: {}, type: {:?}",
+            ctx.accounts.owner.key(),
+            side,
+            price,
+            quantity,
+            order_type
+        );
+        
+        if order.has_extras {
+            let account = ctx.remaining_accounts.first().ok_or(ErrorCode::OrderExtrasMissing)?;
+            OrderExtras::new(order_id, &options).store(
+                account,
+                order_book,
+                &order_book.key(),
+                &order,
+                &ctx.accounts.owner.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+        }
+        
+        // If the order's side doesn't pay fiat, transfer its tokens to escrow
+        if order.escrows_tokens() && market.has_delegated_escrow() {
+            let (escrow_program, forwarded) = escrow::delegated_accounts(
+                ctx.remaining_accounts,
+                usize::from(order.has_extras),
+                &market.escrow_program,
+            )?;
+            escrow::lock(
+                escrow_program,
+                forwarded,
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.owner_token_account.to_account_info(),
+                quantity,
+            )?;
+            market.lock_escrow(order_book, quantity, EscrowChangeReason::Placement)?;
+            msg!("Market: {} tokens locked with escrow program {}", quantity, market.escrow_program);
+        } else if order.escrows_tokens() {
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            );
+            token::transfer(transfer_ctx, quantity)?;
+            market.lock_escrow(order_book, quantity, EscrowChangeReason::Placement)?;
+            msg!("Market: {} tokens transferred to escrow", quantity);
+        }
+        
+        // Insert into CritBit-based order book
+        order_book.insert_order(order)?;
+        order_book.record_placed()?;
+        order_book.touch(clock.slot);
+        ctx.accounts.owner_state.record_placement(client_order_id, order_id, now);
+        
+        emit!(OrderPlaced {
+            order_id,
+            owner: order.owner,
+            side,
+            price,
+            quantity,
+            timestamp: order.timestamp,
+            memo: options.memo,
+        });
+        
+        msg!("Market: Order inserted successfully - ID: {}", order_id);
+        msg!("Market: Total orders in book: {}", order_book.total_orders);
+        
+        Ok(order_id)
     }
 
-    /// Match an order with advanced order type handling
-    pub fn match_order(
-        ctx: Context<MatchOrder>,
-        side: Side,
-        quantity: u64,
-        limit_price: u64,
-        order_type: OrderType,
-    ) -> Result<Vec<(u64, u64, u128)>> {
-        require!(quantity > 0, ErrorCode::InvalidAmount);
-        require!(limit_price > 0, ErrorCode::InvalidPrice);
+    /// Cancel an order and place its replacement in one instruction
+    /// 
+    /// Only the order owner can replace (not a cancel delegate). Ask escrow is
+    /// netted: a larger replacement ask pulls only the difference from the
+    /// owner, a smaller one refunds the difference. Any failure reverts the
+    /// whole instruction, leaving the old order resting untouched. Orders whose
+    /// payment was marked or settled can't be replaced.
+    /// 
+    /// Idempotent like `place_limit_order_v2`: a replacement already placed under
+    /// the same `client_order_id` is returned without touching the book.
+    /// A replacement with extras takes its `OrderExtras` account as the first
+    /// remaining account, as in `place_limit_order_v2`.
+    pub fn cancel_replace<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelReplace<'info>>,
+        old_order_id: u128,
+        old_side: Side,
+        old_price: u64,
+        new_params: NewOrderParams,
+    ) -> Result<u128> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        let client_order_id = new_params.client_order_id;
         
+        if let Some(order_id) = ctx.accounts.owner_state.recent_order_id(client_order_id, now) {
+            msg!(
+                "Market: client order {} was already placed as order {}, returning it",
+                client_order_id,
+                order_id
+            );
+            return Ok(order_id);
+        }
+        
+        let market = &mut ctx.accounts.market;
         let order_book = &mut ctx.accounts.order_book;
-        let taker_owner = ctx.accounts.owner.key();
+        
+        let Some(resting) = order_book.find_order(old_order_id) else {
+            msg!("Market: Replace failed, order {} is not in the book", old_order_id);
+            return Err(ErrorCode::OrderNotFound.into());
+        };
+        let (resting_side, resting_price) = (resting.side, resting.price);
+        if resting_side != old_side || resting_price != old_price {
+            msg!(
+                "Warning: order {} is resting at side: {:?}, price: {} (replace supplied side: {:?}, price: {})",
+                old_order_id,
+                resting_side,
+                resting_price,
+                old_side,
+                old_price
+            );
+        }
+        require!(
+            resting.owner == ctx.accounts.owner.key(),
+            ErrorCode::UnauthorizedCancellation
+        );
+        // Its escrow must still be in the vault for the netting below
+        require!(resting.is_refundable(), ErrorCode::InvalidPaymentStatus);
+        require!(
+            !resting.escrows_tokens() || resting.accepts_refund_to(&ctx.accounts.owner_token_account.key()),
+            ErrorCode::RefundAccountMismatch
+        );
+        
+        // Take the old order out first so the replacement is checked against
+        // the book without it (capacity, post-only crossing)
+        let old_order = order_book.remove_order(old_order_id, resting_side, resting_price)?;
+        order_book.record_cancelled(1)?;
+        
+        let owner_state = &mut ctx.accounts.owner_state;
+        let price = market.check_placement(order_book, owner_state, &new_params, now)?;
+        if price != new_params.price {
+            msg!("Market: Post-only order repriced from {} to {}", new_params.price, price);
+        }
+        owner_state.last_placement_ts = now;
+        let options = new_params.options;
+        
+        let order = market.new_order(
+            ctx.accounts.owner.key(),
+            ctx.accounts.owner_token_account.key(),
+            new_params,
+            price,
+            now,
+        );
+        let Order { order_id, side, quantity, order_type, .. } = order;
         
         msg!(
-            "Market: Matching order - side: {:?}, qty: {}, limit: {}, type: {:?}",
+            "Market: Replacing order {} - side: {:?}, price: {}, qty: {}, type: {:?}",
+            old_order_id,
             side,
+            price,
             quantity,
-            limit_price,
             order_type
         );
         
-        // Check for self-trade before matching
-        if order_book.would_self_trade(side, &taker_owner) {
-            msg!("Market: Self-trade detected, rejecting order");
-            return Err(ErrorCode::SelfTradeNotAllowed.into());
+        if order.has_extras {
+            let account = ctx.remaining_accounts.first().ok_or(ErrorCode::OrderExtrasMissing)?;
+            OrderExtras::new(order_id, &options).store(
+                account,
+                order_book,
+                &order_book.key(),
+                &order,
+                &ctx.accounts.owner.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
         }
         
-        // Execute matching
-        let fills = order_book.match_order(side, quantity, limit_price, taker_owner)?;
+        // Net the escrow: only the difference between the old and new escrowed size moves
+        let escrowed = if old_order.escrows_tokens() { old_order.quantity } else { 0 };
+        let required = if order.escrows_tokens() { quantity } else { 0 };
+        if required > escrowed {
+            let top_up = required - escrowed;
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            );
+            token::transfer(transfer_ctx, top_up)?;
+            market.lock_escrow(order_book, top_up, EscrowChangeReason::Replace)?;
+            msg!("Market: {} additional tokens transferred to escrow", top_up);
+        } else if escrowed > required {
+            let refund = escrowed - required;
+            let vault = EscrowVault::new(
+                &ctx.accounts.escrow_vault,
+                &ctx.accounts.escrow_authority,
+                ctx.bumps.escrow_authority,
+                &ctx.accounts.token_program,
+            );
+            market.refund_from_escrow(
+                order_book,
+                &vault,
+                ctx.accounts.owner_token_account.to_account_info(),
+                refund,
+                EscrowChangeReason::Replace,
+            )?;
+            msg!("Market: Returned {} tokens from escrow", refund);
+        }
         
-        // Handle order type-specific logic
-        match order_type {
-            OrderType::Limit => {
-                // If not fully filled, add remaining as limit order
-                let filled_quantity: u64 = fills.iter().map(|(_, qty, _)| qty).sum();
-                if filled_quantity < quantity {
-                    msg!("Market: Limit order partially filled ({}/{})", filled_quantity, quantity);
-                }
-            },
-            OrderType::Market => {
-                // Market order: accept any fill amount
-                let filled_quantity: u64 = fills.iter().map(|(_, qty, _)| qty).sum();
-                msg!("Market: Market order filled {}/{}", filled_quantity, quantity);
-            },
-            OrderType::PostOnly => {
-                // Post-only: reject if would match immediately
-                if !fills.is_empty() {
-                    msg!("Market: Post-only order would match immediately, rejecting");
-                    return Err(ErrorCode::PostOnlyWouldMatch.into());
-                }
-            },
-            OrderType::ImmediateOrCancel => {
-                // IOC: fill what's possible, cancel rest (no resting order)
-                let filled_quantity: u64 = fills.iter().map(|(_, qty, _)| qty).sum();
-                msg!("Market: IOC filled {}/{}, canceling remainder", filled_quantity, quantity);
-            },
-            OrderType::FillOrKill => {
-                // FOK: must fill completely or reject entirely
-                let filled_quantity: u64 = fills.iter().map(|(_, qty, _)| qty).sum();
-                if filled_quantity < quantity {
-                    msg!("Market: FOK order cannot be fully filled, rejecting");
-                    return Err(ErrorCode::FillOrKillNotFilled.into());
-                }
+        order_book.insert_order(order)?;
+        order_book.record_placed()?;
+        order_book.touch(clock.slot);
+        ctx.accounts.owner_state.record_placement(client_order_id, order_id, now);
+        
+        emit!(OrderCancelled {
+            order_id: old_order_id,
+            owner: old_order.owner,
+            side: old_order.side,
+            price: old_order.price,
+            quantity: old_order.quantity,
+            refund_account: if old_order.escrows_tokens() {
+                ctx.accounts.owner_token_account.key()
+            } else {
+                Pubkey::default()
             },
-        }
+        });
+        emit!(OrderPlaced {
+            order_id,
+            owner: order.owner,
+            side,
+            price,
+            quantity,
+            timestamp: order.timestamp,
+            memo: options.memo,
+        });
         
-        msg!("Market: Matched {} orders, total fills: {}", fills.len(), fills.iter().map(|(_, qty, _)| qty).sum::<u64>());
+        msg!("Market: Order {} replaced by {}", old_order_id, order_id);
         
-        Ok(fills)
+        Ok(order_id)
     }
+
+    /// Cancel an order and return escrowed tokens
+    /// `side` and `price` locate the order; if they are wrong it is found by ID instead
+    /// Orders whose payment was marked or settled can't be cancelled.
+    /// 
+    /// With `unwrap` set on a wrapped SOL market, the refund account is closed
+    /// after the refund so the owner gets native lamports back. Closing needs
+    /// the owner's own signature, so a cancel delegate can't unwrap.
+    /// 
+    /// On a market with an `escrow_program`, the refund is released through it;
+    /// the escrow program and its accounts are passed as remaining accounts.
+    /// 
+    /// A cancel delegate passes the order's `OrderExtras` account first, ahead
+    /// of any escrow program accounts; the owner never needs it.
+    pub fn cancel_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelOrder<'info>>,
+        order_id: u128,
+        side: Side,
+        price: u64,
+        unwrap: bool,
+    ) -> Result<()> {
+        let order_book = &mut ctx.accounts.order_book;
+        
+        // Fall back to an id-based search if (side, price) doesn't match the resting order
+        let Some(resting) = order_book.find_order(order_id) else {
+            msg!("Market: Cancel failed, order {} is not in the book", order_id);
+            return Err(ErrorCode::OrderNotFound.into());
+        };
+        let (side, price) = if resting.side != side || resting.price != price {
+            msg!(
+                "Warning: order {} is resting at side: {:?}, price: {} (cancel supplied side: {:?}, price: {})",
+                order_id,
+                resting.side,
+                resting.price,
+                side,
+                price
+            );
+            (resting.side, resting.price)
+        } else {
+            (side, price)
+        };
+        
+        // Verify the caller is the order owner (or its cancel delegate) before
+        // touching the book or escrow; refunds always go to the owner
+        let by_delegate = ctx.accounts.owner.key() != resting.owner && resting.has_extras;
+        let extras = if by_delegate {
+            OrderExtras::load(ctx.remaining_accounts.first(), &order_book.key(), resting)?
+        } else {
+            OrderExtras::default()
+        };
+        require!(
+            resting.is_cancellable_by(&ctx.accounts.owner.key(), &extras),
+            ErrorCode::UnauthorizedCancellation
+        );
+        require!(resting.is_refundable(), ErrorCode::InvalidPaymentStatus);
+        require!(
+            ctx.accounts.owner_token_account.owner == resting.owner,
+            ErrorCode::InvalidTokenAccountOwner
+        );
+        require!(
+            !resting.escrows_tokens() || resting.accepts_refund_to(&ctx.accounts.owner_token_account.key()),
+            ErrorCode::RefundAccountMismatch
+        );
+        if unwrap {
+            require!(
+                ctx.accounts.owner_token_account.is_native()
+                    && ctx.accounts.owner.key() == resting.owner,
+                ErrorCode::InvalidUnwrap
+            );
+        }
+        
+        // Remove order from order book
+        let order = order_book.remove_order(order_id, side, price)?;
+        order_book.record_cancelled(1)?;
+        order_book.touch(Clock::get()?.slot);
+        
+        msg!(
+            "Market: Cancelling order - ID: {}, owner: {}, signer: {}, side: {:?}, price: {}",
+            order_id,
+            order.owner,
+            ctx.accounts.owner.key(),
+            side,
+            price
+        );
+        
+        // If the order escrowed tokens, return them
+        if order.escrows_tokens() {
+            let remaining_quantity = order.quantity; // quantity is already the remaining amount
+            
+            if remaining_quantity > 0 {
+                let market = &mut ctx.accounts.market;
+                let mut vault = EscrowVault::new(
+                    &ctx.accounts.escrow_vault,
+                    &ctx.accounts.escrow_authority,
+                    ctx.bumps.escrow_authority,
+                    &ctx.accounts.token_program,
+                );
+                if market.has_delegated_escrow() {
+                    vault.delegated = Some(escrow::delegated_accounts(
+                        ctx.remaining_accounts,
+                        usize::from(by_delegate),
+                        &market.escrow_program,
+                    )?);
+                }
+                market.refund_from_escrow(
+                    order_book,
+                    &vault,
+                    ctx.accounts.owner_token_account.to_account_info(),
+                    remaining_quantity,
+                    EscrowChangeReason::Cancel,
+                )?;
+                msg!("Market: Returned {} tokens from escrow", remaining_quantity);
+            }
+        }
+        
+        // Native accounts close with a balance: the wrapped amount and the
+        // rent both go back to the owner as lamports
+        if unwrap {
+            token::close_account(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.owner_token_account.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ))?;
+            msg!("Market: Unwrapped refund account {}", ctx.accounts.owner_token_account.key());
+        }
+        
+        emit!(OrderCancelled {
+            order_id,
+            owner: order.owner,
+            side,
+            price,
+            quantity: order.quantity,
+            refund_account: if order.escrows_tokens() {
+                ctx.accounts.owner_token_account.key()
+            } else {
+                Pubkey::default()
+            },
+        });
+        
+        msg!("Market: Order cancelled successfully");
+        msg!("Market: Total orders remaining: {}", order_book.total_orders);
+        
+        Ok(())
+    }
+
+    /// Cancel several orders in one instruction
+    /// 
+    /// Each order must be cancellable by the signer (owner or cancel delegate).
+    /// Ask refunds are summed per owner and paid with one transfer each to the
+    /// owner's token account, passed in `remaining_accounts`. Orders that can't
+    /// be cancelled, including any whose payment was marked or settled, are
+    /// reported and skipped; the rest still go through.
+    /// 
+    /// A cancel delegate also passes the `OrderExtras` account of each order
+    /// placed with extras, in any position among the refund accounts.
+    pub fn cancel_orders<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelOrders<'info>>,
+        orders: Vec<CancelRequest>,
+    ) -> Result<Vec<CancelResult>> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        require!(
+            !orders.is_empty() && orders.len() <= OrderBook::MAX_CANCEL_BATCH,
+            ErrorCode::InvalidCancelBatch
+        );
+        
+        let signer_key = ctx.accounts.owner.key();
+        let token_mint = ctx.accounts.token_mint.key();
+        let order_book_key = ctx.accounts.order_book.key();
+        // Extras accounts are owned by this program, refund accounts by the token program
+        let (extras_accounts, token_accounts): (Vec<_>, Vec<_>) =
+            ctx.remaining_accounts.iter().partition(|account| *account.owner == crate::ID);
+        let refund_accounts = token_accounts
+            .into_iter()
+            .map(InterfaceAccount::<TokenAccount>::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let mut refunds = vec![0u64; refund_accounts.len()];
+        let is_cancellable = |resting: &Order| {
+            if resting.owner == signer_key || !resting.has_extras {
+                return resting.is_cancellable_by(&signer_key, &OrderExtras::default());
+            }
+            let (address, _) = OrderExtras::address(&order_book_key, &resting.owner, resting.client_order_id);
+            let account = extras_accounts.iter().copied().find(|account| account.key() == address);
+            OrderExtras::load(account, &order_book_key, resting)
+                .is_ok_and(|extras| resting.is_cancellable_by(&signer_key, &extras))
+        };
+        
+        let order_book = &mut ctx.accounts.order_book;
+        let mut results = Vec::with_capacity(orders.len());
+        let mut cancelled = 0u64;
+        for request in orders.iter() {
+            let resting = match order_book.find_order(request.order_id) {
+                Some(resting) if is_cancellable(resting) => *resting,
+                Some(_) => {
+                    msg!("Cancel: not allowed to cancel order {}", request.order_id);
+                    results.push(CancelResult { order_id: request.order_id, cancelled: false });
+                    continue;
+                }
+                None => {
+                    msg!("Cancel: order {} is not in the book", request.order_id);
+                    results.push(CancelResult { order_id: request.order_id, cancelled: false });
+                    continue;
+                }
+            };
+            // A marked or settled order's escrow is spoken for (or already paid out)
+            if !resting.is_refundable() {
+                msg!("Cancel: order {} has a payment in progress or settled", request.order_id);
+                results.push(CancelResult { order_id: request.order_id, cancelled: false });
+                continue;
+            }
+            if resting.side != request.side || resting.price != request.price {
+                msg!(
+                    "Warning: order {} is resting at side: {:?}, price: {} (cancel supplied side: {:?}, price: {})",
+                    request.order_id,
+                    resting.side,
+                    resting.price,
+                    request.side,
+                    request.price
+                );
+            }
+            
+            let mut refund_account = Pubkey::default();
+            if resting.escrows_tokens() && resting.quantity > 0 {
+                let Some(index) = refund_accounts
+                    .iter()
+                    .position(|account| {
+                        account.owner == resting.owner
+                            && account.mint == token_mint
+                            && resting.accepts_refund_to(&account.key())
+                    })
+                else {
+                    msg!("Cancel: no refund account for order {}, leaving it", request.order_id);
+                    results.push(CancelResult { order_id: request.order_id, cancelled: false });
+                    continue;
+                };
+                refunds[index] = refunds[index]
+                    .checked_add(resting.quantity)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                refund_account = refund_accounts[index].key();
+            }
+            
+            let order = order_book.remove_order(request.order_id, resting.side, resting.price)?;
+            emit!(OrderCancelled {
+                order_id: order.order_id,
+                owner: order.owner,
+                side: order.side,
+                price: order.price,
+                quantity: order.quantity,
+                refund_account,
+            });
+            results.push(CancelResult { order_id: request.order_id, cancelled: true });
+            cancelled += 1;
+        }
+        if cancelled > 0 {
+            order_book.record_cancelled(cancelled)?;
+            order_book.touch(Clock::get()?.slot);
+        }
+        
+        let vault = EscrowVault::new(
+            &ctx.accounts.escrow_vault,
+            &ctx.accounts.escrow_authority,
+            ctx.bumps.escrow_authority,
+            &ctx.accounts.token_program,
+        );
+        for (account, amount) in refund_accounts.iter().zip(refunds) {
+            if amount == 0 {
+                continue;
+            }
+            ctx.accounts.market.refund_from_escrow(
+                &mut ctx.accounts.order_book,
+                &vault,
+                account.to_account_info(),
+                amount,
+                EscrowChangeReason::Cancel,
+            )?;
+            msg!("Cancel: refunded {} tokens to {}", amount, account.key());
+        }
+        
+        msg!("Market: Cancelled {} of {} orders", cancelled, orders.len());
+        Ok(results)
+    }
+
+    /// Force-cancel an order (market authority only, e.g. for compliance)
+    /// 
+    /// Works without the owner's consent; ask escrow is still refunded to the
+    /// owner's token account. Settled orders can't be cancelled.
+    pub fn admin_cancel_order(
+        ctx: Context<AdminCancelOrder>,
+        order_id: u128,
+        side: Side,
+        price: u64,
+        reason: u16,
+    ) -> Result<()> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        let order_book = &mut ctx.accounts.order_book;
+        
+        let resting = order_book.find_order(order_id).ok_or(ErrorCode::OrderNotFound)?;
+        require!(
+            resting.payment_status != order::PaymentStatus::Verified,
+            ErrorCode::OrderAlreadySettled
+        );
+        require!(
+            ctx.accounts.owner_token_account.owner == resting.owner,
+            ErrorCode::InvalidTokenAccountOwner
+        );
+        require!(
+            !resting.escrows_tokens() || resting.accepts_refund_to(&ctx.accounts.owner_token_account.key()),
+            ErrorCode::RefundAccountMismatch
+        );
+        
+        let order = order_book.remove_order(order_id, side, price)?;
+        order_book.record_cancelled(1)?;
+        order_book.touch(Clock::get()?.slot);
+        
+        if order.escrows_tokens() && order.quantity > 0 {
+            let vault = EscrowVault::new(
+                &ctx.accounts.escrow_vault,
+                &ctx.accounts.escrow_authority,
+                ctx.bumps.escrow_authority,
+                &ctx.accounts.token_program,
+            );
+            ctx.accounts.market.refund_from_escrow(
+                order_book,
+                &vault,
+                ctx.accounts.owner_token_account.to_account_info(),
+                order.quantity,
+                EscrowChangeReason::AdminCancel,
+            )?;
+            msg!("Market: Returned {} tokens from escrow", order.quantity);
+        }
+        
+        emit!(AdminCancelled {
+            order_id,
+            owner: order.owner,
+            side,
+            price,
+            quantity: order.quantity,
+            reason,
+        });
+        
+        msg!(
+            "Market: Authority {} cancelled order {} (reason {})",
+            ctx.accounts.authority.key(),
+            order_id,
+            reason
+        );
+        Ok(())
+    }
+
+    /// Seed a fresh book with resting orders migrated from another venue (authority only)
+    /// 
+    /// Orders keep their original timestamps, which decide their time priority.
+    /// The imported asks' tokens must already sit in the escrow vault on top of
+    /// what the market's books have locked; they are locked here, nothing is transferred.
+    /// At most `OrderBook::MAX_IMPORT_BATCH` orders per call.
+    pub fn import_orders(ctx: Context<ImportOrders>, orders: Vec<ImportedOrder>) -> Result<()> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        let clock = Clock::get()?;
+        let market = &mut ctx.accounts.market;
+        let order_book = &mut ctx.accounts.order_book;
+        
+        let ask_total = orders
+            .iter()
+            .filter(|order| order.side == Side::Ask)
+            .try_fold(0u64, |total, order| total.checked_add(order.quantity))
+            .ok_or(ErrorCode::MathOverflow)?;
+        let required = market
+            .escrow_locked
+            .checked_add(ask_total)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            ctx.accounts.escrow_vault.amount >= required,
+            ErrorCode::ImportNotFunded
+        );
+        
+        let imported = order_book.import_orders(&orders, market.next_order_sequence, clock.unix_timestamp)?;
+        market.next_order_sequence += imported.len() as u64;
+        if ask_total > 0 {
+            market.lock_escrow(order_book, ask_total, EscrowChangeReason::Import)?;
+        }
+        order_book.touch(clock.slot);
+        
+        for order in &imported {
+            emit!(OrderPlaced {
+                order_id: order.order_id,
+                owner: order.owner,
+                side: order.side,
+                price: order.price,
+                quantity: order.quantity,
+                timestamp: order.timestamp,
+                memo: [0; 16],
+            });
+        }
+        
+        msg!(
+            "Market: Imported {} orders ({} tokens of asks), total orders in book: {}",
+            imported.len(),
+            ask_total,
+            order_book.total_orders
+        );
+        Ok(())
+    }
+
+    /// Mark payment as made by buyer (P2P fiat settlement stub)
+    pub fn mark_payment_made(
+        ctx: Context<MarkPayment>,
+        order_id: u128,
+    ) -> Result<()> {
+        let order_book = &mut ctx.accounts.order_book;
+        let clock = Clock::get()?;
+        order_book.touch(clock.slot);
+        
+        // Find the order in the order book
+        // This is a simplified implementation - in production would need more robust order tracking
+        for queue in order_book.order_queues.iter_mut() {
+            for order in queue.orders.iter_mut() {
+                if order.order_id == order_id {
+                    require!(
+                        order.owner == ctx.accounts.buyer.key(),
+                        ErrorCode::UnauthorizedAction
+                    );
+                    
+                    // Update payment status
+                    order.mark_payment(clock.unix_timestamp, Market::SETTLEMENT_DELAY_SECONDS);
+                    
+                    msg!(
+                        "Payment marked for order {}. Settlement in {} seconds.",
+                        order_id,
+                        Market::SETTLEMENT_DELAY_SECONDS
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        
+        msg!("Mark payment failed: order {} is not in the book", order_id);
+        Err(ErrorCode::OrderNotFound.into())
+    }
+
+    /// Verify settlement after delay and release tokens with ZK proof verification
+    /// 
+    /// Public signals format: [emailHash[8], fromHeaderHash[8], orderId[2], paymentTimestamp]
+    /// Proof format: Groth16 proof (a, b, c points)
+    /// Proofs of payments older than the market's `proof_validity_seconds` are rejected
+    /// Once `set_verifying_key` has run, proofs must also pass the Groth16
+    /// pairing check against the market's active key
+    /// 
+    /// The destination may be any open token account for the mint, including one
+    /// owned by another program's PDA. An order placed with extras passes its
+    /// `OrderExtras` account as the first remaining account. If the order names
+    /// an `on_settle_program`, that program is passed next and CPI'd after the
+    /// payout (see `proof::settle_hook_instruction`).
+    /// 
+    /// An order with a referral split pays `referral_bps` of its quantity to its
+    /// referral token account, passed as the next remaining account, and the
+    /// rest to the destination.
+    /// 
+    /// On a market with an `escrow_program`, both payouts are released through
+    /// it; the escrow program and its accounts follow any hook and referral
+    /// accounts in remaining accounts.
+    /// 
+    /// A proof that fails the pairing check releases nothing but is recorded on
+    /// the order (`SettlementVerificationFailed`); further proofs are rejected
+    /// with `VerificationBackoff` until a window that doubles with each failure
+    /// has elapsed.
+    pub fn verify_settlement<'info>(
+        ctx: Context<'_, '_, 'info, 'info, VerifySettlement<'info>>,
+        order_id: u128,
+        proof_a: Vec<u8>,      // G1 point (64 bytes: 32 bytes x + 32 bytes y)
+        proof_b: Vec<u8>,      // G2 point (128 bytes: 4 coordinates)
+        proof_c: Vec<u8>,      // G1 point (64 bytes: 32 bytes x + 32 bytes y)
+        public_signals: Vec<String>, // Public signals from circuit
+    ) -> Result<()> {
+        let verifying_key = proof::load_verifying_key(&ctx.accounts.verifying_key)?;
+        let request = SettlementRequest {
+            order_id,
+            proof_a,
+            proof_b,
+            proof_c,
+            public_signals,
+        };
+        let order_book_key = ctx.accounts.order_book.key();
+        let order_book = &mut ctx.accounts.order_book;
+        let clock = Clock::get()?;
+        order_book.touch(clock.slot);
+        
+        // Find the order
+        for queue in order_book.order_queues.iter_mut() {
+            for order in queue.orders.iter_mut() {
+                if order.order_id == order_id {
+                    // Verify delay, destination and ZK proof
+                    // Public signals: [emailHash[8], fromHeaderHash[8], orderId[2], paymentTimestamp]
+                    let check = proof::check_settlement(
+                        order,
+                        clock.unix_timestamp,
+                        ctx.accounts.market.proof_validity_seconds,
+                        &ctx.accounts.seller_token_account.key(),
+                        &request,
+                        verifying_key.as_deref(),
+                    );
+                    // A failed pairing check is recorded rather than returned, so the
+                    // backoff it starts survives the transaction
+                    if matches!(&check, Err(err) if *err == ErrorCode::ProofVerificationFailed.into()) {
+                        order.record_failed_verification(clock.unix_timestamp);
+                        emit!(SettlementVerificationFailed {
+                            order_id,
+                            failed_verification_count: order.failed_verification_count,
+                            retry_at: order.verification_retry_at(),
+                        });
+                        msg!(
+                            "Settlement proof rejected for order {} ({} failures), retry after {}",
+                            order_id,
+                            order.failed_verification_count,
+                            order.verification_retry_at()
+                        );
+                        return Ok(());
+                    }
+                    check?;
+                    proof::check_destination_account(
+                        &ctx.accounts.seller_token_account,
+                        &ctx.accounts.token_mint.key(),
+                    )?;
+                    
+                    if verifying_key.is_some() {
+                        msg!("ZK proof verified for order {}", order_id);
+                    } else {
+                        // No key set yet: only the proof structure and order ID were checked
+                        msg!("ZK proof structure verified for order {}", order_id);
+                    }
+                    msg!("Email hash (first): {}", request.public_signals[0]);
+                    msg!("From header hash (first): {}", request.public_signals[8]);
+                    
+                    // The hook program follows the extras account and the
+                    // referral account follows the hook program, if any
+                    let extras = OrderExtras::load(ctx.remaining_accounts.first(), &order_book_key, order)?;
+                    let hook_offset = usize::from(order.has_extras);
+                    let referral_offset = hook_offset + usize::from(extras.on_settle_program != Pubkey::default());
+                    let token_mint = ctx.accounts.token_mint.key();
+                    let referral_amount = extras.referral_amount(order.quantity);
+                    let referral = if extras.referral_bps > 0 {
+                        let Some(referral) = ctx
+                            .remaining_accounts
+                            .get(referral_offset)
+                            .filter(|account| account.key() == extras.referral_recipient)
+                        else {
+                            return Err(ErrorCode::ReferralAccountMissing.into());
+                        };
+                        proof::check_destination_account(referral, &token_mint)?;
+                        Some(referral)
+                    } else {
+                        None
+                    };
+                    
+                    // Update status
+                    order.payment_status = order::PaymentStatus::Verified;
+                    
+                    // Transfer tokens from escrow to seller
+                    let seeds = &[
+                        b"escrow_authority",
+                        token_mint.as_ref(),
+                        &[ctx.bumps.escrow_authority],
+                    ];
+                    let signer = &[&seeds[..]];
+                    
+                    let market = &ctx.accounts.market;
+                    if market.has_delegated_escrow() {
+                        let escrow_offset = referral_offset + usize::from(referral.is_some());
+                        let (program, forwarded) =
+                            escrow::delegated_accounts(ctx.remaining_accounts, escrow_offset, &market.escrow_program)?;
+                        escrow::release(
+                            program,
+                            forwarded,
+                            ctx.accounts.escrow_authority.to_account_info(),
+                            ctx.accounts.seller_token_account.to_account_info(),
+                            order.quantity - referral_amount,
+                            signer,
+                        )?;
+                        if let Some(referral) = referral.filter(|_| referral_amount > 0) {
+                            escrow::release(
+                                program,
+                                forwarded,
+                                ctx.accounts.escrow_authority.to_account_info(),
+                                referral.clone(),
+                                referral_amount,
+                                signer,
+                            )?;
+                            msg!("Referral: {} tokens to {}", referral_amount, referral.key());
+                        }
+                    } else {
+                        let cpi_accounts = Transfer {
+                            from: ctx.accounts.escrow_vault.to_account_info(),
+                            to: ctx.accounts.seller_token_account.to_account_info(),
+                            authority: ctx.accounts.escrow_authority.to_account_info(),
+                        };
+                        let cpi_program = ctx.accounts.token_program.to_account_info();
+                        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                        
+                        token::transfer(cpi_ctx, order.quantity - referral_amount)?;
+                        
+                        if let Some(referral) = referral.filter(|_| referral_amount > 0) {
+                            let cpi_ctx = CpiContext::new_with_signer(
+                                ctx.accounts.token_program.to_account_info(),
+                                Transfer {
+                                    from: ctx.accounts.escrow_vault.to_account_info(),
+                                    to: referral.clone(),
+                                    authority: ctx.accounts.escrow_authority.to_account_info(),
+                                },
+                                signer,
+                            );
+                            token::transfer(cpi_ctx, referral_amount)?;
+                            msg!("Referral: {} tokens to {}", referral_amount, referral.key());
+                        }
+                    }
+                    
+                    emit!(SettlementVerified {
+                        order_id,
+                        quantity: order.quantity,
+                        memo: extras.memo,
+                    });
+                    
+                    let released = order.quantity;
+                    let hook_program = extras.on_settle_program;
+                    ctx.accounts.market.release_escrow(order_book, released, EscrowChangeReason::Settlement)?;
+                    msg!("Settlement verified for order {}. Tokens released.", order_id);
+                    
+                    if hook_program != Pubkey::default() {
+                        let Some(hook) = ctx
+                            .remaining_accounts
+                            .get(hook_offset)
+                            .filter(|account| account.key() == hook_program && account.executable)
+                        else {
+                            return Err(ErrorCode::SettleHookMissing.into());
+                        };
+                        let destination = ctx.accounts.seller_token_account.to_account_info();
+                        let ix = proof::settle_hook_instruction(hook_program, destination.key(), order_id, released);
+                        invoke(&ix, &[destination, hook.clone()])?;
+                        msg!("Settlement hook {} notified for order {}", hook_program, order_id);
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        
+        msg!("Settlement failed: order {} is not in the book", order_id);
+        Err(ErrorCode::OrderNotFound.into())
+    }
+
+    /// Settle several ripe orders in one transaction (for keepers)
+    /// 
+    /// `remaining_accounts` holds each request's payout token account, in the
+    /// same order as `requests`. Orders whose settlement delay or verification
+    /// backoff hasn't expired are skipped and any other rejection is reported,
+    /// without aborting the batch. Proofs failing the pairing check are recorded
+    /// on their orders as in `verify_settlement`. Orders placed with extras are
+    /// reported as failed and settle through `verify_settlement`.
+    pub fn verify_settlement_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, VerifySettlementBatch<'info>>,
+        requests: Vec<SettlementRequest>,
+    ) -> Result<Vec<SettlementResult>> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        require!(
+            !requests.is_empty() && requests.len() <= MAX_SETTLEMENT_BATCH,
+            ErrorCode::InvalidSettlementBatch
+        );
+        require!(
+            ctx.remaining_accounts.len() == requests.len(),
+            ErrorCode::InvalidSettlementBatch
+        );
+        
+        let clock = Clock::get()?;
+        ctx.accounts.order_book.touch(clock.slot);
+        let proof_validity_seconds = ctx.accounts.market.proof_validity_seconds;
+        let verifying_key = proof::load_verifying_key(&ctx.accounts.verifying_key)?;
+        let token_mint = ctx.accounts.token_mint.key();
+        let vault = EscrowVault::new(
+            &ctx.accounts.escrow_vault,
+            &ctx.accounts.escrow_authority,
+            ctx.bumps.escrow_authority,
+            &ctx.accounts.token_program,
+        );
+        
+        let mut results = Vec::with_capacity(requests.len());
+        for (request, destination) in requests.iter().zip(ctx.remaining_accounts.iter()) {
+            let mut order = ctx.accounts.order_book.find_order_mut(request.order_id);
+            let mut status = proof::settlement_status(
+                order.as_deref(),
+                clock.unix_timestamp,
+                proof_validity_seconds,
+                destination.key,
+                request,
+                verifying_key.as_deref(),
+            );
+            if let (SettlementStatus::ProofFailed, Some(order)) = (status, order.as_deref_mut()) {
+                order.record_failed_verification(clock.unix_timestamp);
+                emit!(SettlementVerificationFailed {
+                    order_id: request.order_id,
+                    failed_verification_count: order.failed_verification_count,
+                    retry_at: order.verification_retry_at(),
+                });
+            }
+            if status == SettlementStatus::Settled
+                && proof::check_destination_account(destination, &token_mint).is_err()
+            {
+                status = SettlementStatus::Failed;
+            }
+            // Extras (hooks, referrals, memos) need extra accounts; those orders settle individually
+            if status == SettlementStatus::Settled && order.as_ref().is_some_and(|order| order.has_extras) {
+                msg!("Batch: order {} has extras, use verify_settlement", request.order_id);
+                status = SettlementStatus::Failed;
+            }
+            
+            if let (SettlementStatus::Settled, Some(order)) = (status, order) {
+                order.payment_status = order::PaymentStatus::Verified;
+                emit!(SettlementVerified {
+                    order_id: request.order_id,
+                    quantity: order.quantity,
+                    memo: [0; 16],
+                });
+                
+                let released = order.quantity;
+                ctx.accounts.market.refund_from_escrow(
+                    &mut ctx.accounts.order_book,
+                    &vault,
+                    destination.clone(),
+                    released,
+                    EscrowChangeReason::Settlement,
+                )?;
+            }
+            
+            results.push(SettlementResult {
+                order_id: request.order_id,
+                status,
+            });
+        }
+        
+        msg!("Batch settlement: processed {} orders", results.len());
+        Ok(results)
+    }
+    
+    /// Push back the caller's deadman-switch deadline to `timeout_seconds` from now
+    /// 
+    /// Once the deadline passes, any keeper may pull all of the owner's resting
+    /// orders with `cancel_stale_maker`. A timeout of 0 disarms the switch.
+    pub fn heartbeat(ctx: Context<Heartbeat>, timeout_seconds: u32) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let owner_state = &mut ctx.accounts.owner_state;
+        owner_state.owner = ctx.accounts.owner.key();
+        owner_state.heartbeat_deadline = if timeout_seconds == 0 {
+            0
+        } else {
+            now + timeout_seconds as i64
+        };
+        
+        msg!("Market: Heartbeat deadline for {} set to {}", owner_state.owner, owner_state.heartbeat_deadline);
+        Ok(())
+    }
+
+    /// Cancel a maker's resting orders once its heartbeat deadline has lapsed
+    /// (permissionless, for keepers)
+    /// 
+    /// Cancels up to `OrderBook::MAX_CANCEL_BATCH` orders per call; call again
+    /// while orders remain. `remaining_accounts` holds the owner's funding token
+    /// accounts for ask refunds, as in `sweep_expired`. Orders with a payment in
+    /// progress are left in place. Returns the number of orders cancelled.
+    pub fn cancel_stale_maker<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelStaleMaker<'info>>,
+        owner: Pubkey,
+    ) -> Result<u32> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        let clock = Clock::get()?;
+        require!(ctx.accounts.owner_state.is_stale(clock.unix_timestamp), ErrorCode::MakerNotStale);
+        
+        let token_mint = ctx.accounts.token_mint.key();
+        let refund_accounts = ctx
+            .remaining_accounts
+            .iter()
+            .map(InterfaceAccount::<TokenAccount>::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let mut refunds = vec![0u64; refund_accounts.len()];
+        
+        let order_book = &mut ctx.accounts.order_book;
+        let stale: Vec<Order> = order_book
+            .orders_for_owner(&owner)
+            .into_iter()
+            .filter(|order| order.is_refundable())
+            .take(OrderBook::MAX_CANCEL_BATCH)
+            .collect();
+        let mut cancelled = 0u32;
+        for order in stale {
+            let mut refund_account = Pubkey::default();
+            if order.escrows_tokens() && order.quantity > 0 {
+                let Some(index) = refund_accounts
+                    .iter()
+                    .position(|account| {
+                        account.owner == order.owner
+                            && account.mint == token_mint
+                            && order.accepts_refund_to(&account.key())
+                    })
+                else {
+                    msg!("Stale maker: no refund account for order {}, leaving it", order.order_id);
+                    continue;
+                };
+                refunds[index] = refunds[index]
+                    .checked_add(order.quantity)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                refund_account = refund_accounts[index].key();
+            }
+            
+            order_book.remove_order(order.order_id, order.side, order.price)?;
+            emit!(OrderCancelled {
+                order_id: order.order_id,
+                owner: order.owner,
+                side: order.side,
+                price: order.price,
+                quantity: order.quantity,
+                refund_account,
+            });
+            cancelled += 1;
+        }
+        if cancelled > 0 {
+            order_book.record_cancelled(cancelled as u64)?;
+            order_book.touch(clock.slot);
+        }
+        
+        let vault = EscrowVault::new(
+            &ctx.accounts.escrow_vault,
+            &ctx.accounts.escrow_authority,
+            ctx.bumps.escrow_authority,
+            &ctx.accounts.token_program,
+        );
+        for (account, amount) in refund_accounts.iter().zip(refunds) {
+            if amount == 0 {
+                continue;
+            }
+            ctx.accounts.market.refund_from_escrow(
+                &mut ctx.accounts.order_book,
+                &vault,
+                account.to_account_info(),
+                amount,
+                EscrowChangeReason::Cancel,
+            )?;
+            msg!("Stale maker: refunded {} tokens to {}", amount, account.key());
+        }
+        
+        msg!("Stale maker: cancelled {} orders of {}", cancelled, owner);
+        Ok(cancelled)
+    }
+
+    /// Remove up to `max_count` expired orders from `side` (permissionless, for keepers)
+    /// 
+    /// `remaining_accounts` holds the owners' token accounts for ask refunds;
+    /// each account's refunds go out in one transfer. An expired ask whose owner
+    /// has no account supplied is left for a later sweep, as is any order with a
+    /// payment in progress. Returns the number of orders swept.
+    pub fn sweep_expired<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepExpired<'info>>,
+        side: Side,
+        max_count: u8,
+    ) -> Result<u32> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        let clock = Clock::get()?;
+        let token_mint = ctx.accounts.token_mint.key();
+        let refund_accounts = ctx
+            .remaining_accounts
+            .iter()
+            .map(InterfaceAccount::<TokenAccount>::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let mut refunds = vec![0u64; refund_accounts.len()];
+        
+        let order_book = &mut ctx.accounts.order_book;
+        let mut swept = 0u32;
+        for order in order_book.expired_orders(side, clock.unix_timestamp, max_count as usize) {
+            if order.escrows_tokens() && order.quantity > 0 {
+                let Some(index) = refund_accounts
+                    .iter()
+                    .position(|account| {
+                        account.owner == order.owner
+                            && account.mint == token_mint
+                            && order.accepts_refund_to(&account.key())
+                    })
+                else {
+                    msg!("Sweep: no refund account for order {}, leaving it", order.order_id);
+                    continue;
+                };
+                refunds[index] = refunds[index]
+                    .checked_add(order.quantity)
+                    .ok_or(ErrorCode::MathOverflow)?;
+            }
+            
+            order_book.remove_order(order.order_id, side, order.price)?;
+            emit!(OrderExpired {
+                order_id: order.order_id,
+                owner: order.owner,
+                side,
+                price: order.price,
+                quantity: order.quantity,
+            });
+            swept += 1;
+        }
+        if swept > 0 {
+            order_book.record_cancelled(swept as u64)?;
+            order_book.touch(clock.slot);
+        }
+        
+        let vault = EscrowVault::new(
+            &ctx.accounts.escrow_vault,
+            &ctx.accounts.escrow_authority,
+            ctx.bumps.escrow_authority,
+            &ctx.accounts.token_program,
+        );
+        for (account, amount) in refund_accounts.iter().zip(refunds) {
+            if amount == 0 {
+                continue;
+            }
+            ctx.accounts.market.refund_from_escrow(
+                &mut ctx.accounts.order_book,
+                &vault,
+                account.to_account_info(),
+                amount,
+                EscrowChangeReason::Expiry,
+            )?;
+            msg!("Sweep: refunded {} tokens to {}", amount, account.key());
+        }
+        
+        msg!("Sweep: removed {} expired orders", swept);
+        Ok(swept)
+    }
+    
+    /// Recompute the cached best bid and ask from the trees (permissionless)
+    /// 
+    /// Heals a stale cache after manual intervention such as a migration or
+    /// compaction; on a healthy book it changes nothing.
+    pub fn refresh_best_prices(ctx: Context<RefreshBestPrices>) -> Result<()> {
+        let order_book = &mut ctx.accounts.order_book;
+        if order_book.refresh_best_prices()? {
+            order_book.touch(Clock::get()?.slot);
+            msg!(
+                "Market: Best prices refreshed - best_bid: {}, best_ask: {}",
+                order_book.best_bid,
+                order_book.best_ask
+            );
+        } else {
+            msg!("Market: Best prices already match the book");
+        }
+        Ok(())
+    }
+    
+    /// Re-designate where an order's escrow settles to (order owner only)
+    /// 
+    /// For sellers whose original destination was closed after placement.
+    /// Not possible once the order has settled.
+    pub fn set_settlement_destination(ctx: Context<SetSettlementDestination>, order_id: u128) -> Result<()> {
+        let destination = ctx.accounts.new_destination.key();
+        let order = ctx
+            .accounts
+            .order_book
+            .find_order_mut(order_id)
+            .ok_or(ErrorCode::OrderNotFound)?;
+        
+        require_keys_eq!(order.owner, ctx.accounts.owner.key(), ErrorCode::UnauthorizedAction);
+        require!(
+            order.payment_status != order::PaymentStatus::Verified,
+            ErrorCode::OrderAlreadySettled
+        );
+        
+        let previous = order.settlement_destination;
+        order.settlement_destination = destination;
+        ctx.accounts.order_book.touch(Clock::get()?.slot);
+        
+        emit!(SettlementDestinationChanged {
+            order_id,
+            previous,
+            destination,
+        });
+        
+        msg!("Order {} now settles to {}", order_id, destination);
+        Ok(())
+    }
+    
+    /// Resolve a disputed payment (arbitrator only)
+    /// 
+    /// If `release` is true the escrowed tokens are released as in `verify_settlement`,
+    /// otherwise the payment claim is rejected and the order returns to `Pending`.
+    /// Releasing an order placed with extras takes its `OrderExtras` account as
+    /// the first remaining account.
+    pub fn resolve_dispute(
+        ctx: Context<ResolveDispute>,
+        order_id: u128,
+        release: bool,
+    ) -> Result<()> {
+        require!(!ctx.accounts.market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+        let order_book_key = ctx.accounts.order_book.key();
+        let order_book = &mut ctx.accounts.order_book;
+        order_book.touch(Clock::get()?.slot);
+        
+        let order = order_book
+            .find_order_mut(order_id)
+            .ok_or(ErrorCode::OrderNotFound)?;
+        
+        require!(
+            order.payment_status == order::PaymentStatus::PaymentMarked
+                || order.payment_status == order::PaymentStatus::Disputed,
+            ErrorCode::InvalidPaymentStatus
+        );
+        
+        if !release {
+            order.payment_status = order::PaymentStatus::Pending;
+            order.payment_marked_timestamp = 0;
+            order.settlement_timestamp = 0;
+            
+            msg!("Dispute resolved for order {}: payment claim rejected", order_id);
+            return Ok(());
+        }
+        
+        require_keys_eq!(
+            ctx.accounts.seller_token_account.key(),
+            order.settlement_destination,
+            ErrorCode::InvalidSettlementDestination
+        );
+        proof::check_destination_account(
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.token_mint.key(),
+        )?;
+        
+        let extras = OrderExtras::load(ctx.remaining_accounts.first(), &order_book_key, order)?;
+        order.payment_status = order::PaymentStatus::Verified;
+        emit!(SettlementVerified {
+            order_id,
+            quantity: order.quantity,
+            memo: extras.memo,
+        });
+        
+        let released = order.quantity;
+        let vault = EscrowVault::new(
+            &ctx.accounts.escrow_vault,
+            &ctx.accounts.escrow_authority,
+            ctx.bumps.escrow_authority,
+            &ctx.accounts.token_program,
+        );
+        ctx.accounts.market.refund_from_escrow(
+            order_book,
+            &vault,
+            ctx.accounts.seller_token_account.to_account_info(),
+            released,
+            EscrowChangeReason::DisputeResolution,
+        )?;
+        msg!("Dispute resolved for order {}: tokens released", order_id);
+        Ok(())
+    }
+    
+    /// Reset the order book (close and allow re-init with new structure)
+    pub fn reset_order_book(ctx: Context<ResetOrderBook>) -> Result<()> {
+        msg!("Order book reset. Re-initialize with new structure.");
+        Ok(())
+    }
+
+    /// Match an order with advanced order type handling
+    /// 
+    /// Fills are returned through return data, so other programs can drive matching
+    /// via CPI (`market::cpi::match_order`) and read the resulting `MatchResult`.
+    /// Matches with more than `OrderBook::MAX_RETURNED_FILLS` fills write them to
+    /// the taker's `FillLog` instead and set `fills_in_account`.
+    /// 
+    /// `filters.max_slippage_bps` bounds how far the worst fill price may be
+    /// from the opposing best price at the start of the match. Makers accepting
+    /// none of `filters.accepted_payment_methods` are skipped (empty = any
+    /// payment method). At most `filters.max_makers` distinct maker owners are
+    /// filled (0 = no cap), so a P2P taker can bound how many fiat payments
+    /// they have to send.
+    /// Asks placed with `requires_attestation` are skipped unless the taker's
+    /// `TakerAttestation` PDA exists and hasn't expired.
+    /// With a `filters.jurisdiction`, only makers restricted to that ISO country
+    /// code are filled; without one, only unrestricted makers are.
+    /// 
+    /// The unfilled remainder of a `Limit` or post-only order rests in the book
+    /// at `limit_price`, accepting `payment_method` (comma-separated, as in
+    /// `NewOrderParams`), under the same checks, escrow and events as
+    /// `place_limit_order_v2`; its ID is returned as `resting_order_id`. A
+    /// remainder that can't rest, e.g. one crossing the taker's own orders, is
+    /// dropped if anything filled and fails the match otherwise.
+    pub fn match_order(
+        ctx: Context<MatchOrder>,
+        side: Side,
+        quantity: u64,
+        limit_price: u64,
+        order_type: OrderType,
+        filters: TakerFilters,
+        payment_method: String,
+    ) -> Result<MatchResult> {
+        let TakerFilters {
+            max_slippage_bps,
+            accepted_payment_methods,
+            max_makers,
+            jurisdiction,
+        } = filters;
+        require!(quantity > 0, ErrorCode::InvalidAmount);
+        require!(limit_price > 0, ErrorCode::InvalidPrice);
+        require!(jurisdiction.into_iter().all(is_valid_jurisdiction), ErrorCode::InvalidJurisdiction);
+        require!(payment_method.len() <= Order::MAX_PAYMENT_METHOD_LEN, ErrorCode::PaymentMethodTooLong);
+        
+        let market = &mut ctx.accounts.market;
+        let order_book = &mut ctx.accounts.order_book;
+        let taker_owner = ctx.accounts.owner.key();
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        require!(market.is_open(now), ErrorCode::MarketClosed);
+        
+        msg!(
+            "Market: Matching order - side: {:?}, qty: {}, limit: {}, type: {:?}",
+            side,
+            quantity,
+            limit_price,
+            order_type
+        );
+        
+        // All opposing liquidity is the taker's own: there is nothing to match.
+        // Limit/PostOnly orders skip straight to resting; immediate order
+        // types fail explicitly
+        let self_owned = order_book.opposing_side_owned_by(side, &taker_owner);
+        let mut fills = if self_owned {
+            if matches!(order_type, OrderType::Market | OrderType::ImmediateOrCancel | OrderType::FillOrKill) {
+                msg!("Market: All opposing liquidity is the taker's own, rejecting {:?} order", order_type);
+                return Err(ErrorCode::SelfTradeNotAllowed.into());
+            }
+            msg!("Market: All opposing liquidity is the taker's own, nothing to match");
+            Vec::new()
+        } else {
+            // Check for self-trade before matching
+            if order_book.would_self_trade(side, &taker_owner, market.queue_discipline) {
+                msg!("Market: Self-trade detected, rejecting order");
+                return Err(ErrorCode::SelfTradeNotAllowed.into());
+            }
+            
+            // Slippage check against a simulated pass, before anything is mutated
+            let reference_price = match side {
+                Side::Bid => order_book.best_ask,
+                Side::Ask => order_book.best_bid,
+            };
+            let request = MatchRequest {
+                side,
+                max_quantity: quantity,
+                limit_price,
+                taker_owner,
+                accepted_payment_methods: &accepted_payment_methods,
+                max_makers,
+                taker_attested: TakerAttestation::is_valid(&ctx.accounts.attestation, now)?,
+                taker_jurisdiction: jurisdiction.unwrap_or_default(),
+                discipline: market.queue_discipline,
+                now,
+            };
+            let simulated = order_book.simulate_match(&request);
+            if let Some(worst) = simulated.last() {
+                let slippage_bps = OrderBook::slippage_bps(reference_price, worst.price);
+                if slippage_bps > max_slippage_bps as u64 {
+                    msg!(
+                        "Market: Worst fill {} is {} bps from best {} (max {}), rejecting",
+                        worst.price,
+                        slippage_bps,
+                        reference_price,
+                        max_slippage_bps
+                    );
+                    return Err(ErrorCode::SlippageExceeded.into());
+                }
+            }
+            
+            // Execute matching
+            order_book.match_order(&request)?
+        };
+        if !fills.is_empty() {
+            order_book.record_fills(&fills)?;
+            order_book.touch(clock.slot);
+        }
+        
+        // Every fill gets a market-wide trade ID
+        for fill in fills.iter_mut() {
+            fill.trade_id = market.next_trade_id;
+            market.next_trade_id += 1;
+        }
+        let filled_quantity: u64 = fills.iter().map(|fill| fill.quantity).sum();
+        
+        // Handle order type-specific logic
+        match order_type {
+            OrderType::Limit => {
+                // If not fully filled, the remainder rests below
+                if filled_quantity < quantity {
+                    msg!("Market: Limit order partially filled ({}/{})", filled_quantity, quantity);
+                }
+            },
+            OrderType::Market => {
+                // Market order: accept any fill amount
+                msg!("Market: Market order filled {}/{}", filled_quantity, quantity);
+            },
+            OrderType::PostOnly | OrderType::PostOnlyReprice => {
+                // Post-only: reject if would match immediately
+                if !fills.is_empty() {
+                    msg!("Market: Post-only order would match immediately, rejecting");
+                    return Err(ErrorCode::PostOnlyWouldMatch.into());
+                }
+            },
+            OrderType::ImmediateOrCancel => {
+                // IOC: fill what's possible, cancel rest (no resting order)
+                msg!("Market: IOC filled {}/{}, canceling remainder", filled_quantity, quantity);
+            },
+            OrderType::FillOrKill => {
+                // FOK: must fill completely or reject entirely
+                if filled_quantity < quantity {
+                    msg!("Market: FOK order cannot be fully filled, rejecting");
+                    return Err(ErrorCode::FillOrKillNotFilled.into());
+                }
+            },
+        }
+        
+        for fill in fills.iter() {
+            emit!(OrderMatched {
+                trade_id: fill.trade_id,
+                maker_order_id: fill.maker_order_id,
+                maker_side: side.opposite(),
+                taker_owner,
+                taker_side: side,
+                price: fill.price,
+                quantity: fill.quantity,
+            });
+            
+            if fill.threshold_reached {
+                emit!(OrderFillThresholdReached {
+                    order_id: fill.maker_order_id,
+                    owner: fill.maker_owner,
+                    remaining_quantity: fill.maker_remaining,
+                });
+            }
+        }
+        
+        msg!("Market: Matched {} orders, total fills: {}", fills.len(), filled_quantity);
+        
+        // The unfilled remainder of a Limit or post-only order rests as if
+        // placed with `place_limit_order_v2` at `limit_price`. When it can't,
+        // a match that filled keeps its fills and drops the remainder; one that
+        // filled nothing fails with the reason, as the placement would
+        let remaining = quantity - filled_quantity;
+        let mut resting_order_id = None;
+        if remaining > 0 && matches!(order_type, OrderType::Limit | OrderType::PostOnly | OrderType::PostOnlyReprice) {
+            let params = NewOrderParams {
+                side,
+                price: limit_price,
+                quantity: remaining,
+                order_type,
+                client_order_id: 0,
+                payment_method,
+                options: OrderOptions {
+                    jurisdiction,
+                    ..OrderOptions::default()
+                },
+            };
+            let escrows_tokens = side != params.options.fiat_payer();
+            let owner_state = &mut ctx.accounts.owner_state;
+            let placement = market
+                .check_placement(order_book, owner_state, &params, now)
+                .and_then(|price| {
+                    // Matching skips the taker's own orders; resting across them
+                    // would leave the book crossed against itself
+                    require!(
+                        !order_book.crosses_own_order(side, price, &taker_owner),
+                        ErrorCode::SelfTradeNotAllowed
+                    );
+                    // Delegated escrow needs the escrow program's accounts, which
+                    // only `place_limit_order_v2` takes
+                    require!(!escrows_tokens || !market.has_delegated_escrow(), ErrorCode::EscrowDelegated);
+                    require!(
+                        !escrows_tokens || ctx.accounts.owner_token_account.amount >= remaining,
+                        ErrorCode::InsufficientFunds
+                    );
+                    Ok(price)
+                });
+            match placement {
+                Err(err) if fills.is_empty() => return Err(err),
+                Err(err) => msg!("Market: Remaining {} can't rest, dropping it: {:?}", remaining, err),
+                Ok(price) => {
+                    if price != limit_price {
+                        msg!("Market: Post-only order repriced from {} to {}", limit_price, price);
+                    }
+                    owner_state.owner = taker_owner;
+                    owner_state.last_placement_ts = now;
+                    
+                    let order = market.new_order(
+                        taker_owner,
+                        ctx.accounts.owner_token_account.key(),
+                        params,
+                        price,
+                        now,
+                    );
+                    if escrows_tokens {
+                        let transfer_ctx = CpiContext::new(
+                            ctx.accounts.token_program.to_account_info(),
+                            Transfer {
+                                from: ctx.accounts.owner_token_account.to_account_info(),
+                                to: ctx.accounts.escrow_vault.to_account_info(),
+                                authority: ctx.accounts.owner.to_account_info(),
+                            },
+                        );
+                        token::transfer(transfer_ctx, remaining)?;
+                        market.lock_escrow(order_book, remaining, EscrowChangeReason::Placement)?;
+                        msg!("Market: {} tokens transferred to escrow", remaining);
+                    }
+                    
+                    order_book.insert_order(order)?;
+                    order_book.record_placed()?;
+                    order_book.touch(clock.slot);
+                    owner_state.record_placement(0, order.order_id, now);
+                    
+                    emit!(OrderPlaced {
+                        order_id: order.order_id,
+                        owner: taker_owner,
+                        side,
+                        price,
+                        quantity: remaining,
+                        timestamp: order.timestamp,
+                        memo: [0; 16],
+                    });
+                    msg!("Market: Remaining {} resting as order {}", remaining, order.order_id);
+                    resting_order_id = Some(order.order_id);
+                }
+            }
+        }
+        
+        // Too many fills for return data: hand them back through the fill log.
+        // The log is cleared otherwise so it never holds a previous match's fills
+        let fill_log = &mut ctx.accounts.fill_log;
+        fill_log.owner = taker_owner;
+        fill_log.slot = clock.slot;
+        let fill_count = fills.len() as u32;
+        let fills_in_account = fills.len() > OrderBook::MAX_RETURNED_FILLS;
+        if fills_in_account {
+            require!(fills.len() <= FillLog::MAX_FILLS, ErrorCode::FillLogFull);
+            msg!("Market: {} fills written to fill log {}", fill_count, fill_log.key());
+            fill_log.fills = fills;
+            return Ok(MatchResult {
+                fill_count,
+                fills_in_account,
+                fills: Vec::new(),
+                resting_order_id,
+            });
+        }
+        fill_log.fills.clear();
+        
+        Ok(MatchResult {
+            fill_count,
+            fills_in_account,
+            fills,
+            resting_order_id,
+        })
+    }
+    
+    /// Dry-run placement: runs every `place_limit_order_v2` check (see
+    /// `Market::check_placement`) for `owner` and returns the error a real
+    /// placement would hit, without touching the book or escrow
+    /// Returns true when the order would be accepted
+    pub fn validate_order(ctx: Context<ValidateOrder>, params: NewOrderParams) -> Result<bool> {
+        let owner_state = OwnerState::load(&ctx.accounts.owner_state)?;
+        ctx.accounts.market.check_placement(
+            &ctx.accounts.order_book,
+            &owner_state,
+            &params,
+            Clock::get()?.unix_timestamp,
+        )?;
+        Ok(true)
+    }
+    
+    /// Read-only preview of a taker order: base filled, quote notional, fee,
+    /// net cost (bids) or proceeds (asks) and VWAP against the current book,
+    /// as seen by a taker without a KYC attestation or jurisdiction requirement
+    pub fn quote_for_base(
+        ctx: Context<QuoteForBase>,
+        side: Side,
+        quantity: u64,
+        limit_price: u64,
+    ) -> Result<BaseQuote> {
+        let market = &ctx.accounts.market;
+        let request = market.preview_request(side, quantity, limit_price, Clock::get()?.unix_timestamp);
+        ctx.accounts
+            .order_book
+            .quote_for_base(&request, market.price_scale, Market::TAKER_FEE_BPS)
+    }
+    
+    /// Read-only compute-unit estimate for a `match_order` with these terms, from
+    /// the levels and fills it would touch (see `OrderBook::estimate_match_cost`
+    /// for the cost model); clients size a `ComputeBudget` instruction with it
+    pub fn estimate_match_cost(
+        ctx: Context<QuoteForBase>,
+        side: Side,
+        quantity: u64,
+        limit_price: u64,
+    ) -> Result<MatchCostEstimate> {
+        let request = ctx
+            .accounts
+            .market
+            .preview_request(side, quantity, limit_price, Clock::get()?.unix_timestamp);
+        Ok(ctx.accounts.order_book.estimate_match_cost(&request))
+    }
+    
+    /// Read-only order ID for `(owner, sequence, timestamp)`, exactly as placement
+    /// derives it (see `order::generate_order_id`)
+    pub fn compute_order_id(
+        _ctx: Context<ViewMarket>,
+        owner: Pubkey,
+        sequence: u64,
+        timestamp: i64,
+    ) -> Result<u128> {
+        Ok(generate_order_id(&owner, sequence, timestamp))
+    }
+    
+    /// Read-only quantity a `side` taker could fill without going past `price`
+    /// (asks at or below it for a bid, bids at or above it for an ask), as
+    /// seen by the same taker as `quote_for_base`
+    pub fn fillable_up_to(ctx: Context<QuoteForBase>, side: Side, price: u64) -> Result<u64> {
+        let request = ctx
+            .accounts
+            .market
+            .preview_request(side, u64::MAX, price, Clock::get()?.unix_timestamp);
+        Ok(ctx.accounts.order_book.fillable_up_to(&request))
+    }
+    
+    /// Read-only market configuration (price scale, admin keys, trading flags,
+    /// delays and limits), decoupled from the raw `Market` account layout
+    pub fn get_market_config(ctx: Context<ViewMarket>) -> Result<MarketConfig> {
+        Ok(ctx.accounts.market.config())
+    }
+    
+    /// Read-only best-price history, oldest checkpoint first
+    /// (at most `OrderBook::MAX_PRICE_CHECKPOINTS`)
+    pub fn get_price_history(ctx: Context<ViewOrderBook>) -> Result<Vec<PriceCheckpoint>> {
+        Ok(ctx.accounts.order_book.price_checkpoints.clone())
+    }
+    
+    /// Read-only snapshot of every price level on both sides of the book
+    pub fn get_book_snapshot(ctx: Context<ViewOrderBook>) -> Result<BookSnapshot> {
+        Ok(ctx.accounts.order_book.snapshot())
+    }
+    
+    /// Read-only compact binary snapshot for light clients
+    /// (wire format documented on `OrderBook::serialize_snapshot`)
+    pub fn get_compact_snapshot(ctx: Context<ViewOrderBook>) -> Result<Vec<u8>> {
+        Ok(ctx.accounts.order_book.serialize_snapshot())
+    }
+    
+    /// Read-only top-of-book statistics (best prices, spread, mid)
+    pub fn get_market_stats(ctx: Context<ViewOrderBook>) -> Result<MarketStats> {
+        Ok(ctx.accounts.order_book.stats())
+    }
+    
+    /// Read-only state of a single resting order (quantities, payment status, memo).
+    /// An order placed with extras takes its `OrderExtras` account as the first
+    /// remaining account, without which the memo can't be read
+    pub fn get_order_status(ctx: Context<ViewOrderBook>, order_id: u128) -> Result<OrderStatus> {
+        let order_book = &ctx.accounts.order_book;
+        let order = order_book.find_order(order_id).ok_or(ErrorCode::OrderNotFound)?;
+        let extras = OrderExtras::load(ctx.remaining_accounts.first(), &order_book.key(), order)?;
+        Ok(OrderStatus::new(order, &extras))
+    }
+    
+    /// Read-only list of orders ready for `verify_settlement` (payment marked,
+    /// settlement delay expired); capped at `OrderBook::MAX_LISTED_ORDERS`
+    pub fn list_settleable_orders(ctx: Context<ViewOrderBook>) -> Result<Vec<u128>> {
+        Ok(ctx.accounts.order_book.settleable_orders(Clock::get()?.unix_timestamp))
+    }
+    
+    /// Read-only self-trade check: whether `owner` has a resting order on the
+    /// side an incoming `side` order would hit, at any price level
+    pub fn check_self_trade(ctx: Context<ViewOrderBook>, side: Side, owner: Pubkey) -> Result<bool> {
+        Ok(ctx.accounts.order_book.has_opposing_order(side, &owner))
+    }
+    
+    /// Read-only CritBit tree occupancy (leaves, used and free nodes per side)
+    pub fn get_book_diagnostics(ctx: Context<ViewOrderBook>) -> Result<BookDiagnostics> {
+        Ok(ctx.accounts.order_book.diagnostics())
+    }
+    
+    /// Read-only level headroom per side (used and maximum levels, free and
+    /// total tree nodes) and the orders the account still has room for, so
+    /// clients can warn before `OrderBookFull`
+    pub fn get_book_capacity(ctx: Context<ViewOrderBook>) -> Result<BookCapacity> {
+        Ok(ctx.accounts.order_book.capacity())
+    }
+    
+    /// Read-only audit: recompute cached queue quantities and `total_orders`
+    /// and report the first mismatch, if any
+    pub fn verify_book_integrity(ctx: Context<ViewOrderBook>) -> Result<BookIntegrity> {
+        Ok(ctx.accounts.order_book.verify_integrity())
+    }
+    
+    /// Read-only payment methods the maker of `order_id` accepts, so a taker can
+    /// check the fiat rails before matching
+    pub fn get_order_payment_methods(ctx: Context<ViewOrderBook>, order_id: u128) -> Result<Vec<String>> {
+        ctx.accounts
+            .order_book
+            .find_order(order_id)
+            .map(Order::payment_methods)
+            .ok_or(ErrorCode::OrderNotFound.into())
+    }
+    
+    /// Read-only list of the orders at one price level, in time priority
+    /// (empty if the level doesn't exist, capped at `OrderBook::MAX_LEVEL_ORDERS`)
+    pub fn get_level_orders(ctx: Context<ViewOrderBook>, side: Side, price: u64) -> Result<Vec<LevelOrder>> {
+        Ok(ctx.accounts.order_book.level_orders(side, price))
+    }
+    
+    /// Read-only total of `owner`'s tokens currently locked in escrow by resting asks
+    pub fn get_escrowed_balance(ctx: Context<ViewOrderBook>, owner: Pubkey) -> Result<u64> {
+        Ok(ctx.accounts.order_book.escrowed_balance(&owner))
+    }
+    
+    /// Read-only check that the escrow locked by all of the market's order books
+    /// matches the escrow vault's balance; a mismatch means tokens moved without
+    /// a book accounting for them
+    pub fn verify_escrow_invariant(ctx: Context<ViewEscrow>) -> Result<EscrowInvariant> {
+        Ok(ctx
+            .accounts
+            .market
+            .escrow_invariant(ctx.accounts.escrow_vault.amount))
+    }
+    
+    /// Read-only lifetime counters: volume, fills, orders placed and cancelled
+    pub fn get_lifetime_stats(ctx: Context<ViewOrderBook>) -> Result<LifetimeStats> {
+        Ok(ctx.accounts.order_book.lifetime_stats())
+    }
+    
+    /// Read-only lifetime maker volume for an owner; `None` if the book doesn't
+    /// track them (see `OrderBook::maker_volumes`)
+    pub fn get_maker_volume(ctx: Context<ViewOrderBook>, owner: Pubkey) -> Result<Option<u64>> {
+        Ok(ctx.accounts.order_book.get_maker_volume(&owner))
+    }
+}
+
+// ============================================================================
+// Account Validation Structures
+// ============================================================================
+
+#[derive(Accounts)]
+pub struct InitializeEscrowVault<'info> {
+    #[account(
+        init,
+        payer = payer,
+        token::mint = token_mint,
+        token::authority = escrow_authority,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// PDA that will have authority over the escrow vault
+    /// CHECK: PDA derived from seeds, used as token account authority
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// ============================================================================
+// Account Validation Structures
+// ============================================================================
+
+#[derive(Accounts)]
+pub struct InitializeMarket<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Market::LEN,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Authority for market configuration
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetVerifyingKey<'info> {
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = VerifyingKey::LEN,
+        seeds = [b"verifying_key", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub verifying_key: Account<'info, VerifyingKey>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(taker: Pubkey)]
+pub struct AttestTaker<'info> {
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = TakerAttestation::LEN,
+        seeds = [b"attestation", market.key().as_ref(), taker.as_ref()],
+        bump,
+    )]
+    pub attestation: Account<'info, TakerAttestation>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(taker: Pubkey)]
+pub struct RevokeAttestation<'info> {
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"attestation", market.key().as_ref(), taker.as_ref()],
+        bump,
+    )]
+    pub attestation: Account<'info, TakerAttestation>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOrderBook<'info> {
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEscrowProgram<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FreezeAndSnapshot<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = market.pending_authority == pending_authority.key() @ ErrorCode::UnauthorizedPendingAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub pending_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeOrderBook<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + OrderBook::INIT_SPACE,
+        seeds = [b"order_book", token_mint.key().as_ref(), quote_mint.key().as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Quote asset this book is priced in; one book per (base, quote) pair
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceLimitOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = OwnerState::LEN,
+        seeds = [b"owner_state", market.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub owner_state: Account<'info, OwnerState>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-// ============================================================================
-// Account Validation Structures
-// ============================================================================
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    /// Order owner or the order's cancel delegate
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// The order owner's token account (checked against the order in the handler)
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
 
 #[derive(Accounts)]
-pub struct InitializeEscrowVault<'info> {
+pub struct CancelReplace<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
     #[account(
-        init,
-        payer = payer,
-        token::mint = token_mint,
-        token::authority = escrow_authority,
+        mut,
+        constraint = owner_token_account.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
         seeds = [b"escrow_vault", token_mint.key().as_ref()],
         bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
     )]
     pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// PDA that will have authority over the escrow vault
-    /// CHECK: PDA derived from seeds, used as token account authority
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Exists already: the owner placed the order being replaced
+    #[account(
+        mut,
+        seeds = [b"owner_state", market.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub owner_state: Account<'info, OwnerState>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrders<'info> {
+    /// Owner or cancel delegate of the listed orders
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AdminCancelOrder<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    /// The order owner's token account (checked against the order in the handler)
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ImportOrders<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+        has_one = authority @ ErrorCode::UnauthorizedAuthority,
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Must already hold the imported asks' tokens
+    #[account(
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct MatchOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Funds the escrow of a resting remainder that escrows tokens
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = FillLog::LEN,
+        seeds = [b"fill_log", market.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub fill_log: Account<'info, FillLog>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = OwnerState::LEN,
+        seeds = [b"owner_state", market.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub owner_state: Account<'info, OwnerState>,
+
+    /// CHECK: the taker's attestation PDA; may not exist (see `TakerAttestation::is_valid`)
     #[account(
-        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        seeds = [b"attestation", market.key().as_ref(), owner.key().as_ref()],
         bump,
     )]
-    pub escrow_authority: UncheckedAccount<'info>,
+    pub attestation: UncheckedAccount<'info>,
 
     pub token_mint: InterfaceAccount<'info, Mint>,
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
-
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
-// ============================================================================
-// Account Validation Structures
-// ============================================================================
-
 #[derive(Accounts)]
-pub struct InitializeMarket<'info> {
+pub struct ValidateOrder<'info> {
+    /// CHECK: owner the order would be placed for; only used to find their state
+    pub owner: UncheckedAccount<'info>,
+
+    /// CHECK: the owner's state PDA; may not exist (see `OwnerState::load`)
+    #[account(
+        seeds = [b"owner_state", market.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub owner_state: UncheckedAccount<'info>,
+
     #[account(
-        init,
-        payer = payer,
-        space = Market::LEN,
         seeds = [b"market", token_mint.key().as_ref()],
         bump,
     )]
     pub market: Account<'info, Market>,
 
-    pub token_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
 
-    /// CHECK: Authority for market configuration
-    pub authority: UncheckedAccount<'info>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+#[derive(Accounts)]
+pub struct ViewOrderBook<'info> {
+    #[account(
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
 
-    pub system_program: Program<'info, System>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeOrderBook<'info> {
+pub struct QuoteForBase<'info> {
     #[account(
-        init,
-        payer = payer,
-        space = 8 + OrderBook::INIT_SPACE,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
         bump,
     )]
     pub order_book: Account<'info, OrderBook>,
 
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct ViewEscrow<'info> {
     #[account(
         seeds = [b"market", token_mint.key().as_ref()],
         bump,
     )]
     pub market: Account<'info, Market>,
 
+    #[account(
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
     pub token_mint: InterfaceAccount<'info, Mint>,
+}
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+#[derive(Accounts)]
+pub struct ViewMarket<'info> {
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub market: Account<'info, Market>,
 
-    pub system_program: Program<'info, System>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
 }
 
 #[derive(Accounts)]
-pub struct PlaceLimitOrder<'info> {
+pub struct MarkPayment<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct VerifySettlement<'info> {
     #[account(
         mut,
-        constraint = owner_token_account.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
-        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
     )]
-    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub market: Account<'info, Market>,
 
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
     #[account(
         mut,
         seeds = [b"escrow_vault", token_mint.key().as_ref()],
@@ -512,39 +3428,85 @@ pub struct PlaceLimitOrder<'info> {
         constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
     )]
     pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: must be the order's settlement destination; checked to still be
+    /// an open token account for this mint in the handler
+    #[account(mut)]
+    pub seller_token_account: UncheckedAccount<'info>,
+    
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+    
+    /// CHECK: the market's verifying key PDA; may not exist yet (see `proof::load_verifying_key`)
+    #[account(
+        seeds = [b"verifying_key", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub verifying_key: UncheckedAccount<'info>,
+    
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
 
+#[derive(Accounts)]
+pub struct VerifySettlementBatch<'info> {
     #[account(
         mut,
         seeds = [b"market", token_mint.key().as_ref()],
         bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
     )]
     pub market: Account<'info, Market>,
 
     #[account(
         mut,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
         bump,
     )]
     pub order_book: Account<'info, OrderBook>,
-
+    
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+    
+    /// CHECK: the market's verifying key PDA; may not exist yet (see `proof::load_verifying_key`)
+    #[account(
+        seeds = [b"verifying_key", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub verifying_key: UncheckedAccount<'info>,
+    
     pub token_mint: InterfaceAccount<'info, Mint>,
-
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct CancelOrder<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
+pub struct SweepExpired<'info> {
+    /// Any keeper; sweeping is permissionless
+    pub keeper: Signer<'info>,
+    
     #[account(
         mut,
-        constraint = owner_token_account.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
-        constraint = owner_token_account.mint == token_mint.key() @ ErrorCode::InvalidMint,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
     )]
-    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
-
+    pub order_book: Account<'info, OrderBook>,
+    
     #[account(
         mut,
         seeds = [b"escrow_vault", token_mint.key().as_ref()],
@@ -552,38 +3514,67 @@ pub struct CancelOrder<'info> {
         constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
     )]
     pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
-
+    
     /// CHECK: PDA that has authority over escrow vault
     #[account(
         seeds = [b"escrow_authority", token_mint.key().as_ref()],
         bump,
     )]
     pub escrow_authority: UncheckedAccount<'info>,
-
+    
     #[account(
         mut,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"market", token_mint.key().as_ref()],
         bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
     )]
-    pub order_book: Account<'info, OrderBook>,
+    pub market: Account<'info, Market>,
 
     pub token_mint: InterfaceAccount<'info, Mint>,
-
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct MatchOrder<'info> {
+pub struct RefreshBestPrices<'info> {
+    /// Anyone; refreshing is permissionless
+    pub caller: Signer<'info>,
+    
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct Heartbeat<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
 
     #[account(
-        mut,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"market", token_mint.key().as_ref()],
         bump,
     )]
-    pub order_book: Account<'info, OrderBook>,
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = OwnerState::LEN,
+        seeds = [b"owner_state", market.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub owner_state: Account<'info, OwnerState>,
 
     pub token_mint: InterfaceAccount<'info, Mint>,
 
@@ -591,26 +3582,67 @@ pub struct MatchOrder<'info> {
 }
 
 #[derive(Accounts)]
-pub struct MarkPayment<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    
+#[instruction(owner: Pubkey)]
+pub struct CancelStaleMaker<'info> {
+    /// Any keeper; cancelling a lapsed maker is permissionless
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        seeds = [b"owner_state", market.key().as_ref(), owner.as_ref()],
+        bump,
+    )]
+    pub owner_state: Account<'info, OwnerState>,
+
     #[account(
         mut,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
         bump,
     )]
     pub order_book: Account<'info, OrderBook>,
-    
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", token_mint.key().as_ref()],
+        bump,
+        constraint = escrow_vault.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA that has authority over escrow vault
+    #[account(
+        seeds = [b"escrow_authority", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow_authority: UncheckedAccount<'info>,
+
     pub token_mint: InterfaceAccount<'info, Mint>,
-    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct VerifySettlement<'info> {
+pub struct ResolveDispute<'info> {
+    pub arbitrator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+        has_one = arbitrator @ ErrorCode::UnauthorizedArbitrator,
+    )]
+    pub market: Account<'info, Market>,
+    
     #[account(
         mut,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
         bump,
     )]
     pub order_book: Account<'info, OrderBook>,
@@ -623,8 +3655,10 @@ pub struct VerifySettlement<'info> {
     )]
     pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
     
+    /// CHECK: must be the order's settlement destination; checked to still be
+    /// an open token account for this mint in the handler
     #[account(mut)]
-    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub seller_token_account: UncheckedAccount<'info>,
     
     /// CHECK: PDA that has authority over escrow vault
     #[account(
@@ -637,11 +3671,37 @@ pub struct VerifySettlement<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct SetSettlementDestination<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
+        bump,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        constraint = new_destination.mint == token_mint.key() @ ErrorCode::InvalidMint,
+    )]
+    pub new_destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
 #[derive(Accounts)]
 pub struct ResetOrderBook<'info> {
     #[account(
         mut,
-        seeds = [b"order_book", token_mint.key().as_ref()],
+        seeds = [b"order_book", token_mint.key().as_ref(), order_book.quote_mint.as_ref()],
         bump,
         close = authority,
     )]
@@ -650,6 +3710,7 @@ pub struct ResetOrderBook<'info> {
     #[account(
         seeds = [b"market", token_mint.key().as_ref()],
         bump,
+        constraint = !market.paused @ ErrorCode::MarketPaused,
     )]
     pub market: Account<'info, Market>,
     
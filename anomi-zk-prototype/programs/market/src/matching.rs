@@ -0,0 +1,296 @@
+use anchor_lang::prelude::*;
+use crate::order::{OrderQueue, QueueDiscipline, Side};
+use crate::order_book::Fill;
+
+/// Taker side of a match
+#[derive(Clone, Copy, Debug)]
+pub struct MatchRequest<'a> {
+    pub side: Side,
+    pub max_quantity: u64,
+    pub limit_price: u64,
+    pub taker_owner: Pubkey,
+    /// Makers accepting none of these are skipped (empty = any payment method)
+    pub accepted_payment_methods: &'a [String],
+    /// Cap on distinct maker owners filled (0 = no cap)
+    pub max_makers: u8,
+    /// Whether the taker holds a valid KYC attestation for this market
+    pub taker_attested: bool,
+    /// Only makers restricted to this ISO country code are filled (zeros = unrestricted makers only)
+    pub taker_jurisdiction: [u8; 2],
+    /// Priority within a price level (the market's `queue_discipline`)
+    pub discipline: QueueDiscipline,
+    pub now: i64,
+}
+
+/// Why a maker order was passed over without ending the match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Past its expiry timestamp
+    Expired,
+    /// Still inside its activation delay
+    Inactive,
+    /// Accepts none of the taker's payment methods
+    NoAcceptedPaymentMethod,
+    /// Ask whose reserve price is above the taker's limit
+    BelowReserve,
+    /// Requires a KYC attestation the taker doesn't hold
+    NotAttested,
+    /// Restricted to a different jurisdiction than the taker requires
+    JurisdictionMismatch,
+}
+
+/// Why matching ended before the taker was filled or ran out of acceptable levels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Reached a maker order owned by the taker
+    SelfTrade { maker_order_id: u128 },
+    /// The next fill would exceed `max_makers` distinct makers
+    MakerCap,
+}
+
+/// Fills and side effects of `match_levels`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchOutcome {
+    /// One fill per maker order touched, in execution order
+    pub fills: Vec<Fill>,
+    /// Maker orders passed over, in the order they were seen
+    pub skipped: Vec<(u128, SkipReason)>,
+    pub stopped: Option<StopReason>,
+    /// Price levels whose queue is now empty and must leave the tree
+    pub emptied_levels: Vec<u64>,
+}
+
+impl MatchOutcome {
+    pub fn filled_quantity(&self) -> u64 {
+        self.fills.iter().map(|fill| fill.quantity).sum()
+    }
+}
+
+/// Core matching algorithm: fill `request` against the opposing `levels`
+/// (`(price, queue index)`, best first) in price priority, then
+/// `request.discipline` within each level
+///
+/// Pure: no clock, logging or tree access. Maker orders in `queues` are filled
+/// in place and fully filled ones are removed; everything else the caller has
+/// to act on (tree removals, logs) is reported in the outcome.
+pub fn match_levels(
+    queues: &mut [OrderQueue],
+    levels: &[(u64, u32)],
+    request: &MatchRequest,
+) -> MatchOutcome {
+    let mut outcome = MatchOutcome::default();
+    let mut remaining_quantity = request.max_quantity;
+
+    for &(price, queue_index) in levels {
+        if remaining_quantity == 0 || !price_acceptable(request.side, price, request.limit_price) {
+            break;
+        }
+
+        let queue = &mut queues[queue_index as usize];
+        for maker_order_id in queue.priority_order(request.discipline) {
+            if remaining_quantity == 0 {
+                break;
+            }
+
+            let Some(maker_order) = queue.orders.iter_mut().find(|o| o.order_id == maker_order_id) else {
+                continue;
+            };
+
+            if maker_order.is_expired(request.now) {
+                outcome.skipped.push((maker_order_id, SkipReason::Expired));
+                continue;
+            }
+            if !maker_order.is_active(request.now) {
+                outcome.skipped.push((maker_order_id, SkipReason::Inactive));
+                continue;
+            }
+            if !maker_order.accepts_payment_method(request.accepted_payment_methods) {
+                outcome.skipped.push((maker_order_id, SkipReason::NoAcceptedPaymentMethod));
+                continue;
+            }
+            if !maker_order.meets_reserve(request.limit_price) {
+                outcome.skipped.push((maker_order_id, SkipReason::BelowReserve));
+                continue;
+            }
+            if !maker_order.accepts_taker(request.taker_attested) {
+                outcome.skipped.push((maker_order_id, SkipReason::NotAttested));
+                continue;
+            }
+            if !maker_order.matches_jurisdiction(request.taker_jurisdiction) {
+                outcome.skipped.push((maker_order_id, SkipReason::JurisdictionMismatch));
+                continue;
+            }
+            if maker_order.owner == request.taker_owner {
+                outcome.stopped = Some(StopReason::SelfTrade { maker_order_id });
+                break;
+            }
+            if exceeds_maker_cap(&outcome.fills, &maker_order.owner, request.max_makers) {
+                outcome.stopped = Some(StopReason::MakerCap);
+                break;
+            }
+
+            let fill_quantity = remaining_quantity.min(maker_order.quantity);
+            maker_order.fill(fill_quantity);
+            queue.total_quantity = queue.total_quantity.saturating_sub(fill_quantity);
+            remaining_quantity -= fill_quantity;
+
+            outcome.fills.push(Fill {
+                trade_id: 0,
+                price,
+                quantity: fill_quantity,
+                maker_order_id,
+                maker_owner: maker_order.owner,
+                maker_remaining: maker_order.quantity,
+                threshold_reached: maker_order.check_fill_threshold(),
+            });
+
+            if maker_order.is_filled() {
+                queue.remove(maker_order_id);
+            }
+        }
+
+        if queue.is_empty() {
+            outcome.emptied_levels.push(price);
+        }
+        if outcome.stopped.is_some() {
+            break;
+        }
+    }
+
+    outcome
+}
+
+/// Whether a `side` taker with `limit_price` accepts a maker level at `price`
+pub fn price_acceptable(side: Side, price: u64, limit_price: u64) -> bool {
+    match side {
+        Side::Bid => price <= limit_price,  // Buy: ask price must be <= limit
+        Side::Ask => price >= limit_price,  // Sell: bid price must be >= limit
+    }
+}
+
+/// Whether filling `owner` would add a counterparty beyond `max_makers` (0 = no cap)
+fn exceeds_maker_cap(fills: &[Fill], owner: &Pubkey, max_makers: u8) -> bool {
+    if max_makers == 0 || fills.iter().any(|fill| fill.maker_owner == *owner) {
+        return false;
+    }
+    let mut makers: Vec<&Pubkey> = fills.iter().map(|fill| &fill.maker_owner).collect();
+    makers.sort();
+    makers.dedup();
+    makers.len() >= max_makers as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{generate_order_id, Order, OrderType};
+    use proptest::prelude::*;
+
+    /// Ask-side queues from `(maker index, quantity, activation delay)` orders,
+    /// one level per order at prices 100, 101, ...; returns (queues, levels, makers)
+    fn ask_book(orders: &[(usize, u64, u32)]) -> (Vec<OrderQueue>, Vec<(u64, u32)>, Vec<Pubkey>) {
+        let makers: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let mut queues = Vec::new();
+        let mut levels = Vec::new();
+        for (seq, &(maker, quantity, activation_delay)) in orders.iter().enumerate() {
+            let owner = makers[maker % makers.len()];
+            let mut order = Order::new(
+                generate_order_id(&owner, seq as u64, 1000),
+                owner,
+                quantity,
+                100 + seq as u64,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            order.activation_delay = activation_delay;
+            let mut queue = OrderQueue::new();
+            queue.push(order).unwrap();
+            queues.push(queue);
+            levels.push((order.price, seq as u32));
+        }
+        (queues, levels, makers)
+    }
+
+    fn resting_quantity(queues: &[OrderQueue]) -> u64 {
+        queues.iter().flat_map(|q| q.orders.iter()).map(|o| o.quantity).sum()
+    }
+
+    #[test]
+    fn test_match_levels_reports_side_effects() {
+        // Level 101 is still inactive at now = 1005
+        let (mut queues, levels, makers) = ask_book(&[(0, 10, 0), (1, 10, 60), (2, 10, 0), (3, 10, 0)]);
+        let request = MatchRequest {
+            side: Side::Bid,
+            max_quantity: 25,
+            limit_price: 110,
+            taker_owner: makers[3],
+            accepted_payment_methods: &[],
+            max_makers: 0,
+            taker_attested: false,
+            taker_jurisdiction: [0; 2],
+            discipline: QueueDiscipline::Fifo,
+            now: 1005,
+        };
+
+        let outcome = match_levels(&mut queues, &levels, &request);
+        assert_eq!(outcome.fills.iter().map(|f| f.price).collect::<Vec<_>>(), vec![100, 102]);
+        assert_eq!(outcome.skipped, vec![(queues[1].orders[0].order_id, SkipReason::Inactive)]);
+        assert!(matches!(outcome.stopped, Some(StopReason::SelfTrade { .. })));
+        assert_eq!(outcome.emptied_levels, vec![100, 102]);
+        assert_eq!(outcome.filled_quantity(), 20);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_match_levels_conserves_quantity(
+            orders in prop::collection::vec((0usize..4, 1u64..1_000, 0u32..2), 0..20),
+            max_quantity in 1u64..10_000,
+            limit_offset in 0u64..25,
+            max_makers in 0u8..4,
+            taker_is_maker in any::<bool>(),
+        ) {
+            let (mut queues, levels, makers) = ask_book(&orders);
+            let before = resting_quantity(&queues);
+            let request = MatchRequest {
+                side: Side::Bid,
+                max_quantity,
+                limit_price: 100 + limit_offset,
+                taker_owner: if taker_is_maker { makers[0] } else { Pubkey::new_unique() },
+                accepted_payment_methods: &[],
+                max_makers,
+                taker_attested: false,
+                taker_jurisdiction: [0; 2],
+                discipline: QueueDiscipline::Fifo,
+                now: 1000,
+            };
+
+            let outcome = match_levels(&mut queues, &levels, &request);
+            let filled = outcome.filled_quantity();
+
+            // Never fill more than requested
+            prop_assert!(filled <= max_quantity);
+            // Whatever was filled left the book, nothing else did
+            prop_assert_eq!(resting_quantity(&queues) + filled, before);
+            // Every fill respects the limit and never hits the taker
+            for fill in &outcome.fills {
+                prop_assert!(fill.price <= request.limit_price);
+                prop_assert!(fill.quantity > 0);
+                prop_assert!(fill.maker_owner != request.taker_owner);
+            }
+            // The maker cap holds
+            if max_makers > 0 {
+                let mut owners: Vec<Pubkey> = outcome.fills.iter().map(|f| f.maker_owner).collect();
+                owners.sort();
+                owners.dedup();
+                prop_assert!(owners.len() <= max_makers as usize);
+            }
+            // Emptied levels really are empty
+            for price in &outcome.emptied_levels {
+                let (_, queue_index) = levels.iter().find(|(p, _)| p == price).unwrap();
+                prop_assert!(queues[*queue_index as usize].is_empty());
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program::{create_account, CreateAccount};
+use crate::error::ErrorCode;
+use crate::order_book::OrderBook;
 
 /// Order types supported by the matching engine
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
@@ -13,6 +16,9 @@ pub enum OrderType {
     ImmediateOrCancel,
     /// Fill-or-kill - must fill completely or reject entirely
     FillOrKill,
+    /// Post-only that is repriced one tick inside the opposing best instead of
+    /// being rejected when it would cross
+    PostOnlyReprice,
 }
 
 /// Side of the order book
@@ -33,6 +39,15 @@ impl Side {
     }
 }
 
+/// Which order at a price level has priority, set per market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueDiscipline {
+    /// Oldest first (standard price-time priority)
+    Fifo,
+    /// Newest first
+    Lifo,
+}
+
 /// Payment status for P2P fiat settlement (stub ZK verification)
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PaymentStatus {
@@ -48,6 +63,107 @@ pub enum PaymentStatus {
     Disputed,
 }
 
+/// Share of an ask's settlement paid to a third party, e.g. a referrer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReferralSplit {
+    /// Token account (of the market's mint) the referral share is paid to
+    pub recipient: Pubkey,
+    /// Share of the settled quantity in basis points, 1..=`Order::MAX_REFERRAL_BPS`
+    pub bps: u16,
+}
+
+impl ReferralSplit {
+    /// Names a recipient and a share within 1..=`Order::MAX_REFERRAL_BPS`
+    pub fn is_valid(&self) -> bool {
+        self.recipient != Pubkey::default() && (1..=Order::MAX_REFERRAL_BPS).contains(&self.bps)
+    }
+}
+
+/// Optional per-order settings supplied at placement
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OrderOptions {
+    /// Notify the maker once cumulative fills reach this many basis points (0 = off)
+    pub fill_notify_bps: u16,
+    /// Token account settlement pays out to (defaults to the funding account)
+    pub settlement_destination: Option<Pubkey>,
+    /// Seconds after placement before the order can be matched (cancels are always allowed)
+    pub activation_delay: u32,
+    /// Free-form reference (e.g. invoice number) carried through to settlement
+    pub memo: [u8; 16],
+    /// Extra key allowed to cancel the order (refunds still go to the owner)
+    pub cancel_delegate: Option<Pubkey>,
+    /// Unix timestamp from which the order no longer matches and can be swept (0 = never)
+    pub expiry_timestamp: i64,
+    /// Asks only: lowest taker limit price the ask will fill against (0 = none)
+    pub reserve_price: u64,
+    /// Program notified through its `on_settle` instruction after this order settles
+    pub on_settle_program: Option<Pubkey>,
+    /// Asks only: fill only against takers holding a valid `TakerAttestation`
+    pub requires_attestation: bool,
+    /// Asks only: pay this share of the settlement to a referral account
+    pub referral: Option<ReferralSplit>,
+    /// ISO 3166-1 alpha-2 code (e.g. `*b"US"`); only takers requiring the same code fill it
+    pub jurisdiction: Option<[u8; 2]>,
+    /// Side of the trade that pays fiat (default `Bid`); the other side escrows tokens
+    pub fiat_payer: Option<Side>,
+}
+
+impl OrderOptions {
+    /// Side of the trade that pays fiat (`Bid` unless set)
+    pub fn fiat_payer(&self) -> Side {
+        self.fiat_payer.unwrap_or(Side::Bid)
+    }
+    
+    /// Whether any option set is kept in the order's `OrderExtras` account
+    /// (memo, cancel delegate, settle hook, referral)
+    pub fn needs_extras(&self) -> bool {
+        self.memo != [0; 16]
+            || self.cancel_delegate.is_some()
+            || self.on_settle_program.is_some()
+            || self.referral.is_some()
+    }
+}
+
+/// Terms of a new order, for `place_limit_order_v2` and `cancel_replace`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct NewOrderParams {
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    pub order_type: OrderType,
+    pub client_order_id: u64,
+    pub payment_method: String,
+    pub options: OrderOptions,
+}
+
+/// Taker-side limits on a `match_order`, beyond its side, size and price
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct TakerFilters {
+    /// Furthest the worst fill may be from the opposing best price at the start, in bps
+    pub max_slippage_bps: u16,
+    /// Makers accepting none of these are skipped (empty = any payment method)
+    pub accepted_payment_methods: Vec<String>,
+    /// Distinct maker owners to fill at most (0 = no cap)
+    pub max_makers: u8,
+    /// Only fill makers restricted to this ISO country code (`None` = only unrestricted makers)
+    pub jurisdiction: Option<[u8; 2]>,
+}
+
+/// Resting order carried over from another venue by `import_orders`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ImportedOrder {
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    /// Original placement time; decides the order's time priority in the new book
+    pub timestamp: i64,
+    pub client_order_id: u64,
+    pub payment_method: String,
+    /// Token account settlement pays out to (there is no funding account to default to)
+    pub settlement_destination: Pubkey,
+}
+
 /// Individual order in the order book
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
 pub struct Order {
@@ -59,7 +175,7 @@ pub struct Order {
     pub quantity: u64,
     /// Original quantity (for tracking fills)
     pub original_quantity: u64,
-    /// Price in quote token per base token (scaled by 1e6)
+    /// Price in quote token per base token, as an integer at the market's `price_scale`
     pub price: u64,
     /// Unix timestamp when order was created
     pub timestamp: i64,
@@ -77,8 +193,41 @@ pub struct Order {
     pub payment_status: PaymentStatus,
     /// Timestamp when buyer marked payment as made
     pub payment_marked_timestamp: i64,
-    /// Timestamp when settlement delay expires (10 seconds after marked)
+    /// Timestamp when settlement delay expires (`Market::SETTLEMENT_DELAY_SECONDS` after marked)
     pub settlement_timestamp: i64,
+    
+    /// Fill threshold (basis points of original quantity) to notify the maker at
+    pub fill_notify_bps: u16,
+    /// Whether the fill threshold notification has already fired
+    pub fill_notified: bool,
+    /// Token account that receives escrow on settlement
+    pub settlement_destination: Pubkey,
+    /// Seconds after `timestamp` before the order can be matched
+    pub activation_delay: u32,
+    /// Market-wide placement sequence; lower numbers have time priority
+    pub sequence_number: u64,
+    /// Unix timestamp from which the order is expired (0 = never)
+    pub expiry_timestamp: i64,
+    /// Asks only: the taker's limit must reach this for the ask to fill (0 = none);
+    /// fills still execute at the displayed `price`
+    pub reserve_price: u64,
+    /// Token account the order was placed from; cancel refunds go back to it
+    /// (default = any of the owner's accounts, e.g. for imported orders)
+    pub funding_account: Pubkey,
+    /// Asks only: takers without a valid KYC attestation skip this order
+    pub requires_attestation: bool,
+    /// ISO 3166-1 alpha-2 country code the order is restricted to (zeros = none)
+    pub jurisdiction: [u8; 2],
+    /// Settlement proofs rejected by the pairing check so far
+    pub failed_verification_count: u8,
+    /// Unix timestamp of the last rejected settlement proof (0 = none)
+    pub last_verification_attempt: i64,
+    /// Side of the trade that pays fiat; an order on the other side escrows
+    /// its tokens at placement and is paid out by settlement
+    pub fiat_payer: Side,
+    /// Whether the order's memo, cancel delegate, settle hook or referral are
+    /// set, in its `OrderExtras` account
+    pub has_extras: bool,
 }
 
 impl Order {
@@ -94,7 +243,33 @@ impl Order {
                           32 + // payment_method
                           1 +  // payment_status
                           8 +  // payment_marked_timestamp
-                          8;   // settlement_timestamp
+                          8 +  // settlement_timestamp
+                          2 +  // fill_notify_bps
+                          1 +  // fill_notified
+                          32 + // settlement_destination
+                          4 +  // activation_delay
+                          8 +  // sequence_number
+                          8 +  // expiry_timestamp
+                          8 +  // reserve_price
+                          32 + // funding_account
+                          1 +  // requires_attestation
+                          2 +  // jurisdiction
+                          1 +  // failed_verification_count
+                          8 +  // last_verification_attempt
+                          1 +  // fiat_payer
+                          1;   // has_extras
+    
+    /// Largest referral share an order may carry (10%)
+    pub const MAX_REFERRAL_BPS: u16 = 1_000;
+    
+    /// Wait after the first rejected settlement proof; doubles with each further rejection
+    pub const VERIFICATION_BACKOFF_SECONDS: i64 = 2;
+    
+    /// Doublings after which the backoff stops growing (2s << 10, about 34 minutes)
+    pub const MAX_VERIFICATION_BACKOFF_SHIFT: u8 = 10;
+    
+    /// Longest payment method an order stores, in bytes
+    pub const MAX_PAYMENT_METHOD_LEN: usize = 32;
     
     /// Create a new order
     pub fn new(
@@ -108,9 +283,9 @@ impl Order {
         client_order_id: u64,
         payment_method: String,
     ) -> Self {
-        let mut payment_bytes = [0u8; 32];
+        let mut payment_bytes = [0u8; Self::MAX_PAYMENT_METHOD_LEN];
         let bytes = payment_method.as_bytes();
-        let len = bytes.len().min(32);
+        let len = bytes.len().min(Self::MAX_PAYMENT_METHOD_LEN);
         payment_bytes[..len].copy_from_slice(&bytes[..len]);
         
         Self {
@@ -127,9 +302,130 @@ impl Order {
             payment_status: PaymentStatus::Pending,
             payment_marked_timestamp: 0,
             settlement_timestamp: 0,
+            fill_notify_bps: 0,
+            fill_notified: false,
+            settlement_destination: Pubkey::default(),
+            activation_delay: 0,
+            sequence_number: 0,
+            expiry_timestamp: 0,
+            reserve_price: 0,
+            funding_account: Pubkey::default(),
+            requires_attestation: false,
+            jurisdiction: [0; 2],
+            failed_verification_count: 0,
+            last_verification_attempt: 0,
+            fiat_payer: Side::Bid,
+            has_extras: false,
         }
     }
     
+    /// Apply placement options to a freshly created order; those kept in
+    /// `OrderExtras` only mark it as having extras
+    pub fn with_options(mut self, options: &OrderOptions) -> Self {
+        self.fill_notify_bps = options.fill_notify_bps;
+        self.activation_delay = options.activation_delay;
+        self.expiry_timestamp = options.expiry_timestamp;
+        self.reserve_price = options.reserve_price;
+        self.requires_attestation = options.requires_attestation;
+        self.jurisdiction = options.jurisdiction.unwrap_or_default();
+        self.fiat_payer = options.fiat_payer();
+        self.has_extras = options.needs_extras();
+        self
+    }
+    
+    /// Payment methods the maker accepts, decoded from the 32-byte field
+    /// (NUL-padded; several rails are comma-separated, e.g. "PayPal,Wise")
+    pub fn payment_methods(&self) -> Vec<String> {
+        let len = self.payment_method.iter().position(|&b| b == 0).unwrap_or(32);
+        String::from_utf8_lossy(&self.payment_method[..len])
+            .split(',')
+            .map(|method| method.trim().to_string())
+            .filter(|method| !method.is_empty())
+            .collect()
+    }
+    
+    /// Check if the maker accepts any of `accepted` (case-insensitive; empty = any)
+    pub fn accepts_payment_method(&self, accepted: &[String]) -> bool {
+        accepted.is_empty()
+            || self.payment_methods().iter().any(|method| {
+                accepted.iter().any(|wanted| wanted.trim().eq_ignore_ascii_case(method))
+            })
+    }
+    
+    /// Whether this order escrows tokens: its side doesn't pay fiat (default:
+    /// asks escrow, bids pay fiat; a bid with `fiat_payer = Ask` escrows and
+    /// an ask with it pays fiat)
+    pub fn escrows_tokens(&self) -> bool {
+        self.side != self.fiat_payer
+    }
+    
+    /// Whether the order may leave the book with its escrow refunded: no
+    /// payment has been marked against it, so none is in flight or settled
+    pub fn is_refundable(&self) -> bool {
+        self.payment_status == PaymentStatus::Pending
+    }
+    
+    /// Whether an escrow refund may be paid to `account`
+    pub fn accepts_refund_to(&self, account: &Pubkey) -> bool {
+        self.funding_account == Pubkey::default() || self.funding_account == *account
+    }
+    
+    /// Check if `key` may cancel this order (the owner or the cancel delegate in its `extras`)
+    pub fn is_cancellable_by(&self, key: &Pubkey, extras: &OrderExtras) -> bool {
+        *key == self.owner || (extras.cancel_delegate != Pubkey::default() && *key == extras.cancel_delegate)
+    }
+    
+    /// Check if the order has reached its expiry at `now`
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry_timestamp != 0 && now >= self.expiry_timestamp
+    }
+    
+    /// Check if a taker with `limit_price` meets this ask's reserve (always true for bids)
+    pub fn meets_reserve(&self, limit_price: u64) -> bool {
+        self.side != Side::Ask || limit_price >= self.reserve_price
+    }
+    
+    /// Check if a taker requiring `jurisdiction` (zeros = none) may fill this order
+    /// 
+    /// Segments liquidity both ways: a restricted order only pairs with takers
+    /// requiring the same code, and such takers only fill orders restricted to it.
+    pub fn matches_jurisdiction(&self, jurisdiction: [u8; 2]) -> bool {
+        self.jurisdiction == jurisdiction
+    }
+    
+    /// Check if a taker with (`attested`) or without a KYC attestation may fill this order
+    pub fn accepts_taker(&self, attested: bool) -> bool {
+        attested || !self.requires_attestation
+    }
+    
+    /// Earliest time another settlement proof is accepted (0 = no rejection yet)
+    pub fn verification_retry_at(&self) -> i64 {
+        if self.failed_verification_count == 0 {
+            return 0;
+        }
+        let shift = (self.failed_verification_count - 1).min(Self::MAX_VERIFICATION_BACKOFF_SHIFT);
+        self.last_verification_attempt
+            .saturating_add(Self::VERIFICATION_BACKOFF_SECONDS << shift)
+    }
+    
+    /// Record a settlement proof rejected at `now`, extending the backoff
+    pub fn record_failed_verification(&mut self, now: i64) {
+        self.failed_verification_count = self.failed_verification_count.saturating_add(1);
+        self.last_verification_attempt = now;
+    }
+    
+    /// Check if the order's activation delay has elapsed at `now`
+    pub fn is_active(&self, now: i64) -> bool {
+        now >= self.timestamp.saturating_add(self.activation_delay as i64)
+    }
+    
+    /// Record the buyer's payment at `now`; settlement opens `settlement_delay_seconds` later
+    pub fn mark_payment(&mut self, now: i64, settlement_delay_seconds: u32) {
+        self.payment_status = PaymentStatus::PaymentMarked;
+        self.payment_marked_timestamp = now;
+        self.settlement_timestamp = now.saturating_add(settlement_delay_seconds as i64);
+    }
+    
     /// Check if order is fully filled
     pub fn is_filled(&self) -> bool {
         self.quantity == 0
@@ -147,13 +443,152 @@ impl Order {
     pub fn fill(&mut self, fill_quantity: u64) {
         self.quantity = self.quantity.saturating_sub(fill_quantity);
     }
+    
+    /// Returns true exactly once: the first time cumulative fills reach `fill_notify_bps`
+    pub fn check_fill_threshold(&mut self) -> bool {
+        if self.fill_notify_bps == 0 || self.fill_notified || self.original_quantity == 0 {
+            return false;
+        }
+        
+        let filled = (self.original_quantity - self.quantity) as u128;
+        let threshold = self.fill_notify_bps as u128 * self.original_quantity as u128;
+        if filled * 10_000 >= threshold {
+            self.fill_notified = true;
+            return true;
+        }
+        false
+    }
+}
+
+/// An order's rarely-set options, kept in their own account so that resting
+/// orders don't all carry them in the book
+/// 
+/// Created at placement for orders that set any (`Order::has_extras`), at the
+/// PDA `[b"order_extras", order_book, owner, client_order_id]`: unlike the
+/// order ID, both are known before placement and stored on the order.
+/// Instructions that read an order's extras take this account as their first
+/// remaining account.
+#[account]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct OrderExtras {
+    /// Order the options belong to; a later order reusing the client order ID
+    /// takes the account over once this one has left the book
+    pub order_id: u128,
+    /// Client reference set at placement, never touched by matching
+    pub memo: [u8; 16],
+    /// Key allowed to cancel besides the owner (default = none)
+    pub cancel_delegate: Pubkey,
+    /// Program CPI'd with `on_settle` after `verify_settlement` pays out (default = none)
+    pub on_settle_program: Pubkey,
+    /// Token account paid `referral_bps` of the settlement (default = no referral)
+    pub referral_recipient: Pubkey,
+    /// Referral share of the settled quantity in basis points (0 = no referral)
+    pub referral_bps: u16,
+}
+
+impl OrderExtras {
+    pub const LEN: usize = 8 +  // discriminator
+                          16 + // order_id
+                          16 + // memo
+                          32 + // cancel_delegate
+                          32 + // on_settle_program
+                          32 + // referral_recipient
+                          2;   // referral_bps
+    
+    /// Extras of order `order_id` placed with `options`
+    pub fn new(order_id: u128, options: &OrderOptions) -> Self {
+        let referral = options.referral.unwrap_or_default();
+        Self {
+            order_id,
+            memo: options.memo,
+            cancel_delegate: options.cancel_delegate.unwrap_or_default(),
+            on_settle_program: options.on_settle_program.unwrap_or_default(),
+            referral_recipient: referral.recipient,
+            referral_bps: referral.bps,
+        }
+    }
+    
+    /// PDA holding the extras of `owner`'s order `client_order_id` in `order_book`
+    pub fn address(order_book: &Pubkey, owner: &Pubkey, client_order_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"order_extras", order_book.as_ref(), owner.as_ref(), &client_order_id.to_le_bytes()],
+            &crate::ID,
+        )
+    }
+    
+    /// `order`'s extras, read from `account`; an order without extras needs no
+    /// account and gets the defaults
+    pub fn load(account: Option<&AccountInfo>, order_book: &Pubkey, order: &Order) -> Result<Self> {
+        if !order.has_extras {
+            return Ok(Self::default());
+        }
+        let (address, _) = Self::address(order_book, &order.owner, order.client_order_id);
+        let Some(account) = account.filter(|account| account.key() == address) else {
+            return Err(ErrorCode::OrderExtrasMissing.into());
+        };
+        require_keys_eq!(*account.owner, crate::ID, ErrorCode::OrderExtrasMissing);
+        let extras = Self::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+        require!(extras.order_id == order.order_id, ErrorCode::OrderExtrasMissing);
+        Ok(extras)
+    }
+    
+    /// Write the extras of `order` to `account`, its PDA, creating it paid by
+    /// `payer`. An existing account is reused unless it still belongs to an
+    /// order in `order_book` (the owner reused a client order ID)
+    pub fn store<'info>(
+        &self,
+        account: &AccountInfo<'info>,
+        order_book: &OrderBook,
+        order_book_key: &Pubkey,
+        order: &Order,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+    ) -> Result<()> {
+        let (address, bump) = Self::address(order_book_key, &order.owner, order.client_order_id);
+        require_keys_eq!(account.key(), address, ErrorCode::OrderExtrasMissing);
+        
+        if account.data_is_empty() {
+            let client_order_id = order.client_order_id.to_le_bytes();
+            let bump = [bump];
+            let seeds: &[&[u8]] = &[b"order_extras", order_book_key.as_ref(), order.owner.as_ref(), &client_order_id, &bump];
+            create_account(
+                CpiContext::new_with_signer(
+                    system_program.clone(),
+                    CreateAccount {
+                        from: payer.clone(),
+                        to: account.clone(),
+                    },
+                    &[seeds],
+                ),
+                Rent::get()?.minimum_balance(Self::LEN),
+                Self::LEN as u64,
+                &crate::ID,
+            )?;
+        } else {
+            require_keys_eq!(*account.owner, crate::ID, ErrorCode::OrderExtrasMissing);
+            let previous = Self::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+            require!(order_book.find_order(previous.order_id).is_none(), ErrorCode::ClientOrderIdInUse);
+        }
+        
+        self.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])
+    }
+    
+    /// Part of `quantity` owed to the referral account on settlement (rounded
+    /// down, so any remainder stays with the seller's payout)
+    pub fn referral_amount(&self, quantity: u64) -> u64 {
+        (quantity as u128 * self.referral_bps as u128 / 10_000) as u64
+    }
 }
 
 /// Generate a unique 128-bit order ID
 /// 
 /// Format:
 /// - High 64 bits: timestamp (32 bits) | sequence (32 bits)
-/// - Low 64 bits: hash of owner pubkey
+/// - Low 64 bits: first 8 bytes of the owner pubkey (little-endian)
+/// 
+/// Stable: clients that keep `(owner, sequence_number, timestamp)` can rebuild
+/// an order's ID with this (or the `compute_order_id` view) and look it up.
+/// Only the low 32 bits of `sequence` and `timestamp` are used.
 pub fn generate_order_id(
     owner: &Pubkey,
     sequence: u64,
@@ -163,8 +598,15 @@ pub fn generate_order_id(
     let high = ((timestamp as u64 & 0xFFFFFFFF) << 32) | (sequence & 0xFFFFFFFF);
     
     // Low 64 bits: use first 8 bytes of owner pubkey
+    let low = owner_id_bits(owner);
+    
+    ((high as u128) << 64) | (low as u128)
+}
+
+/// Low 64 bits of an order ID: first 8 bytes of the owner pubkey
+fn owner_id_bits(owner: &Pubkey) -> u64 {
     let owner_bytes = owner.to_bytes();
-    let low = u64::from_le_bytes([
+    u64::from_le_bytes([
         owner_bytes[0],
         owner_bytes[1],
         owner_bytes[2],
@@ -173,22 +615,54 @@ pub fn generate_order_id(
         owner_bytes[5],
         owner_bytes[6],
         owner_bytes[7],
-    ]);
+    ])
+}
+
+/// Check an order ID follows the `generate_order_id` layout for this owner and timestamp
+pub fn is_canonical_order_id(order_id: u128, owner: &Pubkey, timestamp: i64) -> bool {
+    let high = (order_id >> 64) as u64;
+    let low = order_id as u64;
     
-    ((high as u128) << 64) | (low as u128)
+    low == owner_id_bits(owner) && (high >> 32) == (timestamp as u64 & 0xFFFFFFFF)
+}
+
+/// Check a jurisdiction is shaped like an ISO 3166-1 alpha-2 code (two uppercase letters)
+pub fn is_valid_jurisdiction(code: [u8; 2]) -> bool {
+    code.iter().all(u8::is_ascii_uppercase)
+}
+
+/// Check a price scale is a power of ten (1, 10, 100, ...)
+pub fn is_valid_price_scale(price_scale: u32) -> bool {
+    (0..)
+        .map_while(|exp| 10u32.checked_pow(exp))
+        .any(|scale| scale == price_scale)
+}
+
+/// Quote amount for `quantity` base units at `price`, where `price` is an
+/// integer at `price_scale` (so the product is divided back down by the scale)
+/// 
+/// Uses u128 intermediate math and errors with `MathOverflow` if the result
+/// doesn't fit in u64. Rounds down.
+pub fn notional(price: u64, quantity: u64, price_scale: u32) -> Result<u64> {
+    require!(price_scale > 0, ErrorCode::InvalidPriceScale);
+    let value = (price as u128) * (quantity as u128) / (price_scale as u128);
+    u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
 }
 
 /// Order queue at a specific price level
 /// This is a slab allocator-style structure
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct OrderQueue {
-    /// Orders at this price level (FIFO queue)
+    /// Orders at this price level (priority by `sequence_number`, see `QueueDiscipline`)
     pub orders: Vec<Order>,
     /// Total quantity at this price level
     pub total_quantity: u64,
 }
 
 impl OrderQueue {
+    /// Serialized size of a queue with no orders: Vec len (4) + total_quantity (8)
+    pub const EMPTY_LEN: usize = 4 + 8;
+    
     pub fn new() -> Self {
         Self {
             orders: Vec::new(),
@@ -197,9 +671,15 @@ impl OrderQueue {
     }
     
     /// Add an order to the queue
-    pub fn push(&mut self, order: Order) {
-        self.total_quantity += order.quantity;
+    /// Fails with `MathOverflow` (leaving the queue unchanged) if the level's
+    /// total quantity would exceed u64
+    pub fn push(&mut self, order: Order) -> Result<()> {
+        self.total_quantity = self
+            .total_quantity
+            .checked_add(order.quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
         self.orders.push(order);
+        Ok(())
     }
     
     /// Remove an order by order_id
@@ -213,33 +693,69 @@ impl OrderQueue {
         }
     }
     
-    /// Get the first order in the queue (oldest)
-    pub fn peek(&self) -> Option<&Order> {
-        self.orders.first()
+    /// Storage index of the order with priority under `discipline`
+    /// (lowest `sequence_number` for FIFO, highest for LIFO; storage order only breaks ties)
+    fn head_index(&self, discipline: QueueDiscipline) -> Option<usize> {
+        let orders = self.orders.iter().enumerate();
+        match discipline {
+            QueueDiscipline::Fifo => orders.min_by_key(|(_, order)| order.sequence_number),
+            QueueDiscipline::Lifo => orders.max_by_key(|(_, order)| order.sequence_number),
+        }
+        .map(|(index, _)| index)
+    }
+    
+    /// Order IDs in priority order under `discipline`, independent of storage order
+    pub fn priority_order(&self, discipline: QueueDiscipline) -> Vec<u128> {
+        let mut orders: Vec<(u64, u128)> = self.orders
+            .iter()
+            .map(|order| (order.sequence_number, order.order_id))
+            .collect();
+        orders.sort_by_key(|(sequence_number, _)| *sequence_number);
+        if discipline == QueueDiscipline::Lifo {
+            orders.reverse();
+        }
+        orders.into_iter().map(|(_, order_id)| order_id).collect()
+    }
+    
+    /// Get the order with priority (oldest under FIFO, newest under LIFO)
+    pub fn peek(&self, discipline: QueueDiscipline) -> Option<&Order> {
+        self.head_index(discipline).map(|index| &self.orders[index])
     }
     
-    /// Get mutable reference to first order
-    pub fn peek_mut(&mut self) -> Option<&mut Order> {
-        self.orders.first_mut()
+    /// Get mutable reference to the order with priority
+    pub fn peek_mut(&mut self, discipline: QueueDiscipline) -> Option<&mut Order> {
+        self.head_index(discipline).map(move |index| &mut self.orders[index])
     }
     
-    /// Remove and return the first order if it's fully filled
-    pub fn pop_if_filled(&mut self) -> Option<Order> {
-        if let Some(order) = self.orders.first() {
-            if order.is_filled() {
-                let order = self.orders.remove(0);
-                self.total_quantity -= order.quantity;
-                return Some(order);
-            }
+    /// Remove and return the priority order if it's fully filled
+    pub fn pop_if_filled(&mut self, discipline: QueueDiscipline) -> Option<Order> {
+        let index = self.head_index(discipline)?;
+        if self.orders[index].is_filled() {
+            let order = self.orders.remove(index);
+            self.total_quantity -= order.quantity;
+            return Some(order);
         }
         None
     }
     
+    /// Remove every zero-quantity order, wherever it sits in the queue
+    /// Returns how many were removed
+    pub fn purge_filled(&mut self) -> usize {
+        let before = self.orders.len();
+        self.orders.retain(|order| !order.is_filled());
+        before - self.orders.len()
+    }
+    
     /// Check if queue is empty
     pub fn is_empty(&self) -> bool {
         self.orders.is_empty()
     }
     
+    /// Serialized size of the queue
+    pub fn space(&self) -> usize {
+        Self::EMPTY_LEN + self.orders.len() * Order::LEN
+    }
+    
     /// Update total quantity after a fill
     pub fn update_quantity(&mut self, delta: i64) {
         if delta < 0 {
@@ -276,6 +792,117 @@ mod tests {
         assert!(!order.is_filled());
     }
     
+    #[test]
+    fn test_memo_kept_in_extras() {
+        let owner = Pubkey::new_unique();
+        let memo = *b"INV-2024-000017\0";
+        let new_order = || Order::new(
+            generate_order_id(&owner, 1, 1000),
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        );
+        
+        let options = OrderOptions { memo, ..Default::default() };
+        let order = new_order().with_options(&options);
+        assert!(order.has_extras);
+        assert_eq!(OrderExtras::new(order.order_id, &options).memo, memo);
+        
+        // Options stored on the order itself need no extras account
+        let order = new_order().with_options(&OrderOptions { expiry_timestamp: 2000, ..Default::default() });
+        assert!(!order.has_extras);
+    }
+    
+    #[test]
+    fn test_payment_methods_decoding() {
+        let owner = Pubkey::new_unique();
+        let order_with = |payment_method: &str| Order::new(
+            generate_order_id(&owner, 1, 1000),
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            payment_method.to_string(),
+        );
+        
+        assert_eq!(order_with("Bank Transfer").payment_methods(), vec!["Bank Transfer"]);
+        assert_eq!(order_with("PayPal, Wise").payment_methods(), vec!["PayPal", "Wise"]);
+        assert!(order_with("").payment_methods().is_empty());
+        
+        // Exactly 32 bytes fill the field with no NUL terminator
+        let full = "ABCDEFGHIJKLMNOPQRSTUVWXYZ012345";
+        assert_eq!(order_with(full).payment_methods(), vec![full]);
+        
+        let order = order_with("PayPal,Wise");
+        assert!(order.accepts_payment_method(&[]));
+        assert!(order.accepts_payment_method(&["wise".to_string()]));
+        assert!(!order.accepts_payment_method(&["Zelle".to_string()]));
+    }
+    
+    #[test]
+    fn test_cancel_delegate() {
+        let owner = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let new_order = || Order::new(
+            generate_order_id(&owner, 1, 1000),
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        );
+        
+        let options = OrderOptions {
+            cancel_delegate: Some(delegate),
+            ..Default::default()
+        };
+        let order = new_order().with_options(&options);
+        let extras = OrderExtras::new(order.order_id, &options);
+        assert!(order.is_cancellable_by(&owner, &extras));
+        assert!(order.is_cancellable_by(&delegate, &extras));
+        assert!(!order.is_cancellable_by(&Pubkey::new_unique(), &extras));
+        
+        // Without a delegate, the unset default key grants nothing
+        let order = new_order().with_options(&OrderOptions::default());
+        assert!(order.is_cancellable_by(&owner, &OrderExtras::default()));
+        assert!(!order.is_cancellable_by(&Pubkey::default(), &OrderExtras::default()));
+    }
+    
+    #[test]
+    fn test_expiry() {
+        let owner = Pubkey::new_unique();
+        let order = Order::new(
+            generate_order_id(&owner, 1, 1000),
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        );
+        assert!(!order.is_expired(i64::MAX));
+        
+        let order = order.with_options(&OrderOptions {
+            expiry_timestamp: 1060,
+            ..Default::default()
+        });
+        assert!(!order.is_expired(1059));
+        assert!(order.is_expired(1060));
+    }
+    
     #[test]
     fn test_order_fill() {
         let owner = Pubkey::new_unique();
@@ -333,8 +960,8 @@ mod tests {
             "PayPal".to_string(),
         );
         
-        queue.push(order1);
-        queue.push(order2);
+        queue.push(order1).unwrap();
+        queue.push(order2).unwrap();
         
         assert_eq!(queue.total_quantity, 150);
         assert!(!queue.is_empty());
@@ -344,6 +971,35 @@ mod tests {
         assert_eq!(queue.total_quantity, 50);
     }
     
+    #[test]
+    fn test_order_queue_push_overflow() {
+        let owner = Pubkey::new_unique();
+        let mut queue = OrderQueue::new();
+        let order_of = |seq: u64, quantity: u64| Order::new(
+            generate_order_id(&owner, seq, 1000),
+            owner,
+            quantity,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            seq,
+            "PayPal".to_string(),
+        );
+        
+        queue.push(order_of(1, u64::MAX - 10)).unwrap();
+        assert_eq!(
+            queue.push(order_of(2, 11)).unwrap_err(),
+            ErrorCode::MathOverflow.into()
+        );
+        
+        // The failed push leaves the level untouched
+        assert_eq!(queue.total_quantity, u64::MAX - 10);
+        assert_eq!(queue.orders.len(), 1);
+        queue.push(order_of(3, 10)).unwrap();
+        assert_eq!(queue.total_quantity, u64::MAX);
+    }
+    
     #[test]
     fn test_unique_order_ids() {
         let owner1 = Pubkey::new_unique();
@@ -357,4 +1013,62 @@ mod tests {
         assert_ne!(id1, id3); // Different owner
         assert_ne!(id2, id3);
     }
+    
+    #[test]
+    fn test_notional_uses_price_scale() {
+        // 2.5 quote per base at scale 1e6, 4 base units
+        assert_eq!(notional(2_500_000, 4, 1_000_000).unwrap(), 10);
+        assert_eq!(notional(2_500_000, 4, 1).unwrap(), 10_000_000);
+        
+        // Rounds down
+        assert_eq!(notional(1_500_000, 1, 1_000_000).unwrap(), 1);
+        
+        // Overflow past u64 is an error, not a wrap
+        assert_eq!(notional(u64::MAX, 1, 1).unwrap(), u64::MAX);
+        assert_eq!(notional(u64::MAX, 2, 1).unwrap_err(), ErrorCode::MathOverflow.into());
+        assert_eq!(notional(u64::MAX, 2, 10).unwrap(), (u64::MAX as u128 * 2 / 10) as u64);
+    }
+    
+    #[test]
+    fn test_price_scale_must_be_power_of_ten() {
+        for scale in [1, 10, 1_000, 1_000_000, 1_000_000_000] {
+            assert!(is_valid_price_scale(scale));
+        }
+        for scale in [0, 2, 20, 1_000_001, 1_500_000] {
+            assert!(!is_valid_price_scale(scale));
+        }
+    }
+    
+    #[test]
+    fn test_canonical_order_id() {
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 7, 1000);
+        
+        assert!(is_canonical_order_id(order_id, &owner, 1000));
+        assert!(!is_canonical_order_id(order_id, &owner, 1001));
+        assert!(!is_canonical_order_id(order_id, &Pubkey::new_unique(), 1000));
+        assert!(!is_canonical_order_id(u128::MAX, &owner, 1000));
+    }
+    
+    #[test]
+    fn test_referral_split() {
+        let owner = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let referral = ReferralSplit { recipient, bps: 50 };
+        assert!(referral.is_valid());
+        assert!(!ReferralSplit { bps: 0, ..referral }.is_valid());
+        assert!(!ReferralSplit { bps: Order::MAX_REFERRAL_BPS + 1, ..referral }.is_valid());
+        assert!(!ReferralSplit { recipient: Pubkey::default(), ..referral }.is_valid());
+        
+        let order_id = generate_order_id(&owner, 0, 1000);
+        assert_eq!(OrderExtras::default().referral_amount(1_000_000), 0);
+        
+        let extras = OrderExtras::new(order_id, &OrderOptions { referral: Some(referral), ..Default::default() });
+        assert_eq!(extras.referral_recipient, recipient);
+        assert_eq!(extras.referral_amount(1_000_000), 5_000);
+        
+        // Rounds down; the remainder stays with the seller
+        assert_eq!(extras.referral_amount(199), 0);
+        assert_eq!(extras.referral_amount(201), 1);
+    }
 }
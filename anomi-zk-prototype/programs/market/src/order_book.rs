@@ -1,7 +1,274 @@
 use anchor_lang::prelude::*;
-use crate::critbit::CritBitTree;
+use crate::critbit::{CritBitNode, CritBitTree};
 use crate::error::ErrorCode;
-use crate::order::{Order, OrderQueue, OrderType, Side};
+use crate::events::BookCrossed;
+use crate::matching::{match_levels, MatchRequest, SkipReason, StopReason};
+use crate::order::{generate_order_id, ImportedOrder, Order, OrderExtras, OrderQueue, OrderType, PaymentStatus, QueueDiscipline, Side};
+
+/// A single fill produced by matching
+/// 
+/// Returned from `match_order` (and therefore through return data to CPI callers),
+/// so sibling programs can consume fills without re-deriving the tuple layout.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fill {
+    /// Market-wide trade ID, assigned by the `match_order` instruction (0 until then)
+    pub trade_id: u64,
+    /// Price the fill executed at (the maker's price level)
+    pub price: u64,
+    /// Quantity filled
+    pub quantity: u64,
+    /// Maker order that was filled
+    pub maker_order_id: u128,
+    /// Owner of the maker order
+    pub maker_owner: Pubkey,
+    /// Maker quantity left after this fill
+    pub maker_remaining: u64,
+    /// This fill pushed the maker across its `fill_notify_bps` threshold
+    pub threshold_reached: bool,
+}
+
+impl Fill {
+    pub const LEN: usize = 8 + 8 + 8 + 16 + 32 + 8 + 1;
+}
+
+/// Aggregated state of a single price level
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price: u64,
+    /// Sum of the live (remaining) quantity of every order at this price
+    pub quantity: u64,
+    pub order_count: u32,
+}
+
+/// A single resting order at a price level, returned by `get_level_orders`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LevelOrder {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    /// Remaining quantity
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+/// Full view of both sides of the book
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BookSnapshot {
+    /// Bid levels, best (highest) first
+    pub bids: Vec<BookLevel>,
+    /// Ask levels, best (lowest) first
+    pub asks: Vec<BookLevel>,
+    pub total_orders: u64,
+    /// Slot of the book's last mutation (see `OrderBook::last_update_slot`)
+    pub last_update_slot: u64,
+}
+
+/// Lifetime maker volume credited to a single owner
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MakerVolume {
+    pub owner: Pubkey,
+    /// Total quantity filled against this owner's resting orders
+    pub volume: u64,
+}
+
+impl MakerVolume {
+    pub const LEN: usize = 32 + 8;
+}
+
+/// Taker cost preview returned by `quote_for_base`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BaseQuote {
+    /// Base quantity that would fill
+    pub base_filled: u64,
+    /// Quote value of the fills at their execution prices
+    pub gross_notional: u64,
+    /// Taker fee on `gross_notional` (rounded down)
+    pub fee: u64,
+    /// Quote the taker pays (bids: gross + fee) or receives (asks: gross - fee)
+    pub net: u64,
+    /// Volume-weighted average fill price at the market's price scale (0 if nothing fills)
+    pub vwap: u64,
+}
+
+/// Compute-unit estimate returned by `estimate_match_cost`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchCostEstimate {
+    /// Distinct price levels the match would fill at
+    pub levels: u32,
+    /// Maker fills the match would produce
+    pub fills: u32,
+    /// `OrderBook::MATCH_BASE_COMPUTE_UNITS` plus the per-level and per-fill
+    /// costs, capped at `OrderBook::MAX_COMPUTE_UNITS`
+    pub compute_units: u32,
+}
+
+/// Best prices at a slot, recorded by `OrderBook::touch` when they move
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceCheckpoint {
+    pub slot: u64,
+    pub best_bid: u64,
+    /// `u64::MAX` while the ask side is empty
+    pub best_ask: u64,
+}
+
+impl PriceCheckpoint {
+    pub const LEN: usize = 8 + 8 + 8;
+}
+
+/// Top-of-book statistics
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarketStats {
+    pub best_bid: u64,
+    pub best_ask: u64,
+    pub spread: Option<u64>,
+    pub spread_bps: Option<u64>,
+    pub mid_price: Option<u64>,
+    pub total_orders: u64,
+    pub last_update_slot: u64,
+}
+
+/// Lifetime activity counters, returned by `get_lifetime_stats`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LifetimeStats {
+    /// Total quantity filled
+    pub volume: u64,
+    /// Number of fills
+    pub fills: u64,
+    /// Orders placed
+    pub placed: u64,
+    /// Orders cancelled (including expired orders swept)
+    pub cancelled: u64,
+}
+
+/// Current state of a single resting order, returned by `get_order_status`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderStatus {
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    pub original_quantity: u64,
+    pub payment_status: PaymentStatus,
+    pub memo: [u8; 16],
+}
+
+impl OrderStatus {
+    pub fn new(order: &Order, extras: &OrderExtras) -> Self {
+        Self {
+            order_id: order.order_id,
+            owner: order.owner,
+            side: order.side,
+            price: order.price,
+            quantity: order.quantity,
+            original_quantity: order.original_quantity,
+            payment_status: order.payment_status,
+            memo: extras.memo,
+        }
+    }
+}
+
+/// Node occupancy of one side's CritBit tree
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeDiagnostics {
+    pub leaf_count: u32,
+    pub used_nodes: u32,
+    pub free_nodes: u32,
+}
+
+impl TreeDiagnostics {
+    fn of(tree: &CritBitTree) -> Self {
+        Self {
+            leaf_count: tree.leaf_count,
+            used_nodes: tree.used_node_count() as u32,
+            free_nodes: tree.free_node_count() as u32,
+        }
+    }
+}
+
+/// Price level headroom of one side's CritBit tree
+/// 
+/// Every level past the first costs a leaf and an inner node, so a pool of
+/// `max_nodes` holds at most `(max_nodes + 1) / 2` levels: fewer than
+/// `OrderBook::MAX_PRICE_LEVELS`. Clients should warn from `free_nodes`,
+/// not from `used_levels` against `MAX_PRICE_LEVELS`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeCapacity {
+    pub used_levels: u32,
+    /// Most levels the node pool can hold
+    pub max_levels: u32,
+    pub free_nodes: u32,
+    pub max_nodes: u32,
+}
+
+impl TreeCapacity {
+    fn of(tree: &CritBitTree) -> Self {
+        let max_nodes = tree.nodes.len() as u32;
+        Self {
+            used_levels: tree.leaf_count,
+            max_levels: max_nodes.div_ceil(2),
+            free_nodes: tree.free_node_count() as u32,
+            max_nodes,
+        }
+    }
+}
+
+/// Level capacity for both sides, returned by `get_book_capacity`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookCapacity {
+    pub bids: TreeCapacity,
+    pub asks: TreeCapacity,
+    /// More orders the account has room for at existing price levels (a new
+    /// level may also need `OrderQueue::EMPTY_LEN` bytes); usually runs out
+    /// before either tree does
+    pub free_orders: u32,
+}
+
+/// Tree occupancy for both sides, returned by `get_book_diagnostics`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookDiagnostics {
+    pub bids: TreeDiagnostics,
+    pub asks: TreeDiagnostics,
+}
+
+/// First mismatch between a cached book counter and the value recomputed from orders
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityDiscrepancy {
+    /// A queue's `total_quantity` differs from the sum of its orders' quantities
+    QueueQuantity { queue_index: u32, cached: u64, actual: u64 },
+    /// `total_orders` differs from the number of orders across all queues
+    TotalOrders { cached: u64, actual: u64 },
+    /// A fully filled order is still resting in a queue
+    FilledOrderResting { order_id: u128 },
+}
+
+/// Result of `verify_integrity`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookIntegrity {
+    pub ok: bool,
+    pub discrepancy: Option<IntegrityDiscrepancy>,
+}
+
+/// Escrow ledger compared against the escrow vault's balance, returned by
+/// `verify_escrow_invariant`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EscrowInvariant {
+    /// Tokens locked by the market's books (`Market::escrow_locked`)
+    pub locked: u64,
+    /// Actual escrow vault balance
+    pub vault_balance: u64,
+    /// Whether `locked == vault_balance`
+    pub holds: bool,
+}
+
+impl EscrowInvariant {
+    pub fn new(locked: u64, vault_balance: u64) -> Self {
+        Self {
+            locked,
+            vault_balance,
+            holds: locked == vault_balance,
+        }
+    }
+}
 
 /// Order book with CritBit tree for efficient price-level management
 #[account]
@@ -32,6 +299,29 @@ pub struct OrderBook {
     pub best_bid: u64,
     /// Best ask price (cached for quick access)
     pub best_ask: u64,
+    
+    /// Per-owner maker volume (substrate for rebates/rewards); only the first
+    /// `MAX_TRACKED_MAKERS` makers to fill are tracked
+    pub maker_volumes: Vec<MakerVolume>,
+    
+    /// Slot of the last insert/remove/match/settle, for client cache invalidation
+    pub last_update_slot: u64,
+    
+    /// Tokens this book holds in the escrow vault for unsettled asks: escrowed
+    /// ask quantity minus refunds and payouts. A fill moves no tokens, so it
+    /// leaves this alone even though it reduces the ask's resting quantity
+    pub escrow_locked: u64,
+    
+    /// Lifetime counters (see `LifetimeStats`)
+    pub lifetime_volume: u64,
+    pub lifetime_fills: u64,
+    pub lifetime_placed: u64,
+    pub lifetime_cancelled: u64,
+    
+    /// Best-price history, oldest first, at most `MAX_PRICE_CHECKPOINTS` entries
+    pub price_checkpoints: Vec<PriceCheckpoint>,
+    /// Smallest best-price move (basis points) that records a checkpoint (0 = any move)
+    pub checkpoint_threshold_bps: u16,
 }
 
 impl OrderBook {
@@ -40,21 +330,91 @@ impl OrderBook {
     /// This still supports 50 different price levels, much better than Phase 2A's 10 total orders
     pub const MAX_PRICE_LEVELS: usize = 50;
     
-    /// Space needed for OrderBook initialization (manually calculated for serialized size)
-    /// - market: 32
-    /// - base_mint: 32
-    /// - quote_mint: 32
-    /// - bids: CritBitTree (4 + 4 + 4 + 4 + Vec<CritBitNode>: 4 + 50*26) = 1316
-    /// - asks: CritBitTree (4 + 4 + 4 + 4 + Vec<CritBitNode>: 4 + 50*26) = 1316
-    /// - order_queues: Vec (4 + space for ~35 OrderQueues with 1 Order each)
-    ///   Each OrderQueue: 4 (Vec len) + Order::LEN (139) + total_qty (8) = 151 bytes
-    ///   35 queues × 151 = 5285 bytes
+    /// Best-price checkpoints kept; the oldest is dropped when full
+    pub const MAX_PRICE_CHECKPOINTS: usize = 8;
+    
+    /// Maximum number of distinct makers tracked in `maker_volumes`
+    pub const MAX_TRACKED_MAKERS: usize = 16;
+    
+    /// Maximum order IDs returned by a listing view (4 + 60 × 16 bytes fits in return data)
+    pub const MAX_LISTED_ORDERS: usize = 60;
+    
+    /// Maximum orders returned for one price level (4 + 15 × 64 bytes fits in return data)
+    pub const MAX_LEVEL_ORDERS: usize = 15;
+    
+    /// Most orders one `cancel_orders` call can name (25 bytes each in the
+    /// transaction, 17 per result in return data)
+    pub const MAX_CANCEL_BATCH: usize = 20;
+    
+    /// Fixed compute cost of a `match_order` (see `estimate_match_cost`)
+    pub const MATCH_BASE_COMPUTE_UNITS: u32 = 40_000;
+    
+    /// Compute cost of each price level a match touches
+    pub const MATCH_LEVEL_COMPUTE_UNITS: u32 = 8_000;
+    
+    /// Compute cost of each maker fill
+    pub const MATCH_FILL_COMPUTE_UNITS: u32 = 12_000;
+    
+    /// Most compute units a transaction may request
+    pub const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+    
+    /// Most orders one `import_orders` call can carry (about 120 bytes each in the transaction)
+    pub const MAX_IMPORT_BATCH: usize = 6;
+    
+    /// Maximum fills `match_order` returns inline (4 + 1 + 4 + 12 × 81 bytes fits in return data);
+    /// larger matches go to the taker's `FillLog`
+    pub const MAX_RETURNED_FILLS: usize = 12;
+    
+    /// Price step used to reprice a crossing `PostOnlyReprice` order. There is no
+    /// tick size, so this is one unit at the market's price scale
+    pub const REPRICE_TICK: u64 = 1;
+    
+    /// Compact snapshot wire format version (see `serialize_snapshot`)
+    pub const COMPACT_SNAPSHOT_VERSION: u8 = 1;
+    
+    /// Bytes per level in the compact snapshot: side (1) + price (8) + quantity (8) + order_count (4)
+    pub const COMPACT_LEVEL_LEN: usize = 21;
+    
+    /// Best levels per side in the compact snapshot (4 + 3 + 48 × 21 = 1015 bytes fits in return data)
+    pub const MAX_COMPACT_LEVELS_PER_SIDE: usize = 24;
+    
+    /// Largest account a program can create through a CPI (10 KB)
+    pub const MAX_ACCOUNT_SPACE: usize = 10_240;
+    
+    /// Serialized size of every field but the order queues' contents:
+    /// - market, base_mint, quote_mint: 3 × 32
+    /// - bids, asks: 2 × CritBitTree (root, leaf_count, free_list, Vec len + MAX_PRICE_LEVELS nodes)
+    /// - order_queues Vec len: 4
     /// - next_queue_index: 4
-    /// - total_orders: 8
-    /// - best_bid: 8
-    /// - best_ask: 8
-    /// Total: 8044 bytes (under 10KB limit, room for ~35 price levels)
-    pub const INIT_SPACE: usize = 32 + 32 + 32 + 1316 + 1316 + 4 + 5280 + 4 + 8 + 8 + 8;
+    /// - total_orders, best_bid, best_ask: 3 × 8
+    /// - maker_volumes: 4 + MAX_TRACKED_MAKERS × MakerVolume::LEN
+    /// - last_update_slot, escrow_locked: 2 × 8
+    /// - lifetime counters: 4 × 8
+    /// - price_checkpoints: 4 + MAX_PRICE_CHECKPOINTS × PriceCheckpoint::LEN
+    /// - checkpoint_threshold_bps: 2
+    pub const FIXED_SPACE: usize = 3 * 32
+        + 2 * (4 + 4 + 4 + 4 + Self::MAX_PRICE_LEVELS * CritBitNode::LEN)
+        + 4 + 4 + 3 * 8
+        + 4 + Self::MAX_TRACKED_MAKERS * MakerVolume::LEN
+        + 2 * 8 + 4 * 8
+        + 4 + Self::MAX_PRICE_CHECKPOINTS * PriceCheckpoint::LEN
+        + 2;
+    
+    /// Space needed for OrderBook initialization: the largest account after
+    /// the 8-byte discriminator
+    pub const INIT_SPACE: usize = Self::MAX_ACCOUNT_SPACE - 8;
+    
+    /// Bytes left for the order queues' contents. Each queue takes
+    /// `OrderQueue::EMPTY_LEN` plus `Order::LEN` per order, so this budget, not
+    /// the price level trees, limits how many orders the book holds
+    /// (`insert_order` rejects with `OrderBookFull` once it is spent)
+    pub const ORDER_QUEUES_SPACE: usize = Self::INIT_SPACE - Self::FIXED_SPACE;
+    
+    /// Orders the book always has room for, even with each at its own price level
+    pub const MAX_RESTING_ORDERS: usize = Self::ORDER_QUEUES_SPACE / (OrderQueue::EMPTY_LEN + Order::LEN);
+    
+    /// Most orders the book can ever hold, all at one price level
+    pub const MAX_ORDERS: usize = (Self::ORDER_QUEUES_SPACE - OrderQueue::EMPTY_LEN) / Order::LEN;
     
     /// Initialize a new order book
     pub fn new(market: Pubkey, base_mint: Pubkey, quote_mint: Pubkey) -> Self {
@@ -70,34 +430,87 @@ impl OrderBook {
             total_orders: 0,
             best_bid: 0,
             best_ask: u64::MAX,
+            maker_volumes: Vec::new(),
+            last_update_slot: 0,
+            escrow_locked: 0,
+            lifetime_volume: 0,
+            lifetime_fills: 0,
+            lifetime_placed: 0,
+            lifetime_cancelled: 0,
+            price_checkpoints: Vec::new(),
+            checkpoint_threshold_bps: 0,
+        }
+    }
+    
+    /// Stamp a change to the book at `slot`, checkpointing the best prices if
+    /// either moved more than `checkpoint_threshold_bps` since the last checkpoint
+    /// (a side emptying or filling always counts)
+    pub fn touch(&mut self, slot: u64) {
+        self.last_update_slot = slot;
+        
+        let (last_bid, last_ask) = self
+            .price_checkpoints
+            .last()
+            .map(|checkpoint| (checkpoint.best_bid, checkpoint.best_ask))
+            .unwrap_or((0, u64::MAX));
+        let moved = |previous: u64, current: u64| {
+            previous != current
+                && (previous == 0
+                    || previous == u64::MAX
+                    || current == 0
+                    || current == u64::MAX
+                    || Self::slippage_bps(previous, current) > self.checkpoint_threshold_bps as u64)
+        };
+        if !moved(last_bid, self.best_bid) && !moved(last_ask, self.best_ask) {
+            return;
+        }
+        
+        if self.price_checkpoints.len() == Self::MAX_PRICE_CHECKPOINTS {
+            self.price_checkpoints.remove(0);
         }
+        self.price_checkpoints.push(PriceCheckpoint {
+            slot,
+            best_bid: self.best_bid,
+            best_ask: self.best_ask,
+        });
     }
     
     /// Insert an order into the book
     pub fn insert_order(&mut self, order: Order) -> Result<()> {
+        require!(self.has_space_for(order.side, order.price), ErrorCode::OrderBookFull);
+        
         let tree = match order.side {
             Side::Bid => &mut self.bids,
             Side::Ask => &mut self.asks,
         };
         
         // Check if price level already exists
+        // A price always maps to the single queue referenced by its tree leaf
         if let Some(queue_index) = tree.find(order.price) {
             // Add to existing queue
-            self.order_queues[queue_index as usize].push(order);
+            self.order_queues[queue_index as usize].push(order)?;
         } else {
-            // Create new price level
-            require!(
-                self.next_queue_index < Self::MAX_PRICE_LEVELS as u32,
-                ErrorCode::OrderBookFull
-            );
-            
-            let queue_index = self.next_queue_index;
-            self.next_queue_index += 1;
+            // Create new price level, reusing a drained queue slot if one exists
+            // (a queue is only empty once its price level has left the tree)
+            let queue_index = match self.order_queues.iter().position(|q| q.is_empty()) {
+                Some(index) => {
+                    self.order_queues[index] = OrderQueue::new();
+                    index as u32
+                }
+                None => {
+                    require!(
+                        self.next_queue_index < Self::MAX_PRICE_LEVELS as u32,
+                        ErrorCode::OrderBookFull
+                    );
+                    
+                    let index = self.next_queue_index;
+                    self.next_queue_index += 1;
+                    self.order_queues.push(OrderQueue::new());
+                    index
+                }
+            };
             
-            // Create new queue and add order
-            let mut new_queue = OrderQueue::new();
-            new_queue.push(order);
-            self.order_queues.push(new_queue);
+            self.order_queues[queue_index as usize].push(order)?;
             
             // Insert price level into CritBit tree
             tree.insert(order.price, queue_index)?;
@@ -112,7 +525,191 @@ impl OrderBook {
         Ok(())
     }
     
+    /// Bytes the order queues take up, out of `ORDER_QUEUES_SPACE`
+    pub fn order_queues_space(&self) -> usize {
+        self.order_queues.iter().map(OrderQueue::space).sum()
+    }
+    
+    /// Whether the account has room for one more order at `price`: a new
+    /// price level that can't reuse a drained queue also needs a queue
+    fn has_space_for(&self, side: Side, price: u64) -> bool {
+        let tree = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let new_queue = tree.find(price).is_none() && !self.order_queues.iter().any(|q| q.is_empty());
+        let needed = Order::LEN + if new_queue { OrderQueue::EMPTY_LEN } else { 0 };
+        self.order_queues_space() + needed <= Self::ORDER_QUEUES_SPACE
+    }
+    
+    /// Price a `PostOnlyReprice` order should rest at: unchanged unless it would
+    /// cross, otherwise one tick inside the opposing best (below the best ask for
+    /// bids, above the best bid for asks). Other order types keep their price.
+    pub fn post_only_price(&self, side: Side, price: u64, order_type: OrderType) -> Result<u64> {
+        if order_type != OrderType::PostOnlyReprice || !self.crosses(side, price) {
+            return Ok(price);
+        }
+        
+        let repriced = match side {
+            Side::Bid => self.best_ask.checked_sub(Self::REPRICE_TICK),
+            Side::Ask => self.best_bid.checked_add(Self::REPRICE_TICK),
+        };
+        // A bid can't be repriced below the lowest valid price
+        repriced
+            .filter(|price| *price > 0)
+            .ok_or(ErrorCode::PostOnlyWouldMatch.into())
+    }
+    
+    /// Check if an order at `price` would match the opposite side's best
+    fn crosses(&self, side: Side, price: u64) -> bool {
+        match side {
+            Side::Bid => self.best_ask <= price,
+            Side::Ask => self.best_bid > 0 && self.best_bid >= price,
+        }
+    }
+    
+    /// Reject a resting order that would leave the spread under `min_spread_bps`
+    /// (0 = no floor)
+    /// 
+    /// Orders crossing the opposite side are let through to match, and orders
+    /// behind their side's best leave the spread unchanged; only an order that
+    /// tightens it is held to the floor. The spread is measured in basis points
+    /// of the mid, as in `spread_bps`.
+    pub fn check_min_spread(&self, side: Side, price: u64, min_spread_bps: u16) -> Result<()> {
+        if min_spread_bps == 0 || self.crosses(side, price) {
+            return Ok(());
+        }
+        
+        let (best_bid, best_ask) = match side {
+            Side::Bid if price > self.best_bid => (price, self.best_ask),
+            Side::Ask if price < self.best_ask => (self.best_bid, price),
+            _ => return Ok(()),
+        };
+        if best_bid == 0 || best_ask == u64::MAX {
+            return Ok(());
+        }
+        
+        let spread = (best_ask - best_bid) as u128;
+        let mid_price = (best_bid as u128 + best_ask as u128) / 2;
+        require!(
+            spread * 10_000 >= min_spread_bps as u128 * mid_price,
+            ErrorCode::SpreadTooTight
+        );
+        Ok(())
+    }
+    
+    /// Run the placement checks for an order without mutating the book
+    /// 
+    /// Shared by `place_limit_order_v2` and the read-only `validate_order`, so a
+    /// dry run rejects exactly what a real placement would.
+    pub fn check_placement(
+        &self,
+        side: Side,
+        price: u64,
+        quantity: u64,
+        order_type: OrderType,
+    ) -> Result<()> {
+        require!(quantity > 0, ErrorCode::InvalidAmount);
+        require!(price > 0, ErrorCode::InvalidPrice);
+        
+        // Post-only orders must rest, never cross the opposite side
+        // (`PostOnlyReprice` is repriced by `post_only_price` before this check)
+        if matches!(order_type, OrderType::PostOnly | OrderType::PostOnlyReprice) {
+            require!(!self.crosses(side, price), ErrorCode::PostOnlyWouldMatch);
+        }
+        
+        require!(self.has_space_for(side, price), ErrorCode::OrderBookFull);
+        
+        // A new price level needs a queue slot and room in the tree
+        let tree = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        if tree.find(price).is_none() {
+            let has_queue_slot = self.order_queues.iter().any(|q| q.is_empty())
+                || self.next_queue_index < Self::MAX_PRICE_LEVELS as u32;
+            require!(
+                has_queue_slot && tree.can_insert(price),
+                ErrorCode::OrderBookFull
+            );
+        }
+        
+        Ok(())
+    }
+    
+    /// Bulk-insert resting orders migrated from another venue
+    /// 
+    /// Only allowed while nothing has filled or been cancelled on this book.
+    /// Orders are inserted oldest first and numbered from `next_sequence`, so
+    /// time priority follows their original timestamps; every timestamp must
+    /// be no older than the orders already resting (earlier batches), and no
+    /// import may cross the opposite side. Returns the inserted orders in
+    /// sequence order.
+    pub fn import_orders(
+        &mut self,
+        imported: &[ImportedOrder],
+        next_sequence: u64,
+        now: i64,
+    ) -> Result<Vec<Order>> {
+        require!(
+            !imported.is_empty() && imported.len() <= Self::MAX_IMPORT_BATCH,
+            ErrorCode::InvalidImport
+        );
+        require!(
+            self.lifetime_fills == 0 && self.lifetime_cancelled == 0,
+            ErrorCode::InvalidImport
+        );
+        
+        let newest_resting = self
+            .order_queues
+            .iter()
+            .flat_map(|queue| queue.orders.iter())
+            .map(|order| order.timestamp)
+            .max()
+            .unwrap_or(0);
+        
+        let mut by_age: Vec<&ImportedOrder> = imported.iter().collect();
+        by_age.sort_by_key(|entry| entry.timestamp);
+        
+        let mut orders = Vec::with_capacity(by_age.len());
+        for (offset, entry) in by_age.into_iter().enumerate() {
+            require!(
+                entry.timestamp > 0 && entry.timestamp <= now && entry.timestamp >= newest_resting,
+                ErrorCode::InvalidImport
+            );
+            require!(
+                entry.settlement_destination != Pubkey::default(),
+                ErrorCode::InvalidSettlementDestination
+            );
+            self.check_placement(entry.side, entry.price, entry.quantity, OrderType::Limit)?;
+            require!(!self.crosses(entry.side, entry.price), ErrorCode::InvalidImport);
+            
+            let sequence_number = next_sequence + offset as u64;
+            let mut order = Order::new(
+                generate_order_id(&entry.owner, sequence_number, entry.timestamp),
+                entry.owner,
+                entry.quantity,
+                entry.price,
+                entry.timestamp,
+                OrderType::Limit,
+                entry.side,
+                entry.client_order_id,
+                entry.payment_method.clone(),
+            );
+            order.settlement_destination = entry.settlement_destination;
+            order.sequence_number = sequence_number;
+            
+            self.insert_order(order)?;
+            self.record_placed()?;
+            orders.push(order);
+        }
+        
+        Ok(orders)
+    }
+    
     /// Remove an order from the book
+    /// Fails with `PriceLevelNotFound` if `side` has no level at `price`, or
+    /// `OrderNotFoundInQueue` if the level exists but doesn't hold `order_id`
     pub fn remove_order(&mut self, order_id: u128, side: Side, price: u64) -> Result<Order> {
         let tree = match side {
             Side::Bid => &mut self.bids,
@@ -120,13 +717,16 @@ impl OrderBook {
         };
         
         // Find the price level
-        let queue_index = tree.find(price)
-            .ok_or(ErrorCode::OrderNotFound)?;
+        let Some(queue_index) = tree.find(price) else {
+            msg!("Order {} not removed: no {:?} level at price {}", order_id, side, price);
+            return Err(ErrorCode::PriceLevelNotFound.into());
+        };
         
         // Remove from queue
-        let order = self.order_queues[queue_index as usize]
-            .remove(order_id)
-            .ok_or(ErrorCode::OrderNotFound)?;
+        let Some(order) = self.order_queues[queue_index as usize].remove(order_id) else {
+            msg!("Order {} not removed: not in the {:?} queue at price {}", order_id, side, price);
+            return Err(ErrorCode::OrderNotFoundInQueue.into());
+        };
         
         // If queue is now empty, remove price level from tree
         if self.order_queues[queue_index as usize].is_empty() {
@@ -142,8 +742,8 @@ impl OrderBook {
         Ok(order)
     }
     
-    /// Get the best order from a side (lowest ask or highest bid)
-    pub fn get_best_order(&self, side: Side) -> Option<&Order> {
+    /// Get the best order from a side (lowest ask or highest bid, then `discipline`)
+    pub fn get_best_order(&self, side: Side, discipline: QueueDiscipline) -> Option<&Order> {
         let tree = match side {
             Side::Bid => &self.bids,
             Side::Ask => &self.asks,
@@ -154,11 +754,11 @@ impl OrderBook {
             Side::Ask => tree.min()?, // Lowest ask
         };
         
-        self.order_queues[queue_index as usize].peek()
+        self.order_queues[queue_index as usize].peek(discipline)
     }
     
     /// Get mutable reference to best order
-    pub fn get_best_order_mut(&mut self, side: Side) -> Option<&mut Order> {
+    pub fn get_best_order_mut(&mut self, side: Side, discipline: QueueDiscipline) -> Option<&mut Order> {
         let tree = match side {
             Side::Bid => &self.bids,
             Side::Ask => &self.asks,
@@ -169,35 +769,232 @@ impl OrderBook {
             Side::Ask => tree.min()?, // Lowest ask
         };
         
-        self.order_queues[queue_index as usize].peek_mut()
+        self.order_queues[queue_index as usize].peek_mut(discipline)
+    }
+    
+    /// Find a resting order by ID across all price levels
+    pub fn find_order(&self, order_id: u128) -> Option<&Order> {
+        self.order_queues
+            .iter()
+            .flat_map(|queue| queue.orders.iter())
+            .find(|order| order.order_id == order_id)
+    }
+    
+    /// Find a resting order by ID across all price levels
+    pub fn find_order_mut(&mut self, order_id: u128) -> Option<&mut Order> {
+        self.order_queues
+            .iter_mut()
+            .flat_map(|queue| queue.orders.iter_mut())
+            .find(|order| order.order_id == order_id)
+    }
+    
+    /// Rebuild the cached best prices from the trees, e.g. after an admin
+    /// migration touched them directly; returns whether the cache was stale
+    pub fn refresh_best_prices(&mut self) -> Result<bool> {
+        let cached = (self.best_bid, self.best_ask);
+        self.update_best_prices()?;
+        Ok(cached != (self.best_bid, self.best_ask))
     }
     
     /// Update cached best prices
     fn update_best_prices(&mut self) -> Result<()> {
         self.best_bid = self.bids.max().map(|(price, _)| price).unwrap_or(0);
         self.best_ask = self.asks.min().map(|(price, _)| price).unwrap_or(u64::MAX);
+        
+        // Surface a crossed book without failing, so the book stays usable
+        if let Some(warning) = self.crossed_warning() {
+            msg!("Warning: book crossed - best_bid: {}, best_ask: {}", warning.best_bid, warning.best_ask);
+            emit!(warning);
+        }
         Ok(())
     }
     
-    /// Get order book depth for a side
-    pub fn get_depth(&self, side: Side, _levels: usize) -> Vec<(u64, u64)> {
+    /// Warning event if both sides are non-empty and best bid >= best ask
+    pub fn crossed_warning(&self) -> Option<BookCrossed> {
+        let both_sides = self.best_bid > 0 && self.best_ask != u64::MAX;
+        if both_sides && self.best_bid >= self.best_ask {
+            Some(BookCrossed {
+                best_bid: self.best_bid,
+                best_ask: self.best_ask,
+            })
+        } else {
+            None
+        }
+    }
+    
+    /// Top `levels` price levels on a side as (price, quantity), best price first
+    /// 
+    /// Quantity is the live remaining quantity of the level's orders, so
+    /// partially filled makers count only what is left.
+    /// 
+    /// Order comes from the tree's price-sorted leaves (prices are unique keys),
+    /// never from storage or queue layout, so the same book always yields the
+    /// same depth regardless of insertion history.
+    pub fn get_depth(&self, side: Side, levels: usize) -> Vec<(u64, u64)> {
+        let tree = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        
+        let mut leaves = tree.leaves();
+        if side == Side::Bid {
+            leaves.reverse();
+        }
+        leaves
+            .into_iter()
+            .take(levels)
+            .map(|(price, queue_index)| {
+                let queue = &self.order_queues[queue_index as usize];
+                (price, queue.orders.iter().map(|o| o.quantity).sum())
+            })
+            .collect()
+    }
+    
+    /// Get every price level on a side, best price first
+    pub fn levels(&self, side: Side) -> Vec<BookLevel> {
+        let tree = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        
+        let mut levels: Vec<BookLevel> = tree
+            .leaves()
+            .into_iter()
+            .map(|(price, queue_index)| {
+                let queue = &self.order_queues[queue_index as usize];
+                BookLevel {
+                    price,
+                    quantity: queue.orders.iter().map(|o| o.quantity).sum(),
+                    order_count: queue.orders.len() as u32,
+                }
+            })
+            .collect();
+        
+        if side == Side::Bid {
+            levels.reverse();
+        }
+        levels
+    }
+    
+    /// Orders resting at `price` on `side` in time priority, or empty if the level doesn't exist
+    pub fn level_orders(&self, side: Side, price: u64) -> Vec<LevelOrder> {
         let tree = match side {
             Side::Bid => &self.bids,
             Side::Ask => &self.asks,
         };
+        let Some(queue_index) = tree.find(price) else {
+            return Vec::new();
+        };
+        
+        let mut orders: Vec<&Order> = self.order_queues[queue_index as usize].orders.iter().collect();
+        orders.sort_by_key(|order| order.sequence_number);
+        orders
+            .into_iter()
+            .take(Self::MAX_LEVEL_ORDERS)
+            .map(|order| LevelOrder {
+                order_id: order.order_id,
+                owner: order.owner,
+                quantity: order.quantity,
+                timestamp: order.timestamp,
+            })
+            .collect()
+    }
+    
+    /// Snapshot both sides of the book
+    pub fn snapshot(&self) -> BookSnapshot {
+        BookSnapshot {
+            bids: self.levels(Side::Bid),
+            asks: self.levels(Side::Ask),
+            total_orders: self.total_orders,
+            last_update_slot: self.last_update_slot,
+        }
+    }
+    
+    /// Compact binary snapshot for light clients
+    /// 
+    /// Wire format (all integers little-endian):
+    /// - `version: u8` (`COMPACT_SNAPSHOT_VERSION`)
+    /// - `level_count: u16`
+    /// - `level_count` × 21-byte levels: `side: u8` (0 = bid, 1 = ask), `price: u64`,
+    ///   `quantity: u64` (live remaining), `order_count: u32`
+    /// 
+    /// Bids come first (best/highest first), then asks (best/lowest first), each side
+    /// capped at `MAX_COMPACT_LEVELS_PER_SIDE` best levels.
+    pub fn serialize_snapshot(&self) -> Vec<u8> {
+        let bids = self.levels(Side::Bid);
+        let asks = self.levels(Side::Ask);
+        let levels: Vec<(Side, BookLevel)> = bids
+            .into_iter()
+            .take(Self::MAX_COMPACT_LEVELS_PER_SIDE)
+            .map(|level| (Side::Bid, level))
+            .chain(
+                asks.into_iter()
+                    .take(Self::MAX_COMPACT_LEVELS_PER_SIDE)
+                    .map(|level| (Side::Ask, level)),
+            )
+            .collect();
         
-        let mut depth = Vec::new();
-        // TODO: Implement in-order traversal to get top N levels
-        // For now, just return best level
-        if let Some((price, queue_index)) = match side {
-            Side::Bid => tree.max(),
-            Side::Ask => tree.min(),
-        } {
-            let queue = &self.order_queues[queue_index as usize];
-            depth.push((price, queue.total_quantity));
+        let mut data = Vec::with_capacity(3 + levels.len() * Self::COMPACT_LEVEL_LEN);
+        data.push(Self::COMPACT_SNAPSHOT_VERSION);
+        data.extend_from_slice(&(levels.len() as u16).to_le_bytes());
+        for (side, level) in levels {
+            Self::encode_level(&mut data, side, &level);
+        }
+        data
+    }
+    
+    /// Append one level in the 21-byte compact snapshot layout
+    fn encode_level(data: &mut Vec<u8>, side: Side, level: &BookLevel) {
+        data.push(match side {
+            Side::Bid => 0,
+            Side::Ask => 1,
+        });
+        data.extend_from_slice(&level.price.to_le_bytes());
+        data.extend_from_slice(&level.quantity.to_le_bytes());
+        data.extend_from_slice(&level.order_count.to_le_bytes());
+    }
+    
+    /// SHA-256 over a full ladder: every bid level (best first) then every ask
+    /// level (best first), each in the 21-byte compact snapshot layout, uncapped
+    pub fn ladder_checksum(bids: &[BookLevel], asks: &[BookLevel]) -> [u8; 32] {
+        let mut data = Vec::with_capacity((bids.len() + asks.len()) * Self::COMPACT_LEVEL_LEN);
+        for level in bids {
+            Self::encode_level(&mut data, Side::Bid, level);
+        }
+        for level in asks {
+            Self::encode_level(&mut data, Side::Ask, level);
+        }
+        anchor_lang::solana_program::hash::hash(&data).to_bytes()
+    }
+    
+    /// Decode a `serialize_snapshot` blob into (bids, asks)
+    /// Returns None for an unknown version, a bad side byte, or a length mismatch
+    pub fn deserialize_snapshot(data: &[u8]) -> Option<(Vec<BookLevel>, Vec<BookLevel>)> {
+        let (&version, rest) = data.split_first()?;
+        if version != Self::COMPACT_SNAPSHOT_VERSION || rest.len() < 2 {
+            return None;
+        }
+        let level_count = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+        let body = &rest[2..];
+        if body.len() != level_count * Self::COMPACT_LEVEL_LEN {
+            return None;
         }
         
-        depth
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+        for chunk in body.chunks_exact(Self::COMPACT_LEVEL_LEN) {
+            let level = BookLevel {
+                price: u64::from_le_bytes(chunk[1..9].try_into().ok()?),
+                quantity: u64::from_le_bytes(chunk[9..17].try_into().ok()?),
+                order_count: u32::from_le_bytes(chunk[17..21].try_into().ok()?),
+            };
+            match chunk[0] {
+                0 => bids.push(level),
+                1 => asks.push(level),
+                _ => return None,
+            }
+        }
+        Some((bids, asks))
     }
     
     /// Get spread (difference between best bid and best ask)
@@ -216,103 +1013,498 @@ impl OrderBook {
         Some((self.best_bid + self.best_ask) / 2)
     }
     
-    /// Match an order against the book (multi-order matching)
-    /// Returns vector of (price, fill_quantity, order_id) tuples
-    pub fn match_order(
-        &mut self,
-        side: Side,
-        max_quantity: u64,
-        limit_price: u64,
-        taker_owner: Pubkey,
-    ) -> Result<Vec<(u64, u64, u128)>> {
-        let mut fills = Vec::new();
-        let mut remaining_quantity = max_quantity;
-        
-        // Keep matching until filled or no compatible orders
-        while remaining_quantity > 0 {
-            // Get best price from opposing side (get value, not reference)
-            let best_price_result = match side {
-                Side::Bid => self.asks.min(),  // Best ask (lowest price)
-                Side::Ask => self.bids.max(),  // Best bid (highest price)
-            };
-            
-            if best_price_result.is_none() {
-                break;  // No more orders on opposing side
-            }
-            
-            let (price, queue_index) = best_price_result.unwrap();
-            
-            // Check if price is acceptable
-            let price_acceptable = match side {
-                Side::Bid => price <= limit_price,  // Buy: ask price must be <= limit
-                Side::Ask => price >= limit_price,  // Sell: bid price must be >= limit
-            };
-            
-            if !price_acceptable {
-                break;  // No more acceptable prices
-            }
-            
-            // Get order queue at this price level
-            let queue = &mut self.order_queues[queue_index as usize];
-            
-            // Match against first order in queue (FIFO)
-            if let Some(maker_order) = queue.peek_mut() {
-                // Self-trade prevention
-                if maker_order.owner == taker_owner {
-                    msg!("Skipping self-trade: order_id={}", maker_order.order_id);
-                    break;  // Don't match against own orders
-                }
-                
-                let fill_quantity = remaining_quantity.min(maker_order.quantity);
-                
-                // Record fill
-                fills.push((price, fill_quantity, maker_order.order_id));
-                
-                // Update maker order
-                maker_order.fill(fill_quantity);
-                remaining_quantity -= fill_quantity;
-                
-                // If maker order fully filled, remove it
-                if maker_order.is_filled() {
-                    queue.pop_if_filled();
-                    
-                    // If queue now empty, remove price level from tree
-                    if queue.is_empty() {
-                        let tree_to_remove = match side {
-                            Side::Bid => &mut self.asks,
-                            Side::Ask => &mut self.bids,
-                        };
-                        tree_to_remove.remove(price)?;
-                    }
-                }
-            } else {
-                break;  // Queue unexpectedly empty
-            }
+    /// Get spread in basis points relative to mid price
+    pub fn spread_bps(&self) -> Option<u64> {
+        let spread = self.get_spread()? as u128;
+        let mid_price = self.get_mid_price()? as u128;
+        if mid_price == 0 {
+            return None;
         }
-        
-        self.total_orders = self.order_queues
-            .iter()
-            .map(|q| q.orders.len() as u64)
-            .sum();
-        
-        self.update_best_prices()?;
-        
-        Ok(fills)
+        Some((spread * 10_000 / mid_price) as u64)
     }
     
-    /// Check if matching would result in self-trade
-    pub fn would_self_trade(&self, side: Side, owner: &Pubkey) -> bool {
-        if let Some(best_order) = self.get_best_order(side.opposite()) {
-            return best_order.owner == *owner;
+    /// Get top-of-book statistics
+    pub fn stats(&self) -> MarketStats {
+        MarketStats {
+            best_bid: self.best_bid,
+            best_ask: self.best_ask,
+            spread: self.get_spread(),
+            spread_bps: self.spread_bps(),
+            mid_price: self.get_mid_price(),
+            total_orders: self.total_orders,
+            last_update_slot: self.last_update_slot,
         }
-        false
     }
-}
+    
+    /// Every resting order sorted by (side, price, queue position): bids then
+    /// asks, each in ascending price, walking the trees rather than
+    /// `order_queues` so the order doesn't depend on queue slot history
+    fn resting_orders(&self) -> impl Iterator<Item = &Order> + '_ {
+        self.bids
+            .leaves()
+            .into_iter()
+            .chain(self.asks.leaves())
+            .flat_map(|(_, queue_index)| self.order_queues[queue_index as usize].orders.iter())
+    }
+    
+    /// IDs of orders whose payment is marked and whose settlement delay has expired at `now`
+    /// (in `resting_orders` order)
+    pub fn settleable_orders(&self, now: i64) -> Vec<u128> {
+        self.resting_orders()
+            .filter(|order| {
+                order.payment_status == PaymentStatus::PaymentMarked
+                    && order.settlement_timestamp <= now
+            })
+            .map(|order| order.order_id)
+            .take(Self::MAX_LISTED_ORDERS)
+            .collect()
+    }
+    
+    /// CritBit tree occupancy for both sides
+    pub fn diagnostics(&self) -> BookDiagnostics {
+        BookDiagnostics {
+            bids: TreeDiagnostics::of(&self.bids),
+            asks: TreeDiagnostics::of(&self.asks),
+        }
+    }
+    
+    /// Used and maximum levels and nodes of both trees, and the room left for orders
+    pub fn capacity(&self) -> BookCapacity {
+        BookCapacity {
+            bids: TreeCapacity::of(&self.bids),
+            asks: TreeCapacity::of(&self.asks),
+            free_orders: ((Self::ORDER_QUEUES_SPACE - self.order_queues_space()) / Order::LEN) as u32,
+        }
+    }
+    
+    /// Recompute every queue's quantity sum and `total_orders`, comparing them
+    /// against the cached values and reporting the first mismatch
+    pub fn verify_integrity(&self) -> BookIntegrity {
+        let mut actual_orders = 0u64;
+        let mut discrepancy = None;
+        
+        for (index, queue) in self.order_queues.iter().enumerate() {
+            let actual: u64 = queue.orders.iter().map(|o| o.quantity).sum();
+            if discrepancy.is_none() && actual != queue.total_quantity {
+                discrepancy = Some(IntegrityDiscrepancy::QueueQuantity {
+                    queue_index: index as u32,
+                    cached: queue.total_quantity,
+                    actual,
+                });
+            }
+            if discrepancy.is_none() {
+                if let Some(ghost) = queue.orders.iter().find(|o| o.is_filled()) {
+                    discrepancy = Some(IntegrityDiscrepancy::FilledOrderResting {
+                        order_id: ghost.order_id,
+                    });
+                }
+            }
+            actual_orders += queue.orders.len() as u64;
+        }
+        
+        if discrepancy.is_none() && actual_orders != self.total_orders {
+            discrepancy = Some(IntegrityDiscrepancy::TotalOrders {
+                cached: self.total_orders,
+                actual: actual_orders,
+            });
+        }
+        
+        BookIntegrity {
+            ok: discrepancy.is_none(),
+            discrepancy,
+        }
+    }
+    
+    /// Match an order against the book (multi-order matching)
+    /// Returns one `Fill` per maker order touched, in execution order
+    /// 
+    /// Makers still inside their activation delay at `request.now` are skipped
+    /// (they keep their queue position and can only be cancelled until active).
+    /// Makers that accept none of `request.accepted_payment_methods` are skipped
+    /// the same way (an empty list accepts every maker), as are asks whose
+    /// `reserve_price` is above `request.limit_price` and, unless
+    /// `request.taker_attested`, asks that require an attested taker, and
+    /// makers whose jurisdiction differs from `request.taker_jurisdiction`.
+    /// Matching stops before a fill would involve more than `request.max_makers`
+    /// distinct maker owners (0 = no cap).
+    /// 
+    /// The algorithm itself is `matching::match_levels`; this applies its side
+    /// effects to the tree and book counters and logs skipped makers.
+    pub fn match_order(&mut self, request: &MatchRequest) -> Result<Vec<Fill>> {
+        let levels = self.opposing_levels(request.side);
+        let outcome = match_levels(&mut self.order_queues, &levels, request);
+        
+        for (order_id, reason) in outcome.skipped.iter() {
+            match reason {
+                SkipReason::Expired => {
+                    msg!("Skipping expired maker: order_id={}", order_id);
+                }
+                SkipReason::Inactive => {
+                    msg!("Skipping inactive maker: order_id={}", order_id);
+                }
+                SkipReason::NoAcceptedPaymentMethod => {
+                    msg!("Skipping maker without an accepted payment method: order_id={}", order_id);
+                }
+                SkipReason::BelowReserve => {
+                    msg!("Skipping maker whose reserve is above the limit: order_id={}", order_id);
+                }
+                SkipReason::NotAttested => {
+                    msg!("Skipping maker that requires an attested taker: order_id={}", order_id);
+                }
+                SkipReason::JurisdictionMismatch => {
+                    msg!("Skipping maker in another jurisdiction: order_id={}", order_id);
+                }
+            }
+        }
+        match outcome.stopped {
+            Some(StopReason::SelfTrade { maker_order_id }) => {
+                msg!("Skipping self-trade: order_id={}", maker_order_id);
+            }
+            Some(StopReason::MakerCap) => {
+                msg!("Stopping at {} distinct makers", request.max_makers);
+            }
+            None => {}
+        }
+        
+        // Fully filled orders anywhere in the opposing levels must not keep resting
+        let mut emptied_levels = outcome.emptied_levels.clone();
+        for (price, queue_index) in levels.iter() {
+            let queue = &mut self.order_queues[*queue_index as usize];
+            let purged = queue.purge_filled();
+            if purged > 0 {
+                msg!("Purged {} filled orders at price {}", purged, price);
+                if queue.is_empty() && !emptied_levels.contains(price) {
+                    emptied_levels.push(*price);
+                }
+            }
+        }
+        debug_assert!(self
+            .order_queues
+            .iter()
+            .all(|queue| queue.orders.iter().all(|order| !order.is_filled())));
+        
+        // Drop emptied price levels from the tree
+        let tree = match request.side {
+            Side::Bid => &mut self.asks,
+            Side::Ask => &mut self.bids,
+        };
+        for price in emptied_levels.iter() {
+            tree.remove(*price)?;
+        }
+        
+        for fill in outcome.fills.iter() {
+            self.credit_maker_volume(fill.maker_owner, fill.quantity);
+        }
+        
+        self.total_orders = self.order_queues
+            .iter()
+            .map(|q| q.orders.len() as u64)
+            .sum();
+        
+        self.update_best_prices()?;
+        
+        Ok(outcome.fills)
+    }
+    
+    /// Non-mutating counterpart of `match_order`: the fills it would produce
+    /// against the current book (`threshold_reached` included), without touching it
+    pub fn simulate_match(&self, request: &MatchRequest) -> Vec<Fill> {
+        let levels = self.opposing_levels(request.side);
+        let mut queues = self.order_queues.clone();
+        match_levels(&mut queues, &levels, request).fills
+    }
+    
+    /// Estimate the compute units `match_order` needs for `request`, so clients
+    /// can prepend a `ComputeBudget` instruction sized to the sweep
+    /// 
+    /// Cost model: a fixed `MATCH_BASE_COMPUTE_UNITS` (account loading, tree
+    /// walk, escrow bookkeeping), plus `MATCH_LEVEL_COMPUTE_UNITS` per price
+    /// level touched (level lookup, queue compaction, tree removal) and
+    /// `MATCH_FILL_COMPUTE_UNITS` per fill (maker update, fill record, logs and
+    /// `OrderMatched` event). The figures are conservative per-item upper
+    /// bounds, not a measurement of this exact match.
+    pub fn estimate_match_cost(&self, request: &MatchRequest) -> MatchCostEstimate {
+        let fills = self.simulate_match(request);
+        let mut levels = 0u32;
+        for (index, fill) in fills.iter().enumerate() {
+            if index == 0 || fills[index - 1].price != fill.price {
+                levels += 1;
+            }
+        }
+        let fill_count = fills.len() as u32;
+        let compute_units = Self::MATCH_BASE_COMPUTE_UNITS
+            .saturating_add(levels.saturating_mul(Self::MATCH_LEVEL_COMPUTE_UNITS))
+            .saturating_add(fill_count.saturating_mul(Self::MATCH_FILL_COMPUTE_UNITS))
+            .min(Self::MAX_COMPUTE_UNITS);
+        
+        MatchCostEstimate {
+            levels,
+            fills: fill_count,
+            compute_units,
+        }
+    }
+    
+    /// Preview what `request` would cost (bids) or yield (asks) against the
+    /// current book, charging `fee_bps` of the gross notional to the taker
+    pub fn quote_for_base(&self, request: &MatchRequest, price_scale: u32, fee_bps: u16) -> Result<BaseQuote> {
+        require!(price_scale > 0, ErrorCode::InvalidPriceScale);
+        let fills = self.simulate_match(request);
+        
+        let base_filled: u64 = fills.iter().map(|fill| fill.quantity).sum();
+        let value: u128 = fills
+            .iter()
+            .map(|fill| fill.price as u128 * fill.quantity as u128)
+            .sum();
+        let gross_notional = u64::try_from(value / price_scale as u128).map_err(|_| ErrorCode::MathOverflow)?;
+        let fee = (gross_notional as u128 * fee_bps as u128 / 10_000) as u64;
+        let net = match request.side {
+            Side::Bid => gross_notional.checked_add(fee).ok_or(ErrorCode::MathOverflow)?,
+            Side::Ask => gross_notional - fee,
+        };
+        let vwap = if base_filled == 0 { 0 } else { (value / base_filled as u128) as u64 };
+        
+        Ok(BaseQuote {
+            base_filled,
+            gross_notional,
+            fee,
+            net,
+            vwap,
+        })
+    }
+    
+    /// Quantity `request` could fill without trading past its limit price:
+    /// what `simulate_match` fills, so makers matching would skip (expired,
+    /// inactive, filtered out) don't count
+    pub fn fillable_up_to(&self, request: &MatchRequest) -> u64 {
+        self.simulate_match(request).iter().map(|fill| fill.quantity).sum()
+    }
+    
+    /// Price levels an incoming `side` order would hit, best first
+    /// (asks lowest first, bids highest first)
+    fn opposing_levels(&self, side: Side) -> Vec<(u64, u32)> {
+        match side {
+            Side::Bid => self.asks.leaves(),
+            Side::Ask => {
+                let mut levels = self.bids.leaves();
+                levels.reverse();
+                levels
+            }
+        }
+    }
+    
+    /// Deviation of `price` from `reference` in basis points (rounded down)
+    pub fn slippage_bps(reference: u64, price: u64) -> u64 {
+        if reference == 0 {
+            return 0;
+        }
+        let deviation = reference.abs_diff(price) as u128;
+        (deviation * 10_000 / reference as u128).min(u64::MAX as u128) as u64
+    }
+    
+    /// Credit filled quantity to a maker's lifetime volume
+    fn credit_maker_volume(&mut self, owner: Pubkey, quantity: u64) {
+        if let Some(entry) = self.maker_volumes.iter_mut().find(|m| m.owner == owner) {
+            entry.volume = entry.volume.saturating_add(quantity);
+        } else if self.maker_volumes.len() < Self::MAX_TRACKED_MAKERS {
+            self.maker_volumes.push(MakerVolume { owner, volume: quantity });
+        } else {
+            msg!("Maker volume table full, not tracking: {}", owner);
+        }
+    }
+    
+    /// Count a placed order
+    pub fn record_placed(&mut self) -> Result<()> {
+        self.lifetime_placed = self.lifetime_placed.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+    
+    /// Count `count` cancelled orders
+    pub fn record_cancelled(&mut self, count: u64) -> Result<()> {
+        self.lifetime_cancelled = self
+            .lifetime_cancelled
+            .checked_add(count)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+    
+    /// Count a match's fills and their volume
+    pub fn record_fills(&mut self, fills: &[Fill]) -> Result<()> {
+        let volume = fills
+            .iter()
+            .try_fold(0u64, |total, fill| total.checked_add(fill.quantity))
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.lifetime_volume = self.lifetime_volume.checked_add(volume).ok_or(ErrorCode::MathOverflow)?;
+        self.lifetime_fills = self
+            .lifetime_fills
+            .checked_add(fills.len() as u64)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+    
+    pub fn lifetime_stats(&self) -> LifetimeStats {
+        LifetimeStats {
+            volume: self.lifetime_volume,
+            fills: self.lifetime_fills,
+            placed: self.lifetime_placed,
+            cancelled: self.lifetime_cancelled,
+        }
+    }
+    
+    /// Get a maker's lifetime filled volume, or `None` if the owner isn't
+    /// tracked: they never made a fill, or filled once the table was full
+    pub fn get_maker_volume(&self, owner: &Pubkey) -> Option<u64> {
+        self.maker_volumes
+            .iter()
+            .find(|m| m.owner == *owner)
+            .map(|m| m.volume)
+    }
+    
+    /// Check if matching would result in self-trade
+    pub fn would_self_trade(&self, side: Side, owner: &Pubkey, discipline: QueueDiscipline) -> bool {
+        if let Some(best_order) = self.get_best_order(side.opposite(), discipline) {
+            return best_order.owner == *owner;
+        }
+        false
+    }
+    
+    /// Record `amount` tokens moved into the escrow vault
+    /// (see `Market::lock_escrow`, which also keeps the market-wide total)
+    pub fn lock_escrow(&mut self, amount: u64) -> Result<()> {
+        self.escrow_locked = self
+            .escrow_locked
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+    
+    /// Record `amount` tokens paid out of the escrow vault
+    /// Fails if that is more than the book has locked (a leak in its accounting)
+    pub fn release_escrow(&mut self, amount: u64) -> Result<()> {
+        self.escrow_locked = self
+            .escrow_locked
+            .checked_sub(amount)
+            .ok_or(ErrorCode::EscrowLedgerMismatch)?;
+        Ok(())
+    }
+    
+    /// Up to `max_count` orders on `side` expired at `now`, best price first
+    /// Only `Pending` orders are listed: once a payment is marked the order
+    /// stays until it settles or its dispute is resolved
+    pub fn expired_orders(&self, side: Side, now: i64, max_count: usize) -> Vec<Order> {
+        let tree = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let mut leaves = tree.leaves();
+        if side == Side::Bid {
+            leaves.reverse();
+        }
+        
+        leaves
+            .iter()
+            .flat_map(|(_, queue_index)| self.order_queues[*queue_index as usize].orders.iter())
+            .filter(|order| order.is_expired(now) && order.is_refundable())
+            .take(max_count)
+            .copied()
+            .collect()
+    }
+    
+    /// Tokens currently held in escrow for `owner`: the remaining quantity of
+    /// their resting escrowing orders (asks, unless `fiat_payer` flips them)
+    /// whose escrow hasn't been released by settlement
+    pub fn escrowed_balance(&self, owner: &Pubkey) -> u64 {
+        self.resting_orders()
+            .filter(|order| {
+                order.owner == *owner
+                    && order.escrows_tokens()
+                    && order.payment_status != PaymentStatus::Verified
+            })
+            .fold(0u64, |total, order| total.saturating_add(order.quantity))
+    }
+    
+    /// Every order `owner` has resting, on both sides, sorted by (side, price,
+    /// queue position)
+    pub fn orders_for_owner(&self, owner: &Pubkey) -> Vec<Order> {
+        self.resting_orders()
+            .filter(|order| order.owner == *owner)
+            .copied()
+            .collect()
+    }
+    
+    /// Check if `owner` rests anywhere on the side an incoming `side` order would hit
+    /// (every price level, not just the best)
+    pub fn has_opposing_order(&self, side: Side, owner: &Pubkey) -> bool {
+        let tree = match side.opposite() {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        tree.leaves().iter().any(|(_, queue_index)| {
+            self.order_queues[*queue_index as usize]
+                .orders
+                .iter()
+                .any(|order| order.owner == *owner)
+        })
+    }
+    
+    /// Check if an order on `side` at `price` would cross one of `owner`'s own
+    /// orders on the opposite side, which matching never fills
+    pub fn crosses_own_order(&self, side: Side, price: u64, owner: &Pubkey) -> bool {
+        let tree = match side.opposite() {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        tree.leaves()
+            .iter()
+            .filter(|(level, _)| match side {
+                Side::Bid => *level <= price,
+                Side::Ask => *level >= price,
+            })
+            .any(|(_, queue_index)| {
+                self.order_queues[*queue_index as usize]
+                    .orders
+                    .iter()
+                    .any(|order| order.owner == *owner)
+            })
+    }
+    
+    /// Check if the side an incoming `side` order would hit is non-empty and
+    /// every order on it belongs to `owner`
+    pub fn opposing_side_owned_by(&self, side: Side, owner: &Pubkey) -> bool {
+        let tree = match side.opposite() {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let leaves = tree.leaves();
+        !leaves.is_empty()
+            && leaves.iter().all(|(_, queue_index)| {
+                self.order_queues[*queue_index as usize]
+                    .orders
+                    .iter()
+                    .all(|order| order.owner == *owner)
+            })
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::order::generate_order_id;
+    use crate::order::{is_valid_jurisdiction, OrderOptions};
+    
+    /// FIFO bid for `max_quantity` up to `limit_price`, no filters or maker cap
+    fn bid(max_quantity: u64, limit_price: u64, taker_owner: Pubkey, now: i64) -> MatchRequest<'static> {
+        MatchRequest {
+            side: Side::Bid,
+            max_quantity,
+            limit_price,
+            taker_owner,
+            accepted_payment_methods: &[],
+            max_makers: 0,
+            taker_attested: false,
+            taker_jurisdiction: [0; 2],
+            discipline: QueueDiscipline::Fifo,
+            now,
+        }
+    }
     
     #[test]
     fn test_order_book_insert() {
@@ -391,35 +1583,1795 @@ mod tests {
         
         // Mid price
         assert_eq!(book.get_mid_price(), Some(52)); // (50 + 55) / 2
+        
+        // Relative spread
+        assert_eq!(book.spread_bps(), Some(961)); // 5 * 10000 / 52
+        assert_eq!(book.stats().spread_bps, Some(961));
     }
     
     #[test]
-    fn test_order_book_remove() {
-        let market = Pubkey::new_unique();
-        let base_mint = Pubkey::new_unique();
-        let quote_mint = Pubkey::new_unique();
-        let mut book = OrderBook::new(market, base_mint, quote_mint);
+    fn test_cancelling_sole_bid_empties_side() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let place = |book: &mut OrderBook, seq: u64, side: Side, price: u64| {
+            let order = Order::new(
+                generate_order_id(&owner, seq, 1000),
+                owner,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+            order.order_id
+        };
         
+        place(&mut book, 0, Side::Ask, 60);
+        let bid_id = place(&mut book, 1, Side::Bid, 50);
+        assert_eq!(book.best_bid, 50);
+        assert_eq!(book.get_spread(), Some(10));
+        assert_eq!(book.get_mid_price(), Some(55));
+        
+        // Cancelling the only bid resets the side to its empty sentinel
+        book.remove_order(bid_id, Side::Bid, 50).unwrap();
+        assert_eq!(book.best_bid, 0);
+        assert_eq!(book.stats().best_bid, 0);
+        assert!(book.levels(Side::Bid).is_empty());
+        assert_eq!(book.get_spread(), None);
+        assert_eq!(book.get_mid_price(), None);
+        assert_eq!(book.spread_bps(), None);
+        // The other side is untouched and the cache agrees with the tree
+        assert_eq!(book.best_ask, 60);
+        assert!(!book.refresh_best_prices().unwrap());
+        
+        // A new bid below the old one becomes the best bid
+        place(&mut book, 2, Side::Bid, 45);
+        assert_eq!(book.best_bid, 45);
+        assert_eq!(book.get_spread(), Some(15));
+    }
+    
+    #[test]
+    fn test_check_placement_matches_insert() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
         let owner = Pubkey::new_unique();
-        let order_id = generate_order_id(&owner, 1, 1000);
-        let order = Order::new(
-            order_id,
+        
+        let ask = Order::new(
+            generate_order_id(&owner, 0, 1000),
             owner,
+            10,
             100,
-            50,
             1000,
             OrderType::Limit,
-            Side::Bid,
-            1,
+            Side::Ask,
+            0,
             "PayPal".to_string(),
         );
+        book.insert_order(ask).unwrap();
         
-        book.insert_order(order).unwrap();
-        assert_eq!(book.total_orders, 1);
+        assert!(book.check_placement(Side::Bid, 99, 10, OrderType::PostOnly).is_ok());
+        assert_eq!(
+            book.check_placement(Side::Bid, 100, 10, OrderType::PostOnly).unwrap_err(),
+            ErrorCode::PostOnlyWouldMatch.into()
+        );
+        assert_eq!(
+            book.check_placement(Side::Bid, 99, 0, OrderType::Limit).unwrap_err(),
+            ErrorCode::InvalidAmount.into()
+        );
         
-        let removed = book.remove_order(order_id, Side::Bid, 50).unwrap();
-        assert_eq!(removed.order_id, order_id);
-        assert_eq!(book.total_orders, 0);
-        assert_eq!(book.best_bid, 0);
+        // A crossing reprice bid moves one tick below the best ask
+        assert_eq!(book.post_only_price(Side::Bid, 120, OrderType::PostOnlyReprice).unwrap(), 99);
+        assert_eq!(book.post_only_price(Side::Bid, 90, OrderType::PostOnlyReprice).unwrap(), 90);
+        assert_eq!(book.post_only_price(Side::Bid, 120, OrderType::PostOnly).unwrap(), 120);
+        assert!(book.check_placement(Side::Bid, 99, 10, OrderType::PostOnlyReprice).is_ok());
+        
+        // Fill the book with new ask price levels
+        for i in 1..OrderBook::MAX_PRICE_LEVELS as u64 {
+            let order = Order::new(
+                generate_order_id(&owner, i, 1000),
+                owner,
+                10,
+                100 + i,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                i,
+                "PayPal".to_string(),
+            );
+            if book.check_placement(Side::Ask, order.price, 10, OrderType::Limit).is_err() {
+                assert!(book.insert_order(order).is_err());
+                break;
+            }
+            book.insert_order(order).unwrap();
+        }
+        
+        let total_orders = book.total_orders;
+        let err = book.check_placement(Side::Ask, 10_000, 10, OrderType::Limit).unwrap_err();
+        assert_eq!(err, ErrorCode::OrderBookFull.into());
+        
+        // The account's bytes run out before the tree's nodes, so existing
+        // levels are full too, and the dry run changed nothing
+        assert_eq!(
+            book.check_placement(Side::Ask, 100, 10, OrderType::Limit).unwrap_err(),
+            ErrorCode::OrderBookFull.into()
+        );
+        assert_eq!(book.total_orders, total_orders);
+    }
+    
+    #[test]
+    fn test_book_capacity_tracks_free_nodes_per_level() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let max_nodes = OrderBook::MAX_PRICE_LEVELS as u32;
+        let empty = TreeCapacity { used_levels: 0, max_levels: max_nodes.div_ceil(2), free_nodes: max_nodes, max_nodes };
+        let free_orders = (OrderBook::ORDER_QUEUES_SPACE / Order::LEN) as u32;
+        assert_eq!(book.capacity(), BookCapacity { bids: empty, asks: empty, free_orders });
+        
+        let mut seq = 0;
+        let mut place = |book: &mut OrderBook, price: u64| {
+            seq += 1;
+            let order = Order::new(
+                generate_order_id(&owner, seq, 1000),
+                owner,
+                10,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order)
+        };
+        
+        // The first level takes one node, every later one two; joining an
+        // existing level takes none
+        place(&mut book, 100).unwrap();
+        assert_eq!(book.capacity().asks.free_nodes, max_nodes - 1);
+        place(&mut book, 101).unwrap();
+        assert_eq!(book.capacity().asks.free_nodes, max_nodes - 3);
+        place(&mut book, 101).unwrap();
+        assert_eq!(book.capacity().asks.free_nodes, max_nodes - 3);
+        assert_eq!(book.capacity().asks.used_levels, 2);
+        assert_eq!(book.capacity().bids, empty);
+        
+        // The pool holds max_levels, well short of MAX_PRICE_LEVELS, but the
+        // account's bytes run out before even that many levels
+        let asks = book.capacity().asks;
+        assert!(asks.max_levels < OrderBook::MAX_PRICE_LEVELS as u32);
+        let mut price = 102;
+        while book.capacity().free_orders > 0 {
+            place(&mut book, price).unwrap();
+            price += 1;
+        }
+        let asks = book.capacity().asks;
+        assert!(asks.used_levels < asks.max_levels);
+        assert!(asks.free_nodes >= 2);
+        assert_eq!(place(&mut book, price).unwrap_err(), ErrorCode::OrderBookFull.into());
+        assert_eq!(place(&mut book, 100).unwrap_err(), ErrorCode::OrderBookFull.into());
+    }
+    
+    #[test]
+    fn test_max_orders_fill_one_level() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        
+        let mut seq = 0;
+        loop {
+            let order = Order::new(
+                generate_order_id(&owner, seq, 1000),
+                owner,
+                10,
+                100,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq,
+                "PayPal".to_string(),
+            );
+            if let Err(err) = book.insert_order(order) {
+                assert_eq!(err, ErrorCode::OrderBookFull.into());
+                break;
+            }
+            seq += 1;
+        }
+        assert_eq!(book.total_orders, OrderBook::MAX_ORDERS as u64);
+        
+        // A bid sweeping the level fills every order, and the fill log holds them all
+        let fills = book.match_order(&bid(u64::MAX, 100, Pubkey::new_unique(), 1000)).unwrap();
+        assert_eq!(fills.len(), OrderBook::MAX_ORDERS);
+        assert!(fills.len() <= crate::FillLog::MAX_FILLS);
+    }
+    
+    #[test]
+    fn test_full_book_fits_its_account() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        
+        // Every order at its own level, alternating sides, costs the most bytes;
+        // each improves its side's best price, so each is checkpointed
+        let mut seq = 0;
+        loop {
+            let (side, price) = if seq % 2 == 0 { (Side::Bid, 1_000 + seq) } else { (Side::Ask, 2_000 - seq) };
+            let order = Order::new(
+                generate_order_id(&owner, seq, 1000),
+                owner,
+                10,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq,
+                "PayPal".to_string(),
+            );
+            let checked = book.check_placement(side, price, 10, OrderType::Limit);
+            let inserted = book.insert_order(order);
+            assert_eq!(checked.is_ok(), inserted.is_ok());
+            if let Err(err) = inserted {
+                assert_eq!(err, ErrorCode::OrderBookFull.into());
+                break;
+            }
+            book.touch(seq);
+            seq += 1;
+        }
+        assert!(book.total_orders >= OrderBook::MAX_RESTING_ORDERS as u64);
+        assert_eq!(book.capacity().free_orders, 0);
+        
+        // Fill the capped vectors too
+        for _ in 0..OrderBook::MAX_TRACKED_MAKERS {
+            book.credit_maker_volume(Pubkey::new_unique(), 1);
+        }
+        assert_eq!(book.price_checkpoints.len(), OrderBook::MAX_PRICE_CHECKPOINTS);
+        
+        let mut data = Vec::new();
+        book.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), OrderBook::FIXED_SPACE + book.order_queues_space());
+        assert!(data.len() <= OrderBook::INIT_SPACE);
+        
+        let mut order = Vec::new();
+        book.order_queues[0].orders[0].serialize(&mut order).unwrap();
+        assert_eq!(order.len(), Order::LEN);
+    }
+    
+    #[test]
+    fn test_refresh_best_prices_heals_stale_cache() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        for (seq, (side, price)) in [(Side::Bid, 95), (Side::Bid, 97), (Side::Ask, 103), (Side::Ask, 101)]
+            .into_iter()
+            .enumerate()
+        {
+            let order = Order::new(
+                generate_order_id(&owner, seq as u64, 1000),
+                owner,
+                10,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        // A healthy cache is left alone
+        assert!(!book.refresh_best_prices().unwrap());
+        assert_eq!((book.best_bid, book.best_ask), (97, 101));
+        
+        // Corrupt the cache as a manual tree edit would leave it
+        book.best_bid = 50;
+        book.best_ask = 200;
+        assert!(book.refresh_best_prices().unwrap());
+        assert_eq!(book.best_bid, book.bids.max().unwrap().0);
+        assert_eq!(book.best_ask, book.asks.min().unwrap().0);
+        assert_eq!((book.best_bid, book.best_ask), (97, 101));
+        
+        // Empty sides refresh to their sentinels
+        book.bids = CritBitTree::new(OrderBook::MAX_PRICE_LEVELS);
+        book.asks = CritBitTree::new(OrderBook::MAX_PRICE_LEVELS);
+        assert!(book.refresh_best_prices().unwrap());
+        assert_eq!((book.best_bid, book.best_ask), (0, u64::MAX));
+    }
+    
+    #[test]
+    fn test_verify_integrity_flags_corrupted_cache() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        for (seq, price) in [50, 50, 51].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&owner, seq as u64, 1000),
+                owner,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        assert_eq!(book.verify_integrity(), BookIntegrity { ok: true, discrepancy: None });
+        
+        book.total_orders = 5;
+        assert_eq!(
+            book.verify_integrity().discrepancy,
+            Some(IntegrityDiscrepancy::TotalOrders { cached: 5, actual: 3 })
+        );
+        
+        // Queue mismatches are reported before the order count
+        book.order_queues[1].total_quantity = 7;
+        assert_eq!(
+            book.verify_integrity(),
+            BookIntegrity {
+                ok: false,
+                discrepancy: Some(IntegrityDiscrepancy::QueueQuantity {
+                    queue_index: 1,
+                    cached: 7,
+                    actual: 100,
+                }),
+            }
+        );
+    }
+    
+    #[test]
+    fn test_settleable_orders_only_lists_ripe_orders() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let ids: Vec<u128> = (0..3)
+            .map(|seq| {
+                let order = Order::new(
+                    generate_order_id(&owner, seq, 1000),
+                    owner,
+                    100,
+                    50 + seq,
+                    1000,
+                    OrderType::Limit,
+                    Side::Ask,
+                    seq,
+                    "PayPal".to_string(),
+                );
+                book.insert_order(order).unwrap();
+                order.order_id
+            })
+            .collect();
+        
+        // Two orders marked paid with different delays, the third untouched
+        for (order_id, settles_at) in [(ids[0], 1010), (ids[1], 1030)] {
+            let order = book.find_order_mut(order_id).unwrap();
+            order.payment_status = PaymentStatus::PaymentMarked;
+            order.settlement_timestamp = settles_at;
+        }
+        
+        assert!(book.settleable_orders(1005).is_empty());
+        assert_eq!(book.settleable_orders(1010), vec![ids[0]]);
+        assert_eq!(book.settleable_orders(1030), vec![ids[0], ids[1]]);
+    }
+    
+    #[test]
+    fn test_orders_for_owner_sorted_by_side_and_price() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        
+        // Placed out of price order, with another owner's level freeing a queue
+        // slot so later levels reuse a lower `order_queues` index
+        let placements = [
+            (other, Side::Ask, 58),
+            (owner, Side::Ask, 57),
+            (owner, Side::Ask, 55),
+            (owner, Side::Bid, 40),
+            (owner, Side::Ask, 57),
+            (owner, Side::Bid, 30),
+            (owner, Side::Ask, 52),
+        ];
+        let mut ids = Vec::new();
+        for (seq, (placer, side, price)) in placements.into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&placer, seq as u64, 1000),
+                placer,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+            ids.push(order.order_id);
+            if seq == 0 {
+                book.remove_order(order.order_id, side, price).unwrap();
+            }
+        }
+        
+        let listed: Vec<(Side, u64, u128)> = book
+            .orders_for_owner(&owner)
+            .iter()
+            .map(|order| (order.side, order.price, order.order_id))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (Side::Bid, 30, ids[5]),
+                (Side::Bid, 40, ids[3]),
+                (Side::Ask, 52, ids[6]),
+                (Side::Ask, 55, ids[2]),
+                (Side::Ask, 57, ids[1]),
+                (Side::Ask, 57, ids[4]),
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_crossed_warning() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(book.crossed_warning().is_none());
+        
+        // One-sided books are never crossed
+        book.best_bid = 60;
+        assert!(book.crossed_warning().is_none());
+        
+        book.best_ask = 61;
+        assert!(book.crossed_warning().is_none());
+        
+        book.best_ask = 50;
+        let warning = book.crossed_warning().unwrap();
+        assert_eq!((warning.best_bid, warning.best_ask), (60, 50));
+        
+        // Locked (equal) prices also warn
+        book.best_ask = 60;
+        assert!(book.crossed_warning().is_some());
+    }
+    
+    #[test]
+    fn test_has_opposing_order_checks_every_level() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        for (seq, (maker, price)) in [(other, 50), (owner, 55)].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        // Owner's ask sits behind the best level
+        assert!(!book.would_self_trade(Side::Bid, &owner, QueueDiscipline::Fifo));
+        assert!(book.has_opposing_order(Side::Bid, &owner));
+        assert!(!book.has_opposing_order(Side::Ask, &owner));
+        assert!(!book.has_opposing_order(Side::Bid, &Pubkey::new_unique()));
+    }
+    
+    #[test]
+    fn test_escrowed_balance_sums_unsettled_asks() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        for (seq, (maker, side, price, quantity)) in [
+            (owner, Side::Ask, 50, 100),
+            (owner, Side::Ask, 55, 250),
+            (owner, Side::Ask, 60, 40),
+            (owner, Side::Bid, 40, 1_000), // bids hold no escrow
+            (other, Side::Ask, 50, 70),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                quantity,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        assert_eq!(book.escrowed_balance(&owner), 390);
+        assert_eq!(book.escrowed_balance(&other), 70);
+        
+        // A settled ask has already released its escrow
+        let settled = book.level_orders(Side::Ask, 60)[0].order_id;
+        book.find_order_mut(settled).unwrap().payment_status = PaymentStatus::Verified;
+        assert_eq!(book.escrowed_balance(&owner), 350);
+        assert_eq!(book.escrowed_balance(&Pubkey::new_unique()), 0);
+    }
+    
+    #[test]
+    fn test_expired_orders() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        for (seq, (price, expiry)) in [(52, 1100), (50, 0), (51, 1050), (53, 1200), (51, 1100)]
+            .into_iter()
+            .enumerate()
+        {
+            let order = Order::new(
+                generate_order_id(&owner, seq as u64, 1000),
+                owner,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            )
+            .with_options(&OrderOptions {
+                expiry_timestamp: expiry,
+                ..Default::default()
+            });
+            book.insert_order(order).unwrap();
+        }
+        
+        let prices = |orders: Vec<Order>| orders.iter().map(|o| o.price).collect::<Vec<_>>();
+        assert!(book.expired_orders(Side::Ask, 1049, 10).is_empty());
+        assert_eq!(prices(book.expired_orders(Side::Ask, 1100, 10)), vec![51, 51, 52]);
+        assert_eq!(prices(book.expired_orders(Side::Ask, 1100, 2)), vec![51, 51]);
+        assert!(book.expired_orders(Side::Bid, 1100, 10).is_empty());
+        
+        // An order with a payment in progress is never swept
+        let marked = book.expired_orders(Side::Ask, 1100, 1)[0].order_id;
+        book.find_order_mut(marked).unwrap().payment_status = PaymentStatus::PaymentMarked;
+        assert_eq!(prices(book.expired_orders(Side::Ask, 1100, 10)), vec![51, 52]);
+    }
+    
+    #[test]
+    fn test_injected_clock_drives_order_timing() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let order_id = generate_order_id(&maker, 0, 1000);
+        let order = Order::new(order_id, maker, 100, 50, 1000, OrderType::Limit, Side::Ask, 0, "PayPal".to_string())
+            .with_options(&OrderOptions {
+                activation_delay: 5,
+                expiry_timestamp: 1060,
+                ..Default::default()
+            });
+        book.insert_order(order).unwrap();
+        
+        // Matchable only between activation and expiry
+        assert!(book.simulate_match(&bid(10, 50, taker, 1004)).is_empty());
+        assert_eq!(book.simulate_match(&bid(10, 50, taker, 1005)).len(), 1);
+        assert_eq!(book.simulate_match(&bid(10, 50, taker, 1059)).len(), 1);
+        assert!(book.simulate_match(&bid(10, 50, taker, 1060)).is_empty());
+        assert!(book.expired_orders(Side::Ask, 1059, 10).is_empty());
+        assert_eq!(book.expired_orders(Side::Ask, 1060, 10).len(), 1);
+        
+        // Settlement opens exactly one delay after the payment is marked
+        book.find_order_mut(order_id).unwrap().mark_payment(1020, 10);
+        assert!(book.settleable_orders(1029).is_empty());
+        assert_eq!(book.settleable_orders(1030), vec![order_id]);
+        
+        // A marked order is no longer swept once it expires
+        assert!(book.expired_orders(Side::Ask, 1060, 10).is_empty());
+    }
+    
+    #[test]
+    fn test_reserve_price_ask_fills_only_at_or_above_reserve() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let hidden_id = generate_order_id(&maker, 0, 1000);
+        let hidden = Order::new(hidden_id, maker, 100, 50, 1000, OrderType::Limit, Side::Ask, 0, "PayPal".to_string())
+            .with_options(&OrderOptions {
+                reserve_price: 55,
+                ..Default::default()
+            });
+        let plain_id = generate_order_id(&maker, 1, 1000);
+        let plain = Order::new(plain_id, maker, 100, 52, 1000, OrderType::Limit, Side::Ask, 1, "PayPal".to_string());
+        book.insert_order(hidden).unwrap();
+        book.insert_order(plain).unwrap();
+        
+        // Displayed at 50, but a taker limited to 54 skips it for the plain ask at 52
+        assert_eq!(book.best_ask, 50);
+        let fills = book.match_order(&bid(10, 54, taker, 1000)).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, plain_id);
+        assert_eq!(book.find_order(hidden_id).unwrap().quantity, 100);
+        
+        // A taker meeting the reserve fills it at the displayed price
+        let fills = book.match_order(&bid(10, 55, taker, 1000)).unwrap();
+        assert_eq!(fills[0].maker_order_id, hidden_id);
+        assert_eq!(fills[0].price, 50);
+        assert_eq!(book.find_order(hidden_id).unwrap().quantity, 90);
+    }
+    
+    #[test]
+    fn test_attested_ask_fills_only_attested_takers() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let kyc_id = generate_order_id(&maker, 0, 1000);
+        let kyc = Order::new(kyc_id, maker, 100, 50, 1000, OrderType::Limit, Side::Ask, 0, "PayPal".to_string())
+            .with_options(&OrderOptions {
+                requires_attestation: true,
+                ..Default::default()
+            });
+        let open_id = generate_order_id(&maker, 1, 1000);
+        let open = Order::new(open_id, maker, 100, 52, 1000, OrderType::Limit, Side::Ask, 1, "PayPal".to_string());
+        book.insert_order(kyc).unwrap();
+        book.insert_order(open).unwrap();
+        
+        // An un-attested taker passes over the better-priced KYC ask
+        let fills = book.match_order(&bid(10, 60, taker, 1000)).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, open_id);
+        assert_eq!(book.find_order(kyc_id).unwrap().quantity, 100);
+        
+        // An attested taker fills it first
+        let fills = book
+            .match_order(&MatchRequest { taker_attested: true, ..bid(10, 60, taker, 1000) })
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, kyc_id);
+        assert_eq!(book.find_order(kyc_id).unwrap().quantity, 90);
+    }
+    
+    #[test]
+    fn test_taker_jurisdiction_filters_makers() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let mut ids = Vec::new();
+        for (seq, (price, jurisdiction)) in [(50, Some(*b"DE")), (51, None), (52, Some(*b"US"))].into_iter().enumerate() {
+            let id = generate_order_id(&maker, seq as u64, 1000);
+            let order = Order::new(id, maker, 100, price, 1000, OrderType::Limit, Side::Ask, seq as u64, "PayPal".to_string())
+                .with_options(&OrderOptions { jurisdiction, ..Default::default() });
+            book.insert_order(order).unwrap();
+            ids.push(id);
+        }
+        
+        // A US taker skips the cheaper EU and unrestricted asks for the US one
+        let fills = book
+            .match_order(&MatchRequest { taker_jurisdiction: *b"US", ..bid(10, 60, taker, 1000) })
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, ids[2]);
+        
+        // A taker without a requirement only reaches unrestricted makers
+        let fills = book.match_order(&bid(10, 60, taker, 1000)).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, ids[1]);
+        assert_eq!(book.find_order(ids[0]).unwrap().quantity, 100);
+        
+        assert!(is_valid_jurisdiction(*b"US"));
+        assert!(!is_valid_jurisdiction(*b"us"));
+        assert!(!is_valid_jurisdiction([0; 2]));
+    }
+    
+    #[test]
+    fn test_lifetime_stats() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        for seq in 0..3u64 {
+            let order = Order::new(
+                generate_order_id(&owner, seq, 1000),
+                owner,
+                100,
+                50 + seq,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+            book.record_placed().unwrap();
+        }
+        
+        let fills = book.match_order(&bid(150, 60, Pubkey::new_unique(), 2000)).unwrap();
+        book.record_fills(&fills).unwrap();
+        book.record_cancelled(1).unwrap();
+        assert_eq!(
+            book.lifetime_stats(),
+            LifetimeStats { volume: 150, fills: 2, placed: 3, cancelled: 1 }
+        );
+        
+        book.lifetime_cancelled = u64::MAX;
+        assert_eq!(book.record_cancelled(1).unwrap_err(), ErrorCode::MathOverflow.into());
+    }
+    
+    #[test]
+    fn test_escrow_ledger() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        book.lock_escrow(100).unwrap();
+        book.lock_escrow(250).unwrap();
+        book.release_escrow(150).unwrap();
+        assert_eq!(book.escrow_locked, 200);
+        
+        // Releasing more than is locked is rejected and leaves the ledger alone
+        assert_eq!(book.release_escrow(201).unwrap_err(), ErrorCode::EscrowLedgerMismatch.into());
+        assert_eq!(book.escrow_locked, 200);
+        assert_eq!(book.lock_escrow(u64::MAX).unwrap_err(), ErrorCode::MathOverflow.into());
+    }
+    
+    #[test]
+    fn test_opposing_side_owned_by() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let place = |book: &mut OrderBook, maker: Pubkey, price: u64, seq: u64| {
+            let order = Order::new(
+                generate_order_id(&maker, seq, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        };
+        
+        // An empty side is not "owned"
+        assert!(!book.opposing_side_owned_by(Side::Bid, &owner));
+        
+        place(&mut book, owner, 50, 0);
+        place(&mut book, owner, 55, 1);
+        assert!(book.opposing_side_owned_by(Side::Bid, &owner));
+        assert!(!book.opposing_side_owned_by(Side::Ask, &owner));
+        
+        place(&mut book, Pubkey::new_unique(), 60, 2);
+        assert!(!book.opposing_side_owned_by(Side::Bid, &owner));
+    }
+    
+    #[test]
+    fn test_crosses_own_order() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        for (seq, (maker, price)) in [(other, 50), (owner, 55)].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        // Only each maker's own ask counts, and only for bids reaching its price
+        assert!(!book.crosses_own_order(Side::Bid, 54, &owner));
+        assert!(book.crosses_own_order(Side::Bid, 55, &owner));
+        assert!(book.crosses_own_order(Side::Bid, 50, &other));
+        assert!(!book.crosses_own_order(Side::Bid, 49, &other));
+        assert!(!book.crosses_own_order(Side::Ask, 40, &owner));
+    }
+    
+    #[test]
+    fn test_level_orders_in_time_priority() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let makers = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        for (seq, (maker, quantity)) in makers.iter().zip([100, 200, 300]).enumerate() {
+            let mut order = Order::new(
+                generate_order_id(maker, seq as u64, 1000 + seq as i64),
+                *maker,
+                quantity,
+                50,
+                1000 + seq as i64,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            order.sequence_number = seq as u64;
+            book.insert_order(order).unwrap();
+        }
+        
+        let level = book.level_orders(Side::Ask, 50);
+        assert_eq!(level.iter().map(|o| o.owner).collect::<Vec<_>>(), makers.to_vec());
+        assert_eq!(level.iter().map(|o| o.quantity).collect::<Vec<_>>(), vec![100, 200, 300]);
+        assert_eq!(level[2].timestamp, 1002);
+        
+        assert!(book.level_orders(Side::Ask, 51).is_empty());
+        assert!(book.level_orders(Side::Bid, 50).is_empty());
+    }
+    
+    #[test]
+    fn test_simulate_match_leaves_book_untouched() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        for (seq, price) in [50, 52, 60].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        let before = book.snapshot();
+        
+        let taker = Pubkey::new_unique();
+        let simulated = book.simulate_match(&bid(250, 60, taker, 2000));
+        assert_eq!(book.snapshot(), before);
+        assert_eq!(simulated.iter().map(|f| f.price).collect::<Vec<_>>(), vec![50, 52, 60]);
+        assert_eq!(simulated.last().unwrap().maker_remaining, 50);
+        
+        // The real match produces the same fills
+        let fills = book.match_order(&bid(250, 60, taker, 2000)).unwrap();
+        assert_eq!(fills, simulated);
+        
+        // The maker can't hit their own orders in simulation either
+        assert!(book.simulate_match(&bid(10, 60, maker, 2000)).is_empty());
+    }
+    
+    #[test]
+    fn test_quote_for_base_matches_actual_fills() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        for (seq, (price, quantity)) in [(50_015, 300), (50_150, 200), (51_000, 500)].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                quantity,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        let price_scale = 100;
+        let request = bid(700, 51_000, Pubkey::new_unique(), 2000);
+        let quote = book.quote_for_base(&request, price_scale, 25).unwrap();
+        assert_eq!(quote.base_filled, 700);
+        // 50_015×300 + 50_150×200 + 51_000×200 = 35_234_500 at scale 100
+        assert_eq!(quote.gross_notional, 352_345);
+        assert_eq!(quote.fee, 880);
+        assert_eq!(quote.net, 353_225);
+        assert_eq!(quote.vwap, 50_335);
+        
+        // The real match costs what was previewed, up to per-fill rounding
+        let fills = book.match_order(&request).unwrap();
+        let actual_gross: u64 = fills
+            .iter()
+            .map(|fill| crate::order::notional(fill.price, fill.quantity, price_scale).unwrap())
+            .sum();
+        assert_eq!(fills.iter().map(|f| f.quantity).sum::<u64>(), quote.base_filled);
+        assert!(quote.gross_notional.abs_diff(actual_gross) < fills.len() as u64);
+        let actual_net = actual_gross + actual_gross * 25 / 10_000;
+        assert!(quote.net.abs_diff(actual_net) <= fills.len() as u64);
+        
+        // Asks receive gross minus the fee; an empty book quotes nothing
+        let ask_quote = book
+            .quote_for_base(&MatchRequest { side: Side::Ask, ..bid(10, 1, Pubkey::new_unique(), 2000) }, price_scale, 25)
+            .unwrap();
+        assert_eq!(ask_quote, BaseQuote { base_filled: 0, gross_notional: 0, fee: 0, net: 0, vwap: 0 });
+    }
+    
+    #[test]
+    fn test_estimate_match_cost_scales_with_fills() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        // Two orders at 50, one each at 51 and 52
+        for (seq, price) in [50, 50, 51, 52].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        let taker = Pubkey::new_unique();
+        let base = OrderBook::MATCH_BASE_COMPUTE_UNITS;
+        let level = OrderBook::MATCH_LEVEL_COMPUTE_UNITS;
+        let fill = OrderBook::MATCH_FILL_COMPUTE_UNITS;
+        
+        let none = book.estimate_match_cost(&bid(100, 49, taker, 2000));
+        assert_eq!((none.levels, none.fills, none.compute_units), (0, 0, base));
+        
+        let one = book.estimate_match_cost(&bid(100, 52, taker, 2000));
+        assert_eq!((one.levels, one.fills, one.compute_units), (1, 1, base + level + fill));
+        
+        let same_level = book.estimate_match_cost(&bid(200, 52, taker, 2000));
+        assert_eq!((same_level.levels, same_level.fills), (1, 2));
+        
+        let sweep = book.estimate_match_cost(&bid(400, 52, taker, 2000));
+        assert_eq!((sweep.levels, sweep.fills), (3, 4));
+        assert_eq!(sweep.compute_units, base + 3 * level + 4 * fill);
+        assert!(none.compute_units < one.compute_units);
+        assert!(one.compute_units < same_level.compute_units);
+        assert!(same_level.compute_units < sweep.compute_units);
+        
+        // The fills counted are the ones the real match produces
+        assert_eq!(book.match_order(&bid(400, 52, taker, 2000)).unwrap().len(), 4);
+    }
+    
+    #[test]
+    fn test_fillable_up_to_stops_at_price_boundary() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        let levels = [
+            (Side::Ask, 101, 10),
+            (Side::Ask, 101, 5),
+            (Side::Ask, 103, 20),
+            (Side::Ask, 106, 40),
+            (Side::Bid, 99, 7),
+            (Side::Bid, 97, 11),
+            (Side::Bid, 94, 13),
+        ];
+        for (seq, (side, price, quantity)) in levels.into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                quantity,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        // Makers matching would skip don't count
+        let expired = Order::new(
+            generate_order_id(&maker, levels.len() as u64, 1000),
+            maker,
+            50,
+            103,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            levels.len() as u64,
+            "PayPal".to_string(),
+        )
+        .with_options(&OrderOptions { expiry_timestamp: 1500, ..OrderOptions::default() });
+        book.insert_order(expired).unwrap();
+        
+        let taker = Pubkey::new_unique();
+        let buying = |price| book.fillable_up_to(&bid(u64::MAX, price, taker, 2000));
+        let selling = |price| book.fillable_up_to(&MatchRequest { side: Side::Ask, ..bid(u64::MAX, price, taker, 2000) });
+        
+        // Buying: asks at or below the price, boundary level included
+        assert_eq!(buying(100), 0);
+        assert_eq!(buying(101), 15);
+        assert_eq!(buying(105), 35);
+        assert_eq!(buying(106), 75);
+        assert_eq!(buying(u64::MAX), 75);
+        
+        // Selling: bids at or above the price
+        assert_eq!(selling(100), 0);
+        assert_eq!(selling(99), 7);
+        assert_eq!(selling(95), 18);
+        assert_eq!(selling(1), 31);
+        
+        // Before its expiry the skipped ask counts again
+        assert_eq!(book.fillable_up_to(&bid(u64::MAX, 105, taker, 1200)), 85);
+    }
+    
+    #[test]
+    fn test_match_skips_makers_without_accepted_payment_method() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        for (seq, payment_method) in ["PayPal", "Wise,Zelle"].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                50,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                payment_method.to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        let paypal_order = book.order_queues[0].priority_order(QueueDiscipline::Fifo)[0];
+        
+        let taker = Pubkey::new_unique();
+        let zelle = ["zelle".to_string()];
+        let fills = book
+            .match_order(&MatchRequest { accepted_payment_methods: &zelle, ..bid(150, 50, taker, 2000) })
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].quantity, 100);
+        assert_ne!(fills[0].maker_order_id, paypal_order);
+        
+        // The skipped PayPal maker keeps resting
+        assert_eq!(book.find_order(paypal_order).unwrap().quantity, 100);
+    }
+    
+    #[test]
+    fn test_queue_discipline_orders_a_level() {
+        // Three makers at one price, placed oldest to newest
+        let build = || {
+            let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+            let orders: Vec<Order> = (0..3u64)
+                .map(|seq| {
+                    let maker = Pubkey::new_unique();
+                    Order::new(
+                        generate_order_id(&maker, seq, 1000),
+                        maker,
+                        100,
+                        50,
+                        1000,
+                        OrderType::Limit,
+                        Side::Ask,
+                        seq,
+                        "PayPal".to_string(),
+                    )
+                })
+                .collect();
+            for order in &orders {
+                book.insert_order(*order).unwrap();
+            }
+            (book, orders)
+        };
+        let taker = Pubkey::new_unique();
+        
+        let (mut fifo, orders) = build();
+        assert_eq!(fifo.get_best_order(Side::Ask, QueueDiscipline::Fifo).unwrap().order_id, orders[0].order_id);
+        let fills = fifo.match_order(&bid(250, 50, taker, 2000)).unwrap();
+        let filled: Vec<(u128, u64)> = fills.iter().map(|f| (f.maker_order_id, f.quantity)).collect();
+        assert_eq!(filled, vec![(orders[0].order_id, 100), (orders[1].order_id, 100), (orders[2].order_id, 50)]);
+        
+        let (mut lifo, orders) = build();
+        assert_eq!(lifo.get_best_order(Side::Ask, QueueDiscipline::Lifo).unwrap().order_id, orders[2].order_id);
+        let fills = lifo
+            .match_order(&MatchRequest { discipline: QueueDiscipline::Lifo, ..bid(250, 50, taker, 2000) })
+            .unwrap();
+        let filled: Vec<(u128, u64)> = fills.iter().map(|f| (f.maker_order_id, f.quantity)).collect();
+        assert_eq!(filled, vec![(orders[2].order_id, 100), (orders[1].order_id, 100), (orders[0].order_id, 50)]);
+        
+        // Removal by ID doesn't depend on the discipline
+        let oldest = lifo.remove_order(orders[0].order_id, Side::Ask, 50).unwrap();
+        assert_eq!(oldest.quantity, 50);
+    }
+    
+    #[test]
+    fn test_match_stops_at_max_makers() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let makers: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        // maker 0 rests twice; levels 50..54, one maker each after that
+        for (seq, (maker, price)) in [(0, 50), (1, 51), (0, 52), (2, 53), (3, 54), (4, 54)]
+            .into_iter()
+            .enumerate()
+        {
+            let order = Order::new(
+                generate_order_id(&makers[maker], seq as u64, 1000),
+                makers[maker],
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        let taker = Pubkey::new_unique();
+        let capped = MatchRequest { max_makers: 2, ..bid(600, 60, taker, 2000) };
+        let simulated = book.simulate_match(&capped);
+        let fills = book.match_order(&capped).unwrap();
+        assert_eq!(fills, simulated);
+        
+        // maker 0's second order still fills; maker 2 would be a third counterparty
+        assert_eq!(fills.iter().map(|f| f.price).collect::<Vec<_>>(), vec![50, 51, 52]);
+        assert_eq!(book.best_ask, 53);
+        
+        // No cap takes the rest
+        let fills = book.match_order(&bid(600, 60, taker, 2000)).unwrap();
+        assert_eq!(fills.len(), 3);
+    }
+    
+    #[test]
+    fn test_slippage_bps() {
+        assert_eq!(OrderBook::slippage_bps(50, 50), 0);
+        assert_eq!(OrderBook::slippage_bps(50, 60), 2_000);
+        assert_eq!(OrderBook::slippage_bps(50, 40), 2_000);
+        assert_eq!(OrderBook::slippage_bps(3, 4), 3_333);
+        assert_eq!(OrderBook::slippage_bps(0, 10), 0);
+    }
+    
+    #[test]
+    fn test_min_spread_rejects_narrowing_orders() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let bid = Order::new(generate_order_id(&owner, 0, 1000), owner, 10, 90, 1000, OrderType::Limit, Side::Bid, 0, "PayPal".to_string());
+        let ask = Order::new(generate_order_id(&owner, 1, 1000), owner, 10, 110, 1000, OrderType::Limit, Side::Ask, 1, "PayPal".to_string());
+        book.insert_order(bid).unwrap();
+        book.insert_order(ask).unwrap();
+        
+        // 90/110 is a 2000 bps spread; a 1000 bps floor allows bids up to 99
+        assert!(book.check_min_spread(Side::Bid, 99, 1000).is_ok());
+        assert_eq!(
+            book.check_min_spread(Side::Bid, 100, 1000).unwrap_err(),
+            ErrorCode::SpreadTooTight.into()
+        );
+        assert_eq!(
+            book.check_min_spread(Side::Ask, 99, 1000).unwrap_err(),
+            ErrorCode::SpreadTooTight.into()
+        );
+        
+        // Orders behind the best don't change the spread; crossing orders match through
+        assert!(book.check_min_spread(Side::Bid, 80, 3000).is_ok());
+        assert!(book.check_min_spread(Side::Bid, 110, 1000).is_ok());
+        assert!(book.check_min_spread(Side::Ask, 90, 1000).is_ok());
+        
+        // No floor, or no opposite side, means nothing to enforce
+        assert!(book.check_min_spread(Side::Bid, 109, 0).is_ok());
+        let empty = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(empty.check_min_spread(Side::Bid, 100, 1000).is_ok());
+    }
+    
+    #[test]
+    fn test_spread_bps_empty_book() {
+        let book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(book.spread_bps(), None);
+    }
+    
+    #[test]
+    fn test_get_depth_reflects_partial_fills() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        for (seq, (price, quantity)) in [(50, 100), (50, 40), (51, 70)].into_iter().enumerate() {
+            let order = Order::new(generate_order_id(&maker, seq as u64, 1000), maker, quantity, price, 1000, OrderType::Limit, Side::Ask, seq as u64, "PayPal".to_string());
+            book.insert_order(order).unwrap();
+        }
+        assert_eq!(book.get_depth(Side::Ask, 1), vec![(50, 140)]);
+        
+        // Fills the first maker partially; the level keeps both orders
+        let fills = book.match_order(&bid(30, 50, Pubkey::new_unique(), 1000)).unwrap();
+        assert_eq!(fills[0].quantity, 30);
+        assert_eq!(book.get_depth(Side::Ask, 1), vec![(50, 110)]);
+        assert_eq!(book.get_depth(Side::Ask, 2), vec![(50, 110), (51, 70)]);
+        
+        // The cached level total stays in step, through partial and full fills
+        assert!(book.verify_integrity().ok);
+        book.match_order(&bid(80, 50, Pubkey::new_unique(), 1000)).unwrap();
+        assert_eq!(book.get_depth(Side::Ask, 1), vec![(50, 30)]);
+        assert!(book.verify_integrity().ok);
+    }
+    
+    #[test]
+    fn test_match_purges_ghost_orders() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        let mut ids = Vec::new();
+        for (seq, price) in [50, 50, 50, 52].into_iter().enumerate() {
+            let order_id = generate_order_id(&maker, seq as u64, 1000);
+            let order = Order::new(order_id, maker, 100, price, 1000, OrderType::Limit, Side::Ask, seq as u64, "PayPal".to_string());
+            book.insert_order(order).unwrap();
+            ids.push(order_id);
+        }
+        
+        // Ghosts behind the head of the 50 level and alone at 52
+        for ghost in [ids[2], ids[3]] {
+            let order = book.find_order_mut(ghost).unwrap();
+            let quantity = order.quantity;
+            order.quantity = 0;
+            let queue = book.order_queues.iter_mut().find(|q| q.orders.iter().any(|o| o.order_id == ghost)).unwrap();
+            queue.total_quantity -= quantity;
+        }
+        assert_eq!(
+            book.verify_integrity().discrepancy,
+            Some(IntegrityDiscrepancy::FilledOrderResting { order_id: ids[2] })
+        );
+        
+        // A small match at the head is enough to clear both
+        let fills = book.match_order(&bid(10, 50, Pubkey::new_unique(), 1000)).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert!(book.find_order(ids[2]).is_none());
+        assert!(book.find_order(ids[3]).is_none());
+        assert_eq!(book.total_orders, 2);
+        assert_eq!(book.get_depth(Side::Ask, 5), vec![(50, 190)]);
+        assert!(book.verify_integrity().ok);
+    }
+    
+    #[test]
+    fn test_get_depth_is_deterministic() {
+        // Equal quantities at every level, inserted in two different orders
+        let build = |prices: &[u64]| {
+            let mut book = OrderBook::new(Pubkey::default(), Pubkey::default(), Pubkey::default());
+            let owner = Pubkey::new_from_array([7; 32]);
+            for (seq, &price) in prices.iter().enumerate() {
+                let side = if price < 100 { Side::Bid } else { Side::Ask };
+                let order = Order::new(
+                    generate_order_id(&owner, seq as u64, 1000),
+                    owner,
+                    50,
+                    price,
+                    1000,
+                    OrderType::Limit,
+                    side,
+                    seq as u64,
+                    "PayPal".to_string(),
+                );
+                book.insert_order(order).unwrap();
+            }
+            book
+        };
+        let depth_bytes = |book: &OrderBook| -> Vec<u8> {
+            [Side::Bid, Side::Ask]
+                .into_iter()
+                .flat_map(|side| book.get_depth(side, 10))
+                .flat_map(|(price, quantity)| [price.to_le_bytes(), quantity.to_le_bytes()].concat())
+                .collect()
+        };
+        
+        let book = build(&[97, 103, 95, 101, 99, 105, 93]);
+        let first = depth_bytes(&book);
+        for _ in 0..5 {
+            assert_eq!(depth_bytes(&book), first);
+        }
+        assert_eq!(depth_bytes(&build(&[105, 93, 99, 101, 97, 95, 103])), first);
+        
+        assert_eq!(book.get_depth(Side::Bid, 10), vec![(99, 50), (97, 50), (95, 50), (93, 50)]);
+        assert_eq!(book.get_depth(Side::Ask, 2), vec![(101, 50), (103, 50)]);
+    }
+    
+    #[test]
+    fn test_remove_order_reports_missing_level_vs_missing_order() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let order = Order::new(
+            order_id,
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Bid,
+            1,
+            "PayPal".to_string(),
+        );
+        book.insert_order(order).unwrap();
+        
+        assert_eq!(
+            book.remove_order(order_id, Side::Bid, 51).unwrap_err(),
+            ErrorCode::PriceLevelNotFound.into()
+        );
+        assert_eq!(
+            book.remove_order(order_id, Side::Ask, 50).unwrap_err(),
+            ErrorCode::PriceLevelNotFound.into()
+        );
+        assert_eq!(
+            book.remove_order(order_id + 1, Side::Bid, 50).unwrap_err(),
+            ErrorCode::OrderNotFoundInQueue.into()
+        );
+        
+        // Nothing was removed along the way
+        assert_eq!(book.total_orders, 1);
+        assert!(book.remove_order(order_id, Side::Bid, 50).is_ok());
+    }
+    
+    #[test]
+    fn test_order_book_remove() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let order = Order::new(
+            order_id,
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Bid,
+            1,
+            "PayPal".to_string(),
+        );
+        
+        book.insert_order(order).unwrap();
+        assert_eq!(book.total_orders, 1);
+        
+        let removed = book.remove_order(order_id, Side::Bid, 50).unwrap();
+        assert_eq!(removed.order_id, order_id);
+        assert_eq!(book.total_orders, 0);
+        assert_eq!(book.best_bid, 0);
+    }
+    
+    #[test]
+    fn test_match_order_returns_fills() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let ask_id = generate_order_id(&maker, 1, 1000);
+        let ask = Order::new(
+            ask_id,
+            maker,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        );
+        book.insert_order(ask).unwrap();
+        
+        let fills = book.match_order(&bid(60, 50, taker, 2000)).unwrap();
+        assert_eq!(
+            fills,
+            vec![Fill {
+                trade_id: 0,
+                price: 50,
+                quantity: 60,
+                maker_order_id: ask_id,
+                maker_owner: maker,
+                maker_remaining: 40,
+                threshold_reached: false,
+            }]
+        );
+    }
+    
+    #[test]
+    fn test_compact_snapshot_round_trip() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        for (seq, (side, price, quantity)) in [
+            (Side::Ask, 60, 100),
+            (Side::Ask, 55, 250),
+            (Side::Ask, 55, 50),
+            (Side::Bid, 40, 70),
+            (Side::Bid, 45, u64::MAX / 2),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                quantity,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        let data = book.serialize_snapshot();
+        assert_eq!(data[0], OrderBook::COMPACT_SNAPSHOT_VERSION);
+        assert_eq!(data.len(), 3 + 4 * OrderBook::COMPACT_LEVEL_LEN);
+        
+        let (bids, asks) = OrderBook::deserialize_snapshot(&data).unwrap();
+        let snapshot = book.snapshot();
+        assert_eq!(bids, snapshot.bids);
+        assert_eq!(asks, snapshot.asks);
+        
+        // Truncated or unknown-version blobs are rejected
+        assert!(OrderBook::deserialize_snapshot(&data[..data.len() - 1]).is_none());
+        let mut future = data.clone();
+        future[0] = 2;
+        assert!(OrderBook::deserialize_snapshot(&future).is_none());
+        
+        // An empty book is just the header
+        let empty = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(empty.serialize_snapshot(), vec![OrderBook::COMPACT_SNAPSHOT_VERSION, 0, 0]);
+    }
+    
+    #[test]
+    fn test_ladder_checksum_tracks_every_level() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let maker = Pubkey::new_unique();
+        for (seq, (side, price)) in [(Side::Ask, 60), (Side::Ask, 55), (Side::Bid, 40)].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        let checksum = |book: &OrderBook| OrderBook::ladder_checksum(&book.levels(Side::Bid), &book.levels(Side::Ask));
+        let before = checksum(&book);
+        assert_eq!(checksum(&book.clone()), before);
+        
+        // A change deep in the ladder changes the checksum
+        book.order_queues.iter_mut().flat_map(|q| q.orders.iter_mut()).find(|o| o.price == 60).unwrap().quantity = 99;
+        assert_ne!(checksum(&book), before);
+        
+        // So does moving a level to the other side
+        let bids = book.levels(Side::Bid);
+        let asks = book.levels(Side::Ask);
+        assert_ne!(OrderBook::ladder_checksum(&asks, &bids), OrderBook::ladder_checksum(&bids, &asks));
+    }
+    
+    #[test]
+    fn test_snapshot_reflects_live_quantities() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let maker = Pubkey::new_unique();
+        for (seq, (side, price)) in [(Side::Ask, 60), (Side::Ask, 55), (Side::Ask, 55), (Side::Bid, 40)]
+            .into_iter()
+            .enumerate()
+        {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        // Partially fill the best ask level
+        book.match_order(&bid(30, 55, Pubkey::new_unique(), 2000)).unwrap();
+        
+        let snapshot = book.snapshot();
+        assert_eq!(
+            snapshot.asks,
+            vec![
+                BookLevel { price: 55, quantity: 170, order_count: 2 },
+                BookLevel { price: 60, quantity: 100, order_count: 1 },
+            ]
+        );
+        assert_eq!(snapshot.bids, vec![BookLevel { price: 40, quantity: 100, order_count: 1 }]);
+        assert_eq!(snapshot.total_orders, 4);
+    }
+    
+    #[test]
+    fn test_fill_threshold_reached_once() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let maker = Pubkey::new_unique();
+        let ask = Order::new(
+            generate_order_id(&maker, 1, 1000),
+            maker,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        )
+        .with_options(&OrderOptions {
+            fill_notify_bps: 5_000,
+            ..Default::default()
+        });
+        book.insert_order(ask).unwrap();
+        
+        // Fill in increments of 20: threshold (50%) is crossed on the third fill
+        let taker = Pubkey::new_unique();
+        let crossings: Vec<bool> = (0..5)
+            .map(|_| book.match_order(&bid(20, 50, taker, 2000)).unwrap()[0].threshold_reached)
+            .collect();
+        
+        assert_eq!(crossings, vec![false, false, true, false, false]);
+    }
+    
+    #[test]
+    fn test_fifo_preserved_when_price_level_is_recreated() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let maker = Pubkey::new_unique();
+        let new_ask = |seq: u64| {
+            Order::new(
+                generate_order_id(&maker, seq, 1000 + seq as i64),
+                maker,
+                10,
+                50,
+                1000 + seq as i64,
+                OrderType::Limit,
+                Side::Ask,
+                seq,
+                "PayPal".to_string(),
+            )
+        };
+        
+        // Drain the only order at 50 so the price level leaves the tree
+        let first = new_ask(1);
+        book.insert_order(first).unwrap();
+        book.remove_order(first.order_id, Side::Ask, 50).unwrap();
+        assert_eq!(book.asks.find(50), None);
+        
+        // Re-add two orders at the same price
+        let second = new_ask(2);
+        let third = new_ask(3);
+        book.insert_order(second).unwrap();
+        book.insert_order(third).unwrap();
+        
+        // The drained queue slot is reused rather than leaking a new one
+        assert_eq!(book.next_queue_index, 1);
+        
+        let taker = Pubkey::new_unique();
+        let fills = book.match_order(&bid(15, 50, taker, 2000)).unwrap();
+        let filled: Vec<(u128, u64)> = fills.iter().map(|f| (f.maker_order_id, f.quantity)).collect();
+        assert_eq!(filled, vec![(second.order_id, 10), (third.order_id, 5)]);
+    }
+    
+    #[test]
+    fn test_fifo_follows_sequence_number_after_compaction() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let maker = Pubkey::new_unique();
+        let orders: Vec<Order> = (1..=3)
+            .map(|seq| {
+                let mut order = Order::new(
+                    generate_order_id(&maker, seq, 1000),
+                    maker,
+                    10,
+                    50,
+                    1000,
+                    OrderType::Limit,
+                    Side::Ask,
+                    seq,
+                    "PayPal".to_string(),
+                );
+                order.sequence_number = seq;
+                order
+            })
+            .collect();
+        for order in orders.iter() {
+            book.insert_order(*order).unwrap();
+        }
+        
+        // Simulate a compaction that reshuffles queue storage
+        let queue_index = book.asks.find(50).unwrap() as usize;
+        book.order_queues[queue_index].orders.reverse();
+        assert_eq!(book.get_best_order(Side::Ask, QueueDiscipline::Fifo).unwrap().order_id, orders[0].order_id);
+        
+        let taker = Pubkey::new_unique();
+        let fills = book.match_order(&bid(15, 50, taker, 2000)).unwrap();
+        let filled: Vec<(u128, u64)> = fills.iter().map(|f| (f.maker_order_id, f.quantity)).collect();
+        assert_eq!(filled, vec![(orders[0].order_id, 10), (orders[1].order_id, 5)]);
+    }
+    
+    #[test]
+    fn test_maker_volume_credited_per_owner() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let maker_a = Pubkey::new_unique();
+        let maker_b = Pubkey::new_unique();
+        for (seq, (maker, price)) in [(maker_a, 50), (maker_b, 51), (maker_a, 52)].into_iter().enumerate() {
+            let order = Order::new(
+                generate_order_id(&maker, seq as u64, 1000),
+                maker,
+                100,
+                price,
+                1000,
+                OrderType::Limit,
+                Side::Ask,
+                seq as u64,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+        }
+        
+        let taker = Pubkey::new_unique();
+        book.match_order(&bid(100, 52, taker, 2000)).unwrap(); // maker_a @ 50
+        book.match_order(&bid(130, 52, taker, 2000)).unwrap(); // maker_b @ 51, maker_a @ 52
+        
+        assert_eq!(book.get_maker_volume(&maker_a), Some(130));
+        assert_eq!(book.get_maker_volume(&maker_b), Some(100));
+        assert_eq!(book.get_maker_volume(&taker), None);
+        
+        // Once the table is full, new makers are reported untracked rather than as zero
+        while book.maker_volumes.len() < OrderBook::MAX_TRACKED_MAKERS {
+            book.credit_maker_volume(Pubkey::new_unique(), 1);
+        }
+        let late = Pubkey::new_unique();
+        book.credit_maker_volume(late, 10);
+        assert_eq!(book.get_maker_volume(&late), None);
+        book.credit_maker_volume(maker_b, 10);
+        assert_eq!(book.get_maker_volume(&maker_b), Some(110));
+    }
+    
+    #[test]
+    fn test_inactive_maker_skipped_until_activation() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut book = OrderBook::new(market, base_mint, quote_mint);
+        
+        let slow_maker = Pubkey::new_unique();
+        let fresh_maker = Pubkey::new_unique();
+        let resting = Order::new(
+            generate_order_id(&slow_maker, 1, 1000),
+            slow_maker,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        );
+        let delayed = Order::new(
+            generate_order_id(&fresh_maker, 2, 1005),
+            fresh_maker,
+            100,
+            49,
+            1005,
+            OrderType::Limit,
+            Side::Ask,
+            2,
+            "PayPal".to_string(),
+        )
+        .with_options(&OrderOptions {
+            activation_delay: 10,
+            ..Default::default()
+        });
+        book.insert_order(resting).unwrap();
+        book.insert_order(delayed).unwrap();
+        
+        let taker = Pubkey::new_unique();
+        
+        // Before activation the better-priced delayed ask is skipped
+        let fills = book.match_order(&bid(20, 50, taker, 1010)).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, resting.order_id);
+        
+        // Once active it matches first
+        let fills = book.match_order(&bid(20, 50, taker, 1015)).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_order_id, delayed.order_id);
+    }
+    
+    #[test]
+    fn test_import_orders_preserves_timestamp_priority() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        
+        let imported: Vec<ImportedOrder> = [1003, 1001, 1004, 1000, 1002]
+            .into_iter()
+            .map(|timestamp| ImportedOrder {
+                owner: Pubkey::new_unique(),
+                side: Side::Ask,
+                price: 50,
+                quantity: 10,
+                timestamp,
+                client_order_id: timestamp as u64,
+                payment_method: "PayPal".to_string(),
+                settlement_destination: Pubkey::new_unique(),
+            })
+            .collect();
+        
+        let orders = book.import_orders(&imported, 7, 2000).unwrap();
+        assert_eq!(orders.len(), 5);
+        assert_eq!(book.total_orders, 5);
+        assert_eq!(book.lifetime_placed, 5);
+        assert_eq!(
+            orders.iter().map(|o| o.sequence_number).collect::<Vec<_>>(),
+            vec![7, 8, 9, 10, 11]
+        );
+        
+        // Time priority follows the original timestamps, not the batch order
+        let level = book.level_orders(Side::Ask, 50);
+        assert_eq!(
+            level.iter().map(|o| o.timestamp).collect::<Vec<_>>(),
+            vec![1000, 1001, 1002, 1003, 1004]
+        );
+        
+        // FIFO matching fills the oldest imports first
+        let fills = book.match_order(&bid(25, 50, Pubkey::new_unique(), 2000)).unwrap();
+        let filled_owners: Vec<Pubkey> = fills.iter().map(|f| f.maker_owner).collect();
+        let oldest_owners: Vec<Pubkey> = level.iter().take(3).map(|o| o.owner).collect();
+        assert_eq!(filled_owners, oldest_owners);
+        assert_eq!(fills[2].quantity, 5);
+        book.record_fills(&fills).unwrap();
+        
+        // Once the book has traded it no longer accepts imports
+        let late = ImportedOrder { timestamp: 1005, ..imported[0].clone() };
+        assert!(book.import_orders(&[late], 12, 2000).is_err());
+    }
+    
+    #[test]
+    fn test_price_checkpoints_follow_best_price_moves() {
+        let mut book = OrderBook::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        book.checkpoint_threshold_bps = 100;
+        let maker = Pubkey::new_unique();
+        let place = |book: &mut OrderBook, seq: u64, side: Side, price: u64, slot: u64| {
+            let order = Order::new(
+                generate_order_id(&maker, seq, 1000),
+                maker,
+                10,
+                price,
+                1000,
+                OrderType::Limit,
+                side,
+                seq,
+                "PayPal".to_string(),
+            );
+            book.insert_order(order).unwrap();
+            book.touch(slot);
+        };
+        
+        place(&mut book, 0, Side::Ask, 10_000, 1);
+        place(&mut book, 1, Side::Bid, 9_000, 2);
+        // 0.5% better ask: under the 1% threshold, no checkpoint
+        place(&mut book, 2, Side::Ask, 9_950, 3);
+        // 2% better than the last checkpointed ask
+        place(&mut book, 3, Side::Ask, 9_800, 4);
+        // Behind the best bid, nothing moves
+        place(&mut book, 4, Side::Bid, 8_000, 5);
+        
+        let history: Vec<(u64, u64, u64)> = book
+            .price_checkpoints
+            .iter()
+            .map(|c| (c.slot, c.best_bid, c.best_ask))
+            .collect();
+        assert_eq!(
+            history,
+            vec![(1, 0, 10_000), (2, 9_000, 10_000), (4, 9_000, 9_800)]
+        );
+        
+        // The ring keeps only the newest checkpoints
+        book.checkpoint_threshold_bps = 0;
+        for seq in 5..15 {
+            place(&mut book, seq, Side::Bid, 9_000 + seq, 10 + seq);
+        }
+        assert_eq!(book.price_checkpoints.len(), OrderBook::MAX_PRICE_CHECKPOINTS);
+        assert_eq!(book.price_checkpoints.last().unwrap().best_bid, 9_014);
+        assert_eq!(book.price_checkpoints[0].slot, 17);
     }
 }
@@ -0,0 +1,918 @@
+//! ZK payment proof helpers
+//!
+//! Public signals format: [emailHash[8], fromHeaderHash[8], orderId[2], paymentTimestamp]
+//! The order ID is split into two u64 decimal strings: low at [16], high at [17].
+//! The payment timestamp (unix seconds, from the signed email) is at [18].
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_spl::token;
+use anchor_spl::token_interface::TokenAccount;
+use solana_bn254::prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing};
+use crate::error::ErrorCode;
+use crate::order::{is_canonical_order_id, Order, PaymentStatus};
+use crate::VerifyingKey;
+
+/// Number of public signals emitted by the payment circuit
+pub const PUBLIC_SIGNAL_COUNT: usize = 19;
+
+/// Index of the low 64 bits of the order ID
+pub const ORDER_ID_LOW_INDEX: usize = 16;
+
+/// Index of the high 64 bits of the order ID
+pub const ORDER_ID_HIGH_INDEX: usize = 17;
+
+/// Index of the payment timestamp
+pub const PAYMENT_TIMESTAMP_INDEX: usize = 18;
+
+/// BN254 scalar field modulus; every public signal must be a canonical element below it
+pub const FIELD_MODULUS: &str =
+    "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+
+/// BN254 base field modulus, big-endian; proof point coordinates must be below it
+pub const BASE_FIELD_MODULUS_BE: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29,
+    0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d,
+    0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
+
+/// Size of one encoded field element in proof points
+pub const FIELD_ELEMENT_BYTES: usize = 32;
+
+/// Encoded size of a G1 point (x, y)
+pub const G1_POINT_BYTES: usize = 2 * FIELD_ELEMENT_BYTES;
+
+/// Encoded size of a G2 point (x_im, x_re, y_im, y_re)
+pub const G2_POINT_BYTES: usize = 4 * FIELD_ELEMENT_BYTES;
+
+/// Size of a Groth16 verifying key for the payment circuit:
+/// alpha (G1), beta, gamma, delta (G2), then one IC point (G1) per public
+/// signal plus the constant term, every coordinate little-endian like proof points
+pub const VERIFYING_KEY_LEN: usize =
+    G1_POINT_BYTES + 3 * G2_POINT_BYTES + (PUBLIC_SIGNAL_COUNT + 1) * G1_POINT_BYTES;
+
+/// Maximum settlements per `verify_settlement_batch` call
+/// Each entry carries 256 bytes of proof points plus its public signals
+/// (~400 bytes even with minimal signals), so two fill a 1232-byte transaction
+pub const MAX_SETTLEMENT_BATCH: usize = 2;
+
+/// Proof settling one order: an entry of a `verify_settlement_batch` call, or
+/// the arguments of `verify_settlement`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SettlementRequest {
+    pub order_id: u128,
+    pub proof_a: Vec<u8>,
+    pub proof_b: Vec<u8>,
+    pub proof_c: Vec<u8>,
+    pub public_signals: Vec<String>,
+}
+
+/// Per-order outcome of a batch settlement
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettlementStatus {
+    /// Proof accepted and escrow released
+    Settled,
+    /// Settlement delay has not expired yet; retry later
+    Skipped,
+    /// Order missing or settlement rejected
+    Failed,
+    /// Proof failed the pairing check; recorded on the order, retry after its backoff
+    ProofFailed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SettlementResult {
+    pub order_id: u128,
+    pub status: SettlementStatus,
+}
+
+/// Check a decimal string is a field element: non-empty, digits only, below the modulus
+fn is_field_element(signal: &str) -> bool {
+    if signal.is_empty() || !signal.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    
+    let digits = signal.trim_start_matches('0');
+    // Equal-length decimal strings compare numerically as bytes
+    digits.len() < FIELD_MODULUS.len()
+        || (digits.len() == FIELD_MODULUS.len() && digits < FIELD_MODULUS)
+}
+
+/// Convert one little-endian field element (circom's byte order) to the
+/// big-endian encoding the alt_bn128 syscalls expect
+pub fn le_to_be_field(bytes: &[u8]) -> Result<[u8; FIELD_ELEMENT_BYTES]> {
+    let mut be: [u8; FIELD_ELEMENT_BYTES] = bytes
+        .try_into()
+        .map_err(|_| error!(ErrorCode::InvalidProof))?;
+    be.reverse();
+    Ok(be)
+}
+
+/// Convert a proof point (a run of little-endian coordinates) to big-endian,
+/// rejecting any coordinate that isn't a canonical base field element
+pub fn proof_point_to_be(point: &[u8]) -> Result<Vec<u8>> {
+    let coordinates = point.chunks_exact(FIELD_ELEMENT_BYTES);
+    require!(coordinates.remainder().is_empty(), ErrorCode::InvalidProof);
+    
+    let mut be = Vec::with_capacity(point.len());
+    for (index, coordinate) in coordinates.enumerate() {
+        let coordinate = le_to_be_field(coordinate)?;
+        // Equal-length big-endian arrays compare numerically
+        if coordinate >= BASE_FIELD_MODULUS_BE {
+            msg!("Invalid proof: coordinate {} is not a canonical field element", index);
+            return Err(ErrorCode::InvalidProof.into());
+        }
+        be.extend_from_slice(&coordinate);
+    }
+    
+    Ok(be)
+}
+
+/// Check a verifying key has the payment circuit's layout and canonical coordinates
+/// (structure only: whether its points lie on the curve is left to the pairing)
+pub fn check_verifying_key(key: &[u8]) -> Result<()> {
+    if key.len() != VERIFYING_KEY_LEN {
+        msg!("Invalid verifying key: expected {} bytes, got {}", VERIFYING_KEY_LEN, key.len());
+        return Err(ErrorCode::InvalidVerifyingKey.into());
+    }
+    proof_point_to_be(key).map_err(|_| error!(ErrorCode::InvalidVerifyingKey))?;
+    Ok(())
+}
+
+/// The market's active verifying key, or None while `set_verifying_key` has never run
+/// 
+/// `account` is the market's `verifying_key` PDA; until it is created,
+/// settlement keeps to the structural proof checks.
+pub fn load_verifying_key(account: &AccountInfo) -> Result<Option<Vec<u8>>> {
+    if account.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*account.owner, crate::ID, ErrorCode::InvalidVerifyingKey);
+    let verifying_key = VerifyingKey::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    Ok(Some(verifying_key.active))
+}
+
+/// Big-endian 32-byte scalar of a decimal public signal
+pub fn signal_to_scalar(signal: &str) -> Result<[u8; 32]> {
+    require!(is_field_element(signal), ErrorCode::InvalidProof);
+    
+    let mut scalar = [0u8; 32];
+    for digit in signal.bytes() {
+        let mut carry = (digit - b'0') as u16;
+        for byte in scalar.iter_mut().rev() {
+            let value = *byte as u16 * 10 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+    }
+    Ok(scalar)
+}
+
+/// Negate a big-endian G1 point: (x, y) -> (x, p - y), the identity maps to itself
+pub fn negate_g1(point: &[u8]) -> Vec<u8> {
+    let (x, y) = point.split_at(FIELD_ELEMENT_BYTES);
+    if point.iter().all(|&b| b == 0) {
+        return point.to_vec();
+    }
+    
+    let mut negated = [0u8; FIELD_ELEMENT_BYTES];
+    let mut borrow = 0i16;
+    for i in (0..FIELD_ELEMENT_BYTES).rev() {
+        let mut digit = BASE_FIELD_MODULUS_BE[i] as i16 - y[i] as i16 - borrow;
+        borrow = if digit < 0 { 1 } else { 0 };
+        if digit < 0 {
+            digit += 256;
+        }
+        negated[i] = digit as u8;
+    }
+    [x, &negated].concat()
+}
+
+/// Groth16 pairing check of a proof against `verifying_key` (see `VERIFYING_KEY_LEN`)
+/// 
+/// Accepts when e(-A, B) · e(alpha, beta) · e(vk_x, gamma) · e(C, delta) = 1,
+/// with vk_x = IC[0] + Σ signal_i · IC[i + 1].
+pub fn verify_groth16(
+    verifying_key: &[u8],
+    proof_a: &[u8],
+    proof_b: &[u8],
+    proof_c: &[u8],
+    public_signals: &[String],
+) -> Result<()> {
+    require!(public_signals.len() == PUBLIC_SIGNAL_COUNT, ErrorCode::InvalidProof);
+    let key = proof_point_to_be(verifying_key).map_err(|_| error!(ErrorCode::InvalidVerifyingKey))?;
+    require!(key.len() == VERIFYING_KEY_LEN, ErrorCode::InvalidVerifyingKey);
+    
+    let (alpha, rest) = key.split_at(G1_POINT_BYTES);
+    let (beta, rest) = rest.split_at(G2_POINT_BYTES);
+    let (gamma, rest) = rest.split_at(G2_POINT_BYTES);
+    let (delta, ic) = rest.split_at(G2_POINT_BYTES);
+    
+    let mut vk_x = ic[..G1_POINT_BYTES].to_vec();
+    for (signal, point) in public_signals.iter().zip(ic[G1_POINT_BYTES..].chunks_exact(G1_POINT_BYTES)) {
+        let term = alt_bn128_multiplication(&[point, &signal_to_scalar(signal)?].concat())
+            .map_err(|_| error!(ErrorCode::InvalidVerifyingKey))?;
+        vk_x = alt_bn128_addition(&[vk_x.as_slice(), &term].concat())
+            .map_err(|_| error!(ErrorCode::InvalidVerifyingKey))?;
+    }
+    
+    let pairing_input = [
+        negate_g1(&proof_point_to_be(proof_a)?).as_slice(),
+        &proof_point_to_be(proof_b)?,
+        alpha,
+        beta,
+        &vk_x,
+        gamma,
+        &proof_point_to_be(proof_c)?,
+        delta,
+    ]
+    .concat();
+    let result = alt_bn128_pairing(&pairing_input).map_err(|_| error!(ErrorCode::InvalidProof))?;
+    require!(result.last() == Some(&1), ErrorCode::ProofVerificationFailed);
+    
+    Ok(())
+}
+
+/// Validate every public signal before any of them is indexed or parsed
+pub fn validate_public_signals(public_signals: &[String]) -> Result<()> {
+    if public_signals.len() < PUBLIC_SIGNAL_COUNT {
+        msg!(
+            "Invalid proof: expected {} public signals, got {}",
+            PUBLIC_SIGNAL_COUNT,
+            public_signals.len()
+        );
+        return Err(ErrorCode::InvalidProof.into());
+    }
+    
+    for (index, signal) in public_signals.iter().take(PUBLIC_SIGNAL_COUNT).enumerate() {
+        if signal.is_empty() {
+            msg!("Invalid proof: public signal {} is empty", index);
+            return Err(ErrorCode::InvalidProof.into());
+        }
+        if !is_field_element(signal) {
+            msg!("Invalid proof: public signal {} is not a field element", index);
+            return Err(ErrorCode::InvalidProof.into());
+        }
+    }
+    
+    Ok(())
+}
+
+/// Reconstruct the order ID committed to by the proof's public signals
+pub fn parse_proof_order_id(public_signals: &[String]) -> Result<u128> {
+    validate_public_signals(public_signals)?;
+    
+    let low = public_signals[ORDER_ID_LOW_INDEX]
+        .parse::<u64>()
+        .map_err(|_| ErrorCode::InvalidProof)?;
+    let high = public_signals[ORDER_ID_HIGH_INDEX]
+        .parse::<u64>()
+        .map_err(|_| ErrorCode::InvalidProof)?;
+    
+    Ok(((high as u128) << 64) | (low as u128))
+}
+
+/// Payment timestamp committed to by the proof's public signals
+pub fn parse_payment_timestamp(public_signals: &[String]) -> Result<i64> {
+    validate_public_signals(public_signals)?;
+    
+    public_signals[PAYMENT_TIMESTAMP_INDEX]
+        .parse::<i64>()
+        .map_err(|_| ErrorCode::InvalidProof.into())
+}
+
+/// Reject a proof whose payment is more than `proof_validity_seconds` older
+/// than `now` (0 = proofs never expire)
+pub fn check_proof_freshness(public_signals: &[String], now: i64, proof_validity_seconds: u32) -> Result<()> {
+    let paid_at = parse_payment_timestamp(public_signals)?;
+    if proof_validity_seconds == 0 {
+        return Ok(());
+    }
+    
+    let age = now.saturating_sub(paid_at);
+    if age > proof_validity_seconds as i64 {
+        msg!(
+            "Proof expired: payment at {} is {}s old (max {}s)",
+            paid_at,
+            age,
+            proof_validity_seconds
+        );
+        return Err(ErrorCode::ProofExpired.into());
+    }
+    
+    Ok(())
+}
+
+/// Check the proof's order ID is the order being settled
+/// 
+/// Besides matching `order_id`, the high/low split must follow the canonical
+/// `generate_order_id` encoding for the order's owner and timestamp, so a
+/// crafted pair of u64 halves can't coincidentally reconstruct a valid id.
+pub fn verify_proof_order_id(public_signals: &[String], order: &Order) -> Result<()> {
+    let proof_order_id = parse_proof_order_id(public_signals)?;
+    
+    require!(
+        proof_order_id == order.order_id,
+        ErrorCode::ProofOrderIdMismatch
+    );
+    require!(
+        is_canonical_order_id(proof_order_id, &order.owner, order.timestamp),
+        ErrorCode::InvalidProof
+    );
+    
+    Ok(())
+}
+
+/// Check a settlement request against the order it settles
+/// 
+/// Covers the order's escrow direction, payment status, settlement delay, verification backoff,
+/// payout destination, proof order ID, proof age and proof format. Shared by `verify_settlement` and
+/// `verify_settlement_batch`.
+pub fn check_settlement(
+    order: &Order,
+    now: i64,
+    proof_validity_seconds: u32,
+    destination: &Pubkey,
+    request: &SettlementRequest,
+    verifying_key: Option<&[u8]>,
+) -> Result<()> {
+    let SettlementRequest { proof_a, proof_b, proof_c, public_signals, .. } = request;
+    
+    // Only the side that escrowed tokens has anything to release
+    require!(order.escrows_tokens(), ErrorCode::InvalidSide);
+    
+    // Only a payment the buyer marked, and that hasn't been released yet, can settle
+    require!(
+        order.payment_status != PaymentStatus::Verified,
+        ErrorCode::OrderAlreadySettled
+    );
+    require!(
+        order.payment_status == PaymentStatus::PaymentMarked,
+        ErrorCode::InvalidPaymentStatus
+    );
+    
+    // Check settlement delay has passed
+    require!(
+        now >= order.settlement_timestamp,
+        ErrorCode::SettlementDelayNotExpired
+    );
+    
+    // Back off after rejected proofs so the pairing check can't be spammed
+    require!(now >= order.verification_retry_at(), ErrorCode::VerificationBackoff);
+    
+    require_keys_eq!(
+        *destination,
+        order.settlement_destination,
+        ErrorCode::InvalidSettlementDestination
+    );
+    
+    // Order ID must match and follow the canonical encoding
+    verify_proof_order_id(public_signals, order)?;
+    
+    // The payment must be recent enough
+    check_proof_freshness(public_signals, now, proof_validity_seconds)?;
+    
+    // Verify proof format
+    require!(
+        proof_a.len() == 64 && proof_b.len() == 128 && proof_c.len() == 64,
+        ErrorCode::InvalidProof
+    );
+    
+    // Points must be canonical before they can be handed to the alt_bn128 syscalls
+    for point in [proof_a, proof_b, proof_c] {
+        proof_point_to_be(point)?;
+    }
+    
+    // With a key set, the proof must also pass the pairing check
+    if let Some(verifying_key) = verifying_key {
+        verify_groth16(verifying_key, proof_a, proof_b, proof_c, public_signals)?;
+    }
+    
+    Ok(())
+}
+
+/// Check a payout account is still an open, unfrozen token account for `mint`
+/// 
+/// The destination is fixed at placement and its close authority may have
+/// closed it since; this fails with a clear error instead of inside the transfer.
+pub fn check_destination_account(account: &AccountInfo, mint: &Pubkey) -> Result<()> {
+    if account.owner != &token::ID || account.data_is_empty() {
+        msg!("Settlement destination {} is closed or not a token account", account.key);
+        return Err(ErrorCode::InvalidSettlementDestination.into());
+    }
+    
+    let token_account = TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])
+        .map_err(|_| ErrorCode::InvalidSettlementDestination)?;
+    require_keys_eq!(token_account.mint, *mint, ErrorCode::InvalidSettlementDestination);
+    require!(!token_account.is_frozen(), ErrorCode::InvalidSettlementDestination);
+    
+    Ok(())
+}
+
+/// CPI into an order's on-settle hook after its escrow was paid to `destination`
+/// 
+/// The hook program implements the Anchor instruction
+/// `on_settle(order_id: u128, amount: u64)` with `destination` as its only
+/// (read-only) account.
+pub fn settle_hook_instruction(
+    hook_program: Pubkey,
+    destination: Pubkey,
+    order_id: u128,
+    amount: u64,
+) -> Instruction {
+    let mut data = hash(b"global:on_settle").to_bytes()[..8].to_vec();
+    data.extend_from_slice(&order_id.to_le_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    
+    Instruction {
+        program_id: hook_program,
+        accounts: vec![AccountMeta::new_readonly(destination, false)],
+        data,
+    }
+}
+
+/// Decide the outcome of one batch entry without settling it
+pub fn settlement_status(
+    order: Option<&Order>,
+    now: i64,
+    proof_validity_seconds: u32,
+    destination: &Pubkey,
+    request: &SettlementRequest,
+    verifying_key: Option<&[u8]>,
+) -> SettlementStatus {
+    let order = match order {
+        Some(order) => order,
+        None => {
+            msg!("Batch settlement: order {} not found", request.order_id);
+            return SettlementStatus::Failed;
+        }
+    };
+    
+    let check = check_settlement(order, now, proof_validity_seconds, destination, request, verifying_key);
+    
+    match check {
+        Ok(()) => SettlementStatus::Settled,
+        Err(err)
+            if err == ErrorCode::SettlementDelayNotExpired.into()
+                || err == ErrorCode::VerificationBackoff.into() =>
+        {
+            SettlementStatus::Skipped
+        }
+        Err(err) if err == ErrorCode::ProofVerificationFailed.into() => {
+            msg!("Batch settlement: order {} proof failed verification", request.order_id);
+            SettlementStatus::ProofFailed
+        }
+        Err(err) => {
+            msg!("Batch settlement: order {} rejected: {:?}", request.order_id, err);
+            SettlementStatus::Failed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{generate_order_id, OrderOptions, OrderType, PaymentStatus, Side};
+    
+    fn signals(low: &str, high: &str) -> Vec<String> {
+        signals_paid_at(low, high, 1000)
+    }
+    
+    fn signals_paid_at(low: &str, high: &str, paid_at: i64) -> Vec<String> {
+        let mut signals = vec!["1".to_string(); ORDER_ID_LOW_INDEX];
+        signals.push(low.to_string());
+        signals.push(high.to_string());
+        signals.push(paid_at.to_string());
+        signals
+    }
+    
+    fn order_with_id(order_id: u128, owner: Pubkey) -> Order {
+        Order::new(
+            order_id,
+            owner,
+            100,
+            50,
+            1000,
+            OrderType::Limit,
+            Side::Ask,
+            1,
+            "PayPal".to_string(),
+        )
+    }
+    
+    /// An order whose buyer has marked the fiat payment, ready to settle
+    fn marked_order(order_id: u128, owner: Pubkey) -> Order {
+        let mut order = order_with_id(order_id, owner);
+        order.payment_status = PaymentStatus::PaymentMarked;
+        order
+    }
+    
+    /// A well-formed (all-zero) proof of `order_id` with these public signals
+    fn zero_proof(order_id: u128, signals: &[String]) -> SettlementRequest {
+        SettlementRequest {
+            order_id,
+            proof_a: vec![0; 64],
+            proof_b: vec![0; 128],
+            proof_c: vec![0; 64],
+            public_signals: signals.to_vec(),
+        }
+    }
+    
+    #[test]
+    fn test_proof_order_id_roundtrip() {
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 3, 1000);
+        let order = order_with_id(order_id, owner);
+        
+        let low = (order_id as u64).to_string();
+        let high = ((order_id >> 64) as u64).to_string();
+        
+        assert_eq!(parse_proof_order_id(&signals(&low, &high)).unwrap(), order_id);
+        assert!(verify_proof_order_id(&signals(&low, &high), &order).is_ok());
+    }
+    
+    #[test]
+    fn test_proof_order_id_boundary_values() {
+        let max = u64::MAX.to_string();
+        assert_eq!(parse_proof_order_id(&signals(&max, &max)).unwrap(), u128::MAX);
+        
+        // One past u64 range does not parse
+        let overflow = "18446744073709551616";
+        assert_eq!(
+            parse_proof_order_id(&signals(overflow, "0")).unwrap_err(),
+            ErrorCode::InvalidProof.into()
+        );
+        
+        // A well-formed but non-canonical id is rejected even if the order carries it
+        let owner = Pubkey::new_unique();
+        let order = order_with_id(u128::MAX, owner);
+        assert_eq!(
+            verify_proof_order_id(&signals(&max, &max), &order).unwrap_err(),
+            ErrorCode::InvalidProof.into()
+        );
+    }
+    
+    #[test]
+    fn test_check_settlement_delay_and_destination() {
+        let owner = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let mut order = marked_order(order_id, owner);
+        order.settlement_timestamp = 1010;
+        order.settlement_destination = destination;
+        
+        let low = (order_id as u64).to_string();
+        let high = ((order_id >> 64) as u64).to_string();
+        let signals = signals(&low, &high);
+        let check = |now: i64, destination: &Pubkey| {
+            check_settlement(&order, now, 0, destination, &zero_proof(order_id, &signals), None)
+        };
+        
+        assert_eq!(
+            check(1009, &destination).unwrap_err(),
+            ErrorCode::SettlementDelayNotExpired.into()
+        );
+        assert_eq!(
+            check(1010, &owner).unwrap_err(),
+            ErrorCode::InvalidSettlementDestination.into()
+        );
+        assert!(check(1010, &destination).is_ok());
+    }
+    
+    #[test]
+    fn test_settlement_requires_marked_payment() {
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let mut order = marked_order(order_id, owner);
+        order.settlement_timestamp = 1010;
+        order.settlement_destination = owner;
+        
+        let signals = signals(&(order_id as u64).to_string(), &((order_id >> 64) as u64).to_string());
+        let request = zero_proof(order_id, &signals);
+        let check = |order: &Order| check_settlement(order, 1010, 0, &owner, &request, None);
+        assert!(check(&order).is_ok());
+        
+        // Nobody has marked the payment yet
+        order.payment_status = PaymentStatus::Pending;
+        assert_eq!(check(&order).unwrap_err(), ErrorCode::InvalidPaymentStatus.into());
+        assert_eq!(
+            settlement_status(Some(&order), 1010, 0, &owner, &request, None),
+            SettlementStatus::Failed
+        );
+        
+        // Already settled: the escrow must not be released twice
+        order.payment_status = PaymentStatus::Verified;
+        assert_eq!(check(&order).unwrap_err(), ErrorCode::OrderAlreadySettled.into());
+        assert_eq!(
+            settlement_status(Some(&order), 1010, 0, &owner, &request, None),
+            SettlementStatus::Failed
+        );
+    }
+    
+    #[test]
+    fn test_settlement_follows_fiat_direction() {
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let low = (order_id as u64).to_string();
+        let high = ((order_id >> 64) as u64).to_string();
+        let signals = signals(&low, &high);
+        let check = |order: &Order| {
+            check_settlement(order, 1010, 0, &owner, &zero_proof(order.order_id, &signals), None)
+        };
+        let order_on = |side: Side, fiat_payer: Option<Side>| {
+            let mut order = Order::new(order_id, owner, 100, 50, 1000, OrderType::Limit, side, 1, "PayPal".to_string())
+                .with_options(&OrderOptions { fiat_payer, ..Default::default() });
+            order.payment_status = PaymentStatus::PaymentMarked;
+            order.settlement_destination = owner;
+            order
+        };
+        
+        // By default the ask escrows and the bid pays fiat
+        assert!(order_on(Side::Ask, None).escrows_tokens());
+        assert!(check(&order_on(Side::Ask, None)).is_ok());
+        assert_eq!(check(&order_on(Side::Bid, None)).unwrap_err(), ErrorCode::InvalidSide.into());
+        
+        // With the ask side paying fiat, the bid escrows and settles instead
+        assert!(order_on(Side::Bid, Some(Side::Ask)).escrows_tokens());
+        assert!(check(&order_on(Side::Bid, Some(Side::Ask))).is_ok());
+        assert_eq!(
+            check(&order_on(Side::Ask, Some(Side::Ask))).unwrap_err(),
+            ErrorCode::InvalidSide.into()
+        );
+    }
+    
+    #[test]
+    fn test_failed_verifications_back_off() {
+        let owner = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let mut order = marked_order(order_id, owner);
+        order.settlement_timestamp = 1000;
+        order.settlement_destination = owner;
+        
+        let low = (order_id as u64).to_string();
+        let high = ((order_id >> 64) as u64).to_string();
+        let signals = signals(&low, &high);
+        let check = |order: &Order, now: i64| {
+            check_settlement(order, now, 0, &owner, &zero_proof(order.order_id, &signals), None)
+        };
+        
+        // Two proofs rejected by the pairing check, the second once the first backoff lapsed
+        order.record_failed_verification(1000);
+        assert_eq!(order.verification_retry_at(), 1002);
+        assert_eq!(check(&order, 1001).unwrap_err(), ErrorCode::VerificationBackoff.into());
+        order.record_failed_verification(1002);
+        assert_eq!(order.failed_verification_count, 2);
+        
+        // An immediate third attempt is blocked until the doubled window elapses
+        assert_eq!(order.verification_retry_at(), 1006);
+        assert_eq!(check(&order, 1002).unwrap_err(), ErrorCode::VerificationBackoff.into());
+        assert_eq!(check(&order, 1005).unwrap_err(), ErrorCode::VerificationBackoff.into());
+        assert!(check(&order, 1006).is_ok());
+        
+        let request = zero_proof(order_id, &signals);
+        assert_eq!(
+            settlement_status(Some(&order), 1002, 0, &owner, &request, None),
+            SettlementStatus::Skipped
+        );
+        
+        // The window stops growing after MAX_VERIFICATION_BACKOFF_SHIFT doublings
+        order.failed_verification_count = u8::MAX;
+        order.record_failed_verification(5000);
+        assert_eq!(order.failed_verification_count, u8::MAX);
+        assert_eq!(
+            order.verification_retry_at(),
+            5000 + (Order::VERIFICATION_BACKOFF_SECONDS << Order::MAX_VERIFICATION_BACKOFF_SHIFT)
+        );
+    }
+    
+    #[test]
+    fn test_batch_skips_premature_orders() {
+        let owner = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let request_for = |order: &Order| {
+            let signals = signals(
+                &(order.order_id as u64).to_string(),
+                &((order.order_id >> 64) as u64).to_string(),
+            );
+            zero_proof(order.order_id, &signals)
+        };
+        
+        // Two ripe orders and one whose delay runs past `now`
+        let orders: Vec<Order> = [1000, 1005, 1030]
+            .into_iter()
+            .enumerate()
+            .map(|(seq, settles_at)| {
+                let mut order = marked_order(generate_order_id(&owner, seq as u64, 1000), owner);
+                order.settlement_timestamp = settles_at;
+                order.settlement_destination = destination;
+                order
+            })
+            .collect();
+        
+        let statuses: Vec<SettlementStatus> = orders
+            .iter()
+            .map(|order| settlement_status(Some(order), 1010, 0, &destination, &request_for(order), None))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![SettlementStatus::Settled, SettlementStatus::Settled, SettlementStatus::Skipped]
+        );
+        
+        let missing = request_for(&orders[0]);
+        assert_eq!(
+            settlement_status(None, 1010, 0, &destination, &missing, None),
+            SettlementStatus::Failed
+        );
+    }
+    
+    #[test]
+    fn test_stale_proof_rejected() {
+        let owner = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let order_id = generate_order_id(&owner, 1, 1000);
+        let mut order = marked_order(order_id, owner);
+        order.settlement_timestamp = 1010;
+        order.settlement_destination = destination;
+        
+        let low = (order_id as u64).to_string();
+        let high = ((order_id >> 64) as u64).to_string();
+        let check = |paid_at: i64, validity: u32| {
+            let signals = signals_paid_at(&low, &high, paid_at);
+            check_settlement(&order, 2000, validity, &destination, &zero_proof(order_id, &signals), None)
+        };
+        
+        // Exactly at the limit is still fresh, one second past is not
+        assert!(check(1400, 600).is_ok());
+        assert_eq!(check(1399, 600).unwrap_err(), ErrorCode::ProofExpired.into());
+        // 0 disables expiry
+        assert!(check(0, 0).is_ok());
+        
+        let mut bad = signals_paid_at(&low, &high, 1400);
+        bad[PAYMENT_TIMESTAMP_INDEX] = u64::MAX.to_string();
+        assert_eq!(parse_payment_timestamp(&bad).unwrap_err(), ErrorCode::InvalidProof.into());
+    }
+    
+    #[test]
+    fn test_empty_public_signals_rejected() {
+        let empty = vec![String::new(); PUBLIC_SIGNAL_COUNT];
+        assert_eq!(
+            validate_public_signals(&empty).unwrap_err(),
+            ErrorCode::InvalidProof.into()
+        );
+        assert_eq!(
+            parse_proof_order_id(&empty).unwrap_err(),
+            ErrorCode::InvalidProof.into()
+        );
+        assert_eq!(
+            validate_public_signals(&[]).unwrap_err(),
+            ErrorCode::InvalidProof.into()
+        );
+    }
+    
+    #[test]
+    fn test_public_signals_field_bounds() {
+        let with_first = |first: &str| {
+            let mut signals = signals("1", "0");
+            signals[0] = first.to_string();
+            signals
+        };
+        
+        // Largest field element is accepted, the modulus itself is not
+        let max_element = "21888242871839275222246405745257275088548364400416034343698204186575808495616";
+        assert!(validate_public_signals(&with_first(max_element)).is_ok());
+        assert!(validate_public_signals(&with_first(FIELD_MODULUS)).is_err());
+        assert!(validate_public_signals(&with_first(&format!("1{}", max_element))).is_err());
+        
+        // Leading zeros don't change the value
+        assert!(validate_public_signals(&with_first(&format!("000{}", max_element))).is_ok());
+        
+        assert!(validate_public_signals(&with_first("-1")).is_err());
+        assert!(validate_public_signals(&with_first("0x10")).is_err());
+    }
+    
+    #[test]
+    fn test_le_to_be_field() {
+        let mut le = [0u8; 32];
+        le[0] = 0x01;
+        le[1] = 0x02;
+        le[31] = 0xff;
+        
+        let be = le_to_be_field(&le).unwrap();
+        assert_eq!(be[31], 0x01);
+        assert_eq!(be[30], 0x02);
+        assert_eq!(be[0], 0xff);
+        
+        // Only whole 32-byte elements convert
+        assert!(le_to_be_field(&le[..31]).is_err());
+        assert!(le_to_be_field(&[0u8; 33]).is_err());
+        
+        // A point converts coordinate by coordinate
+        let mut point = [0u8; 64];
+        point[0] = 7;
+        point[32] = 9;
+        let be = proof_point_to_be(&point).unwrap();
+        assert_eq!((be[31], be[63]), (7, 9));
+    }
+    
+    #[test]
+    fn test_out_of_field_coordinate_rejected() {
+        let mut max_coordinate = BASE_FIELD_MODULUS_BE;
+        max_coordinate[31] -= 1;
+        max_coordinate.reverse();
+        let mut modulus = BASE_FIELD_MODULUS_BE;
+        modulus.reverse();
+        
+        let mut point = [0u8; 64];
+        point[32..].copy_from_slice(&max_coordinate);
+        assert!(proof_point_to_be(&point).is_ok());
+        
+        point[32..].copy_from_slice(&modulus);
+        assert_eq!(proof_point_to_be(&point).unwrap_err(), ErrorCode::InvalidProof.into());
+        assert!(proof_point_to_be(&[0xff; 64]).is_err());
+    }
+    
+    #[test]
+    fn test_settle_hook_instruction_layout() {
+        let hook = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let ix = settle_hook_instruction(hook, destination, 7 << 64 | 9, 500);
+        
+        assert_eq!(ix.program_id, hook);
+        assert_eq!(ix.accounts, vec![AccountMeta::new_readonly(destination, false)]);
+        assert_eq!(ix.data.len(), 8 + 16 + 8);
+        assert_eq!(&ix.data[..8], &hash(b"global:on_settle").to_bytes()[..8]);
+        assert_eq!(u128::from_le_bytes(ix.data[8..24].try_into().unwrap()), 7 << 64 | 9);
+        assert_eq!(u64::from_le_bytes(ix.data[24..].try_into().unwrap()), 500);
+    }
+    
+    #[test]
+    fn test_proof_order_id_mismatch() {
+        let owner = Pubkey::new_unique();
+        let order = order_with_id(generate_order_id(&owner, 1, 1000), owner);
+        
+        assert_eq!(
+            verify_proof_order_id(&signals("1", "0"), &order).unwrap_err(),
+            ErrorCode::ProofOrderIdMismatch.into()
+        );
+        
+        // The right halves in the wrong signal slots reconstruct a different id
+        let low = (order.order_id as u64).to_string();
+        let high = ((order.order_id >> 64) as u64).to_string();
+        assert!(verify_proof_order_id(&signals(&low, &high), &order).is_ok());
+        assert_eq!(
+            verify_proof_order_id(&signals(&high, &low), &order).unwrap_err(),
+            ErrorCode::ProofOrderIdMismatch.into()
+        );
+    }
+    
+    #[test]
+    fn test_verifying_key_structure() {
+        assert!(check_verifying_key(&[0u8; VERIFYING_KEY_LEN]).is_ok());
+        assert_eq!(
+            check_verifying_key(&[0u8; VERIFYING_KEY_LEN - 32]).unwrap_err(),
+            ErrorCode::InvalidVerifyingKey.into()
+        );
+        
+        // A coordinate at the modulus is not canonical
+        let mut key = vec![0u8; VERIFYING_KEY_LEN];
+        let mut modulus_le = BASE_FIELD_MODULUS_BE;
+        modulus_le.reverse();
+        key[VERIFYING_KEY_LEN - 32..].copy_from_slice(&modulus_le);
+        assert_eq!(check_verifying_key(&key).unwrap_err(), ErrorCode::InvalidVerifyingKey.into());
+    }
+    
+    #[test]
+    fn test_signal_to_scalar() {
+        let mut expected = [0u8; 32];
+        expected[30..].copy_from_slice(&[0x01, 0x00]);
+        assert_eq!(signal_to_scalar("256").unwrap(), expected);
+        
+        let high = signal_to_scalar(&u128::MAX.to_string()).unwrap();
+        assert_eq!(high[..16], [0u8; 16]);
+        assert_eq!(high[16..], [0xff; 16]);
+        
+        assert!(signal_to_scalar(FIELD_MODULUS).is_err());
+        assert!(signal_to_scalar("12a").is_err());
+    }
+    
+    #[test]
+    fn test_negate_g1() {
+        // The identity is its own negation
+        assert_eq!(negate_g1(&[0u8; 64]), vec![0u8; 64]);
+        
+        // -(1, 2) = (1, p - 2)
+        let mut generator = [0u8; 64];
+        generator[31] = 1;
+        generator[63] = 2;
+        let negated = negate_g1(&generator);
+        let mut expected_y = BASE_FIELD_MODULUS_BE;
+        expected_y[31] -= 2;
+        assert_eq!(negated[..32], generator[..32]);
+        assert_eq!(negated[32..], expected_y);
+        
+        // Negating twice gives the point back
+        assert_eq!(negate_g1(&negated), generator.to_vec());
+    }
+}
@@ -13,6 +13,7 @@ pub mod order_store {
     pub fn create_matched_order(
         ctx: Context<CreateMatchedOrder>,
         order_id: u64,
+        trade_id: u64,
         bidder: Pubkey,
         seller: Pubkey,
         token_mint: Pubkey,
@@ -22,6 +23,7 @@ pub mod order_store {
         let matched_order = &mut ctx.accounts.matched_order;
         
         matched_order.order_id = order_id;
+        matched_order.trade_id = trade_id;
         matched_order.bidder = bidder;
         matched_order.seller = seller;
         matched_order.token_mint = token_mint;
@@ -117,6 +119,7 @@ pub struct CancelOrder<'info> {
 #[account]
 pub struct MatchedOrder {
     pub order_id: u64,
+    pub trade_id: u64, // Market-wide trade ID of the fill (from `OrderMatched`)
     pub bidder: Pubkey,
     pub seller: Pubkey,
     pub token_mint: Pubkey,
@@ -130,6 +133,7 @@ pub struct MatchedOrder {
 impl MatchedOrder {
     pub const LEN: usize = 8 + // discriminator
         8 + // order_id
+        8 + // trade_id
         32 + // bidder
         32 + // seller
         32 + // token_mint
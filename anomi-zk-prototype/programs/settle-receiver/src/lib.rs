@@ -0,0 +1,27 @@
+use anchor_lang::prelude::*;
+
+declare_id!("688CPDaw9iiLdNNGa4eoRCbWBF6Rw7PUE3eaAYsxyqdy");
+
+/// Stub downstream protocol for tests: receives the market's on-settle hook
+/// and logs it
+#[program]
+pub mod settle_receiver {
+    use super::*;
+
+    /// Called by the market after an order's escrow was paid to `destination`
+    pub fn on_settle(ctx: Context<OnSettle>, order_id: u128, amount: u64) -> Result<()> {
+        msg!(
+            "SettleReceiver: received {} tokens for order {} into {}",
+            amount,
+            order_id,
+            ctx.accounts.destination.key()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct OnSettle<'info> {
+    /// CHECK: token account the market paid out to; only logged
+    pub destination: UncheckedAccount<'info>,
+}
@@ -62,7 +62,7 @@ async function main() {
   );
 
   const [orderBook, orderBookBump] = PublicKey.findProgramAddressSync(
-    [Buffer.from("order_book"), tokenMint.toBuffer()],
+    [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
     program.programId
   );
 
@@ -110,7 +110,7 @@ async function main() {
   console.log("[2/3] Initializing market...");
   try {
     const tx2 = await (program.methods as any)
-      .initializeMarket()
+      .initializeMarket(1000000)
       .accounts({
         market,
         tokenMint,
@@ -136,6 +136,7 @@ async function main() {
         orderBook,
         market,
         tokenMint,
+        quoteMint: tokenMint,
         payer: provider.wallet.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -58,7 +58,10 @@ async function main() {
         { bid: {} }, // Side enum
         new anchor.BN(100), // quantity
         new anchor.BN(50), // limit_price
-        { limit: {} } // OrderType enum
+        { limit: {} }, // OrderType enum
+        10000, // max_slippage_bps
+        [], // accepted payment methods (any)
+        0 // max distinct makers (no cap)
       )
       .accounts({
         owner: buyer.publicKey, // Buyer is the taker
@@ -114,7 +114,7 @@ async function main() {
       marketProgramId
     );
     const [orderBookPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("order_book"), tokenMint.toBuffer()],
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
       marketProgramId
     );
     const [escrowVaultPda] = PublicKey.findProgramAddressSync(
@@ -323,7 +323,10 @@ async function main() {
             { bid: {} },
             new anchor.BN(100),
             new anchor.BN(50),
-            { limit: {} }
+            { limit: {} },
+            10000, // max_slippage_bps
+            [], // accepted payment methods (any)
+            0 // max distinct makers (no cap)
           )
           .accounts({
             owner: buyerKeypair.publicKey,
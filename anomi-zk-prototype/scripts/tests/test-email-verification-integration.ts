@@ -81,7 +81,7 @@ async function main() {
                     b: Buffer.alloc(128, 2),
                     c: Buffer.alloc(64, 3),
                 },
-                publicSignals: Array(18).fill("0").map((_, i) => i.toString()),
+                publicSignals: Array(19).fill("0").map((_, i) => i.toString()),
                 emailHash: "mock_hash",
                 fromHeader: "e.statement@telenorbank.pk",
                 orderId: orderId
@@ -95,7 +95,7 @@ async function main() {
                 b: Buffer.alloc(128, 2),
                 c: Buffer.alloc(64, 3),
             },
-            publicSignals: Array(18).fill("0").map((_, i) => i.toString()),
+            publicSignals: Array(19).fill("0").map((_, i) => i.toString()),
             emailHash: "mock_hash",
             fromHeader: "e.statement@telenorbank.pk",
             orderId: orderId
@@ -111,10 +111,11 @@ async function main() {
     const proofC = Array.from(proofData.proof.c);
     const publicSignals = proofData.publicSignals;
 
-    // Update order ID in public signals (last 2 elements)
+    // Update order ID (elements 16 and 17) and payment timestamp (element 18) in public signals
     const orderIdBigInt = BigInt(orderId);
     publicSignals[16] = (orderIdBigInt & BigInt("0xFFFFFFFFFFFFFFFF")).toString();
     publicSignals[17] = ((orderIdBigInt >> BigInt(64)) & BigInt("0xFFFFFFFFFFFFFFFF")).toString();
+    publicSignals[18] = Math.floor(Date.now() / 1000).toString();
 
     console.log("✅ Proof formatted");
     console.log("   Proof A length:", proofA.length);
@@ -59,7 +59,7 @@ async function main() {
     marketProgramId
   );
   const [orderBookPda] = PublicKey.findProgramAddressSync(
-    [Buffer.from("order_book"), tokenMint.toBuffer()],
+    [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
     marketProgramId
   );
   const [escrowVaultPda] = PublicKey.findProgramAddressSync(
@@ -136,7 +136,10 @@ async function main() {
         { bid: {} }, // Side::Bid (buyer wants to buy)
         new anchor.BN(100), // quantity: 100 tokens
         new anchor.BN(50),  // limit_price: 50 (willing to pay up to 50)
-        { limit: {} } // OrderType::Limit
+        { limit: {} }, // OrderType::Limit
+        10000, // max_slippage_bps
+        [], // accepted payment methods (any)
+        0 // max distinct makers (no cap)
       )
       .accounts({
         owner: buyerKeypair.publicKey, // taker (buyer)
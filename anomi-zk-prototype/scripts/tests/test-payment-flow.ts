@@ -63,7 +63,7 @@ async function main() {
     marketProgramId
   );
   const [orderBookPda] = PublicKey.findProgramAddressSync(
-    [Buffer.from("order_book"), tokenMint.toBuffer()],
+    [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
     marketProgramId
   );
   const [escrowVaultPda] = PublicKey.findProgramAddressSync(
@@ -247,7 +247,10 @@ async function main() {
         { bid: {} },
         bidQuantity,
         bidPrice,
-        { limit: {} }
+        { limit: {} },
+        10000, // max_slippage_bps
+        [], // accepted payment methods (any)
+        0 // max distinct makers (no cap)
       )
       .accounts({
         owner: buyerKeypair.publicKey,
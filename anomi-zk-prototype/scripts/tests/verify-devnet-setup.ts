@@ -111,7 +111,7 @@ async function main() {
   console.log("\n4. Checking OrderBook account...");
   try {
     const [orderBookPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("order_book"), tokenMint.toBuffer()],
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
       marketProgramId
     );
     const orderBookInfo = await connection.getAccountInfo(orderBookPda);
@@ -0,0 +1,256 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+describe("Escrow delegation", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const escrowProgram = anchor.workspace.EscrowStub as any;
+  const payer = provider.wallet.publicKey;
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let sellerTokenAccount: PublicKey;
+
+  // PDAs
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+  let custody: PublicKey;
+  let custodyVault: PublicKey;
+
+  const SETTLEMENT_DELAY_MS = 11000;
+
+  const expectError = async (promise: Promise<any>, code: string) => {
+    try {
+      await promise;
+      expect.fail(`expected ${code}`);
+    } catch (err: any) {
+      expect(err.error?.errorCode?.code ?? err.toString()).to.contain(code);
+    }
+  };
+
+  /** The escrow program followed by the accounts its lock/release take */
+  const escrowAccounts = () => [
+    { pubkey: escrowProgram.programId, isWritable: false, isSigner: false },
+    { pubkey: custody, isWritable: false, isSigner: false },
+    { pubkey: custodyVault, isWritable: true, isSigner: false },
+    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+  ];
+
+  const balance = async (account: PublicKey) =>
+    (await getAccount(provider.connection, account)).amount.toString();
+
+  const placeAsk = async (price: number, quantity: number, remaining = escrowAccounts()) => {
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType: { limit: {} },
+        clientOrderId: new BN(marketAccount.nextOrderSequence),
+        paymentMethod: "Bank Transfer",
+        options: defaultOrderOptions,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(remaining)
+      .signers([seller])
+      .rpc();
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    return book.orderQueues.flatMap((q: any) => q.orders).find((o: any) => o.price.toNumber() === price);
+  };
+
+  const setEscrowProgram = (escrow: PublicKey) =>
+    marketProgram.methods
+      .setEscrowProgram(escrow)
+      .accounts({ market, orderBook, tokenMint, authority: payer })
+      .rpc();
+
+  before(async () => {
+    seller = Keypair.generate();
+    await provider.connection.requestAirdrop(seller.publicKey, 5e9);
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(provider.connection, seller, tokenMint, seller.publicKey);
+    await mintTo(provider.connection, seller, tokenMint, sellerTokenAccount, seller.publicKey, 1000000000);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [custody] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), tokenMint.toBuffer()],
+      escrowProgram.programId
+    );
+    [custodyVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), tokenMint.toBuffer()],
+      escrowProgram.programId
+    );
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market, tokenMint, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({ orderBook, market, tokenMint, quoteMint: tokenMint, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+
+    // Only the market's escrow authority may release from the stub's custody
+    await escrowProgram.methods
+      .initializeCustody(escrowAuthority)
+      .accounts({
+        custody,
+        vault: custodyVault,
+        mint: tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  });
+
+  it("Locks, refunds and settles escrow through a delegated escrow program", async () => {
+    await expectError(
+      marketProgram.methods
+        .setEscrowProgram(escrowProgram.programId)
+        .accounts({ market, orderBook, tokenMint, authority: seller.publicKey })
+        .signers([seller])
+        .rpc(),
+      "UnauthorizedAuthority"
+    );
+    await setEscrowProgram(escrowProgram.programId);
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    expect(marketAccount.escrowProgram.toBase58()).to.equal(escrowProgram.programId.toBase58());
+
+    // The escrow program has to be supplied
+    await expectError(placeAsk(50000, 1000000, []), "EscrowProgramMissing");
+
+    // Placement locks the ask's tokens with the escrow program, not the in-house vault
+    const kept = await placeAsk(50000, 3000000);
+    const cancelled = await placeAsk(51000, 2000000);
+    expect(await balance(custodyVault)).to.equal("5000000");
+    expect(await balance(escrowVault)).to.equal("0");
+
+    // Custody can't move while tokens are locked
+    await expectError(setEscrowProgram(PublicKey.default), "EscrowInUse");
+
+    // Cancelling releases the refund through the escrow program
+    const beforeCancel = BigInt(await balance(sellerTokenAccount));
+    await marketProgram.methods
+      .cancelOrder(cancelled.orderId, { ask: {} }, new BN(51000), false)
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        escrowAuthority,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(escrowAccounts())
+      .signers([seller])
+      .rpc();
+    expect((BigInt(await balance(sellerTokenAccount)) - beforeCancel).toString()).to.equal("2000000");
+    expect(await balance(custodyVault)).to.equal("3000000");
+
+    // Settlement pays out of the escrow program's custody
+    await marketProgram.methods
+      .markPaymentMade(kept.orderId)
+      .accounts({ buyer: seller.publicKey, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+      .signers([seller])
+      .rpc();
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    const beforeSettle = BigInt(await balance(sellerTokenAccount));
+    await marketProgram.methods
+      .verifySettlement(kept.orderId, Buffer.alloc(64), Buffer.alloc(128), Buffer.alloc(64), [
+        ...Array(16).fill("1"),
+        kept.orderId.maskn(64).toString(),
+        kept.orderId.shrn(64).toString(),
+        Math.floor(Date.now() / 1000).toString(),
+      ])
+      .accounts({
+        orderBook,
+        escrowVault,
+        sellerTokenAccount,
+        escrowAuthority,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(escrowAccounts())
+      .rpc();
+    expect((BigInt(await balance(sellerTokenAccount)) - beforeSettle).toString()).to.equal("3000000");
+    expect(await balance(custodyVault)).to.equal("0");
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    expect(book.escrowLocked.toNumber()).to.equal(0);
+
+    // With nothing locked, custody can return to the in-house vault
+    await setEscrowProgram(PublicKey.default);
+  });
+});
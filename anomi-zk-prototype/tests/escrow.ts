@@ -85,7 +85,7 @@ describe("Phase 0.5: Token Escrow", () => {
     );
     
     [orderBook] = PublicKey.findProgramAddressSync(
-      [Buffer.from("order_book"), tokenMint.toBuffer()],
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
       marketProgram.programId
     );
     
@@ -0,0 +1,314 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+// Any slippage, payment method, number of makers and jurisdiction
+const noTakerFilters = { maxSlippageBps: 10000, acceptedPaymentMethods: [], maxMakers: 0, jurisdiction: null };
+
+/**
+ * Drives place/cancel/match/settle against the market program, captures every
+ * emitted event, replays them into an in-memory mirror and checks the mirror
+ * against `get_book_snapshot`. If this fails, events are not lossless.
+ */
+describe("Order book event replay", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const eventParser = new anchor.EventParser(
+    marketProgram.programId,
+    new anchor.BorshCoder(marketProgram.idl)
+  );
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let buyer: Keypair;
+  let sellerTokenAccount: PublicKey;
+  let buyerTokenAccount: PublicKey;
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+
+  const events: { name: string; data: any }[] = [];
+
+  const captureEvents = async (signature: string) => {
+    await provider.connection.confirmTransaction(signature, "confirmed");
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    for (const event of eventParser.parseLogs(tx?.meta?.logMessages ?? [])) {
+      events.push(event);
+    }
+  };
+
+  const place = async (owner: Keypair, ownerTokenAccount: PublicKey, side: any, price: number, quantity: number) => {
+    const signature = await marketProgram.methods
+      .placeLimitOrderV2({
+        side,
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType: { limit: {} },
+        clientOrderId: new BN(events.length),
+        paymentMethod: "Bank Transfer",
+        options: defaultOrderOptions,
+      })
+      .accounts({
+        owner: owner.publicKey,
+        ownerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+    await captureEvents(signature);
+    return events[events.length - 1].data.orderId as BN;
+  };
+
+  before(async () => {
+    seller = Keypair.generate();
+    buyer = Keypair.generate();
+    for (const kp of [seller, buyer]) {
+      await provider.connection.requestAirdrop(kp.publicKey, 5e9);
+    }
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(provider.connection, seller, tokenMint, seller.publicKey);
+    buyerTokenAccount = await createAccount(provider.connection, buyer, tokenMint, buyer.publicKey);
+    await mintTo(provider.connection, seller, tokenMint, sellerTokenAccount, seller.publicKey, 1000000000);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    const payer = provider.wallet.publicKey;
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market, tokenMint, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({ orderBook, market, tokenMint, quoteMint: tokenMint, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+  });
+
+  it("Replaying emitted events reconstructs the on-chain book", async () => {
+    const askA = await place(seller, sellerTokenAccount, { ask: {} }, 55000, 100000000);
+    await place(seller, sellerTokenAccount, { ask: {} }, 55000, 40000000);
+    const askC = await place(seller, sellerTokenAccount, { ask: {} }, 60000, 70000000);
+    await place(buyer, buyerTokenAccount, { bid: {} }, 40000, 25000000);
+
+    // Cancel one ask
+    await captureEvents(
+      await marketProgram.methods
+        .cancelOrder(askC, { ask: {} }, new BN(60000), false)
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          escrowAuthority,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc()
+    );
+
+    // Partially fill the 55 level
+    await captureEvents(
+      await marketProgram.methods
+        .matchOrder({ bid: {} }, new BN(30000000), new BN(55000), { immediateOrCancel: {} }, noTakerFilters, "Bank Transfer")
+        .accounts({ owner: buyer.publicKey, ownerTokenAccount: buyerTokenAccount, market, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+        .signers([buyer])
+        .rpc()
+    );
+
+    // Settle the partially filled ask
+    await marketProgram.methods
+      .markPaymentMade(askA)
+      .accounts({ buyer: seller.publicKey, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+      .signers([seller])
+      .rpc();
+    await new Promise((resolve) => setTimeout(resolve, 11000));
+
+    const low = askA.maskn(64).toString();
+    const high = askA.shrn(64).toString();
+    const paidAt = Math.floor(Date.now() / 1000).toString();
+    const publicSignals = [...Array(16).fill("1"), low, high, paidAt];
+    await captureEvents(
+      await marketProgram.methods
+        .verifySettlement(askA, Buffer.alloc(64), Buffer.alloc(128), Buffer.alloc(64), publicSignals)
+        .accounts({
+          orderBook,
+          escrowVault,
+          sellerTokenAccount,
+          escrowAuthority,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc()
+    );
+
+    // Replay into the mirror
+    const mirror = new Map<string, { side: string; price: number; quantity: number }>();
+    for (const { name, data } of events) {
+      switch (name) {
+        case "orderPlaced":
+          mirror.set(data.orderId.toString(), {
+            side: Object.keys(data.side)[0],
+            price: data.price.toNumber(),
+            quantity: data.quantity.toNumber(),
+          });
+          break;
+        case "orderCancelled":
+          mirror.delete(data.orderId.toString());
+          break;
+        case "orderMatched": {
+          const order = mirror.get(data.makerOrderId.toString())!;
+          order.quantity -= data.quantity.toNumber();
+          if (order.quantity === 0) {
+            mirror.delete(data.makerOrderId.toString());
+          }
+          break;
+        }
+        case "settlementVerified":
+          // Settlement releases escrow but does not change resting depth
+          break;
+      }
+    }
+
+    const mirrorLevels = (side: string) => {
+      const levels = new Map<number, { quantity: number; orderCount: number }>();
+      for (const order of mirror.values()) {
+        if (order.side !== side) continue;
+        const level = levels.get(order.price) ?? { quantity: 0, orderCount: 0 };
+        level.quantity += order.quantity;
+        level.orderCount += 1;
+        levels.set(order.price, level);
+      }
+      return [...levels.entries()]
+        .sort(([a], [b]) => (side === "bid" ? b - a : a - b))
+        .map(([price, level]) => ({ price, ...level }));
+    };
+
+    const snapshot = await marketProgram.methods
+      .getBookSnapshot()
+      .accounts({ orderBook, tokenMint })
+      .view();
+    const chainLevels = (levels: any[]) =>
+      levels.map((l) => ({ price: l.price.toNumber(), quantity: l.quantity.toNumber(), orderCount: l.orderCount }));
+
+    expect(events.map((e) => e.name)).to.include.members([
+      "orderPlaced",
+      "orderCancelled",
+      "orderMatched",
+      "settlementVerified",
+    ]);
+
+    // Fills are attributed to the taker as well as the maker
+    const matched = events.filter((e) => e.name === "orderMatched");
+    expect(matched).to.not.be.empty;
+    for (const { data } of matched) {
+      expect(data.takerOwner.toBase58()).to.equal(buyer.publicKey.toBase58());
+      expect(Object.keys(data.takerSide)[0]).to.equal("bid");
+      expect(Object.keys(data.makerSide)[0]).to.equal("ask");
+    }
+
+    expect(chainLevels(snapshot.bids)).to.deep.equal(mirrorLevels("bid"));
+    expect(chainLevels(snapshot.asks)).to.deep.equal(mirrorLevels("ask"));
+    expect(snapshot.totalOrders.toNumber()).to.equal(mirror.size);
+  });
+
+  it("EscrowChanged events track the escrow vault balance", async () => {
+    const changes = events.filter((e) => e.name === "escrowChanged").map((e) => e.data);
+    expect(changes.map((c) => Object.keys(c.reason)[0])).to.include.members(["placement", "cancel", "settlement"]);
+
+    // Each event's expected total is the running sum of deltas (the vault started empty)
+    let running = BigInt(0);
+    for (const change of changes) {
+      expect(change.mint.toBase58()).to.equal(tokenMint.toBase58());
+      running += BigInt(change.delta.toString());
+      expect(change.newExpectedTotal.toString()).to.equal(running.toString());
+    }
+
+    const vault = await getAccount(provider.connection, escrowVault);
+    expect(vault.amount.toString()).to.equal(running.toString());
+  });
+
+  it("A fill leaves the escrow total unchanged until its tokens leave the vault", async () => {
+    const invariant = () =>
+      marketProgram.methods.verifyEscrowInvariant().accounts({ market, escrowVault, tokenMint }).view();
+    const ask = await place(seller, sellerTokenAccount, { ask: {} }, 50000, 10000000);
+    const before = await invariant();
+
+    const signature = await marketProgram.methods
+      .matchOrder({ bid: {} }, new BN(4000000), new BN(50000), { immediateOrCancel: {} }, noTakerFilters, "Bank Transfer")
+      .accounts({ owner: buyer.publicKey, ownerTokenAccount: buyerTokenAccount, market, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+      .signers([buyer])
+      .rpc();
+    await captureEvents(signature);
+    const fill = events.find((e) => e.name === "orderMatched" && e.data.makerOrderId.eq(ask));
+    expect(fill.data.quantity.toNumber()).to.equal(4000000);
+
+    // The filled tokens are still escrowed, so the total still counts them
+    const after = await invariant();
+    expect(after.locked.toString()).to.equal(before.locked.toString());
+    expect(after.holds).to.equal(true);
+  });
+});
@@ -0,0 +1,784 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+// Any slippage, payment method, number of makers and jurisdiction
+const noTakerFilters = { maxSlippageBps: 10000, acceptedPaymentMethods: [], maxMakers: 0, jurisdiction: null };
+
+describe("Market administration", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const authority = provider.wallet.publicKey;
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let arbitrator: Keypair;
+  let stranger: Keypair;
+  let sellerTokenAccount: PublicKey;
+  let strangerTokenAccount: PublicKey;
+
+  // PDAs
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+
+  const expectError = async (promise: Promise<any>, code: string) => {
+    try {
+      await promise;
+      expect.fail(`expected ${code}`);
+    } catch (err: any) {
+      expect(err.error?.errorCode?.code ?? err.toString()).to.contain(code);
+    }
+  };
+
+  const placeAsk = (price: number, quantity: number, options = defaultOrderOptions) =>
+    place({ ask: {} }, price, quantity, options);
+
+  const place = async (side: any, price: number, quantity: number, options = defaultOrderOptions) => {
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side,
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType: { limit: {} },
+        clientOrderId: new BN(marketAccount.nextOrderSequence),
+        paymentMethod: "Bank Transfer",
+        options,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    return orders[orders.length - 1];
+  };
+
+  before(async () => {
+    seller = Keypair.generate();
+    arbitrator = Keypair.generate();
+    stranger = Keypair.generate();
+
+    for (const kp of [seller, arbitrator, stranger]) {
+      await provider.connection.requestAirdrop(kp.publicKey, 5e9);
+    }
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(
+      provider.connection,
+      seller,
+      tokenMint,
+      seller.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      seller,
+      tokenMint,
+      sellerTokenAccount,
+      seller.publicKey,
+      1000000000
+    );
+    strangerTokenAccount = await createAccount(provider.connection, stranger, tokenMint, stranger.publicKey);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer: authority,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({
+        market,
+        tokenMint,
+        authority,
+        payer: authority,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({
+        orderBook,
+        market,
+        tokenMint,
+        quoteMint: tokenMint,
+        payer: authority,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  });
+
+  it("Reports the price scale set at initialization", async () => {
+    const config = await marketProgram.methods
+      .getMarketConfig()
+      .accounts({ market, tokenMint })
+      .view();
+
+    expect(config.priceScale).to.equal(1000000);
+    expect(config.authority.toBase58()).to.equal(authority.toBase58());
+    expect(config.tokenMint.toBase58()).to.equal(tokenMint.toBase58());
+  });
+
+  it("Returns a versioned config reflecting initialization and admin updates", async () => {
+    const getConfig = () => marketProgram.methods.getMarketConfig().accounts({ market, tokenMint }).view();
+
+    const initial = await getConfig();
+    expect(initial.version).to.equal(4);
+    expect(initial.arbitrator.toBase58()).to.equal(authority.toBase58());
+    expect(initial.bidsFrozen).to.equal(false);
+    expect(initial.asksFrozen).to.equal(false);
+    expect(initial.minActivationDelay).to.equal(0);
+    expect(initial.maxOrderQuantity.toNumber()).to.equal(0);
+    expect(initial.placementCooldownSeconds).to.equal(0);
+    expect(initial.proofValiditySeconds).to.equal(0);
+    expect(initial.queueDiscipline).to.deep.equal({ fifo: {} });
+    expect(initial.settlementDelaySeconds).to.equal(10);
+    expect(initial.minSpreadBps).to.equal(0);
+    expect(initial.tradingOpenTs.toNumber()).to.equal(0);
+    expect(initial.tradingCloseTs.toNumber()).to.equal(0);
+    expect(initial.paused).to.equal(false);
+
+    const update = { market, tokenMint, authority };
+    await marketProgram.methods.setMaxOrderQuantity(new BN(123456789)).accounts(update).rpc();
+    await marketProgram.methods.setProofValidity(900).accounts(update).rpc();
+    await marketProgram.methods.setQueueDiscipline({ lifo: {} }).accounts(update).rpc();
+    await marketProgram.methods.setSideFrozen({ bid: {} }, true).accounts(update).rpc();
+
+    const updated = await getConfig();
+    expect(updated.maxOrderQuantity.toNumber()).to.equal(123456789);
+    expect(updated.proofValiditySeconds).to.equal(900);
+    expect(updated.queueDiscipline).to.deep.equal({ lifo: {} });
+    expect(updated.bidsFrozen).to.equal(true);
+    expect(updated.asksFrozen).to.equal(false);
+
+    await marketProgram.methods.setMaxOrderQuantity(new BN(0)).accounts(update).rpc();
+    await marketProgram.methods.setProofValidity(0).accounts(update).rpc();
+    await marketProgram.methods.setQueueDiscipline({ fifo: {} }).accounts(update).rpc();
+    await marketProgram.methods.setSideFrozen({ bid: {} }, false).accounts(update).rpc();
+  });
+
+  it("Supports separate order books per quote asset for the same base", async () => {
+    const usdc = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    const pyusd = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+
+    const books = [];
+    for (const quoteMint of [usdc, pyusd]) {
+      const [book] = PublicKey.findProgramAddressSync(
+        [Buffer.from("order_book"), tokenMint.toBuffer(), quoteMint.toBuffer()],
+        marketProgram.programId
+      );
+      await marketProgram.methods
+        .initializeOrderBookV2()
+        .accounts({
+          orderBook: book,
+          market,
+          tokenMint,
+          quoteMint,
+          payer: authority,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      books.push(book);
+    }
+
+    expect(books[0].toBase58()).to.not.equal(books[1].toBase58());
+    const [usdcBook, pyusdBook] = await Promise.all(
+      books.map((book) => marketProgram.account.orderBook.fetch(book))
+    );
+    expect(usdcBook.baseMint.toBase58()).to.equal(tokenMint.toBase58());
+    expect(pyusdBook.baseMint.toBase58()).to.equal(tokenMint.toBase58());
+    expect(usdcBook.quoteMint.toBase58()).to.equal(usdc.toBase58());
+    expect(pyusdBook.quoteMint.toBase58()).to.equal(pyusd.toBase58());
+  });
+
+  it("Keeps one escrow ledger across all quote books of the base mint", async () => {
+    const eurc = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    const [eurcBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), eurc.toBuffer()],
+      marketProgram.programId
+    );
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({
+        orderBook: eurcBook,
+        market,
+        tokenMint,
+        quoteMint: eurc,
+        payer: authority,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    // Asks on both books escrow into the one vault
+    await placeAsk(70000, 2000000);
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: new BN(70000),
+        quantity: new BN(3000000),
+        orderType: { limit: {} },
+        clientOrderId: new BN(marketAccount.nextOrderSequence),
+        paymentMethod: "Bank Transfer",
+        options: defaultOrderOptions,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        market,
+        orderBook: eurcBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+
+    const verifyInvariant = () =>
+      marketProgram.methods.verifyEscrowInvariant().accounts({ market, escrowVault, tokenMint }).view();
+    let invariant = await verifyInvariant();
+    expect(invariant.holds).to.equal(true);
+    const books = await Promise.all(
+      [orderBook, eurcBook].map((book) => marketProgram.account.orderBook.fetch(book))
+    );
+    expect(invariant.locked.toString()).to.equal(
+      books.reduce((total: BN, book: any) => total.add(book.escrowLocked), new BN(0)).toString()
+    );
+
+    // The main book's escrow can't fund an import into the EURC book
+    const imported = {
+      owner: seller.publicKey,
+      side: { ask: {} },
+      price: new BN(71000),
+      quantity: new BN(1000000),
+      timestamp: new BN(Math.floor(Date.now() / 1000)),
+      clientOrderId: new BN(0),
+      paymentMethod: "Bank Transfer",
+      settlementDestination: sellerTokenAccount,
+    };
+    const importOrders = () =>
+      marketProgram.methods
+        .importOrders([imported])
+        .accounts({ authority, market, escrowVault, orderBook: eurcBook, tokenMint })
+        .rpc();
+    await expectError(importOrders(), "ImportNotFunded");
+
+    // Once the import's own tokens are in the vault it goes through
+    await mintTo(provider.connection, seller, tokenMint, escrowVault, seller.publicKey, 1000000);
+    await importOrders();
+    invariant = await verifyInvariant();
+    expect(invariant.holds).to.equal(true);
+  });
+
+  it("Only the arbitrator can resolve disputes", async () => {
+    await marketProgram.methods
+      .setArbitrator(arbitrator.publicKey)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    expect(marketAccount.arbitrator.toBase58()).to.equal(arbitrator.publicKey.toBase58());
+
+    const order = await placeAsk(50000, 100000000);
+    await marketProgram.methods
+      .markPaymentMade(order.orderId)
+      .accounts({
+        buyer: seller.publicKey,
+        orderBook,
+        tokenMint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+
+    const resolveAccounts = (signer: PublicKey) => ({
+      arbitrator: signer,
+      market,
+      orderBook,
+      escrowVault,
+      sellerTokenAccount,
+      escrowAuthority,
+      tokenMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    // The market authority no longer adjudicates disputes
+    await expectError(
+      marketProgram.methods
+        .resolveDispute(order.orderId, true)
+        .accounts(resolveAccounts(authority))
+        .rpc(),
+      "UnauthorizedArbitrator"
+    );
+
+    await expectError(
+      marketProgram.methods
+        .resolveDispute(order.orderId, true)
+        .accounts(resolveAccounts(stranger.publicKey))
+        .signers([stranger])
+        .rpc(),
+      "UnauthorizedArbitrator"
+    );
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await marketProgram.methods
+      .resolveDispute(order.orderId, true)
+      .accounts(resolveAccounts(arbitrator.publicKey))
+      .signers([arbitrator])
+      .rpc();
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+
+    expect((after.amount - before.amount).toString()).to.equal(order.quantity.toString());
+  });
+
+  it("Freezing asks rejects new asks but still allows bids and cancels", async () => {
+    const resting = await placeAsk(52000, 10000000);
+
+    await marketProgram.methods
+      .setSideFrozen({ ask: {} }, true)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+
+    await expectError(placeAsk(53000, 10000000), "SideFrozen");
+
+    const bid = await place({ bid: {} }, 40000, 10000000);
+    expect(bid.orderId.toString()).to.not.equal(resting.orderId.toString());
+
+    await marketProgram.methods
+      .cancelOrder(resting.orderId, { ask: {} }, new BN(52000), false)
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        escrowAuthority,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+
+    // Only the authority can toggle the freeze
+    await expectError(
+      marketProgram.methods
+        .setSideFrozen({ ask: {} }, false)
+        .accounts({ market, tokenMint, authority: stranger.publicKey })
+        .signers([stranger])
+        .rpc(),
+      "UnauthorizedAuthority"
+    );
+
+    await marketProgram.methods
+      .setSideFrozen({ ask: {} }, false)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+    await placeAsk(53000, 10000000);
+  });
+
+  it("Delayed makers are skipped until their activation delay elapses", async () => {
+    await marketProgram.methods
+      .setMinActivationDelay(2)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+
+    // The per-order delay wins when it exceeds the market minimum
+    const ask = await placeAsk(30000, 10000000, { ...defaultOrderOptions, activationDelay: 5 });
+    expect(ask.activationDelay).to.equal(5);
+
+    const matchBid = () =>
+      marketProgram.methods
+        .matchOrder({ bid: {} }, new BN(10000000), new BN(30000), { immediateOrCancel: {} }, noTakerFilters, "Bank Transfer")
+        .accounts({ owner: stranger.publicKey, ownerTokenAccount: strangerTokenAccount, market, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+        .signers([stranger])
+        .rpc();
+    const restingQuantity = async () => {
+      const book = await marketProgram.account.orderBook.fetch(orderBook);
+      const order = book.orderQueues
+        .flatMap((q: any) => q.orders)
+        .find((o: any) => o.orderId.eq(ask.orderId));
+      return order ? order.quantity.toNumber() : 0;
+    };
+
+    await matchBid();
+    expect(await restingQuantity()).to.equal(10000000);
+
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    await matchBid();
+    expect(await restingQuantity()).to.equal(0);
+  });
+
+  it("Transfers authority only after the proposed key accepts", async () => {
+    const newAuthority = Keypair.generate();
+
+    await marketProgram.methods
+      .proposeAuthority(newAuthority.publicKey)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+
+    let marketAccount = await marketProgram.account.market.fetch(market);
+    expect(marketAccount.authority.toBase58()).to.equal(authority.toBase58());
+    expect(marketAccount.pendingAuthority.toBase58()).to.equal(newAuthority.publicKey.toBase58());
+
+    // A key that was not proposed cannot accept
+    await expectError(
+      marketProgram.methods
+        .acceptAuthority()
+        .accounts({ market, tokenMint, pendingAuthority: stranger.publicKey })
+        .signers([stranger])
+        .rpc(),
+      "UnauthorizedPendingAuthority"
+    );
+
+    await marketProgram.methods
+      .acceptAuthority()
+      .accounts({ market, tokenMint, pendingAuthority: newAuthority.publicKey })
+      .signers([newAuthority])
+      .rpc();
+
+    marketAccount = await marketProgram.account.market.fetch(market);
+    expect(marketAccount.authority.toBase58()).to.equal(newAuthority.publicKey.toBase58());
+    expect(marketAccount.pendingAuthority.toBase58()).to.equal(PublicKey.default.toBase58());
+
+    // The old authority has lost admin rights; hand them back for later tests
+    await expectError(
+      marketProgram.methods
+        .proposeAuthority(authority)
+        .accounts({ market, tokenMint, authority })
+        .rpc(),
+      "UnauthorizedAuthority"
+    );
+    await marketProgram.methods
+      .proposeAuthority(authority)
+      .accounts({ market, tokenMint, authority: newAuthority.publicKey })
+      .signers([newAuthority])
+      .rpc();
+    await marketProgram.methods
+      .acceptAuthority()
+      .accounts({ market, tokenMint, pendingAuthority: authority })
+      .rpc();
+  });
+
+  it("Rejects orders above the maximum order quantity", async () => {
+    await marketProgram.methods
+      .setMaxOrderQuantity(new BN(5000000))
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+
+    await expectError(placeAsk(61000, 5000001), "QuantityTooLarge");
+    const atCap = await placeAsk(61000, 5000000);
+    expect(atCap.quantity.toNumber()).to.equal(5000000);
+
+    // 0 lifts the cap
+    await marketProgram.methods
+      .setMaxOrderQuantity(new BN(0))
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+    await placeAsk(61000, 5000001);
+  });
+
+  it("Throttles placements inside the cooldown but never cancels", async () => {
+    await marketProgram.methods
+      .setPlacementCooldown(3)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+
+    // Let any earlier placement age out of the window first
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    const first = await placeAsk(62000, 1000000);
+    await expectError(placeAsk(62000, 1000000), "Throttled");
+
+    await marketProgram.methods
+      .cancelOrder(first.orderId, { ask: {} }, new BN(62000), false)
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        escrowAuthority,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    await placeAsk(62000, 1000000);
+
+    await marketProgram.methods
+      .setPlacementCooldown(0)
+      .accounts({ market, tokenMint, authority })
+      .rpc();
+  });
+
+  it("Returns the original order when a client order ID is retried", async () => {
+    const placeWithClientId = async (clientOrderId: number) => {
+      const signature = await marketProgram.methods
+        .placeLimitOrderV2({
+          side: { ask: {} },
+          price: new BN(63000),
+          quantity: new BN(1000000),
+          orderType: { limit: {} },
+          clientOrderId: new BN(clientOrderId),
+          paymentMethod: "Bank Transfer",
+          options: defaultOrderOptions,
+        })
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          market,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc({ commitment: "confirmed" });
+
+      // The u128 order ID comes back as return data
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const prefix = `Program return: ${marketProgram.programId.toBase58()} `;
+      const data = tx!.meta!.logMessages!.find((log) => log.startsWith(prefix))!.slice(prefix.length);
+      return new BN(Buffer.from(data, "base64"), "le");
+    };
+    const restingWithClientId = async (clientOrderId: number) => {
+      const book = await marketProgram.account.orderBook.fetch(orderBook);
+      return book.orderQueues
+        .flatMap((q: any) => q.orders)
+        .filter((o: any) => o.owner.equals(seller.publicKey) && o.clientOrderId.eqn(clientOrderId));
+    };
+
+    const first = await placeWithClientId(424242);
+    // A retry is a fresh transaction a moment later
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    const retry = await placeWithClientId(424242);
+
+    expect(retry.toString()).to.equal(first.toString());
+    const resting = await restingWithClientId(424242);
+    expect(resting).to.have.length(1);
+    expect(resting[0].orderId.toString()).to.equal(first.toString());
+  });
+
+  it("Lets the authority force-cancel an order and refunds the owner", async () => {
+    await placeAsk(64000, 7000000);
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const order = book.orderQueues
+      .flatMap((q: any) => q.orders)
+      .find((o: any) => o.price.toNumber() === 64000);
+
+    const adminCancel = (signer: PublicKey) =>
+      marketProgram.methods
+        .adminCancelOrder(order.orderId, { ask: {} }, new BN(64000), 7)
+        .accounts({
+          authority: signer,
+          market,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          escrowAuthority,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        });
+
+    await expectError(
+      adminCancel(stranger.publicKey).signers([stranger]).rpc(),
+      "UnauthorizedAuthority"
+    );
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await adminCancel(authority).rpc();
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("7000000");
+
+    const bookAfter = await marketProgram.account.orderBook.fetch(orderBook);
+    const remaining = bookAfter.orderQueues
+      .flatMap((q: any) => q.orders)
+      .filter((o: any) => o.orderId.toString() === order.orderId.toString());
+    expect(remaining).to.have.length(0);
+
+    // Already gone
+    await expectError(adminCancel(authority).rpc(), "OrderNotFound");
+  });
+
+  it("Rejects a bid that narrows the spread below the market's floor", async () => {
+    await place({ bid: {} }, 1000, 1000000);
+    const stats = await marketProgram.methods.getMarketStats().accounts({ orderBook, tokenMint }).view();
+    const bestAsk = stats.bestAsk.toNumber();
+
+    // Require 10% of mid between the best bid and best ask
+    await marketProgram.methods.setMinSpread(1000).accounts({ market, tokenMint, authority }).rpc();
+    await expectError(place({ bid: {} }, bestAsk - 1, 1000000), "SpreadTooTight");
+
+    // A bid at or below 19/21 of the ask keeps a 10% spread and still rests
+    const widest = Math.floor((bestAsk * 19) / 21) - 1;
+    await place({ bid: {} }, widest, 1000000);
+    const after = await marketProgram.methods.getMarketStats().accounts({ orderBook, tokenMint }).view();
+    expect(after.bestBid.toNumber()).to.equal(widest);
+
+    await marketProgram.methods.setMinSpread(0).accounts({ market, tokenMint, authority }).rpc();
+  });
+
+  it("Enforces trading hours on placements and matches but not cancels", async () => {
+    const update = { market, tokenMint, authority };
+    const now = (await provider.connection.getBlockTime(await provider.connection.getSlot()))!;
+    const resting = await placeAsk(64000, 1000000);
+
+    // Window closed an hour ago
+    await marketProgram.methods.setTradingHours(new BN(now - 7200), new BN(now - 3600)).accounts(update).rpc();
+    await expectError(placeAsk(64500, 1000000), "MarketClosed");
+    await expectError(
+      marketProgram.methods
+        .matchOrder({ bid: {} }, new BN(1000000), new BN(64000), { immediateOrCancel: {} }, noTakerFilters, "Bank Transfer")
+        .accounts({ owner: stranger.publicKey, ownerTokenAccount: strangerTokenAccount, market, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+        .signers([stranger])
+        .rpc(),
+      "MarketClosed"
+    );
+    await marketProgram.methods
+      .cancelOrder(resting.orderId, { ask: {} }, new BN(64000), false)
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        escrowAuthority,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+
+    // Inside the window
+    await marketProgram.methods.setTradingHours(new BN(now - 3600), new BN(now + 3600)).accounts(update).rpc();
+    await placeAsk(64500, 1000000);
+
+    await expectError(
+      marketProgram.methods.setTradingHours(new BN(now), new BN(now - 1)).accounts(update).rpc(),
+      "InvalidTradingHours"
+    );
+    await marketProgram.methods.setTradingHours(new BN(0), new BN(0)).accounts(update).rpc();
+  });
+
+  it("Freezes the market and emits the full ladder for auditors", async () => {
+    await placeAsk(65000, 1000000);
+    await place({ bid: {} }, 20000, 1000000);
+
+    const signature = await marketProgram.methods
+      .freezeAndSnapshot()
+      .accounts({ market, orderBook, tokenMint, authority })
+      .rpc({ commitment: "confirmed" });
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const eventParser = new anchor.EventParser(marketProgram.programId, new anchor.BorshCoder(marketProgram.idl));
+    const audit = [...eventParser.parseLogs(tx!.meta!.logMessages!)].find((e) => e.name === "bookAudit")!.data;
+
+    // The event carries every level, not just the top of book
+    const snapshot = await marketProgram.methods.getBookSnapshot().accounts({ orderBook, tokenMint }).view();
+    const ladder = (levels: any[]) => levels.map((l) => [l.price.toString(), l.quantity.toString(), l.orderCount]);
+    expect(ladder(audit.asks)).to.deep.equal(ladder(snapshot.asks));
+    expect(ladder(audit.bids)).to.deep.equal(ladder(snapshot.bids));
+    expect(audit.asks.some((l: any) => l.price.toNumber() === 65000)).to.equal(true);
+    expect(audit.bids.some((l: any) => l.price.toNumber() === 20000)).to.equal(true);
+    expect(audit.checksum).to.have.length(32);
+
+    // Nothing can change the book after the snapshot: not placements, matches or cancels
+    const config = await marketProgram.methods.getMarketConfig().accounts({ market, tokenMint }).view();
+    expect(config.paused).to.equal(true);
+    await expectError(placeAsk(66000, 1000000), "MarketPaused");
+    await expectError(place({ bid: {} }, 21000, 1000000), "MarketPaused");
+    const matchAsk = () =>
+      marketProgram.methods
+        .matchOrder({ bid: {} }, new BN(1000000), new BN(65000), { immediateOrCancel: {} }, noTakerFilters, "Bank Transfer")
+        .accounts({ owner: stranger.publicKey, ownerTokenAccount: strangerTokenAccount, market, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+        .signers([stranger])
+        .rpc();
+    await expectError(matchAsk(), "MarketPaused");
+    const resting = await marketProgram.methods.getBookSnapshot().accounts({ orderBook, tokenMint }).view();
+    expect(ladder(resting.asks)).to.deep.equal(ladder(snapshot.asks));
+    await expectError(
+      marketProgram.methods
+        .sweepExpired({ ask: {} }, 10)
+        .accounts({ keeper: stranger.publicKey, orderBook, escrowVault, escrowAuthority, market, tokenMint, tokenProgram: TOKEN_PROGRAM_ID })
+        .signers([stranger])
+        .rpc(),
+      "MarketPaused"
+    );
+
+    // Paused until explicitly resumed
+    await marketProgram.methods.setPaused(false).accounts({ market, tokenMint, authority }).rpc();
+    await placeAsk(66000, 1000000);
+    await matchAsk();
+  });
+});
@@ -0,0 +1,650 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getAccount,
+  createWrappedNativeAccount,
+  NATIVE_MINT,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+describe("Order cancellation", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const payer = provider.wallet.publicKey;
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let stranger: Keypair;
+  let sellerTokenAccount: PublicKey;
+  let strangerTokenAccount: PublicKey;
+
+  // PDAs
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+
+  const expectError = async (promise: Promise<any>, code: string) => {
+    try {
+      await promise;
+      expect.fail(`expected ${code}`);
+    } catch (err: any) {
+      expect(err.error?.errorCode?.code ?? err.toString()).to.contain(code);
+    }
+  };
+
+  // PDA holding an order's memo, cancel delegate, settle hook and referral
+  const orderExtras = (owner: PublicKey, clientOrderId: BN) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("order_extras"), orderBook.toBuffer(), owner.toBuffer(), clientOrderId.toArrayLike(Buffer, "le", 8)],
+      marketProgram.programId
+    )[0];
+
+  const placeAsk = async (price: number, quantity: number, options = defaultOrderOptions) => {
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    const clientOrderId = new BN(marketAccount.nextOrderSequence);
+    const needsExtras =
+      options.memo.some((b: number) => b !== 0) || options.cancelDelegate || options.onSettleProgram || options.referral;
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType: { limit: {} },
+        clientOrderId,
+        paymentMethod: "Bank Transfer",
+        options,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(
+        needsExtras ? [{ pubkey: orderExtras(seller.publicKey, clientOrderId), isWritable: true, isSigner: false }] : []
+      )
+      .signers([seller])
+      .rpc();
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    return orders[orders.length - 1];
+  };
+
+  const cancel = (owner: Keypair, ownerTokenAccount: PublicKey, orderId: BN, side: any, price: number, remaining: any[] = []) =>
+    marketProgram.methods
+      .cancelOrder(orderId, side, new BN(price), false)
+      .accounts({
+        owner: owner.publicKey,
+        ownerTokenAccount,
+        escrowVault,
+        escrowAuthority,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(remaining)
+      .signers([owner])
+      .rpc({ commitment: "confirmed" });
+
+  /** Program logs of a confirmed transaction */
+  const logsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return tx?.meta?.logMessages ?? [];
+  };
+
+  before(async () => {
+    seller = Keypair.generate();
+    stranger = Keypair.generate();
+    for (const kp of [seller, stranger]) {
+      await provider.connection.requestAirdrop(kp.publicKey, 5e9);
+    }
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(provider.connection, seller, tokenMint, seller.publicKey);
+    strangerTokenAccount = await createAccount(provider.connection, stranger, tokenMint, stranger.publicKey);
+    await mintTo(provider.connection, seller, tokenMint, sellerTokenAccount, seller.publicKey, 1000000000);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market, tokenMint, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({ orderBook, market, tokenMint, quoteMint: tokenMint, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+  });
+
+  it("Cancels by order ID when the supplied price is wrong", async () => {
+    const order = await placeAsk(50000, 30000000);
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+
+    // Ownership is still enforced on the fallback path
+    await expectError(
+      cancel(stranger, strangerTokenAccount, order.orderId, { ask: {} }, 49000),
+      "UnauthorizedCancellation"
+    );
+
+    const logs = await logsOf(await cancel(seller, sellerTokenAccount, order.orderId, { ask: {} }, 49000));
+    expect(logs.some((log) => log.includes("Warning") && log.includes("price: 50000"))).to.equal(true);
+
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("30000000");
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    expect(orders.find((o: any) => o.orderId.eq(order.orderId))).to.be.undefined;
+  });
+
+  it("Lets the order's cancel delegate cancel, refunding the owner", async () => {
+    const delegate = Keypair.generate();
+    const order = await placeAsk(51000, 20000000, { ...defaultOrderOptions, cancelDelegate: delegate.publicKey });
+    expect(order.hasExtras).to.equal(true);
+    const address = orderExtras(seller.publicKey, order.clientOrderId);
+    const extras = await marketProgram.account.orderExtras.fetch(address);
+    expect(extras.cancelDelegate.toBase58()).to.equal(delegate.publicKey.toBase58());
+    const extrasAccount = [{ pubkey: address, isWritable: false, isSigner: false }];
+
+    // The delegate must pass the extras account naming it
+    await expectError(
+      cancel(delegate, sellerTokenAccount, order.orderId, { ask: {} }, 51000),
+      "OrderExtrasMissing"
+    );
+    // A random key is still rejected
+    await expectError(
+      cancel(stranger, strangerTokenAccount, order.orderId, { ask: {} }, 51000, extrasAccount),
+      "UnauthorizedCancellation"
+    );
+    // The delegate can't redirect the refund to another account
+    await expectError(
+      cancel(delegate, strangerTokenAccount, order.orderId, { ask: {} }, 51000, extrasAccount),
+      "InvalidTokenAccountOwner"
+    );
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await cancel(delegate, sellerTokenAccount, order.orderId, { ask: {} }, 51000, extrasAccount);
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("20000000");
+  });
+
+  it("Keeps the escrow ledger equal to the vault balance through placements and cancels", async () => {
+    const expectInvariant = async (locked: number) => {
+      const invariant = await marketProgram.methods
+        .verifyEscrowInvariant()
+        .accounts({ market, escrowVault, tokenMint })
+        .view();
+      expect(invariant.holds).to.equal(true);
+      expect(invariant.locked.toNumber()).to.equal(locked);
+      expect(invariant.vaultBalance.toNumber()).to.equal(locked);
+    };
+
+    // Earlier tests cancelled everything they placed
+    await expectInvariant(0);
+
+    const orders = [];
+    let locked = 0;
+    for (const [price, quantity] of [[52000, 5000000], [53000, 7000000], [54000, 9000000]]) {
+      orders.push(await placeAsk(price, quantity));
+      locked += quantity;
+      await expectInvariant(locked);
+    }
+
+    // Cancel some of the asks; the rest stay locked
+    for (const order of orders.slice(0, 2)) {
+      await cancel(seller, sellerTokenAccount, order.orderId, { ask: {} }, order.price.toNumber());
+      locked -= order.quantity.toNumber();
+      await expectInvariant(locked);
+    }
+    expect(locked).to.equal(9000000);
+  });
+
+  it("Sweeps only expired orders and refunds their escrow", async () => {
+    const restingPrices = async () => {
+      const book = await marketProgram.account.orderBook.fetch(orderBook);
+      return book.orderQueues
+        .flatMap((q: any) => q.orders)
+        .map((o: any) => o.price.toNumber())
+        .sort((a: number, b: number) => a - b);
+    };
+    const sweep = () =>
+      marketProgram.methods
+        .sweepExpired({ ask: {} }, 10)
+        .accounts({
+          keeper: stranger.publicKey,
+          orderBook,
+          escrowVault,
+          escrowAuthority,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([{ pubkey: sellerTokenAccount, isWritable: true, isSigner: false }])
+        .signers([stranger])
+        .rpc({ commitment: "confirmed" });
+
+    const chainTime = async () => (await provider.connection.getBlockTime(await provider.connection.getSlot()))!;
+    const expiry = (await chainTime()) + 3;
+    await placeAsk(55000, 1000000, { ...defaultOrderOptions, expiryTimestamp: new BN(expiry) });
+    await placeAsk(56000, 2000000, { ...defaultOrderOptions, expiryTimestamp: new BN(expiry) });
+    await placeAsk(57000, 4000000, { ...defaultOrderOptions, expiryTimestamp: new BN(expiry + 3600) });
+
+    // Nothing has expired yet (the 54000 ask from the previous test never expires)
+    await sweep();
+    expect(await restingPrices()).to.deep.equal([54000, 55000, 56000, 57000]);
+
+    while ((await chainTime()) < expiry) {
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await sweep();
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+
+    expect(await restingPrices()).to.deep.equal([54000, 57000]);
+    expect((after.amount - before.amount).toString()).to.equal("3000000");
+
+    const invariant = await marketProgram.methods
+      .verifyEscrowInvariant()
+      .accounts({ market, escrowVault, tokenMint })
+      .view();
+    expect(invariant.holds).to.equal(true);
+    expect(invariant.locked.toNumber()).to.equal(13000000);
+  });
+
+  it("Cancel-replaces an ask, moving only the escrow difference", async () => {
+    const findAt = async (price: number) => {
+      const book = await marketProgram.account.orderBook.fetch(orderBook);
+      return book.orderQueues.flatMap((q: any) => q.orders).find((o: any) => o.price.toNumber() === price);
+    };
+    const replace = (order: any, price: number, quantity: number, options = defaultOrderOptions, clientOrderId = 0) =>
+      marketProgram.methods
+        .cancelReplace(order.orderId, { ask: {} }, order.price, {
+          side: { ask: {} },
+          price: new BN(price),
+          quantity: new BN(quantity),
+          orderType: { limit: {} },
+          clientOrderId: new BN(clientOrderId),
+          paymentMethod: "Bank Transfer",
+          options,
+        })
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          escrowAuthority,
+          market,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc();
+
+    await placeAsk(60000, 100000000);
+    const old = await findAt(60000);
+
+    // The replacement fails after the old order was taken out of the book;
+    // the whole instruction reverts, so the old order is still resting
+    await expectError(
+      replace(old, 65000, 120000000, { ...defaultOrderOptions, expiryTimestamp: new BN(1) }),
+      "InvalidExpiry"
+    );
+    expect((await findAt(60000)).orderId.eq(old.orderId)).to.equal(true);
+
+    const sellerBefore = await getAccount(provider.connection, sellerTokenAccount);
+    const vaultBefore = await getAccount(provider.connection, escrowVault);
+    await replace(old, 65000, 120000000);
+    const sellerAfter = await getAccount(provider.connection, sellerTokenAccount);
+    const vaultAfter = await getAccount(provider.connection, escrowVault);
+
+    expect((sellerBefore.amount - sellerAfter.amount).toString()).to.equal("20000000");
+    expect((vaultAfter.amount - vaultBefore.amount).toString()).to.equal("20000000");
+    expect(await findAt(60000)).to.be.undefined;
+    const replacement = await findAt(65000);
+    expect(replacement.quantity.toNumber()).to.equal(120000000);
+    expect(replacement.orderId.eq(old.orderId)).to.equal(false);
+
+    // Shrinking the ask refunds the difference
+    await replace(replacement, 65000, 90000000);
+    const sellerShrunk = await getAccount(provider.connection, sellerTokenAccount);
+    expect((sellerShrunk.amount - sellerAfter.amount).toString()).to.equal("30000000");
+
+    // A retried replace with the same client order id returns the first replacement
+    const shrunk = await findAt(65000);
+    await replace(shrunk, 66000, 90000000, defaultOrderOptions, 4242);
+    const moved = await findAt(66000);
+    await replace(shrunk, 67000, 90000000, defaultOrderOptions, 4242);
+    expect(await findAt(67000)).to.be.undefined;
+    expect((await findAt(66000)).orderId.eq(moved.orderId)).to.equal(true);
+
+    // Once a payment is marked against it, the order can't be replaced
+    await marketProgram.methods
+      .markPaymentMade(moved.orderId)
+      .accounts({ buyer: seller.publicKey, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+      .signers([seller])
+      .rpc();
+    await expectError(replace(moved, 68000, 90000000), "InvalidPaymentStatus");
+
+    const invariant = await marketProgram.methods
+      .verifyEscrowInvariant()
+      .accounts({ market, escrowVault, tokenMint })
+      .view();
+    expect(invariant.holds).to.equal(true);
+  });
+
+  it("Cancels a chosen subset of orders in one call", async () => {
+    const prices = [70000, 71000, 72000, 73000, 74000];
+    const quantities = [1000000, 2000000, 3000000, 4000000, 5000000];
+    for (const [i, price] of prices.entries()) {
+      await placeAsk(price, quantities[i]);
+    }
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const resting = book.orderQueues.flatMap((q: any) => q.orders);
+    const placed = prices.map((price) => resting.find((o: any) => o.price.toNumber() === price));
+
+    const chosen = [placed[0], placed[2], placed[4]];
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await marketProgram.methods
+      .cancelOrders(chosen.map((o: any) => ({ orderId: o.orderId, side: { ask: {} }, price: o.price })))
+      .accounts({
+        owner: seller.publicKey,
+        orderBook,
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([{ pubkey: sellerTokenAccount, isWritable: true, isSigner: false }])
+      .signers([seller])
+      .rpc();
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+
+    // One aggregated refund for the three cancelled asks
+    expect((after.amount - before.amount).toString()).to.equal("9000000");
+
+    const remaining = (await marketProgram.account.orderBook.fetch(orderBook)).orderQueues
+      .flatMap((q: any) => q.orders)
+      .map((o: any) => o.orderId.toString());
+    for (const order of chosen) {
+      expect(remaining).to.not.include(order.orderId.toString());
+    }
+    for (const order of [placed[1], placed[3]]) {
+      expect(remaining).to.include(order.orderId.toString());
+    }
+  });
+
+  it("Refunds a cancelled ask only to the account that funded it", async () => {
+    await placeAsk(52500, 5000000);
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const order = book.orderQueues
+      .flatMap((q: any) => q.orders)
+      .find((o: any) => o.price.toNumber() === 52500);
+    expect(order.fundingAccount.toBase58()).to.equal(sellerTokenAccount.toBase58());
+
+    // Another owner's account, and another account of the same owner, are both refused
+    const otherSellerAccount = await createAccount(
+      provider.connection,
+      seller,
+      tokenMint,
+      seller.publicKey,
+      Keypair.generate()
+    );
+    await expectError(
+      cancel(seller, strangerTokenAccount, order.orderId, { ask: {} }, 52500),
+      "InvalidTokenAccountOwner"
+    );
+    await expectError(
+      cancel(seller, otherSellerAccount, order.orderId, { ask: {} }, 52500),
+      "RefundAccountMismatch"
+    );
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    const signature = await cancel(seller, sellerTokenAccount, order.orderId, { ask: {} }, 52500);
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("5000000");
+
+    // The refund destination is echoed in the cancel event
+    const eventParser = new anchor.EventParser(marketProgram.programId, new anchor.BorshCoder(marketProgram.idl));
+    const cancelled = [...eventParser.parseLogs(await logsOf(signature))].find((e) => e.name === "orderCancelled");
+    expect(cancelled?.data.refundAccount.toBase58()).to.equal(sellerTokenAccount.toBase58());
+  });
+
+  it("Lets a keeper cancel a maker's orders once its heartbeat lapses, not before", async () => {
+    const keeper = Keypair.generate();
+    await provider.connection.requestAirdrop(keeper.publicKey, 1e9);
+
+    await marketProgram.methods
+      .heartbeat(5)
+      .accounts({ owner: seller.publicKey, market, tokenMint, systemProgram: SystemProgram.programId })
+      .signers([seller])
+      .rpc();
+    await placeAsk(53500, 4000000);
+
+    const cancelStale = () =>
+      marketProgram.methods
+        .cancelStaleMaker(seller.publicKey)
+        .accounts({
+          keeper: keeper.publicKey,
+          market,
+          orderBook,
+          escrowVault,
+          escrowAuthority,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([{ pubkey: sellerTokenAccount, isWritable: true, isSigner: false }])
+        .signers([keeper])
+        .rpc();
+    const sellerOrders = async () =>
+      (await marketProgram.account.orderBook.fetch(orderBook)).orderQueues
+        .flatMap((q: any) => q.orders)
+        .filter((o: any) => o.owner.equals(seller.publicKey));
+
+    // Still within the deadline
+    await expectError(cancelStale(), "MakerNotStale");
+    const resting = await sellerOrders();
+    expect(resting.length).to.be.greaterThan(0);
+    const inProgress = resting.filter((o: any) => o.paymentStatus.pending === undefined);
+    const escrowed = resting
+      .filter((o: any) => o.paymentStatus.pending !== undefined)
+      .reduce((total: number, o: any) => total + o.quantity.toNumber(), 0);
+
+    await new Promise((resolve) => setTimeout(resolve, 7000));
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await cancelStale();
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+
+    // Orders with a payment in progress are left in place
+    expect((await sellerOrders()).map((o: any) => o.orderId.toString())).to.deep.equal(
+      inProgress.map((o: any) => o.orderId.toString())
+    );
+    expect((after.amount - before.amount).toString()).to.equal(escrowed.toString());
+  });
+
+  it("Unwraps a wrapped SOL refund back to native lamports on cancel", async () => {
+    const pda = (label: string, ...keys: PublicKey[]) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(label), ...keys.map((k) => k.toBuffer())],
+        marketProgram.programId
+      )[0];
+    const wsolMarket = pda("market", NATIVE_MINT);
+    const wsolOrderBook = pda("order_book", NATIVE_MINT, NATIVE_MINT);
+    const wsolVault = pda("escrow_vault", NATIVE_MINT);
+    const wsolAuthority = pda("escrow_authority", NATIVE_MINT);
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault: wsolVault,
+        escrowAuthority: wsolAuthority,
+        tokenMint: NATIVE_MINT,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market: wsolMarket, tokenMint: NATIVE_MINT, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({
+        orderBook: wsolOrderBook,
+        market: wsolMarket,
+        tokenMint: NATIVE_MINT,
+        quoteMint: NATIVE_MINT,
+        payer,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    // Wrap 1 SOL and escrow half of it in an ask
+    const wrapped = await createWrappedNativeAccount(
+      provider.connection,
+      seller,
+      seller.publicKey,
+      1e9,
+      Keypair.generate()
+    );
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: new BN(54500),
+        quantity: new BN(5e8),
+        orderType: { limit: {} },
+        clientOrderId: new BN(0),
+        paymentMethod: "Bank Transfer",
+        options: defaultOrderOptions,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: wrapped,
+        escrowVault: wsolVault,
+        market: wsolMarket,
+        orderBook: wsolOrderBook,
+        tokenMint: NATIVE_MINT,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+    const order = (await marketProgram.account.orderBook.fetch(wsolOrderBook)).orderQueues
+      .flatMap((q: any) => q.orders)
+      .find((o: any) => o.price.toNumber() === 54500);
+
+    const cancelWsol = (unwrap: boolean) =>
+      marketProgram.methods
+        .cancelOrder(order.orderId, { ask: {} }, new BN(54500), unwrap)
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: wrapped,
+          escrowVault: wsolVault,
+          escrowAuthority: wsolAuthority,
+          orderBook: wsolOrderBook,
+          tokenMint: NATIVE_MINT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc({ commitment: "confirmed" });
+
+    const before = await provider.connection.getBalance(seller.publicKey, "confirmed");
+    await cancelWsol(true);
+    const after = await provider.connection.getBalance(seller.publicKey, "confirmed");
+
+    // The full wrapped balance (refund included) comes back as lamports, less the fee
+    expect(after - before).to.be.greaterThan(1e9 - 10000);
+    expect(await provider.connection.getAccountInfo(wrapped, "confirmed")).to.equal(null);
+    const book = await marketProgram.account.orderBook.fetch(wsolOrderBook);
+    expect(book.orderQueues.flatMap((q: any) => q.orders)).to.be.empty;
+  });
+
+  it("Refuses to unwrap on a market that isn't wrapped SOL", async () => {
+    await placeAsk(54000, 1000000);
+    const order = (await marketProgram.account.orderBook.fetch(orderBook)).orderQueues
+      .flatMap((q: any) => q.orders)
+      .find((o: any) => o.price.toNumber() === 54000);
+
+    await expectError(
+      marketProgram.methods
+        .cancelOrder(order.orderId, { ask: {} }, new BN(54000), true)
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          escrowAuthority,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc(),
+      "InvalidUnwrap"
+    );
+    await cancel(seller, sellerTokenAccount, order.orderId, { ask: {} }, 54000);
+  });
+});
@@ -0,0 +1,485 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+describe("Order matching", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const payer = provider.wallet.publicKey;
+  const eventParser = new anchor.EventParser(
+    marketProgram.programId,
+    new anchor.BorshCoder(marketProgram.idl)
+  );
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let buyer: Keypair;
+  let sellerTokenAccount: PublicKey;
+  let buyerTokenAccount: PublicKey;
+
+  // PDAs
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+
+  /** Events emitted by a confirmed transaction */
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return [...eventParser.parseLogs(tx?.meta?.logMessages ?? [])];
+  };
+
+  const placeAsk = async (
+    price: number,
+    quantity: number,
+    options = defaultOrderOptions,
+    paymentMethod = "Bank Transfer",
+    owner: Keypair = seller,
+    ownerTokenAccount: PublicKey = sellerTokenAccount
+  ) => {
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType: { limit: {} },
+        clientOrderId: new BN(marketAccount.nextOrderSequence),
+        paymentMethod,
+        options,
+      })
+      .accounts({
+        owner: owner.publicKey,
+        ownerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    return orders[orders.length - 1];
+  };
+
+  const matchBid = (
+    quantity: number,
+    limitPrice: number,
+    orderType: any = { immediateOrCancel: {} },
+    taker: Keypair = buyer,
+    maxSlippageBps = 10000,
+    acceptedPaymentMethods: string[] = [],
+    maxMakers = 0,
+    jurisdiction: number[] | null = null,
+    takerTokenAccount: PublicKey = taker === seller ? sellerTokenAccount : buyerTokenAccount,
+    paymentMethod = "Bank Transfer"
+  ) =>
+    marketProgram.methods
+      .matchOrder(
+        { bid: {} },
+        new BN(quantity),
+        new BN(limitPrice),
+        orderType,
+        { maxSlippageBps, acceptedPaymentMethods, maxMakers, jurisdiction },
+        paymentMethod
+      )
+      .accounts({
+        owner: taker.publicKey,
+        ownerTokenAccount: takerTokenAccount,
+        market,
+        orderBook,
+        tokenMint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([taker])
+      .rpc({ commitment: "confirmed" });
+
+  const expectError = async (promise: Promise<any>, code: string) => {
+    try {
+      await promise;
+      expect.fail(`expected ${code}`);
+    } catch (err: any) {
+      expect(err.error?.errorCode?.code ?? err.toString()).to.contain(code);
+    }
+  };
+
+  before(async () => {
+    seller = Keypair.generate();
+    buyer = Keypair.generate();
+    for (const kp of [seller, buyer]) {
+      await provider.connection.requestAirdrop(kp.publicKey, 5e9);
+    }
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(provider.connection, seller, tokenMint, seller.publicKey);
+    await mintTo(provider.connection, seller, tokenMint, sellerTokenAccount, seller.publicKey, 1000000000);
+    buyerTokenAccount = await createAccount(provider.connection, buyer, tokenMint, buyer.publicKey);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market, tokenMint, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({ orderBook, market, tokenMint, quoteMint: tokenMint, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+  });
+
+  it("Assigns every fill a unique, increasing trade ID", async () => {
+    await placeAsk(50000, 10000000);
+    await placeAsk(50000, 10000000);
+    await placeAsk(51000, 10000000);
+    await placeAsk(52000, 10000000);
+
+    const tradeIds: number[] = [];
+    for (const signature of [await matchBid(25000000, 52000), await matchBid(15000000, 52000)]) {
+      for (const { name, data } of await eventsOf(signature)) {
+        if (name === "orderMatched") tradeIds.push(data.tradeId.toNumber());
+      }
+    }
+
+    // 3 fills then 2 fills (the 51000 remainder and the 52000 level)
+    expect(tradeIds).to.deep.equal([0, 1, 2, 3, 4]);
+
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    expect(marketAccount.nextTradeId.toNumber()).to.equal(5);
+  });
+
+  it("Lists a price level's orders in time priority", async () => {
+    for (const quantity of [1000000, 2000000, 3000000]) {
+      await placeAsk(70000, quantity);
+    }
+
+    const level = await marketProgram.methods
+      .getLevelOrders({ ask: {} }, new BN(70000))
+      .accounts({ orderBook, tokenMint })
+      .view();
+    expect(level.map((o: any) => o.quantity.toNumber())).to.deep.equal([1000000, 2000000, 3000000]);
+    expect(level.every((o: any) => o.owner.equals(seller.publicKey))).to.equal(true);
+
+    const timestamps = level.map((o: any) => o.timestamp.toNumber());
+    expect([...timestamps].sort((a, b) => a - b)).to.deep.equal(timestamps);
+
+    const empty = await marketProgram.methods
+      .getLevelOrders({ ask: {} }, new BN(70001))
+      .accounts({ orderBook, tokenMint })
+      .view();
+    expect(empty).to.have.length(0);
+  });
+
+  it("Handles a taker who owns all of the opposing liquidity", async () => {
+    // Every resting ask now belongs to the seller
+    const snapshot = () => marketProgram.methods.getBookSnapshot().accounts({ orderBook, tokenMint }).view();
+    const before = await snapshot();
+    expect(before.asks).to.not.be.empty;
+
+    // Immediate order types fail explicitly
+    for (const orderType of [{ market: {} }, { immediateOrCancel: {} }, { fillOrKill: {} }]) {
+      await expectError(matchBid(1000000, 70000, orderType, seller), "SelfTradeNotAllowed");
+    }
+
+    // A post-only bid crossing the taker's own asks is rejected, as in placement
+    await expectError(matchBid(1000000, 70000, { postOnly: {} }, seller), "PostOnlyWouldMatch");
+
+    // So is a limit bid that would rest across them
+    await expectError(matchBid(1000000, 70000, { limit: {} }, seller), "SelfTradeNotAllowed");
+
+    // A limit bid below them rests in full with no fills
+    const events = await eventsOf(await matchBid(1000000, 20000, { limit: {} }, seller));
+    expect(events.filter((e) => e.name === "orderMatched")).to.have.length(0);
+    expect(events.filter((e) => e.name === "orderPlaced").map((e) => e.data.price.toNumber())).to.deep.equal([20000]);
+    const after = await snapshot();
+    expect(after.totalOrders.toNumber()).to.equal(before.totalOrders.toNumber() + 1);
+  });
+
+  it("Aborts a match whose worst fill exceeds the slippage tolerance", async () => {
+    // Clear the 70000 level, then build a thin book: 1 token at 100000, the rest 10% higher
+    await matchBid(6000000, 70000);
+    await placeAsk(100000, 1000000);
+    await placeAsk(110000, 10000000);
+    const bookBefore = await marketProgram.methods.getBookSnapshot().accounts({ orderBook, tokenMint }).view();
+
+    // Sweeping into the 110000 level is 1000 bps from the best ask
+    await expectError(matchBid(5000000, 110000, { immediateOrCancel: {} }, buyer, 500), "SlippageExceeded");
+    const bookAfter = await marketProgram.methods.getBookSnapshot().accounts({ orderBook, tokenMint }).view();
+    expect(bookAfter.totalOrders.toNumber()).to.equal(bookBefore.totalOrders.toNumber());
+
+    // Within tolerance the same sweep goes through
+    const events = await eventsOf(await matchBid(5000000, 110000, { immediateOrCancel: {} }, buyer, 1000));
+    expect(events.filter((e) => e.name === "orderMatched").map((e) => e.data.price.toNumber())).to.deep.equal([
+      100000, 110000,
+    ]);
+  });
+
+  it("Exposes a maker's payment methods and lets takers filter on them", async () => {
+    const ask = await placeAsk(120000, 2000000, defaultOrderOptions, "PayPal,Wise");
+
+    const methods = await marketProgram.methods
+      .getOrderPaymentMethods(ask.orderId)
+      .accounts({ orderBook, tokenMint })
+      .view();
+    expect(methods).to.deep.equal(["PayPal", "Wise"]);
+
+    // No resting maker takes Zelle, so nothing fills
+    let events = await eventsOf(await matchBid(1000000, 120000, { immediateOrCancel: {} }, buyer, 10000, ["Zelle"]));
+    expect(events.filter((e) => e.name === "orderMatched")).to.have.length(0);
+
+    // Wise skips the cheaper bank-transfer asks and fills the PayPal/Wise maker
+    events = await eventsOf(await matchBid(1000000, 120000, { immediateOrCancel: {} }, buyer, 10000, ["Wise"]));
+    const fills = events.filter((e) => e.name === "orderMatched");
+    expect(fills).to.have.length(1);
+    expect(fills[0].data.makerOrderId.toString()).to.equal(ask.orderId.toString());
+  });
+
+  it("Stops filling once the taker's maximum number of makers is reached", async () => {
+    // Clear the remaining seller asks so five single-order makers make up the book
+    await matchBid(7000000, 120000);
+    const makers = [...Array(5)].map(() => Keypair.generate());
+    for (const maker of makers) {
+      await provider.connection.requestAirdrop(maker.publicKey, 5e9);
+    }
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+    for (const [i, maker] of makers.entries()) {
+      const makerTokenAccount = await createAccount(provider.connection, maker, tokenMint, maker.publicKey);
+      await mintTo(provider.connection, seller, tokenMint, makerTokenAccount, seller.publicKey, 1000000);
+      await placeAsk(130000 + i * 1000, 1000000, defaultOrderOptions, "Bank Transfer", maker, makerTokenAccount);
+    }
+
+    const events = await eventsOf(
+      await matchBid(5000000, 134000, { immediateOrCancel: {} }, buyer, 10000, [], 2)
+    );
+    expect(events.filter((e) => e.name === "orderMatched")).to.have.length(2);
+
+    // The other three makers are untouched
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const resting = book.orderQueues.flatMap((q: any) => q.orders);
+    const untouched = makers.filter((maker) =>
+      resting.some((o: any) => o.owner.equals(maker.publicKey) && o.quantity.toNumber() === 1000000)
+    );
+    expect(untouched).to.have.length(3);
+  });
+
+  it("Counts lifetime volume, fills, placements and cancellations", async () => {
+    const lifetimeStats = () => marketProgram.methods.getLifetimeStats().accounts({ orderBook, tokenMint }).view();
+    const before = await lifetimeStats();
+
+    await placeAsk(140000, 1000000);
+    await placeAsk(141000, 1000000);
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const toCancel = book.orderQueues.flatMap((q: any) => q.orders).find((o: any) => o.price.toNumber() === 141000);
+    await marketProgram.methods
+      .cancelOrder(toCancel.orderId, { ask: {} }, new BN(141000), false)
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        escrowAuthority,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+    const fills = (await eventsOf(await matchBid(2500000, 140000))).filter((e) => e.name === "orderMatched");
+    expect(fills).to.not.be.empty;
+
+    const after = await lifetimeStats();
+    const volume = fills.reduce((total, e) => total + e.data.quantity.toNumber(), 0);
+    expect(after.placed.sub(before.placed).toNumber()).to.equal(2);
+    expect(after.cancelled.sub(before.cancelled).toNumber()).to.equal(1);
+    expect(after.fills.sub(before.fills).toNumber()).to.equal(fills.length);
+    expect(after.volume.sub(before.volume).toNumber()).to.equal(volume);
+  });
+
+  it("Writes fills that don't fit in return data to the taker's fill log", async () => {
+    const prices = Array.from({ length: 13 }, (_, i) => 150000 + i * 100);
+    for (const price of prices) {
+      await placeAsk(price, 1000000);
+    }
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const placed = book.orderQueues
+      .flatMap((q: any) => q.orders)
+      .filter((o: any) => prices.includes(o.price.toNumber()))
+      .map((o: any) => o.orderId.toString());
+    expect(placed).to.have.length(13);
+
+    const signature = await matchBid(1000000000, prices[prices.length - 1]);
+
+    // MatchResult: fill_count (u32), fills_in_account (bool), fills (empty vec),
+    // resting_order_id (none: an IOC remainder doesn't rest)
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const prefix = `Program return: ${marketProgram.programId.toBase58()} `;
+    const data = Buffer.from(
+      tx!.meta!.logMessages!.find((log) => log.startsWith(prefix))!.slice(prefix.length),
+      "base64"
+    );
+    const fillCount = data.readUInt32LE(0);
+    expect(fillCount).to.be.greaterThan(12);
+    expect(data[4]).to.equal(1);
+    expect(data.readUInt32LE(5)).to.equal(0);
+    expect(data[9]).to.equal(0);
+
+    const [fillLog] = PublicKey.findProgramAddressSync(
+      [Buffer.from("fill_log"), market.toBuffer(), buyer.publicKey.toBuffer()],
+      marketProgram.programId
+    );
+    const log = await marketProgram.account.fillLog.fetch(fillLog);
+    expect(log.owner.toBase58()).to.equal(buyer.publicKey.toBase58());
+    expect(log.fills).to.have.length(fillCount);
+    const logged = log.fills.map((f: any) => f.makerOrderId.toString());
+    for (const orderId of placed) {
+      expect(logged).to.include(orderId);
+    }
+    const matched = (await eventsOf(signature)).filter((e) => e.name === "orderMatched");
+    expect(log.fills.map((f: any) => f.tradeId.toNumber())).to.deep.equal(
+      matched.map((e) => e.data.tradeId.toNumber())
+    );
+  });
+
+  it("Lets only attested takers fill asks that require KYC", async () => {
+    await placeAsk(90000, 1000000, { ...defaultOrderOptions, requiresAttestation: true });
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const resting = book.orderQueues
+      .flatMap((q: any) => q.orders)
+      .find((o: any) => o.price.toNumber() === 90000);
+    expect(resting.requiresAttestation).to.equal(true);
+
+    // Without an attestation the taker skips it
+    let events = await eventsOf(await matchBid(1000000, 90000));
+    expect(events.filter((e) => e.name === "orderMatched")).to.have.length(0);
+
+    // Only the market authority can attest
+    const attest = (authority: PublicKey) =>
+      marketProgram.methods
+        .attestTaker(buyer.publicKey, new BN(0))
+        .accounts({ market, tokenMint, authority, systemProgram: SystemProgram.programId });
+    await expectError(attest(buyer.publicKey).signers([buyer]).rpc(), "UnauthorizedAuthority");
+    await attest(payer).rpc();
+
+    events = await eventsOf(await matchBid(1000000, 90000));
+    const fills = events.filter((e) => e.name === "orderMatched");
+    expect(fills).to.have.length(1);
+    expect(fills[0].data.makerOrderId.toString()).to.equal(resting.orderId.toString());
+
+    await marketProgram.methods
+      .revokeAttestation(buyer.publicKey)
+      .accounts({ market, tokenMint, authority: payer })
+      .rpc();
+  });
+
+  it("Lets a US taker skip an EU-only ask and fill a US ask", async () => {
+    const code = (country: string) => [...Buffer.from(country)];
+    await expectError(placeAsk(84000, 1000000, { ...defaultOrderOptions, jurisdiction: code("us") }), "InvalidJurisdiction");
+
+    await placeAsk(85000, 1000000, { ...defaultOrderOptions, jurisdiction: code("DE") });
+    await placeAsk(86000, 1000000, { ...defaultOrderOptions, jurisdiction: code("US") });
+    const resting = async () =>
+      (await marketProgram.account.orderBook.fetch(orderBook)).orderQueues.flatMap((q: any) => q.orders);
+    const euAsk = (await resting()).find((o: any) => o.price.toNumber() === 85000);
+    const usAsk = (await resting()).find((o: any) => o.price.toNumber() === 86000);
+    expect(euAsk.jurisdiction).to.deep.equal(code("DE"));
+
+    const events = await eventsOf(
+      await matchBid(2000000, 86000, { immediateOrCancel: {} }, buyer, 10000, [], 0, code("US"))
+    );
+    const fills = events.filter((e) => e.name === "orderMatched");
+    expect(fills).to.have.length(1);
+    expect(fills[0].data.makerOrderId.toString()).to.equal(usAsk.orderId.toString());
+
+    // The EU ask is untouched
+    const eu = (await resting()).find((o: any) => o.orderId.eq(euAsk.orderId));
+    expect(eu.quantity.toNumber()).to.equal(1000000);
+  });
+
+  it("Rests the unfilled remainder of a limit match at its limit price", async () => {
+    const ask = await placeAsk(30000, 1000000);
+
+    const events = await eventsOf(await matchBid(3000000, 30000, { limit: {} }));
+    const fills = events.filter((e) => e.name === "orderMatched");
+    expect(fills.map((e) => e.data.makerOrderId.toString())).to.deep.equal([ask.orderId.toString()]);
+    const placed = events.filter((e) => e.name === "orderPlaced");
+    expect(placed).to.have.length(1);
+    expect(placed[0].data.price.toNumber()).to.equal(30000);
+    expect(placed[0].data.quantity.toNumber()).to.equal(2000000);
+
+    const level = await marketProgram.methods
+      .getLevelOrders({ bid: {} }, new BN(30000))
+      .accounts({ orderBook, tokenMint })
+      .view();
+    expect(level).to.have.length(1);
+    expect(level[0].owner.equals(buyer.publicKey)).to.equal(true);
+    expect(level[0].quantity.toNumber()).to.equal(2000000);
+    expect(level[0].orderId.toString()).to.equal(placed[0].data.orderId.toString());
+
+    // The remainder's payment method is the taker's own and must fit in an order
+    await expectError(
+      matchBid(1000000, 30000, { limit: {} }, buyer, 10000, [], 0, null, buyerTokenAccount, "x".repeat(33)),
+      "PaymentMethodTooLong"
+    );
+  });
+});
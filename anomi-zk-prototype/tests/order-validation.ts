@@ -0,0 +1,268 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+describe("Order validation dry run", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const payer = provider.wallet.publicKey;
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let sellerTokenAccount: PublicKey;
+
+  // PDAs
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+
+  const expectError = async (promise: Promise<any>, code: string) => {
+    try {
+      await promise;
+      expect.fail(`expected ${code}`);
+    } catch (err: any) {
+      expect(err.error?.errorCode?.code ?? err.toString()).to.contain(code);
+    }
+  };
+
+  const place = async (side: any, price: number, quantity: number, orderType: any = { limit: {} }) => {
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    return marketProgram.methods
+      .placeLimitOrderV2({
+        side,
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType,
+        clientOrderId: new BN(marketAccount.nextOrderSequence),
+        paymentMethod: "Bank Transfer",
+        options: defaultOrderOptions,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([seller])
+      .rpc();
+  };
+
+  const validate = (side: any, price: number, quantity: number, orderType: any = { limit: {} }) => {
+    const [ownerState] = PublicKey.findProgramAddressSync(
+      [Buffer.from("owner_state"), market.toBuffer(), seller.publicKey.toBuffer()],
+      marketProgram.programId
+    );
+    return marketProgram.methods
+      .validateOrder({
+        side,
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType,
+        clientOrderId: new BN(0),
+        paymentMethod: "Bank Transfer",
+        options: defaultOrderOptions,
+      })
+      .accounts({ owner: seller.publicKey, ownerState, market, orderBook, tokenMint })
+      .view();
+  };
+
+  const snapshot = () =>
+    marketProgram.methods.getBookSnapshot().accounts({ orderBook, tokenMint }).view();
+
+  before(async () => {
+    seller = Keypair.generate();
+    await provider.connection.requestAirdrop(seller.publicKey, 5e9);
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(provider.connection, seller, tokenMint, seller.publicKey);
+    await mintTo(provider.connection, seller, tokenMint, sellerTokenAccount, seller.publicKey, 1000000000);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market, tokenMint, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({ orderBook, market, tokenMint, quoteMint: tokenMint, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+
+    await place({ ask: {} }, 50000, 10000000);
+  });
+
+  it("Rejects what a real placement rejects without changing the book", async () => {
+    const before = await snapshot();
+
+    await expectError(validate({ bid: {} }, 50000, 10000000, { postOnly: {} }), "PostOnlyWouldMatch");
+    await expectError(place({ bid: {} }, 50000, 10000000, { postOnly: {} }), "PostOnlyWouldMatch");
+
+    await expectError(validate({ ask: {} }, 51000, 0), "InvalidAmount");
+    await expectError(place({ ask: {} }, 51000, 0), "InvalidAmount");
+
+    // A valid order passes the dry run but is not placed
+    expect(await validate({ bid: {} }, 49000, 10000000, { postOnly: {} })).to.equal(true);
+
+    const after = await snapshot();
+    expect(after.totalOrders.toNumber()).to.equal(before.totalOrders.toNumber());
+    expect(after.bids).to.have.length(0);
+  });
+
+  it("Rejects frozen and out-of-hours orders in both the dry run and a real placement", async () => {
+    const update = { market, tokenMint, authority: payer };
+    const before = await snapshot();
+
+    await marketProgram.methods.setSideFrozen({ bid: {} }, true).accounts(update).rpc();
+    await expectError(validate({ bid: {} }, 49000, 1000000), "SideFrozen");
+    await expectError(place({ bid: {} }, 49000, 1000000), "SideFrozen");
+    await marketProgram.methods.setSideFrozen({ bid: {} }, false).accounts(update).rpc();
+
+    const now = Math.floor(Date.now() / 1000);
+    await marketProgram.methods.setTradingHours(new BN(now + 3600), new BN(0)).accounts(update).rpc();
+    await expectError(validate({ bid: {} }, 49000, 1000000), "MarketClosed");
+    await expectError(place({ bid: {} }, 49000, 1000000), "MarketClosed");
+    await marketProgram.methods.setTradingHours(new BN(0), new BN(0)).accounts(update).rpc();
+
+    expect(await validate({ bid: {} }, 49000, 1000000)).to.equal(true);
+    const after = await snapshot();
+    expect(after.totalOrders.toNumber()).to.equal(before.totalOrders.toNumber());
+  });
+
+  it("Reports whether an owner would hit their own resting order", async () => {
+    const checkSelfTrade = (side: any, owner: PublicKey) =>
+      marketProgram.methods.checkSelfTrade(side, owner).accounts({ orderBook, tokenMint }).view();
+
+    // The seller rests an ask at 50000 from setup
+    expect(await checkSelfTrade({ bid: {} }, seller.publicKey)).to.equal(true);
+    expect(await checkSelfTrade({ ask: {} }, seller.publicKey)).to.equal(false);
+    expect(await checkSelfTrade({ bid: {} }, Keypair.generate().publicKey)).to.equal(false);
+  });
+
+  it("Recomputes a resting order's ID from its owner, sequence and timestamp", async () => {
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const order = book.orderQueues.flatMap((q: any) => q.orders)[0];
+
+    const orderId = await marketProgram.methods
+      .computeOrderId(order.owner, order.sequenceNumber, order.timestamp)
+      .accounts({ market, tokenMint })
+      .view();
+    expect(orderId.toString()).to.equal(order.orderId.toString());
+
+    // Any other sequence gives a different ID
+    const other = await marketProgram.methods
+      .computeOrderId(order.owner, order.sequenceNumber.addn(1), order.timestamp)
+      .accounts({ market, tokenMint })
+      .view();
+    expect(other.toString()).to.not.equal(order.orderId.toString());
+  });
+
+  it("Reprices a crossing post-only-reprice bid one tick below the best ask", async () => {
+    // The best ask is 50000 from setup
+    expect(await validate({ bid: {} }, 50500, 1000000, { postOnlyReprice: {} })).to.equal(true);
+    await place({ bid: {} }, 50500, 1000000, { postOnlyReprice: {} });
+
+    const { bids } = await snapshot();
+    expect(bids.map((l: any) => l.price.toNumber())).to.deep.equal([49999]);
+    expect(bids[0].quantity.toNumber()).to.equal(1000000);
+  });
+
+  it("Advances the book's last update slot when an order is placed in a later slot", async () => {
+    const before = await snapshot();
+    const placedAfter = (await provider.connection.getSlot()) + 1;
+    while ((await provider.connection.getSlot()) < placedAfter) {
+      await new Promise((resolve) => setTimeout(resolve, 100));
+    }
+
+    await place({ ask: {} }, 50000, 1000000);
+
+    const after = await snapshot();
+    expect(after.lastUpdateSlot.toNumber()).to.be.greaterThan(before.lastUpdateSlot.toNumber());
+    expect(after.lastUpdateSlot.toNumber()).to.be.at.least(placedAfter);
+
+    const stats = await marketProgram.methods.getMarketStats().accounts({ orderBook, tokenMint }).view();
+    expect(stats.lastUpdateSlot.toNumber()).to.equal(after.lastUpdateSlot.toNumber());
+  });
+
+  it("Reports OrderBookFull once the account has no room for another order", async () => {
+    // The account's bytes run out before the ask tree's 25 levels do
+    let placed = 0;
+    for (let i = 1; i < 25; i++) {
+      try {
+        await place({ ask: {} }, 50000 + i * 100, 1000000);
+        placed++;
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("OrderBookFull");
+        break;
+      }
+    }
+    expect(placed).to.be.lessThan(24);
+
+    const before = await snapshot();
+    await expectError(validate({ ask: {} }, 90000, 1000000), "OrderBookFull");
+    await expectError(place({ ask: {} }, 90000, 1000000), "OrderBookFull");
+
+    // Existing levels have no room either
+    await expectError(validate({ ask: {} }, 50000, 1000000), "OrderBookFull");
+    const capacity = await marketProgram.methods.getBookCapacity().accounts({ orderBook, tokenMint }).view();
+    expect(capacity.freeOrders).to.equal(0);
+    const after = await snapshot();
+    expect(after.totalOrders.toNumber()).to.equal(before.totalOrders.toNumber());
+  });
+});
@@ -10,6 +10,21 @@ import {
 import { expect } from "chai";
 import BN from "bn.js";
 
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
 describe("Phase 2: OrderBook with CritBit Integration", () => {
   anchor.setProvider(anchor.AnchorProvider.env());
   
@@ -101,7 +116,7 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
     );
     
     [orderBook] = PublicKey.findProgramAddressSync(
-      [Buffer.from("order_book"), tokenMint.toBuffer()],
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
       marketProgram.programId
     );
     
@@ -141,7 +156,7 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
     
     // Initialize Market account
     await marketProgram.methods
-      .initializeMarket()
+      .initializeMarket(1000000)
       .accounts({
         market,
         tokenMint,
@@ -162,6 +177,7 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
         orderBook,
         market,
         tokenMint,
+        quoteMint: tokenMint,
         payer: provider.wallet.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -186,14 +202,15 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
     const paymentMethod = "Bank Transfer";
     
     const tx = await marketProgram.methods
-      .placeLimitOrderV2(
+      .placeLimitOrderV2({
         side,
         price,
         quantity,
         orderType,
         clientOrderId,
-        paymentMethod
-      )
+        paymentMethod,
+        options: defaultOrderOptions,
+      })
       .accounts({
         owner: seller1.publicKey,
         ownerTokenAccount: seller1TokenAccount,
@@ -235,14 +252,15 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
     const price2 = new BN(45000); // Lower price
     
     await marketProgram.methods
-      .placeLimitOrderV2(
-        { ask: {} },
-        price2,
-        quantity2,
-        { limit: {} },
-        new BN(67890),
-        "PayPal"
-      )
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: price2,
+        quantity: quantity2,
+        orderType: { limit: {} },
+        clientOrderId: new BN(67890),
+        paymentMethod: "PayPal",
+        options: defaultOrderOptions,
+      })
       .accounts({
         owner: seller2.publicKey,
         ownerTokenAccount: seller2TokenAccount,
@@ -261,14 +279,15 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
     const price3 = new BN(55000); // Higher price
     
     await marketProgram.methods
-      .placeLimitOrderV2(
-        { ask: {} },
-        price3,
-        quantity3,
-        { limit: {} },
-        new BN(11111),
-        "Venmo"
-      )
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: price3,
+        quantity: quantity3,
+        orderType: { limit: {} },
+        clientOrderId: new BN(11111),
+        paymentMethod: "Venmo",
+        options: defaultOrderOptions,
+      })
       .accounts({
         owner: seller1.publicKey,
         ownerTokenAccount: seller1TokenAccount,
@@ -350,14 +369,15 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
       
       try {
         await marketProgram.methods
-          .placeLimitOrderV2(
-            { ask: {} },
+          .placeLimitOrderV2({
+            side: { ask: {} },
             price,
             quantity,
-            { limit: {} },
-            new BN(20000 + i),
-            `Method${i}`
-          )
+            orderType: { limit: {} },
+            clientOrderId: new BN(20000 + i),
+            paymentMethod: `Method${i}`,
+            options: defaultOrderOptions,
+          })
           .accounts({
             owner: i % 2 === 0 ? seller1.publicKey : seller2.publicKey,
             ownerTokenAccount: i % 2 === 0 ? seller1TokenAccount : seller2TokenAccount,
@@ -413,14 +433,15 @@ describe("Phase 2: OrderBook with CritBit Integration", () => {
     const price = new BN(75);
     
     const txSig = await marketProgram.methods
-      .placeLimitOrderV2(
-        { ask: {} },  // Side::Ask
+      .placeLimitOrderV2({
+        side: { ask: {} },
         price,
         quantity,
-        { limit: {} }, // OrderType::Limit
-        new BN(999), // client_order_id
-        "CancelTest"
-      )
+        orderType: { limit: {} },
+        clientOrderId: new BN(999),
+        paymentMethod: "CancelTest",
+        options: defaultOrderOptions,
+      })
       .accounts({
         owner: seller1.publicKey,
         ownerTokenAccount: seller1TokenAccount,
@@ -29,6 +29,24 @@ import BN from "bn.js";
  * 10. Token Escrow Flows
  */
 
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+// Any slippage, payment method, number of makers and jurisdiction
+const noTakerFilters = { maxSlippageBps: 10000, acceptedPaymentMethods: [], maxMakers: 0, jurisdiction: null };
+
 describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
   anchor.setProvider(anchor.AnchorProvider.env());
   
@@ -109,7 +127,7 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
     );
     
     [orderBook] = PublicKey.findProgramAddressSync(
-      [Buffer.from("order_book"), tokenMint.toBuffer()],
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
       marketProgram.programId
     );
     
@@ -165,7 +183,7 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       console.log("\n   Test 1.2: Market initialization");
       
       await marketProgram.methods
-        .initializeMarket()
+        .initializeMarket(1000000)
         .accounts({
           market,
           tokenMint,
@@ -193,6 +211,7 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
           orderBook,
           market,
           tokenMint,
+          quoteMint: tokenMint,
           payer: provider.wallet.publicKey,
           systemProgram: SystemProgram.programId,
         })
@@ -224,14 +243,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       const quantity = new BN(100_000_000); // 100 tokens
       
       await marketProgram.methods
-        .placeLimitOrderV2(
-          { ask: {} },
+        .placeLimitOrderV2({
+          side: { ask: {} },
           price,
           quantity,
-          { limit: {} },
-          new BN(1),
-          "Test Order"
-        )
+          orderType: { limit: {} },
+          clientOrderId: new BN(1),
+          paymentMethod: "Test Order",
+          options: defaultOrderOptions,
+        })
         .accounts({
           owner: user.keypair.publicKey,
           ownerTokenAccount: user.tokenAccount,
@@ -261,14 +281,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       for (let i = 0; i < prices.length; i++) {
         const user = users[i + 1];
         await marketProgram.methods
-          .placeLimitOrderV2(
-            { ask: {} },
-            new BN(prices[i]),
-            new BN(50_000_000),
-            { limit: {} },
-            new BN(i + 2),
-            `Order${i + 2}`
-          )
+          .placeLimitOrderV2({
+            side: { ask: {} },
+            price: new BN(prices[i]),
+            quantity: new BN(50_000_000),
+            orderType: { limit: {} },
+            clientOrderId: new BN(i + 2),
+            paymentMethod: `Order${i + 2}`,
+            options: defaultOrderOptions,
+          })
           .accounts({
             owner: user.keypair.publicKey,
             ownerTokenAccount: user.tokenAccount,
@@ -322,14 +343,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       const beforeOrders = (await marketProgram.account.orderBook.fetch(orderBook)).totalOrders;
       
       await marketProgram.methods
-        .placeLimitOrderV2(
-          { ask: {} },
-          new BN(110_000),
-          new BN(25_000_000),
-          { limit: {} },
-          new BN(100),
-          "Limit Order"
-        )
+        .placeLimitOrderV2({
+          side: { ask: {} },
+          price: new BN(110_000),
+          quantity: new BN(25_000_000),
+          orderType: { limit: {} },
+          clientOrderId: new BN(100),
+          paymentMethod: "Limit Order",
+          options: defaultOrderOptions,
+        })
         .accounts({
           owner: user.keypair.publicKey,
           ownerTokenAccount: user.tokenAccount,
@@ -362,10 +384,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(10_000_000), // 10 tokens
             new BN(200_000), // High limit price to ensure match
-            { market: {} }
+            { market: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: user.keypair.publicKey,
+            ownerTokenAccount: user.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -393,10 +419,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(50_000_000),
             new BN(150_000), // Price that would match existing asks
-            { postOnly: {} }
+            { postOnly: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: user.keypair.publicKey,
+            ownerTokenAccount: user.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -428,10 +458,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(5_000_000),
             new BN(120_000),
-            { immediateOrCancel: {} }
+            { immediateOrCancel: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: user.keypair.publicKey,
+            ownerTokenAccount: user.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -459,10 +493,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(10000_000_000), // Huge quantity
             new BN(200_000),
-            { fillOrKill: {} }
+            { fillOrKill: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: user.keypair.publicKey,
+            ownerTokenAccount: user.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -501,10 +539,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(200_000_000), // Large quantity to match multiple
             new BN(150_000), // Price limit
-            { limit: {} }
+            { limit: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: user.keypair.publicKey,
+            ownerTokenAccount: user.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -529,14 +571,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       for (let i = 0; i < 3; i++) {
         const user = users[i];
         await marketProgram.methods
-          .placeLimitOrderV2(
-            { ask: {} },
-            samePrice,
-            new BN(10_000_000),
-            { limit: {} },
-            new BN(200 + i),
-            `FIFO-${i}`
-          )
+          .placeLimitOrderV2({
+            side: { ask: {} },
+            price: samePrice,
+            quantity: new BN(10_000_000),
+            orderType: { limit: {} },
+            clientOrderId: new BN(200 + i),
+            paymentMethod: `FIFO-${i}`,
+            options: defaultOrderOptions,
+          })
           .accounts({
             owner: user.keypair.publicKey,
             ownerTokenAccount: user.tokenAccount,
@@ -579,10 +622,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(50_000_000),
             new BN(100_000), // Exact price of their ask
-            { limit: {} }
+            { limit: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: user.keypair.publicKey,
+            ownerTokenAccount: user.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -623,14 +670,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       const price = new BN(125_000);
       
       await marketProgram.methods
-        .placeLimitOrderV2(
-          { ask: {} },
+        .placeLimitOrderV2({
+          side: { ask: {} },
           price,
           quantity,
-          { limit: {} },
-          new BN(300),
-          "Cancel Test"
-        )
+          orderType: { limit: {} },
+          clientOrderId: new BN(300),
+          paymentMethod: "Cancel Test",
+          options: defaultOrderOptions,
+        })
         .accounts({
           owner: user.keypair.publicKey,
           ownerTokenAccount: user.tokenAccount,
@@ -689,14 +737,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       const price = new BN(130_000);
       
       await marketProgram.methods
-        .placeLimitOrderV2(
-          { ask: {} },
+        .placeLimitOrderV2({
+          side: { ask: {} },
           price,
-          askQuantity,
-          { limit: {} },
-          new BN(400),
-          "Partial Fill Test"
-        )
+          quantity: askQuantity,
+          orderType: { limit: {} },
+          clientOrderId: new BN(400),
+          paymentMethod: "Partial Fill Test",
+          options: defaultOrderOptions,
+        })
         .accounts({
           owner: seller.keypair.publicKey,
           ownerTokenAccount: seller.tokenAccount,
@@ -717,10 +766,14 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
             { bid: {} },
             new BN(50_000_000), // Only 50 tokens
             price,
-            { limit: {} }
+            { limit: {} },
+            noTakerFilters,
+            "Bank Transfer"
           )
           .accounts({
             owner: buyer.keypair.publicKey,
+            ownerTokenAccount: buyer.tokenAccount,
+            market,
             orderBook,
             tokenMint,
             systemProgram: SystemProgram.programId,
@@ -745,14 +798,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       
       try {
         await marketProgram.methods
-          .placeLimitOrderV2(
-            { ask: {} },
-            new BN(100_000),
-            new BN(0), // Zero quantity
-            { limit: {} },
-            new BN(500),
-            "Zero Qty"
-          )
+          .placeLimitOrderV2({
+            side: { ask: {} },
+            price: new BN(100_000),
+            quantity: new BN(0),
+            orderType: { limit: {} },
+            clientOrderId: new BN(500),
+            paymentMethod: "Zero Qty",
+            options: defaultOrderOptions,
+          })
           .accounts({
             owner: user.keypair.publicKey,
             ownerTokenAccount: user.tokenAccount,
@@ -786,14 +840,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       
       try {
         await marketProgram.methods
-          .placeLimitOrderV2(
-            { ask: {} },
-            new BN(0), // Zero price
-            new BN(100_000_000),
-            { limit: {} },
-            new BN(600),
-            "Zero Price"
-          )
+          .placeLimitOrderV2({
+            side: { ask: {} },
+            price: new BN(0),
+            quantity: new BN(100_000_000),
+            orderType: { limit: {} },
+            clientOrderId: new BN(600),
+            paymentMethod: "Zero Price",
+            options: defaultOrderOptions,
+          })
           .accounts({
             owner: user.keypair.publicKey,
             ownerTokenAccount: user.tokenAccount,
@@ -842,14 +897,15 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
         
         try {
           await marketProgram.methods
-            .placeLimitOrderV2(
-              { ask: {} },
+            .placeLimitOrderV2({
+              side: { ask: {} },
               price,
               quantity,
-              { limit: {} },
-              new BN(1000 + i),
-              `Stress-${i}`
-            )
+              orderType: { limit: {} },
+              clientOrderId: new BN(1000 + i),
+              paymentMethod: `Stress-${i}`,
+              options: defaultOrderOptions,
+            })
             .accounts({
               owner: user.keypair.publicKey,
               ownerTokenAccount: user.tokenAccount,
@@ -907,7 +963,7 @@ describe("🏭 PRODUCTION READINESS TEST SUITE", () => {
       );
       
       const [derivedOrderBook, obBump] = PublicKey.findProgramAddressSync(
-        [Buffer.from("order_book"), tokenMint.toBuffer()],
+        [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
         marketProgram.programId
       );
       
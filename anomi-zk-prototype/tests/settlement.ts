@@ -0,0 +1,702 @@
+import * as anchor from "@coral-xyz/anchor";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getAccount,
+  closeAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import BN from "bn.js";
+
+const defaultOrderOptions = {
+  fillNotifyBps: 0,
+  settlementDestination: null,
+  activationDelay: 0,
+  memo: Array(16).fill(0),
+  cancelDelegate: null,
+  expiryTimestamp: new BN(0),
+  reservePrice: new BN(0),
+  onSettleProgram: null,
+  requiresAttestation: false,
+  referral: null,
+  jurisdiction: null,
+  fiatPayer: null,
+};
+
+describe("P2P settlement", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const provider = anchor.AnchorProvider.env();
+  const marketProgram = anchor.workspace.Market as any;
+  const payer = provider.wallet.publicKey;
+
+  let tokenMint: PublicKey;
+  let seller: Keypair;
+  let sellerTokenAccount: PublicKey;
+  let coldWalletTokenAccount: PublicKey;
+
+  // PDAs
+  let market: PublicKey;
+  let orderBook: PublicKey;
+  let escrowVault: PublicKey;
+  let escrowAuthority: PublicKey;
+
+  const SETTLEMENT_DELAY_MS = 11000;
+
+  const expectError = async (promise: Promise<any>, code: string) => {
+    try {
+      await promise;
+      expect.fail(`expected ${code}`);
+    } catch (err: any) {
+      expect(err.error?.errorCode?.code ?? err.toString()).to.contain(code);
+    }
+  };
+
+  /** Public signals with the order id split into [16] low / [17] high and the payment time at [18] */
+  const publicSignalsFor = (orderId: BN, paidAt = Math.floor(Date.now() / 1000)) => [
+    ...Array(16).fill("1"),
+    orderId.maskn(64).toString(),
+    orderId.shrn(64).toString(),
+    paidAt.toString(),
+  ];
+
+  // PDA holding an order's memo, cancel delegate, settle hook and referral
+  const orderExtras = (owner: PublicKey, clientOrderId: BN) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("order_extras"), orderBook.toBuffer(), owner.toBuffer(), clientOrderId.toArrayLike(Buffer, "le", 8)],
+      marketProgram.programId
+    )[0];
+
+  const placeAsk = async (price: number, quantity: number, options = defaultOrderOptions) => {
+    const marketAccount = await marketProgram.account.market.fetch(market);
+    const clientOrderId = new BN(marketAccount.nextOrderSequence);
+    const needsExtras =
+      options.memo.some((b: number) => b !== 0) || options.cancelDelegate || options.onSettleProgram || options.referral;
+    await marketProgram.methods
+      .placeLimitOrderV2({
+        side: { ask: {} },
+        price: new BN(price),
+        quantity: new BN(quantity),
+        orderType: { limit: {} },
+        clientOrderId,
+        paymentMethod: "Bank Transfer",
+        options,
+      })
+      .accounts({
+        owner: seller.publicKey,
+        ownerTokenAccount: sellerTokenAccount,
+        escrowVault,
+        market,
+        orderBook,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(
+        needsExtras ? [{ pubkey: orderExtras(seller.publicKey, clientOrderId), isWritable: true, isSigner: false }] : []
+      )
+      .signers([seller])
+      .rpc();
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    return orders[orders.length - 1];
+  };
+
+  const markPaid = (orderId: BN) =>
+    marketProgram.methods
+      .markPaymentMade(orderId)
+      .accounts({ buyer: seller.publicKey, orderBook, tokenMint, systemProgram: SystemProgram.programId })
+      .signers([seller])
+      .rpc();
+
+  const eventParser = new anchor.EventParser(
+    marketProgram.programId,
+    new anchor.BorshCoder(marketProgram.idl)
+  );
+
+  /** Events emitted by a confirmed transaction */
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return [...eventParser.parseLogs(tx?.meta?.logMessages ?? [])];
+  };
+
+  const settle = (orderId: BN, destination: PublicKey, paidAt?: number, remaining: any[] = []) =>
+    marketProgram.methods
+      .verifySettlement(
+        orderId,
+        Buffer.alloc(64),
+        Buffer.alloc(128),
+        Buffer.alloc(64),
+        publicSignalsFor(orderId, paidAt)
+      )
+      .accounts({
+        orderBook,
+        escrowVault,
+        sellerTokenAccount: destination,
+        escrowAuthority,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(remaining)
+      .rpc({ commitment: "confirmed" });
+
+  const extrasOf = (order: any) => ({
+    pubkey: orderExtras(seller.publicKey, order.clientOrderId),
+    isWritable: false,
+    isSigner: false,
+  });
+
+  before(async () => {
+    seller = Keypair.generate();
+    const coldWallet = Keypair.generate();
+    await provider.connection.requestAirdrop(seller.publicKey, 5e9);
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+
+    tokenMint = await createMint(provider.connection, seller, seller.publicKey, null, 6);
+    sellerTokenAccount = await createAccount(provider.connection, seller, tokenMint, seller.publicKey);
+    coldWalletTokenAccount = await createAccount(provider.connection, seller, tokenMint, coldWallet.publicKey);
+    await mintTo(provider.connection, seller, tokenMint, sellerTokenAccount, seller.publicKey, 1000000000);
+
+    [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [orderBook] = PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), tokenMint.toBuffer(), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_vault"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+    [escrowAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_authority"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    await marketProgram.methods
+      .initializeEscrowVault()
+      .accounts({
+        escrowVault,
+        escrowAuthority,
+        tokenMint,
+        payer,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await marketProgram.methods
+      .initializeMarket(1000000)
+      .accounts({ market, tokenMint, authority: payer, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+    await marketProgram.methods
+      .initializeOrderBookV2()
+      .accounts({ orderBook, market, tokenMint, quoteMint: tokenMint, payer, systemProgram: SystemProgram.programId })
+      .rpc();
+  });
+
+  it("Settles to the settlement destination chosen at placement", async () => {
+    const order = await placeAsk(50000, 40000000, {
+      ...defaultOrderOptions,
+      settlementDestination: coldWalletTokenAccount,
+    });
+    expect(order.settlementDestination.toBase58()).to.equal(coldWalletTokenAccount.toBase58());
+
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    // The funding account is no longer an acceptable payout target
+    await expectError(settle(order.orderId, sellerTokenAccount), "InvalidSettlementDestination");
+
+    await settle(order.orderId, coldWalletTokenAccount);
+    const cold = await getAccount(provider.connection, coldWalletTokenAccount);
+    expect(cold.amount.toString()).to.equal("40000000");
+  });
+
+  it("Only settles an order whose payment was marked, and only once", async () => {
+    const order = await placeAsk(50000, 1000000);
+    await expectError(settle(order.orderId, sellerTokenAccount), "InvalidPaymentStatus");
+
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+    await settle(order.orderId, sellerTokenAccount);
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await expectError(settle(order.orderId, sellerTokenAccount), "OrderAlreadySettled");
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect(after.amount.toString()).to.equal(before.amount.toString());
+  });
+
+  it("Won't cancel and refund an order that has settled", async () => {
+    const order = await placeAsk(50000, 2000000);
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+    await settle(order.orderId, sellerTokenAccount);
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    const signature = await marketProgram.methods
+      .cancelOrders([{ orderId: order.orderId, side: { ask: {} }, price: order.price }])
+      .accounts({ owner: seller.publicKey, orderBook, escrowVault, escrowAuthority, tokenMint, tokenProgram: TOKEN_PROGRAM_ID })
+      .remainingAccounts([{ pubkey: sellerTokenAccount, isWritable: true, isSigner: false }])
+      .signers([seller])
+      .rpc({ commitment: "confirmed" });
+    expect((await eventsOf(signature)).filter((e) => e.name === "orderCancelled")).to.have.length(0);
+
+    await expectError(
+      marketProgram.methods
+        .cancelOrder(order.orderId, { ask: {} }, order.price, false)
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          escrowAuthority,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc(),
+      "InvalidPaymentStatus"
+    );
+
+    // No second payout of the settled escrow
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect(after.amount.toString()).to.equal(before.amount.toString());
+  });
+
+  it("Settles to a re-designated destination after the original was closed", async () => {
+    const original = await createAccount(provider.connection, seller, tokenMint, seller.publicKey, Keypair.generate());
+    const order = await placeAsk(56000, 2000000, {
+      ...defaultOrderOptions,
+      settlementDestination: original,
+    });
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    await closeAccount(provider.connection, seller, original, seller.publicKey, seller);
+    await expectError(settle(order.orderId, original), "InvalidSettlementDestination");
+
+    // Only the order owner can re-designate
+    const stranger = Keypair.generate();
+    await expectError(
+      marketProgram.methods
+        .setSettlementDestination(order.orderId)
+        .accounts({ owner: stranger.publicKey, orderBook, newDestination: sellerTokenAccount, tokenMint })
+        .signers([stranger])
+        .rpc(),
+      "UnauthorizedAction"
+    );
+
+    await marketProgram.methods
+      .setSettlementDestination(order.orderId)
+      .accounts({ owner: seller.publicKey, orderBook, newDestination: sellerTokenAccount, tokenMint })
+      .signers([seller])
+      .rpc();
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await settle(order.orderId, sellerTokenAccount);
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("2000000");
+  });
+
+  it("Batch settlement settles ripe orders and skips premature ones", async () => {
+    const ripe = await placeAsk(51000, 10000000);
+    await markPaid(ripe.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    const premature = await placeAsk(52000, 20000000);
+    await markPaid(premature.orderId);
+
+    const request = (orderId: BN) => ({
+      orderId,
+      proofA: Buffer.alloc(64),
+      proofB: Buffer.alloc(128),
+      proofC: Buffer.alloc(64),
+      publicSignals: publicSignalsFor(orderId),
+    });
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    const signature = await marketProgram.methods
+      .verifySettlementBatch([request(ripe.orderId), request(premature.orderId)])
+      .accounts({ orderBook, escrowVault, escrowAuthority, tokenMint, tokenProgram: TOKEN_PROGRAM_ID })
+      .remainingAccounts(
+        [sellerTokenAccount, sellerTokenAccount].map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+      )
+      .rpc({ commitment: "confirmed" });
+
+    // Return data: Vec<SettlementResult { order_id: u128, status: u8 }>
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const returnLog = tx!.meta!.logMessages!.find((log) => log.startsWith("Program return:"))!;
+    const data = Buffer.from(returnLog.split(" ")[3], "base64");
+    const statuses = [0, 1].map((i) => data[4 + i * 17 + 16]);
+    expect(statuses).to.deep.equal([0, 1]); // Settled, Skipped
+
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("10000000");
+
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    const status = (orderId: BN) => Object.keys(orders.find((o: any) => o.orderId.eq(orderId)).paymentStatus)[0];
+    expect(status(ripe.orderId)).to.equal("verified");
+    expect(status(premature.orderId)).to.equal("paymentMarked");
+  });
+
+  it("Rejects a proof whose payment is older than the proof validity window", async () => {
+    await marketProgram.methods
+      .setProofValidity(600)
+      .accounts({ market, tokenMint, authority: payer })
+      .rpc();
+
+    const order = await placeAsk(55000, 3000000);
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    const anHourAgo = Math.floor(Date.now() / 1000) - 3600;
+    await expectError(settle(order.orderId, sellerTokenAccount, anHourAgo), "ProofExpired");
+
+    const before = await getAccount(provider.connection, sellerTokenAccount);
+    await settle(order.orderId, sellerTokenAccount);
+    const after = await getAccount(provider.connection, sellerTokenAccount);
+    expect((after.amount - before.amount).toString()).to.equal("3000000");
+
+    await marketProgram.methods
+      .setProofValidity(0)
+      .accounts({ market, tokenMint, authority: payer })
+      .rpc();
+  });
+
+  it("Carries the order memo from placement through settlement", async () => {
+    const memo = Buffer.alloc(16);
+    memo.write("INV-2024-000017");
+
+    const order = await placeAsk(53000, 5000000, { ...defaultOrderOptions, memo: [...memo] });
+    expect(order.hasExtras).to.equal(true);
+
+    const status = await marketProgram.methods
+      .getOrderStatus(order.orderId)
+      .accounts({ orderBook, tokenMint })
+      .remainingAccounts([extrasOf(order)])
+      .view();
+    expect(Buffer.from(status.memo).equals(memo)).to.equal(true);
+
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    // The memo lives in the order's extras account, which has to be supplied
+    await expectError(settle(order.orderId, sellerTokenAccount), "OrderExtrasMissing");
+    const events = await eventsOf(await settle(order.orderId, sellerTokenAccount, undefined, [extrasOf(order)]));
+    const settled = events.find((e) => e.name === "settlementVerified")!;
+    expect(settled.data.orderId.toString()).to.equal(order.orderId.toString());
+    expect(Buffer.from(settled.data.memo).equals(memo)).to.equal(true);
+  });
+
+  it("Lists only orders whose settlement delay has expired", async () => {
+    const ripe = await placeAsk(54000, 1000000);
+    await markPaid(ripe.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    const pending = await placeAsk(55000, 1000000);
+    await markPaid(pending.orderId);
+
+    const listed: BN[] = await marketProgram.methods
+      .listSettleableOrders()
+      .accounts({ orderBook, tokenMint })
+      .view();
+    const ids = listed.map((id) => id.toString());
+
+    expect(ids).to.include(ripe.orderId.toString());
+    expect(ids).to.not.include(pending.orderId.toString());
+  });
+
+  it("Reports an owner's escrowed balance across resting asks", async () => {
+    const escrowed = async () =>
+      (
+        await marketProgram.methods
+          .getEscrowedBalance(seller.publicKey)
+          .accounts({ orderBook, tokenMint })
+          .view()
+      ).toNumber();
+
+    // Settled asks from earlier tests have already released their escrow
+    const before = await escrowed();
+    await placeAsk(56000, 3000000);
+    await placeAsk(57000, 4000000);
+    expect(await escrowed()).to.equal(before + 7000000);
+  });
+
+  it("Settles into a program-owned token account and notifies its on-settle hook", async () => {
+    const receiverProgram = anchor.workspace.SettleReceiver as any;
+    const [receiverVault] = PublicKey.findProgramAddressSync([Buffer.from("vault")], receiverProgram.programId);
+    const receiverTokenAccount = await createAccount(
+      provider.connection,
+      seller,
+      tokenMint,
+      receiverVault,
+      Keypair.generate()
+    );
+
+    const order = await placeAsk(58000, 6000000, {
+      ...defaultOrderOptions,
+      settlementDestination: receiverTokenAccount,
+      onSettleProgram: receiverProgram.programId,
+    });
+    const extras = await marketProgram.account.orderExtras.fetch(extrasOf(order).pubkey);
+    expect(extras.onSettleProgram.toBase58()).to.equal(receiverProgram.programId.toBase58());
+
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    // The hook program has to be supplied after the extras account
+    await expectError(settle(order.orderId, receiverTokenAccount, undefined, [extrasOf(order)]), "SettleHookMissing");
+
+    const signature = await marketProgram.methods
+      .verifySettlement(order.orderId, Buffer.alloc(64), Buffer.alloc(128), Buffer.alloc(64), publicSignalsFor(order.orderId))
+      .accounts({
+        orderBook,
+        escrowVault,
+        sellerTokenAccount: receiverTokenAccount,
+        escrowAuthority,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([extrasOf(order), { pubkey: receiverProgram.programId, isWritable: false, isSigner: false }])
+      .rpc({ commitment: "confirmed" });
+
+    const received = await getAccount(provider.connection, receiverTokenAccount);
+    expect(received.amount.toString()).to.equal("6000000");
+    expect(received.owner.toBase58()).to.equal(receiverVault.toBase58());
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const logs = tx?.meta?.logMessages ?? [];
+    expect(
+      logs.some((log) => log.includes(`SettleReceiver: received 6000000 tokens for order ${order.orderId.toString()}`))
+    ).to.equal(true);
+  });
+
+  it("Splits a settlement between the seller and a 50 bps referral account", async () => {
+    const referrer = Keypair.generate();
+    const referralTokenAccount = await createAccount(provider.connection, seller, tokenMint, referrer.publicKey);
+    const destination = await createAccount(provider.connection, seller, tokenMint, seller.publicKey, Keypair.generate());
+
+    // Out-of-range shares are rejected at placement
+    await expectError(
+      placeAsk(58500, 1000000, { ...defaultOrderOptions, referral: { recipient: referralTokenAccount, bps: 1001 } }),
+      "InvalidReferral"
+    );
+
+    await placeAsk(58500, 4000000, {
+      ...defaultOrderOptions,
+      settlementDestination: destination,
+      referral: { recipient: referralTokenAccount, bps: 50 },
+    });
+    const order = (await marketProgram.account.orderBook.fetch(orderBook)).orderQueues
+      .flatMap((q: any) => q.orders)
+      .find((o: any) => o.price.toNumber() === 58500);
+    const extras = await marketProgram.account.orderExtras.fetch(extrasOf(order).pubkey);
+    expect(extras.referralBps).to.equal(50);
+    expect(extras.referralRecipient.toBase58()).to.equal(referralTokenAccount.toBase58());
+
+    await markPaid(order.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    // The referral account has to be supplied after the extras account
+    await expectError(settle(order.orderId, destination, undefined, [extrasOf(order)]), "ReferralAccountMissing");
+
+    const escrowBefore = (await getAccount(provider.connection, escrowVault)).amount;
+    await marketProgram.methods
+      .verifySettlement(order.orderId, Buffer.alloc(64), Buffer.alloc(128), Buffer.alloc(64), publicSignalsFor(order.orderId))
+      .accounts({
+        orderBook,
+        escrowVault,
+        sellerTokenAccount: destination,
+        escrowAuthority,
+        tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([extrasOf(order), { pubkey: referralTokenAccount, isWritable: true, isSigner: false }])
+      .rpc({ commitment: "confirmed" });
+
+    // 50 bps of 4000000 is 20000; together the two payouts are exactly the escrowed quantity
+    const paid = (await getAccount(provider.connection, destination)).amount;
+    const referred = (await getAccount(provider.connection, referralTokenAccount)).amount;
+    const escrowAfter = (await getAccount(provider.connection, escrowVault)).amount;
+    expect(referred.toString()).to.equal("20000");
+    expect(paid.toString()).to.equal("3980000");
+    expect((escrowBefore - escrowAfter).toString()).to.equal("4000000");
+  });
+
+  it("Escrows and settles a bid whose counterparty pays fiat", async () => {
+    const placeBid = async (price: number, quantity: number, options: any) => {
+      const marketAccount = await marketProgram.account.market.fetch(market);
+      await marketProgram.methods
+        .placeLimitOrderV2({
+          side: { bid: {} },
+          price: new BN(price),
+          quantity: new BN(quantity),
+          orderType: { limit: {} },
+          clientOrderId: new BN(marketAccount.nextOrderSequence),
+          paymentMethod: "Bank Transfer",
+          options,
+        })
+        .accounts({
+          owner: seller.publicKey,
+          ownerTokenAccount: sellerTokenAccount,
+          escrowVault,
+          market,
+          orderBook,
+          tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc();
+      const book = await marketProgram.account.orderBook.fetch(orderBook);
+      return book.orderQueues.flatMap((q: any) => q.orders).find((o: any) => o.price.toNumber() === price);
+    };
+
+    // A default bid pays fiat itself: nothing is escrowed and there is nothing to settle
+    const escrowBefore = (await getAccount(provider.connection, escrowVault)).amount;
+    const fiatBid = await placeBid(1000, 1000000, defaultOrderOptions);
+    expect(fiatBid.fiatPayer).to.deep.equal({ bid: {} });
+    expect((await getAccount(provider.connection, escrowVault)).amount).to.equal(escrowBefore);
+
+    // With the ask side paying fiat, the bid maker escrows its tokens instead
+    const tokenBid = await placeBid(1100, 5000000, {
+      ...defaultOrderOptions,
+      settlementDestination: coldWalletTokenAccount,
+      fiatPayer: { ask: {} },
+    });
+    expect(tokenBid.fiatPayer).to.deep.equal({ ask: {} });
+    const escrowed = (await getAccount(provider.connection, escrowVault)).amount;
+    expect((escrowed - escrowBefore).toString()).to.equal("5000000");
+
+    await markPaid(fiatBid.orderId);
+    await markPaid(tokenBid.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    await expectError(settle(fiatBid.orderId, sellerTokenAccount), "InvalidSide");
+
+    const before = (await getAccount(provider.connection, coldWalletTokenAccount)).amount;
+    await settle(tokenBid.orderId, coldWalletTokenAccount);
+    const after = (await getAccount(provider.connection, coldWalletTokenAccount)).amount;
+    expect((after - before).toString()).to.equal("5000000");
+    expect((await getAccount(provider.connection, escrowVault)).amount).to.equal(escrowBefore);
+  });
+
+  it("Checks proofs against the verifying key set by the authority, rotates it, and backs off failed proofs", async () => {
+    // alpha, beta, gamma, delta, then 20 IC points; coordinates little-endian
+    const VERIFYING_KEY_LEN = 64 + 3 * 128 + 20 * 64;
+    const le = (value: string) => new BN(value).toArrayLike(Buffer, "le", 32);
+
+    // The all-identity key accepts the all-identity proof the other tests send
+    const identityKey = Buffer.alloc(VERIFYING_KEY_LEN);
+    // With alpha and beta the curve generators, e(alpha, beta) != 1 and it fails
+    const generatorKey = Buffer.alloc(VERIFYING_KEY_LEN);
+    Buffer.concat([
+      le("1"),
+      le("2"),
+      le("11559732032986387107991004021392285783925812861821192530917403151452391805634"),
+      le("10857046999023057135944570762232829481370756359578518086990519993285655852781"),
+      le("4082367875863433681332203403145435568316851327593401208105741076214120093531"),
+      le("8495653923123431417604973247489272438418190587263600148770280649306958101930"),
+    ]).copy(generatorKey);
+
+    const setVerifyingKey = async (key: Buffer) => {
+      for (let offset = 0; offset < key.length; offset += 576) {
+        await marketProgram.methods
+          .writeVerifyingKey(offset, key.subarray(offset, offset + 576))
+          .accounts({ market, tokenMint, authority: payer })
+          .rpc();
+      }
+      await marketProgram.methods.setVerifyingKey().accounts({ market, tokenMint, authority: payer }).rpc();
+    };
+    const [verifyingKey] = PublicKey.findProgramAddressSync(
+      [Buffer.from("verifying_key"), tokenMint.toBuffer()],
+      marketProgram.programId
+    );
+
+    // Only the market authority can stage a key, and a malformed key can't be activated
+    await expectError(
+      marketProgram.methods
+        .writeVerifyingKey(0, Buffer.alloc(64))
+        .accounts({ market, tokenMint, authority: seller.publicKey })
+        .signers([seller])
+        .rpc(),
+      "UnauthorizedAuthority"
+    );
+    await expectError(
+      marketProgram.methods
+        .writeVerifyingKey(VERIFYING_KEY_LEN - 32, Buffer.alloc(64))
+        .accounts({ market, tokenMint, authority: payer })
+        .rpc(),
+      "InvalidVerifyingKey"
+    );
+
+    await placeAsk(59000, 1000000);
+    await placeAsk(59500, 2000000);
+    const book = await marketProgram.account.orderBook.fetch(orderBook);
+    const orders = book.orderQueues.flatMap((q: any) => q.orders);
+    const first = orders.find((o: any) => o.price.toNumber() === 59000);
+    const second = orders.find((o: any) => o.price.toNumber() === 59500);
+    await markPaid(first.orderId);
+    await markPaid(second.orderId);
+    await new Promise((resolve) => setTimeout(resolve, SETTLEMENT_DELAY_MS));
+
+    const failedVerifications = async (orderId: BN) => {
+      const book = await marketProgram.account.orderBook.fetch(orderBook);
+      const order = book.orderQueues.flatMap((q: any) => q.orders).find((o: any) => o.orderId.eq(orderId));
+      return order.failedVerificationCount;
+    };
+    // A rejected proof releases nothing but is recorded on the order
+    const expectProofRejected = async (orderId: BN, failures: number) => {
+      const before = (await getAccount(provider.connection, sellerTokenAccount)).amount;
+      const events = await eventsOf(await settle(orderId, sellerTokenAccount));
+      const after = (await getAccount(provider.connection, sellerTokenAccount)).amount;
+      expect(after).to.equal(before);
+      const failed = events.find((e) => e.name === "settlementVerificationFailed");
+      expect(failed?.data.failedVerificationCount).to.equal(failures);
+      expect(await failedVerifications(orderId)).to.equal(failures);
+    };
+    const BACKOFF_MS = 2000;
+
+    await setVerifyingKey(generatorKey);
+    await expectProofRejected(first.orderId, 1);
+
+    // After rotating and waiting out the backoff, the same proof verifies against the new key...
+    await setVerifyingKey(identityKey);
+    await new Promise((resolve) => setTimeout(resolve, BACKOFF_MS + 1000));
+    const before = (await getAccount(provider.connection, sellerTokenAccount)).amount;
+    await settle(first.orderId, sellerTokenAccount);
+    const after = (await getAccount(provider.connection, sellerTokenAccount)).amount;
+    expect((after - before).toString()).to.equal("1000000");
+
+    // ...and fails again once the old key is back
+    await setVerifyingKey(generatorKey);
+    await expectProofRejected(second.orderId, 1);
+
+    // A second failure doubles the backoff, which blocks an immediate third attempt
+    await new Promise((resolve) => setTimeout(resolve, BACKOFF_MS + 1000));
+    await expectProofRejected(second.orderId, 2);
+    await expectError(settle(second.orderId, sellerTokenAccount), "VerificationBackoff");
+    expect(await failedVerifications(second.orderId)).to.equal(2);
+
+    const stored = await marketProgram.account.verifyingKey.fetch(verifyingKey);
+    expect(stored.version).to.equal(3);
+    expect(Buffer.from(stored.active).equals(generatorKey)).to.equal(true);
+  });
+});
@@ -7,6 +7,9 @@ pragma circom 2.0.0;
     1. Constraint A (The "Seal"): DKIM signature hash matches email hash - proves Google sent the email
     2. Constraint B (The "Letter"): From header matches .*@telenorbank\.pk - proves email is from Easypaisa/Telenor
     
+    The payment timestamp (the email's Date header, unix seconds) is a public input
+    so the market can reject stale proofs.
+    
     Note: Full RSA-2048 verification in Circom is computationally expensive (millions of constraints).
     This implementation verifies the signature hash matches the email hash, which is the core check.
     The RSA signature verification (signature^e mod n) can be done outside the circuit or added later.
@@ -21,6 +24,7 @@ template EmailVerification(maxFromHeaderBytes) {
     signal input emailHash[8];           // SHA-256 hash of canonicalized email (8 * 32 bits = 256 bits)
     signal input fromHeaderHash[8];      // SHA-256 hash of From header (8 * 32 bits = 256 bits)
     signal input orderId[2];             // Order ID (u128 = 2 * 64 bits)
+    signal input paymentTimestamp;       // Unix seconds from the Date header (covered by the DKIM-signed headers)
     
     // Private inputs (hidden in proof)
     signal input fromHeader[maxFromHeaderBytes];     // From header as byte array
@@ -151,6 +151,25 @@ class EmailParser {
         return fromHeader.trim();
     }
     
+    /**
+     * Extract the payment time from the email's Date header
+     * @param {Object} email - Parsed email object
+     * @returns {number} Unix timestamp in seconds
+     */
+    static extractPaymentTimestamp(email) {
+        const dateHeader = email.headers['date'];
+        if (!dateHeader) {
+            throw new Error('Date header not found');
+        }
+        
+        const millis = Date.parse(dateHeader);
+        if (Number.isNaN(millis)) {
+            throw new Error(`Unparseable Date header: ${dateHeader}`);
+        }
+        
+        return Math.floor(millis / 1000);
+    }
+    
     /**
      * Canonicalize email for DKIM verification
      * @param {Object} email - Parsed email object
@@ -158,6 +158,7 @@ class AnomiProofGenerator {
             const email = EmailParser.parseEML(emlPath);
             const dkim = EmailParser.extractDKIMSignature(email);
             const fromHeader = EmailParser.extractFromHeader(email);
+            const paymentTimestamp = EmailParser.extractPaymentTimestamp(email);
             const emailHashData = EmailParser.computeEmailHash(email, dkim.headers);
             
             console.log("DKIM Domain:", dkim.domain);
@@ -219,6 +220,7 @@ class AnomiProofGenerator {
                 emailHash: emailHash,
                 fromHeaderHash: fromHeaderHash,
                 orderId: orderIdArray,
+                paymentTimestamp: paymentTimestamp.toString(),
                 
                 // Private inputs
                 fromHeader: fromHeaderArray,